@@ -0,0 +1,165 @@
+//! QuadTree vs brute force (10k rects)
+//!
+//! `cargo bench --bench quadtree`
+
+use std::{hint::black_box, time::Instant};
+
+// Note: バイナリクレートなのでモジュールを直接取り込む
+#[path = "../src/quadtree.rs"]
+#[allow(dead_code)]
+mod quadtree;
+
+use quadtree::{QuadTree, QuadTreeRect};
+
+const ELEMENT_COUNT: usize = 10_000;
+const QUERY_COUNT: usize = 10_000;
+const AREA_SIZE: u32 = 8192;
+
+/// 再現性のために固定シードのxorshift
+struct XorShift(u64);
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn range(&mut self, max: u32) -> u32 {
+        (self.next() % max as u64) as u32
+    }
+
+    fn rect(&mut self) -> QuadTreeRect {
+        let (left, top) = (self.range(AREA_SIZE - 256), self.range(AREA_SIZE - 256));
+        let (w, h) = (self.range(255) + 1, self.range(255) + 1);
+
+        QuadTreeRect {
+            left,
+            top,
+            right: left + w,
+            bottom: top + h,
+        }
+    }
+}
+
+fn measure<R>(label: &str, f: impl FnOnce() -> R) -> R {
+    let t = Instant::now();
+    let r = f();
+    println!(
+        "{label:<32} {:>10.3} ms",
+        t.elapsed().as_secs_f64() * 1000.0
+    );
+
+    r
+}
+
+fn brute_nearest(rects: &[QuadTreeRect], x: u32, y: u32) -> Option<usize> {
+    // 同じ距離なら大きいインデックスのもの（QuadTree::nearestと同じ）
+    (0..rects.len()).min_by_key(|&n| (rects[n].distance_squared(x, y), std::cmp::Reverse(n)))
+}
+
+fn main() {
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+    let mut rects = (0..ELEMENT_COUNT).map(|_| rng.rect()).collect::<Vec<_>>();
+    let points = (0..QUERY_COUNT)
+        .map(|_| (rng.range(AREA_SIZE), rng.range(AREA_SIZE)))
+        .collect::<Vec<_>>();
+    let query_rects = (0..QUERY_COUNT).map(|_| rng.rect()).collect::<Vec<_>>();
+
+    let mut qt = QuadTree::new();
+    measure("insert", || {
+        for (n, r) in rects.iter().enumerate() {
+            qt.insert(n, *r).unwrap();
+        }
+    });
+
+    let qt_point = measure("point query (quadtree)", || {
+        points
+            .iter()
+            .map(|&(x, y)| qt.iter_at_point(x, y).max())
+            .collect::<Vec<_>>()
+    });
+    let brute_point = measure("point query (brute force)", || {
+        points
+            .iter()
+            .map(|&(x, y)| black_box(&rects).iter().rposition(|r| r.contains(x, y)))
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(qt_point, brute_point);
+
+    let qt_range = measure("rect query (quadtree)", || {
+        query_rects
+            .iter()
+            .map(|q| {
+                let mut xs = qt.query_rect(q);
+                xs.sort_unstable();
+                xs
+            })
+            .collect::<Vec<_>>()
+    });
+    let brute_range = measure("rect query (brute force)", || {
+        query_rects
+            .iter()
+            .map(|q| {
+                (0..rects.len())
+                    .filter(|&n| black_box(&rects)[n].intersects(q))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(qt_range, brute_range);
+
+    let qt_nearest = measure("nearest (quadtree)", || {
+        points
+            .iter()
+            .map(|&(x, y)| qt.nearest(x, y))
+            .collect::<Vec<_>>()
+    });
+    let brute_nearest = measure("nearest (brute force)", || {
+        points
+            .iter()
+            .map(|&(x, y)| brute_nearest(black_box(&rects), x, y))
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(qt_nearest, brute_nearest);
+
+    measure("move all", || {
+        for (n, r) in rects.iter_mut().enumerate() {
+            *r = rng.rect();
+            qt.move_to(n, *r).unwrap();
+        }
+    });
+    for &(x, y) in points.iter().take(1000) {
+        assert_eq!(
+            qt.iter_at_point(x, y).max(),
+            rects.iter().rposition(|r| r.contains(x, y))
+        );
+    }
+
+    measure("remove half", || {
+        for n in (0..ELEMENT_COUNT).step_by(2) {
+            qt.remove(n).unwrap();
+        }
+    });
+    for &(x, y) in points.iter().take(1000) {
+        assert_eq!(
+            qt.nearest(x, y),
+            (1..ELEMENT_COUNT)
+                .step_by(2)
+                .min_by_key(|&n| (rects[n].distance_squared(x, y), std::cmp::Reverse(n)))
+        );
+    }
+
+    assert!(
+        qt.insert(
+            ELEMENT_COUNT,
+            QuadTreeRect {
+                left: 0,
+                top: 0,
+                right: QuadTree::EXTENT,
+                bottom: 0,
+            },
+        )
+        .is_err()
+    );
+}
@@ -1,9 +1,16 @@
-Texture2D tex : register(t0);
+Texture2DArray tex : register(t0);
 SamplerState smp : register(s0);
 
-float4 main(float4 pos : SV_Position, float2 uv : TEXCOORD0) : SV_Target {
-    float4 c = tex.Sample(smp, uv);
-    c.rgb *= c.a;
+struct RenderParams {
+    float2 pixelSize;
+    float2 offset;
+    uint premultiply;
+} renderParams : register(c0);
+
+float4 main(float4 pos : SV_Position, float2 uv : TEXCOORD0, nointerpolation uint page : TEXCOORD1) : SV_Target {
+    float4 c = tex.Sample(smp, float3(uv, page));
+    // アトラスがpremultipliedのときはすでに掛かっている
+    if (renderParams.premultiply != 0) c.rgb *= c.a;
     
     return c;
 }
@@ -1,8 +1,82 @@
-use std::path::{Path, PathBuf};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use uuid::Uuid;
 
-use crate::{coordinate::SizePixels, peridot};
+use crate::{
+    asset_lint::{self, LintIssue},
+    bg_worker::BackgroundWorkCancellationToken,
+    coordinate::SizePixels,
+    grid_slice::{self, GridSliceParams},
+    peridot,
+    region_detect::RegionDetectParams,
+    sprite_filter::{SpriteFilter, SpriteFilterTarget},
+    sprite_group, sprite_packing,
+};
+
+/// スプライトを詰め直すときの入力（`AppState::auto_pack_request`）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutoPackRequest {
+    sprite_ids: Vec<Uuid>,
+    sizes: Vec<(u32, u32)>,
+    mip_levels: u32,
+    block_size: u32,
+    max_size: u32,
+}
+impl AutoPackRequest {
+    pub fn pack(
+        &self,
+        on_progress: impl FnMut(f32),
+    ) -> Result<sprite_packing::PackedLayout, sprite_packing::PackError> {
+        sprite_packing::pack_with_progress(
+            &self.sizes,
+            self.mip_levels,
+            self.block_size,
+            self.max_size,
+            on_progress,
+        )
+    }
+}
+
+/// 読み込んだスプライトのソース画像の大きさを確かめるときの入力（`AppState::load`）
+///
+/// 画像のヘッダを読むのでバックグラウンドで行う
+#[derive(Debug, Clone)]
+pub struct SourceSizeCheckRequest {
+    generation: u64,
+    sprites: Vec<peridot::Sprite>,
+}
+impl SourceSizeCheckRequest {
+    pub const fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn check(&self) -> Vec<SourceSizeMismatch> {
+        asset_lint::check_sources(&self.sprites)
+            .into_iter()
+            .filter_map(|i| match i {
+                LintIssue::DimensionMismatch {
+                    id,
+                    name,
+                    path,
+                    stored_size,
+                    fitted_size,
+                    ..
+                } => Some(SourceSizeMismatch {
+                    sprite_id: id,
+                    name,
+                    source_path: path,
+                    stored_size,
+                    fitted_size,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+}
 
 #[derive(Debug)]
 pub struct SpriteInfo {
@@ -10,6 +84,8 @@ pub struct SpriteInfo {
     id: Uuid,
     pub name: String,
     pub source_path: PathBuf,
+    pub source_left: u32,
+    pub source_top: u32,
     pub width: u32,
     pub height: u32,
     pub left: u32,
@@ -18,14 +94,33 @@ pub struct SpriteInfo {
     pub right_slice: u32,
     pub top_slice: u32,
     pub bottom_slice: u32,
+    /// スプライト内の基準点（正規化座標、左上が(0, 0)）
+    pub pivot_x: f32,
+    pub pivot_y: f32,
+    /// `/`区切りのグループパス（空文字列はグループなし）
+    pub group: String,
     pub selected: bool,
 }
 impl SpriteInfo {
     pub fn new(name: String, source_path: PathBuf, width: u32, height: u32) -> Self {
+        Self::new_sub_rect(name, source_path, 0, 0, width, height)
+    }
+
+    /// ソース画像の一部分だけを使うスプライト
+    pub fn new_sub_rect(
+        name: String,
+        source_path: PathBuf,
+        source_left: u32,
+        source_top: u32,
+        width: u32,
+        height: u32,
+    ) -> Self {
         Self {
             id: Uuid::new_v4(),
             name,
             source_path,
+            source_left,
+            source_top,
             width,
             height,
             left: 0,
@@ -34,6 +129,9 @@ impl SpriteInfo {
             right_slice: 0,
             top_slice: 0,
             bottom_slice: 0,
+            pivot_x: peridot::Sprite::DEFAULT_PIVOT.0,
+            pivot_y: peridot::Sprite::DEFAULT_PIVOT.1,
+            group: String::new(),
             selected: false,
         }
     }
@@ -49,19 +147,265 @@ impl SpriteInfo {
     pub const fn bottom(&self) -> u32 {
         self.top + self.height
     }
+
+    /// スプライト左上からのピクセル位置を正規化されたピボット位置に変換する
+    ///
+    /// `snap`が有効な場合はピクセルの中心に吸着させる
+    pub fn pivot_from_local_pixels(&self, x: f32, y: f32, snap: bool) -> (f32, f32) {
+        fn normalize(v: f32, extent: u32, snap: bool) -> f32 {
+            if extent == 0 {
+                return 0.5;
+            }
+
+            let v = v.clamp(0.0, extent as f32);
+            let v = if snap {
+                (v.floor() + 0.5).min(extent as f32 - 0.5)
+            } else {
+                v
+            };
+
+            v / extent as f32
+        }
+
+        (
+            normalize(x, self.width, snap),
+            normalize(y, self.height, snap),
+        )
+    }
+}
+
+/// よく使うピボット位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PivotPreset {
+    Center,
+    BottomCenter,
+    TopLeft,
+    TopCenter,
+    TopRight,
+    MiddleLeft,
+    MiddleRight,
+    BottomLeft,
+    BottomRight,
+}
+impl PivotPreset {
+    const ALL: &'static [Self] = &[
+        Self::Center,
+        Self::BottomCenter,
+        Self::TopLeft,
+        Self::TopCenter,
+        Self::TopRight,
+        Self::MiddleLeft,
+        Self::MiddleRight,
+        Self::BottomLeft,
+        Self::BottomRight,
+    ];
+
+    pub const fn value(&self) -> (f32, f32) {
+        match self {
+            Self::Center => (0.5, 0.5),
+            Self::BottomCenter => (0.5, 1.0),
+            Self::TopLeft => (0.0, 0.0),
+            Self::TopCenter => (0.5, 0.0),
+            Self::TopRight => (1.0, 0.0),
+            Self::MiddleLeft => (0.0, 0.5),
+            Self::MiddleRight => (1.0, 0.5),
+            Self::BottomLeft => (0.0, 1.0),
+            Self::BottomRight => (1.0, 1.0),
+        }
+    }
+
+    /// 指定位置に一致するプリセットの次のもの（一致しなければ先頭）
+    pub fn next_of(pivot_x: f32, pivot_y: f32) -> Self {
+        match Self::ALL
+            .iter()
+            .position(|p| p.value() == (pivot_x, pivot_y))
+        {
+            Some(n) => Self::ALL[(n + 1) % Self::ALL.len()],
+            None => Self::ALL[0],
+        }
+    }
+}
+
+/// 自動検出されたスプライト候補（採用されたものだけがスプライトとして追加される）
+#[derive(Debug, Clone)]
+pub struct SpriteCandidate {
+    pub source_path: PathBuf,
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+    pub accepted: bool,
+}
+impl SpriteCandidate {
+    pub const fn right(&self) -> u32 {
+        self.left + self.width
+    }
+
+    pub const fn bottom(&self) -> u32 {
+        self.top + self.height
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationFrameInfo {
+    pub sprite_id: Uuid,
+    pub duration_ms: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationInfo {
+    pub name: String,
+    pub loop_mode: peridot::AnimationLoopMode,
+    pub frames: Vec<AnimationFrameInfo>,
+}
+impl AnimationInfo {
+    pub const DEFAULT_FRAME_DURATION_MS: u32 = 100;
+}
+
+pub struct AnimationPreviewFrame {
+    pub duration_ms: u32,
+    /// 読み込みが終わるまではNone
+    pub image: Option<image::RgbaImage>,
+}
+
+/// アニメーションの再生プレビュー用のデータ
+///
+/// フレーム画像はバックグラウンドで読み込まれるので、`is_ready`になるまでは再生できない
+pub struct AnimationPreview {
+    generation: u64,
+    /// プレビューが終わったらフレームの読み込みを取り消す
+    cancellation_token: BackgroundWorkCancellationToken,
+    pub animation_index: usize,
+    pub loop_mode: peridot::AnimationLoopMode,
+    pub frames: Vec<AnimationPreviewFrame>,
+}
+impl AnimationPreview {
+    pub fn is_ready(&self) -> bool {
+        self.frames.iter().all(|x| x.image.is_some())
+    }
+}
+
+/// プレビュー用に読み込みが必要なフレームの情報
+pub struct AnimationPreviewFrameRequest {
+    pub generation: u64,
+    pub cancellation_token: BackgroundWorkCancellationToken,
+    pub frame_index: usize,
+    pub source_path: PathBuf,
+    pub source_left: u32,
+    pub source_top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// 保存したあとにソース画像の大きさが変わって、記録している大きさと合わなくなったスプライト
+#[derive(Debug, Clone)]
+pub struct SourceSizeMismatch {
+    pub sprite_id: Uuid,
+    pub name: String,
+    pub source_path: PathBuf,
+    pub stored_size: (u32, u32),
+    /// ソースに合わせたときの大きさ（切り出し位置がソースの外にあれば0になる）
+    pub fitted_size: (u32, u32),
+}
+
+/// 大きさを合わせるときにアトラス上で動かさない角
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnchorCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// 名前の中の数字部分を数値として比較する（`walk_2` < `walk_10`）
+fn natural_name_order(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(ca), Some(cb)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+
+        if ca.is_ascii_digit() && cb.is_ascii_digit() {
+            let an = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+            let bn = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+            let (ad, bd) = (
+                a[..an].trim_start_matches('0'),
+                b[..bn].trim_start_matches('0'),
+            );
+            let o = ad.len().cmp(&bd.len()).then_with(|| ad.cmp(bd));
+            if o != Ordering::Equal {
+                return o;
+            }
+
+            (a, b) = (&a[an..], &b[bn..]);
+            continue;
+        }
+
+        if ca != cb {
+            return ca.cmp(&cb);
+        }
+
+        (a, b) = (&a[ca.len_utf8()..], &b[cb.len_utf8()..]);
+    }
 }
 
 pub struct AppState {
     atlas_size: SizePixels,
     atlas_size_view_feedbacks: Vec<Box<dyn FnMut(&SizePixels)>>,
+    alpha_mode: peridot::AlphaMode,
+    alpha_mode_view_feedbacks: Vec<Box<dyn FnMut(peridot::AlphaMode)>>,
+    /// 書き出すときのミップマップのレベル数（自動配置もこれに合わせる）
+    mip_levels: u32,
+    mip_levels_view_feedbacks: Vec<Box<dyn FnMut(u32)>>,
+    /// 書き出すときのブロック圧縮の設定（画面では編集しないが、保存したときに消えないように持っておく）
+    compression: peridot::TextureCompression,
+    compression_quality: peridot::CompressionQuality,
     sprites: Vec<SpriteInfo>,
     sprites_view_feedbacks: Vec<Box<dyn FnMut(&[SpriteInfo])>>,
     visible_menu: bool,
     visible_menu_view_feedbacks: Vec<Box<dyn FnMut(bool, bool)>>,
     current_open_path: Option<PathBuf>,
+    /// 開いているドキュメントに紐づくバックグラウンド作業の取り消し用（別のドキュメントを開いたら取り消す）
+    document_cancellation_token: BackgroundWorkCancellationToken,
+    document_cancellation_token_view_feedbacks:
+        Vec<Box<dyn FnMut(&BackgroundWorkCancellationToken)>>,
     current_open_path_view_feedbacks: Vec<Box<dyn FnMut(&Option<PathBuf>)>>,
+    sprite_candidates: Vec<SpriteCandidate>,
+    sprite_candidates_view_feedbacks: Vec<Box<dyn FnMut(&[SpriteCandidate])>>,
+    /// 候補を検出したソース画像（候補の下に表示する）
+    sprite_candidate_sheet: Option<image::RgbaImage>,
+    sprite_candidate_sheet_view_feedbacks: Vec<Box<dyn FnMut(Option<&image::RgbaImage>)>>,
+    region_detect_params: RegionDetectParams,
+    region_detect_params_view_feedbacks: Vec<Box<dyn FnMut(&RegionDetectParams)>>,
+    grid_slice_params: GridSliceParams,
+    grid_slice_params_view_feedbacks: Vec<Box<dyn FnMut(&GridSliceParams)>>,
+    animations: Vec<AnimationInfo>,
+    animations_view_feedbacks: Vec<Box<dyn FnMut(&[AnimationInfo])>>,
+    animation_preview: Option<AnimationPreview>,
+    animation_preview_generation: u64,
+    animation_preview_view_feedbacks: Vec<Box<dyn FnMut(Option<&AnimationPreview>)>>,
+    sprite_filter_query: String,
+    /// 最後に解釈できたクエリ（入力途中で解釈できないときは直前のものを使い続ける）
+    sprite_filter: Option<SpriteFilter>,
+    sprite_filter_view_feedbacks: Vec<Box<dyn FnMut(&str, Option<&SpriteFilter>)>>,
+    /// 読み込んだときに見つかった、ソースの大きさと合わないスプライト（どうするか決まるまで持っておく）
+    source_size_mismatches: Vec<SourceSizeMismatch>,
+    /// ダイアログの答えが前に読み込んだファイルのものでないか確かめるためのもの（読み込むたびに増やす）
+    source_size_mismatches_generation: u64,
+    source_size_mismatches_view_feedbacks: Vec<Box<dyn FnMut(&[SourceSizeMismatch], u64)>>,
 }
 impl AppState {
+    /// D3D11で扱える最大のテクスチャサイズ
+    pub const MAX_ATLAS_SIZE: u32 = 16384;
+    /// 切り替えで選べる自動検出のアルファのしきい値
+    pub const REGION_DETECT_ALPHA_THRESHOLDS: [u8; 5] = [0, 16, 64, 128, 254];
+    /// 切り替えで選べる自動検出でまとめる距離（ピクセル）
+    pub const REGION_DETECT_MERGE_DISTANCES: [u32; 6] = [0, 1, 2, 4, 8, 16];
+    /// 切り替えで選べるグリッドのセルの大きさ（ピクセル、正方形）
+    pub const GRID_SLICE_CELL_SIZES: [u32; 8] = [8, 16, 24, 32, 48, 64, 96, 128];
+    /// 切り替えで選べるグリッドの外周の余白とセル同士の間隔（ピクセル）
+    pub const GRID_SLICE_GAPS: [u32; 5] = [0, 1, 2, 4, 8];
+
     pub fn new() -> Self {
         Self {
             atlas_size: SizePixels {
@@ -69,12 +413,44 @@ impl AppState {
                 height: 32,
             },
             atlas_size_view_feedbacks: Vec::new(),
+            alpha_mode: peridot::AlphaMode::Straight,
+            alpha_mode_view_feedbacks: Vec::new(),
+            mip_levels: 1,
+            mip_levels_view_feedbacks: Vec::new(),
+            compression: peridot::TextureCompression::None,
+            compression_quality: peridot::CompressionQuality::Fast,
             sprites: Vec::new(),
             sprites_view_feedbacks: Vec::new(),
             visible_menu: false,
             visible_menu_view_feedbacks: Vec::new(),
             current_open_path: None,
+            document_cancellation_token: BackgroundWorkCancellationToken::new(),
+            document_cancellation_token_view_feedbacks: Vec::new(),
             current_open_path_view_feedbacks: Vec::new(),
+            sprite_candidates: Vec::new(),
+            sprite_candidates_view_feedbacks: Vec::new(),
+            sprite_candidate_sheet: None,
+            sprite_candidate_sheet_view_feedbacks: Vec::new(),
+            region_detect_params: RegionDetectParams::default(),
+            region_detect_params_view_feedbacks: Vec::new(),
+            grid_slice_params: GridSliceParams {
+                cell_width: 32,
+                cell_height: 32,
+                margin: 0,
+                spacing: 0,
+            },
+            grid_slice_params_view_feedbacks: Vec::new(),
+            animations: Vec::new(),
+            animations_view_feedbacks: Vec::new(),
+            animation_preview: None,
+            animation_preview_generation: 0,
+            animation_preview_view_feedbacks: Vec::new(),
+            sprite_filter_query: String::new(),
+            sprite_filter: None,
+            sprite_filter_view_feedbacks: Vec::new(),
+            source_size_mismatches: Vec::new(),
+            source_size_mismatches_generation: 0,
+            source_size_mismatches_view_feedbacks: Vec::new(),
         }
     }
 
@@ -102,6 +478,10 @@ impl AppState {
         }
     }
 
+    pub fn sprites(&self) -> &[SpriteInfo] {
+        &self.sprites
+    }
+
     pub fn selected_sprites_with_index(
         &self,
     ) -> impl DoubleEndedIterator<Item = (usize, &SpriteInfo)> {
@@ -136,6 +516,34 @@ impl AppState {
         }
     }
 
+    pub fn set_sprite_pivot(&mut self, index: usize, pivot_x: f32, pivot_y: f32) {
+        let target_sprite = &mut self.sprites[index];
+        target_sprite.pivot_x = pivot_x.clamp(0.0, 1.0);
+        target_sprite.pivot_y = pivot_y.clamp(0.0, 1.0);
+
+        for cb in self.sprites_view_feedbacks.iter_mut() {
+            cb(&self.sprites);
+        }
+    }
+
+    /// 選択中のスプライトのピボットを次のプリセットに切り替える（先頭のスプライトの値を基準にする）
+    pub fn cycle_selected_sprites_pivot_preset(&mut self) {
+        let Some((_, first)) = self.selected_sprites_with_index().next() else {
+            // nothing selected
+            return;
+        };
+        let (pivot_x, pivot_y) = PivotPreset::next_of(first.pivot_x, first.pivot_y).value();
+
+        for x in self.sprites.iter_mut().filter(|x| x.selected) {
+            x.pivot_x = pivot_x;
+            x.pivot_y = pivot_y;
+        }
+
+        for cb in self.sprites_view_feedbacks.iter_mut() {
+            cb(&self.sprites);
+        }
+    }
+
     pub fn select_sprite(&mut self, index: usize) {
         for (n, x) in self.sprites.iter_mut().enumerate() {
             x.selected = n == index;
@@ -146,6 +554,53 @@ impl AppState {
         }
     }
 
+    /// グループ（サブグループを含む）内のスプライトをまとめて選択する
+    ///
+    /// `additive`の場合は既存の選択を残す
+    pub fn select_sprite_group(&mut self, group: &str, additive: bool) {
+        for x in self.sprites.iter_mut() {
+            let in_group = sprite_group::contains(group, &x.group);
+            x.selected = in_group || (additive && x.selected);
+        }
+
+        for cb in self.sprites_view_feedbacks.iter_mut() {
+            cb(&self.sprites);
+        }
+    }
+
+    pub fn sprite_filter_query(&self) -> &str {
+        &self.sprite_filter_query
+    }
+
+    pub fn set_sprite_filter_query(&mut self, query: String) {
+        match SpriteFilter::parse(&query) {
+            Ok(f) => self.sprite_filter = f,
+            Err(e) => {
+                tracing::debug!({ %query, %e }, "sprite filter query is not complete");
+            }
+        }
+        self.sprite_filter_query = query;
+
+        for cb in self.sprite_filter_view_feedbacks.iter_mut() {
+            cb(&self.sprite_filter_query, self.sprite_filter.as_ref());
+        }
+    }
+
+    /// 検索にマッチしたスプライトをすべて選択する（検索していないときは何もしない）
+    pub fn select_sprite_filter_matches(&mut self) {
+        let Some(ref f) = self.sprite_filter else {
+            return;
+        };
+
+        for x in self.sprites.iter_mut() {
+            x.selected = f.matches(&SpriteFilterTarget::from_sprite(x));
+        }
+
+        for cb in self.sprites_view_feedbacks.iter_mut() {
+            cb(&self.sprites);
+        }
+    }
+
     pub fn deselect_sprite(&mut self) {
         for x in self.sprites.iter_mut() {
             x.selected = false;
@@ -156,6 +611,384 @@ impl AppState {
         }
     }
 
+    pub fn sprite_candidates(&self) -> &[SpriteCandidate] {
+        &self.sprite_candidates
+    }
+
+    pub fn set_sprite_candidates(
+        &mut self,
+        candidates: Vec<SpriteCandidate>,
+        sheet: image::RgbaImage,
+    ) {
+        self.sprite_candidates = candidates;
+        self.sprite_candidate_sheet = Some(sheet);
+
+        for cb in self.sprite_candidates_view_feedbacks.iter_mut() {
+            cb(&self.sprite_candidates);
+        }
+        for cb in self.sprite_candidate_sheet_view_feedbacks.iter_mut() {
+            cb(self.sprite_candidate_sheet.as_ref());
+        }
+    }
+
+    fn clear_sprite_candidate_sheet(&mut self) {
+        self.sprite_candidate_sheet = None;
+
+        for cb in self.sprite_candidate_sheet_view_feedbacks.iter_mut() {
+            cb(None);
+        }
+    }
+
+    pub const fn region_detect_params(&self) -> &RegionDetectParams {
+        &self.region_detect_params
+    }
+
+    /// 自動検出のアルファのしきい値を`REGION_DETECT_ALPHA_THRESHOLDS`の順に切り替える
+    pub fn cycle_region_detect_alpha_threshold(&mut self) {
+        let current = self.region_detect_params.alpha_threshold;
+        self.region_detect_params.alpha_threshold = Self::REGION_DETECT_ALPHA_THRESHOLDS
+            .into_iter()
+            .find(|&x| x > current)
+            .unwrap_or(Self::REGION_DETECT_ALPHA_THRESHOLDS[0]);
+
+        for cb in self.region_detect_params_view_feedbacks.iter_mut() {
+            cb(&self.region_detect_params);
+        }
+    }
+
+    /// 自動検出でまとめる距離を`REGION_DETECT_MERGE_DISTANCES`の順に切り替える
+    pub fn cycle_region_detect_merge_distance(&mut self) {
+        let current = self.region_detect_params.merge_distance;
+        self.region_detect_params.merge_distance = Self::REGION_DETECT_MERGE_DISTANCES
+            .into_iter()
+            .find(|&x| x > current)
+            .unwrap_or(Self::REGION_DETECT_MERGE_DISTANCES[0]);
+
+        for cb in self.region_detect_params_view_feedbacks.iter_mut() {
+            cb(&self.region_detect_params);
+        }
+    }
+
+    pub const fn grid_slice_params(&self) -> &GridSliceParams {
+        &self.grid_slice_params
+    }
+
+    /// グリッドのセルの大きさを`GRID_SLICE_CELL_SIZES`の順に切り替える
+    pub fn cycle_grid_slice_cell_size(&mut self) {
+        let size = Self::next_in_cycle(
+            &Self::GRID_SLICE_CELL_SIZES,
+            self.grid_slice_params.cell_width,
+        );
+        self.grid_slice_params.cell_width = size;
+        self.grid_slice_params.cell_height = size;
+
+        for cb in self.grid_slice_params_view_feedbacks.iter_mut() {
+            cb(&self.grid_slice_params);
+        }
+    }
+
+    /// グリッドの外周の余白を`GRID_SLICE_GAPS`の順に切り替える
+    pub fn cycle_grid_slice_margin(&mut self) {
+        self.grid_slice_params.margin =
+            Self::next_in_cycle(&Self::GRID_SLICE_GAPS, self.grid_slice_params.margin);
+
+        for cb in self.grid_slice_params_view_feedbacks.iter_mut() {
+            cb(&self.grid_slice_params);
+        }
+    }
+
+    /// グリッドのセル同士の間隔を`GRID_SLICE_GAPS`の順に切り替える
+    pub fn cycle_grid_slice_spacing(&mut self) {
+        self.grid_slice_params.spacing =
+            Self::next_in_cycle(&Self::GRID_SLICE_GAPS, self.grid_slice_params.spacing);
+
+        for cb in self.grid_slice_params_view_feedbacks.iter_mut() {
+            cb(&self.grid_slice_params);
+        }
+    }
+
+    /// `values`（昇順）のうち`current`より大きい最初のもの（なければ先頭に戻る）
+    fn next_in_cycle(values: &[u32], current: u32) -> u32 {
+        values
+            .iter()
+            .copied()
+            .find(|&x| x > current)
+            .unwrap_or(values[0])
+    }
+
+    pub fn toggle_sprite_candidate(&mut self, index: usize) {
+        let c = &mut self.sprite_candidates[index];
+        c.accepted = !c.accepted;
+
+        for cb in self.sprite_candidates_view_feedbacks.iter_mut() {
+            cb(&self.sprite_candidates);
+        }
+    }
+
+    /// 採用された候補をソース画像上と同じ位置に配置したスプライトとして追加する
+    /// 採用された候補を、置いてあるスプライトと重ならないところへ追加する
+    ///
+    /// 入りきらないときは候補をそのまま残す
+    pub fn commit_sprite_candidates(&mut self) -> Result<(), sprite_packing::PackError> {
+        let accepted = self
+            .sprite_candidates
+            .iter()
+            .filter(|x| x.accepted)
+            .collect::<Vec<_>>();
+        let layout = sprite_packing::place_around(
+            &self
+                .sprites
+                .iter()
+                .map(|x| [x.left, x.top, x.width, x.height])
+                .collect::<Vec<_>>(),
+            &accepted
+                .iter()
+                .map(|x| (x.width, x.height))
+                .collect::<Vec<_>>(),
+            self.atlas_size.width.max(self.atlas_size.height),
+            Self::MAX_ATLAS_SIZE,
+        )?;
+
+        let candidates = core::mem::take(&mut self.sprite_candidates);
+        for cb in self.sprite_candidates_view_feedbacks.iter_mut() {
+            cb(&self.sprite_candidates);
+        }
+        self.clear_sprite_candidate_sheet();
+
+        // 同じシートから取り込み済みなら続きの番号にする
+        let mut next_indices = HashMap::<String, u32>::new();
+        let new_sprites = candidates
+            .into_iter()
+            .filter(|x| x.accepted)
+            .zip(layout.positions)
+            .map(|(x, (left, top))| {
+                let stem = x
+                    .source_path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned();
+                let index = next_indices.entry(stem.clone()).or_insert_with(|| {
+                    grid_slice::next_name_index(&stem, self.sprites.iter().map(|s| s.name.as_str()))
+                });
+                let mut s = SpriteInfo::new_sub_rect(
+                    format!("{stem}_{index}"),
+                    x.source_path,
+                    x.left,
+                    x.top,
+                    x.width,
+                    x.height,
+                );
+                *index += 1;
+                s.left = left;
+                s.top = top;
+
+                s
+            })
+            .collect::<Vec<_>>();
+        self.add_sprites(new_sprites);
+
+        Ok(())
+    }
+
+    pub fn discard_sprite_candidates(&mut self) {
+        self.sprite_candidates.clear();
+
+        for cb in self.sprite_candidates_view_feedbacks.iter_mut() {
+            cb(&self.sprite_candidates);
+        }
+        self.clear_sprite_candidate_sheet();
+    }
+
+    pub fn toggle_sprite_selection(&mut self, index: usize) {
+        self.sprites[index].selected = !self.sprites[index].selected;
+
+        for cb in self.sprites_view_feedbacks.iter_mut() {
+            cb(&self.sprites);
+        }
+    }
+
+    pub fn animations(&self) -> &[AnimationInfo] {
+        &self.animations
+    }
+
+    /// 選択中のスプライトを名前順に並べてアニメーションを作る
+    pub fn add_animation_from_selected_sprites(&mut self) -> Option<usize> {
+        let mut frames = self
+            .sprites
+            .iter()
+            .filter(|x| x.selected)
+            .collect::<Vec<_>>();
+        if frames.is_empty() {
+            return None;
+        }
+        frames.sort_by(|a, b| natural_name_order(&a.name, &b.name));
+
+        // 共通のプレフィックスから連番部分と区切り文字を除いたものを名前にする（walk_0..walk_7 => walk）
+        let mut prefix = frames[0].name.as_str();
+        for x in frames.iter().skip(1) {
+            let common = prefix
+                .char_indices()
+                .zip(x.name.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(prefix.len().min(x.name.len()), |((n, _), _)| n);
+            prefix = &prefix[..common];
+        }
+        let base_name =
+            prefix.trim_end_matches(|c: char| !c.is_alphanumeric() || c.is_ascii_digit());
+        let base_name = if base_name.is_empty() {
+            "animation"
+        } else {
+            base_name
+        };
+        let mut name = String::from(base_name);
+        let mut suffix = 2;
+        while self.animations.iter().any(|x| x.name == name) {
+            name = format!("{base_name}_{suffix}");
+            suffix += 1;
+        }
+
+        let animation = AnimationInfo {
+            name,
+            loop_mode: peridot::AnimationLoopMode::Loop,
+            frames: frames
+                .into_iter()
+                .map(|x| AnimationFrameInfo {
+                    sprite_id: x.id,
+                    duration_ms: AnimationInfo::DEFAULT_FRAME_DURATION_MS,
+                })
+                .collect(),
+        };
+        self.animations.push(animation);
+
+        for cb in self.animations_view_feedbacks.iter_mut() {
+            cb(&self.animations);
+        }
+
+        Some(self.animations.len() - 1)
+    }
+
+    pub fn remove_animation(&mut self, index: usize) {
+        self.animations.remove(index);
+        if self.animation_preview.is_some() {
+            // インデックスがずれるので止める
+            self.end_animation_preview();
+        }
+
+        for cb in self.animations_view_feedbacks.iter_mut() {
+            cb(&self.animations);
+        }
+    }
+
+    pub fn cycle_animation_loop_mode(&mut self, index: usize) {
+        let a = &mut self.animations[index];
+        a.loop_mode = match a.loop_mode {
+            peridot::AnimationLoopMode::Once => peridot::AnimationLoopMode::Loop,
+            peridot::AnimationLoopMode::Loop => peridot::AnimationLoopMode::PingPong,
+            peridot::AnimationLoopMode::PingPong => peridot::AnimationLoopMode::Once,
+        };
+
+        for cb in self.animations_view_feedbacks.iter_mut() {
+            cb(&self.animations);
+        }
+
+        if let Some(ref mut p) = self.animation_preview {
+            if p.animation_index == index {
+                p.loop_mode = self.animations[index].loop_mode;
+                for cb in self.animation_preview_view_feedbacks.iter_mut() {
+                    cb(Some(p));
+                }
+            }
+        }
+    }
+
+    pub fn animation_preview(&self) -> Option<&AnimationPreview> {
+        self.animation_preview.as_ref()
+    }
+
+    /// プレビューを開始して、読み込みが必要なフレームの一覧を返す
+    ///
+    /// 読み込んだ画像は`set_animation_preview_frame`で渡す
+    pub fn begin_animation_preview(&mut self, index: usize) -> Vec<AnimationPreviewFrameRequest> {
+        self.animation_preview_generation += 1;
+        let generation = self.animation_preview_generation;
+        if let Some(p) = self.animation_preview.take() {
+            p.cancellation_token.cancel();
+        }
+        let cancellation_token = BackgroundWorkCancellationToken::new();
+
+        let a = &self.animations[index];
+        let mut frames = Vec::with_capacity(a.frames.len());
+        let mut requests = Vec::with_capacity(a.frames.len());
+        for f in a.frames.iter() {
+            let Some(s) = self.sprites.iter().find(|x| x.id == f.sprite_id) else {
+                tracing::warn!({ sprite_id = ?f.sprite_id }, "animation frame refers missing sprite");
+                continue;
+            };
+
+            requests.push(AnimationPreviewFrameRequest {
+                generation,
+                cancellation_token: cancellation_token.clone(),
+                frame_index: frames.len(),
+                source_path: s.source_path.clone(),
+                source_left: s.source_left,
+                source_top: s.source_top,
+                width: s.width,
+                height: s.height,
+            });
+            frames.push(AnimationPreviewFrame {
+                duration_ms: f.duration_ms,
+                image: None,
+            });
+        }
+
+        let p = self.animation_preview.insert(AnimationPreview {
+            generation,
+            cancellation_token,
+            animation_index: index,
+            loop_mode: a.loop_mode,
+            frames,
+        });
+        for cb in self.animation_preview_view_feedbacks.iter_mut() {
+            cb(Some(p));
+        }
+
+        requests
+    }
+
+    pub fn set_animation_preview_frame(
+        &mut self,
+        generation: u64,
+        frame_index: usize,
+        image: image::RgbaImage,
+    ) {
+        let Some(ref mut p) = self.animation_preview else {
+            return;
+        };
+        if p.generation != generation {
+            // 古いプレビューの読み込み結果
+            return;
+        }
+
+        p.frames[frame_index].image = Some(image);
+        // Note: 途中経過は通知しない（全部そろってから再生を始める）
+        if p.is_ready() {
+            for cb in self.animation_preview_view_feedbacks.iter_mut() {
+                cb(Some(p));
+            }
+        }
+    }
+
+    pub fn end_animation_preview(&mut self) {
+        if let Some(p) = self.animation_preview.take() {
+            p.cancellation_token.cancel();
+        }
+
+        for cb in self.animation_preview_view_feedbacks.iter_mut() {
+            cb(None);
+        }
+    }
+
     pub fn toggle_menu(&mut self) {
         self.visible_menu = !self.visible_menu;
 
@@ -164,6 +997,83 @@ impl AppState {
         }
     }
 
+    pub const fn alpha_mode(&self) -> peridot::AlphaMode {
+        self.alpha_mode
+    }
+
+    pub fn toggle_alpha_mode(&mut self) {
+        self.alpha_mode = match self.alpha_mode {
+            peridot::AlphaMode::Straight => peridot::AlphaMode::Premultiplied,
+            peridot::AlphaMode::Premultiplied => peridot::AlphaMode::Straight,
+        };
+
+        for cb in self.alpha_mode_view_feedbacks.iter_mut() {
+            cb(self.alpha_mode);
+        }
+        // Note: アトラスに入れる画素が変わるので、スプライトを表示し直してもらう
+        for cb in self.sprites_view_feedbacks.iter_mut() {
+            cb(&self.sprites);
+        }
+    }
+
+    pub const fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    /// ミップマップのレベル数を1から`peridot::MAX_MIP_LEVELS`まで順に切り替える
+    pub fn cycle_mip_levels(&mut self) {
+        self.mip_levels = self.mip_levels % peridot::MAX_MIP_LEVELS + 1;
+
+        for cb in self.mip_levels_view_feedbacks.iter_mut() {
+            cb(self.mip_levels);
+        }
+    }
+
+    /// 自動配置に必要なものを取り出す（詰めるのは時間がかかるのでバックグラウンドで行う）
+    pub fn auto_pack_request(&self) -> AutoPackRequest {
+        AutoPackRequest {
+            sprite_ids: self.sprites.iter().map(|x| x.id).collect(),
+            sizes: self.sprites.iter().map(|x| (x.width, x.height)).collect(),
+            mip_levels: self.mip_levels,
+            block_size: self.compression.block_size(),
+            max_size: Self::MAX_ATLAS_SIZE,
+        }
+    }
+
+    /// 詰め直した結果を反映する（詰めている間にスプライトや設定が変わっていたら何もせずfalse）
+    pub fn apply_auto_pack(
+        &mut self,
+        request: &AutoPackRequest,
+        layout: sprite_packing::PackedLayout,
+    ) -> bool {
+        if *request != self.auto_pack_request() {
+            return false;
+        }
+
+        for (x, (left, top)) in self.sprites.iter_mut().zip(layout.positions) {
+            x.left = left;
+            x.top = top;
+        }
+        // Note: 詰め直したら小さくなることもあるので、広げるだけでなくそのまま設定する
+        self.atlas_size = SizePixels {
+            width: layout.size,
+            height: layout.size,
+        };
+
+        for cb in self.atlas_size_view_feedbacks.iter_mut() {
+            cb(&self.atlas_size);
+        }
+        for cb in self.sprites_view_feedbacks.iter_mut() {
+            cb(&self.sprites);
+        }
+
+        true
+    }
+
+    pub const fn document_cancellation_token(&self) -> &BackgroundWorkCancellationToken {
+        &self.document_cancellation_token
+    }
+
     pub const fn is_visible_menu(&self) -> bool {
         self.visible_menu
     }
@@ -172,12 +1082,18 @@ impl AppState {
         let mut asset = peridot::SpriteAtlasAsset {
             width: self.atlas_size.width,
             height: self.atlas_size.height,
+            alpha_mode: self.alpha_mode,
+            mip_levels: self.mip_levels,
+            compression: self.compression,
+            compression_quality: self.compression_quality,
             sprites: self
                 .sprites
                 .iter()
                 .map(|x| peridot::Sprite {
                     id: x.id.clone(),
                     source_path: x.source_path.clone(),
+                    source_left: x.source_left,
+                    source_top: x.source_top,
                     name: x.name.clone(),
                     width: x.width,
                     height: x.height,
@@ -187,10 +1103,30 @@ impl AppState {
                     border_top: x.top_slice,
                     border_right: x.right_slice,
                     border_bottom: x.bottom_slice,
+                    pivot_x: x.pivot_x,
+                    pivot_y: x.pivot_y,
+                    group: x.group.clone(),
+                })
+                .collect(),
+            animations: self
+                .animations
+                .iter()
+                .map(|x| peridot::Animation {
+                    name: x.name.clone(),
+                    loop_mode: x.loop_mode,
+                    frames: x
+                        .frames
+                        .iter()
+                        .map(|f| peridot::AnimationFrame {
+                            sprite_id: f.sprite_id,
+                            duration_ms: f.duration_ms,
+                        })
+                        .collect(),
                 })
                 .collect(),
         };
         asset.sprites.sort_by(|a, b| a.id.cmp(&b.id));
+        asset.animations.sort_by(|a, b| a.name.cmp(&b.name));
 
         asset.write(
             &mut std::fs::File::options()
@@ -201,13 +1137,21 @@ impl AppState {
         )
     }
 
+    /// 読み込んだあとにソース画像の大きさを確かめるための入力を返す（結果は`apply_source_size_check`で反映する）
     pub fn load(
         &mut self,
         path: impl AsRef<Path>,
-    ) -> Result<(), peridot::SpriteAtlasAssetReadError> {
+    ) -> Result<SourceSizeCheckRequest, peridot::SpriteAtlasAssetReadError> {
         let asset = peridot::SpriteAtlasAsset::read(&mut std::io::BufReader::new(
             std::fs::File::open(&path)?,
         ))?;
+        // Note: 前のファイルの結果は残さない。確かめ終わるまでは記録している大きさのまま扱う
+        self.source_size_mismatches.clear();
+        self.source_size_mismatches_generation += 1;
+        let source_size_check = SourceSizeCheckRequest {
+            generation: self.source_size_mismatches_generation,
+            sprites: asset.sprites.clone(),
+        };
 
         self.sprites.clear();
         self.sprites
@@ -215,6 +1159,8 @@ impl AppState {
                 id: x.id,
                 name: x.name,
                 source_path: x.source_path,
+                source_left: x.source_left,
+                source_top: x.source_top,
                 width: x.width,
                 height: x.height,
                 left: x.left,
@@ -223,25 +1169,234 @@ impl AppState {
                 right_slice: x.border_right,
                 top_slice: x.border_top,
                 bottom_slice: x.border_bottom,
+                pivot_x: x.pivot_x,
+                pivot_y: x.pivot_y,
+                group: x.group,
                 selected: false,
             }));
+        self.animations.clear();
+        self.animations
+            .extend(asset.animations.into_iter().map(|x| {
+                AnimationInfo {
+                    name: x.name,
+                    loop_mode: x.loop_mode,
+                    frames: x
+                        .frames
+                        .into_iter()
+                        .map(|f| AnimationFrameInfo {
+                            sprite_id: f.sprite_id,
+                            duration_ms: f.duration_ms,
+                        })
+                        .collect(),
+                }
+            }));
         self.atlas_size.width = asset.width;
         self.atlas_size.height = asset.height;
+        self.alpha_mode = asset.alpha_mode;
+        self.mip_levels = asset.mip_levels;
+        self.compression = asset.compression;
+        self.compression_quality = asset.compression_quality;
         self.current_open_path = Some(path.as_ref().into());
+        core::mem::take(&mut self.document_cancellation_token).cancel();
+        self.end_animation_preview();
+
+        // Note: 以降のフィードバックで始まる読み込みが新しいトークンを使うように先に知らせる
+        for cb in self.document_cancellation_token_view_feedbacks.iter_mut() {
+            cb(&self.document_cancellation_token);
+        }
 
         for cb in self.atlas_size_view_feedbacks.iter_mut() {
             cb(&self.atlas_size);
         }
 
+        for cb in self.alpha_mode_view_feedbacks.iter_mut() {
+            cb(self.alpha_mode);
+        }
+
+        for cb in self.mip_levels_view_feedbacks.iter_mut() {
+            cb(self.mip_levels);
+        }
+
         for cb in self.sprites_view_feedbacks.iter_mut() {
             cb(&self.sprites);
         }
 
+        for cb in self.animations_view_feedbacks.iter_mut() {
+            cb(&self.animations);
+        }
+
         for cb in self.current_open_path_view_feedbacks.iter_mut() {
             cb(&self.current_open_path);
         }
 
-        Ok(())
+        for cb in self.source_size_mismatches_view_feedbacks.iter_mut() {
+            cb(
+                &self.source_size_mismatches,
+                self.source_size_mismatches_generation,
+            );
+        }
+
+        Ok(source_size_check)
+    }
+
+    /// ソース画像の大きさを確かめた結果を反映する（確かめている間に別のファイルを開いていたら何もしない）
+    pub fn apply_source_size_check(
+        &mut self,
+        generation: u64,
+        mismatches: Vec<SourceSizeMismatch>,
+    ) {
+        if generation != self.source_size_mismatches_generation {
+            tracing::info!("discarding stale source size check");
+            return;
+        }
+
+        self.source_size_mismatches = mismatches;
+
+        for cb in self.source_size_mismatches_view_feedbacks.iter_mut() {
+            cb(
+                &self.source_size_mismatches,
+                self.source_size_mismatches_generation,
+            );
+        }
+    }
+
+    /// ソースの大きさと合わないスプライトの大きさを合わせる
+    ///
+    /// `anchor`の角をアトラス上で動かさないようにして、`clamp_slices`なら9スライスの幅も収まるように詰める。
+    /// 合わせたことで新しく重なるようになったスプライトの名前の組を返す。
+    /// `generation`がフィードバックで渡したものと違えば（聞いている間に別のファイルを開いた）何もしない
+    pub fn reconcile_source_sizes(
+        &mut self,
+        generation: u64,
+        anchor: AnchorCorner,
+        clamp_slices: bool,
+    ) -> Vec<(String, String)> {
+        if generation != self.source_size_mismatches_generation {
+            tracing::info!("discarding stale source size reconciliation");
+            return Vec::new();
+        }
+
+        let mismatches = core::mem::take(&mut self.source_size_mismatches);
+
+        let mut changed = Vec::new();
+        let mut previous_rects = Vec::new();
+        for m in mismatches.iter() {
+            let (width, height) = m.fitted_size;
+            if width == 0 || height == 0 {
+                // Note: 切り出し位置がソースの外に出てしまったものは合わせようがないのでそのままにしておく（プレビューには読み込まれない）
+                tracing::warn!({ name = m.name, path = ?m.source_path }, "sprite region is outside of the source");
+                continue;
+            }
+            let Some(n) = self.sprites.iter().position(|x| x.id == m.sprite_id) else {
+                continue;
+            };
+            let s = &mut self.sprites[n];
+            if (s.width, s.height) != m.stored_size {
+                // 読み込んだあとに変わっている
+                continue;
+            }
+
+            previous_rects.push(Self::sprite_rect(s));
+            if matches!(anchor, AnchorCorner::TopRight | AnchorCorner::BottomRight) {
+                s.left = s.right().saturating_sub(width);
+            }
+            if matches!(anchor, AnchorCorner::BottomLeft | AnchorCorner::BottomRight) {
+                s.top = s.bottom().saturating_sub(height);
+            }
+            s.width = width;
+            s.height = height;
+            if clamp_slices {
+                s.left_slice = s.left_slice.min(width);
+                s.right_slice = s.right_slice.min(width - s.left_slice);
+                s.top_slice = s.top_slice.min(height);
+                s.bottom_slice = s.bottom_slice.min(height - s.top_slice);
+            }
+            changed.push(n);
+        }
+
+        // 合わせる前から重なっていたものは報告しない
+        // Note: 相手も大きさを合わせたものなら、合わせる前どうし・合わせたあとどうしで比べる
+        let previous_rect_of = |m: usize| {
+            changed.iter().position(|&c| c == m).map_or_else(
+                || Self::sprite_rect(&self.sprites[m]),
+                |k| previous_rects[k],
+            )
+        };
+        let mut overlaps = Vec::new();
+        for (&n, &previous_rect) in changed.iter().zip(previous_rects.iter()) {
+            let rect = Self::sprite_rect(&self.sprites[n]);
+            for (m, other) in self.sprites.iter().enumerate() {
+                if m == n || (changed.contains(&m) && m < n) {
+                    // 自分自身と、大きさを合わせたもの同士で既に見た組
+                    continue;
+                }
+
+                if Self::rect_intersects(rect, Self::sprite_rect(other))
+                    && !Self::rect_intersects(previous_rect, previous_rect_of(m))
+                {
+                    overlaps.push((self.sprites[n].name.clone(), other.name.clone()));
+                }
+            }
+        }
+
+        if !changed.is_empty() {
+            let mut max_required_size = self.atlas_size;
+            for &n in changed.iter() {
+                // Power of Twoに丸める（そうするとUV計算が正確になるため）
+                max_required_size.width = max_required_size
+                    .width
+                    .max(self.sprites[n].right())
+                    .next_power_of_two();
+                max_required_size.height = max_required_size
+                    .height
+                    .max(self.sprites[n].bottom())
+                    .next_power_of_two();
+            }
+            if max_required_size != self.atlas_size {
+                self.atlas_size = max_required_size;
+                for cb in self.atlas_size_view_feedbacks.iter_mut() {
+                    cb(&self.atlas_size);
+                }
+            }
+
+            for cb in self.sprites_view_feedbacks.iter_mut() {
+                cb(&self.sprites);
+            }
+        }
+
+        for cb in self.source_size_mismatches_view_feedbacks.iter_mut() {
+            cb(
+                &self.source_size_mismatches,
+                self.source_size_mismatches_generation,
+            );
+        }
+
+        overlaps
+    }
+
+    /// ソースの大きさと合わないスプライトを記録している大きさのままにする（`generation`は`reconcile_source_sizes`と同じ）
+    pub fn dismiss_source_size_mismatches(&mut self, generation: u64) {
+        if generation != self.source_size_mismatches_generation {
+            tracing::info!("discarding stale source size reconciliation");
+            return;
+        }
+
+        self.source_size_mismatches.clear();
+
+        for cb in self.source_size_mismatches_view_feedbacks.iter_mut() {
+            cb(
+                &self.source_size_mismatches,
+                self.source_size_mismatches_generation,
+            );
+        }
+    }
+
+    const fn sprite_rect(s: &SpriteInfo) -> [u32; 4] {
+        [s.left, s.top, s.right(), s.bottom()]
+    }
+
+    const fn rect_intersects(a: [u32; 4], b: [u32; 4]) -> bool {
+        a[0] < b[2] && b[0] < a[2] && a[1] < b[3] && b[1] < a[3]
     }
 
     // TODO: unregister
@@ -256,6 +1411,21 @@ impl AppState {
         self.atlas_size_view_feedbacks.push(Box::new(fb));
     }
 
+    // TODO: unregister
+    pub fn register_alpha_mode_view_feedback(
+        &mut self,
+        mut fb: impl FnMut(peridot::AlphaMode) + 'static,
+    ) {
+        fb(self.alpha_mode);
+        self.alpha_mode_view_feedbacks.push(Box::new(fb));
+    }
+
+    // TODO: unregister
+    pub fn register_mip_levels_view_feedback(&mut self, mut fb: impl FnMut(u32) + 'static) {
+        fb(self.mip_levels);
+        self.mip_levels_view_feedbacks.push(Box::new(fb));
+    }
+
     // TODO: unregister
     pub fn register_visible_menu_view_feedback(
         &mut self,
@@ -265,6 +1435,16 @@ impl AppState {
         self.visible_menu_view_feedbacks.push(Box::new(fb));
     }
 
+    // TODO: unregister
+    pub fn register_document_cancellation_token_view_feedback(
+        &mut self,
+        mut fb: impl FnMut(&BackgroundWorkCancellationToken) + 'static,
+    ) {
+        fb(&self.document_cancellation_token);
+        self.document_cancellation_token_view_feedbacks
+            .push(Box::new(fb));
+    }
+
     // TODO: unregister
     pub fn register_current_open_path_view_feedback(
         &mut self,
@@ -273,4 +1453,81 @@ impl AppState {
         fb(&self.current_open_path);
         self.current_open_path_view_feedbacks.push(Box::new(fb));
     }
+
+    // TODO: unregister
+    pub fn register_sprite_candidates_view_feedback(
+        &mut self,
+        mut fb: impl FnMut(&[SpriteCandidate]) + 'static,
+    ) {
+        fb(&self.sprite_candidates);
+        self.sprite_candidates_view_feedbacks.push(Box::new(fb));
+    }
+
+    // TODO: unregister
+    pub fn register_sprite_candidate_sheet_view_feedback(
+        &mut self,
+        mut fb: impl FnMut(Option<&image::RgbaImage>) + 'static,
+    ) {
+        fb(self.sprite_candidate_sheet.as_ref());
+        self.sprite_candidate_sheet_view_feedbacks
+            .push(Box::new(fb));
+    }
+
+    // TODO: unregister
+    pub fn register_grid_slice_params_view_feedback(
+        &mut self,
+        mut fb: impl FnMut(&GridSliceParams) + 'static,
+    ) {
+        fb(&self.grid_slice_params);
+        self.grid_slice_params_view_feedbacks.push(Box::new(fb));
+    }
+
+    // TODO: unregister
+    pub fn register_region_detect_params_view_feedback(
+        &mut self,
+        mut fb: impl FnMut(&RegionDetectParams) + 'static,
+    ) {
+        fb(&self.region_detect_params);
+        self.region_detect_params_view_feedbacks.push(Box::new(fb));
+    }
+
+    // TODO: unregister
+    pub fn register_animations_view_feedback(
+        &mut self,
+        mut fb: impl FnMut(&[AnimationInfo]) + 'static,
+    ) {
+        fb(&self.animations);
+        self.animations_view_feedbacks.push(Box::new(fb));
+    }
+
+    // TODO: unregister
+    pub fn register_animation_preview_view_feedback(
+        &mut self,
+        mut fb: impl FnMut(Option<&AnimationPreview>) + 'static,
+    ) {
+        fb(self.animation_preview.as_ref());
+        self.animation_preview_view_feedbacks.push(Box::new(fb));
+    }
+
+    // TODO: unregister
+    pub fn register_sprite_filter_view_feedback(
+        &mut self,
+        mut fb: impl FnMut(&str, Option<&SpriteFilter>) + 'static,
+    ) {
+        fb(&self.sprite_filter_query, self.sprite_filter.as_ref());
+        self.sprite_filter_view_feedbacks.push(Box::new(fb));
+    }
+
+    // TODO: unregister
+    pub fn register_source_size_mismatches_view_feedback(
+        &mut self,
+        mut fb: impl FnMut(&[SourceSizeMismatch], u64) + 'static,
+    ) {
+        fb(
+            &self.source_size_mismatches,
+            self.source_size_mismatches_generation,
+        );
+        self.source_size_mismatches_view_feedbacks
+            .push(Box::new(fb));
+    }
 }
@@ -0,0 +1,647 @@
+//! 2つの.psaの意味的な差分（スプライトはUUID、アニメーションは名前で対応付ける）
+
+use std::{collections::BTreeMap, io::Write, path::PathBuf};
+
+use uuid::Uuid;
+
+use crate::{
+    json_writer::{self, Separator},
+    peridot::{Animation, AnimationFrame, AnimationLoopMode, Sprite, SpriteAtlasAsset},
+};
+
+/// アトラス全体の設定の変更
+#[derive(Debug, Clone, PartialEq)]
+pub enum AtlasChange {
+    Resized {
+        from: (u32, u32),
+        to: (u32, u32),
+    },
+    /// 書き出しの設定（alpha_modeなど）の変更
+    Setting {
+        name: &'static str,
+        from: String,
+        to: String,
+    },
+}
+
+/// スプライトが切り出されるソース画像上の位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceRef {
+    pub path: PathBuf,
+    pub left: u32,
+    pub top: u32,
+}
+
+/// 両方にあるスプライトの項目ごとの変更
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpriteFieldChange {
+    Renamed {
+        from: String,
+        to: String,
+    },
+    Regrouped {
+        from: String,
+        to: String,
+    },
+    Moved {
+        from: (u32, u32),
+        to: (u32, u32),
+    },
+    Resized {
+        from: (u32, u32),
+        to: (u32, u32),
+    },
+    /// 9-sliceの境界（left, top, right, bottom）の変更
+    Resliced {
+        from: [u32; 4],
+        to: [u32; 4],
+    },
+    PivotChanged {
+        from: (f32, f32),
+        to: (f32, f32),
+    },
+    SourceChanged {
+        from: SourceRef,
+        to: SourceRef,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpriteChange {
+    Added {
+        id: Uuid,
+        name: String,
+    },
+    Removed {
+        id: Uuid,
+        name: String,
+    },
+    /// `name`は新しいほうの名前
+    Modified {
+        id: Uuid,
+        name: String,
+        changes: Vec<SpriteFieldChange>,
+    },
+}
+
+/// 両方にあるアニメーションの項目ごとの変更
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnimationFieldChange {
+    LoopModeChanged {
+        from: AnimationLoopMode,
+        to: AnimationLoopMode,
+    },
+    /// フレームの並び（スプライトと表示時間）の変更
+    FramesChanged {
+        from: Vec<AnimationFrame>,
+        to: Vec<AnimationFrame>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnimationChange {
+    Added {
+        name: String,
+    },
+    Removed {
+        name: String,
+    },
+    Modified {
+        name: String,
+        changes: Vec<AnimationFieldChange>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AssetDiff {
+    pub atlas: Vec<AtlasChange>,
+    /// IDの順
+    pub sprites: Vec<SpriteChange>,
+    /// 名前の順
+    pub animations: Vec<AnimationChange>,
+}
+
+pub fn diff(old: &SpriteAtlasAsset, new: &SpriteAtlasAsset) -> AssetDiff {
+    let mut atlas = Vec::new();
+    if (old.width, old.height) != (new.width, new.height) {
+        atlas.push(AtlasChange::Resized {
+            from: (old.width, old.height),
+            to: (new.width, new.height),
+        });
+    }
+    for (name, from, to) in [
+        (
+            "alpha_mode",
+            old.alpha_mode.as_str().to_owned(),
+            new.alpha_mode.as_str().to_owned(),
+        ),
+        (
+            "mip_levels",
+            old.mip_levels.to_string(),
+            new.mip_levels.to_string(),
+        ),
+        (
+            "compression",
+            old.compression.as_str().to_owned(),
+            new.compression.as_str().to_owned(),
+        ),
+        (
+            "compression_quality",
+            old.compression_quality.as_str().to_owned(),
+            new.compression_quality.as_str().to_owned(),
+        ),
+    ] {
+        if from != to {
+            atlas.push(AtlasChange::Setting { name, from, to });
+        }
+    }
+
+    // Note: ファイル上はIDでソートされているはずだが、手で編集されたものでも動くようにマップにする
+    let mut pairs = BTreeMap::<Uuid, (Option<&Sprite>, Option<&Sprite>)>::new();
+    for s in old.sprites.iter() {
+        pairs.entry(s.id).or_default().0 = Some(s);
+    }
+    for s in new.sprites.iter() {
+        pairs.entry(s.id).or_default().1 = Some(s);
+    }
+
+    let sprites = pairs
+        .into_iter()
+        .filter_map(|(id, p)| match p {
+            (None, Some(n)) => Some(SpriteChange::Added {
+                id,
+                name: n.name.clone(),
+            }),
+            (Some(o), None) => Some(SpriteChange::Removed {
+                id,
+                name: o.name.clone(),
+            }),
+            (Some(o), Some(n)) => {
+                let changes = diff_sprite(o, n);
+
+                (!changes.is_empty()).then(|| SpriteChange::Modified {
+                    id,
+                    name: n.name.clone(),
+                    changes,
+                })
+            }
+            (None, None) => None,
+        })
+        .collect();
+
+    // Note: アニメーションにはIDがないので、名前を変えたものは削除と追加になる
+    let mut animation_pairs = BTreeMap::<&str, (Option<&Animation>, Option<&Animation>)>::new();
+    for a in old.animations.iter() {
+        animation_pairs.entry(&a.name).or_default().0 = Some(a);
+    }
+    for a in new.animations.iter() {
+        animation_pairs.entry(&a.name).or_default().1 = Some(a);
+    }
+
+    let animations = animation_pairs
+        .into_iter()
+        .filter_map(|(name, p)| match p {
+            (None, Some(_)) => Some(AnimationChange::Added { name: name.into() }),
+            (Some(_), None) => Some(AnimationChange::Removed { name: name.into() }),
+            (Some(o), Some(n)) => {
+                let changes = diff_animation(o, n);
+
+                (!changes.is_empty()).then(|| AnimationChange::Modified {
+                    name: name.into(),
+                    changes,
+                })
+            }
+            (None, None) => None,
+        })
+        .collect();
+
+    AssetDiff {
+        atlas,
+        sprites,
+        animations,
+    }
+}
+
+fn diff_animation(old: &Animation, new: &Animation) -> Vec<AnimationFieldChange> {
+    let mut changes = Vec::new();
+    if old.loop_mode != new.loop_mode {
+        changes.push(AnimationFieldChange::LoopModeChanged {
+            from: old.loop_mode,
+            to: new.loop_mode,
+        });
+    }
+    if old.frames != new.frames {
+        changes.push(AnimationFieldChange::FramesChanged {
+            from: old.frames.clone(),
+            to: new.frames.clone(),
+        });
+    }
+
+    changes
+}
+
+fn diff_sprite(old: &Sprite, new: &Sprite) -> Vec<SpriteFieldChange> {
+    let mut changes = Vec::new();
+    if old.name != new.name {
+        changes.push(SpriteFieldChange::Renamed {
+            from: old.name.clone(),
+            to: new.name.clone(),
+        });
+    }
+    if old.group != new.group {
+        changes.push(SpriteFieldChange::Regrouped {
+            from: old.group.clone(),
+            to: new.group.clone(),
+        });
+    }
+    if (old.left, old.top) != (new.left, new.top) {
+        changes.push(SpriteFieldChange::Moved {
+            from: (old.left, old.top),
+            to: (new.left, new.top),
+        });
+    }
+    if (old.width, old.height) != (new.width, new.height) {
+        changes.push(SpriteFieldChange::Resized {
+            from: (old.width, old.height),
+            to: (new.width, new.height),
+        });
+    }
+    let borders = |s: &Sprite| [s.border_left, s.border_top, s.border_right, s.border_bottom];
+    if borders(old) != borders(new) {
+        changes.push(SpriteFieldChange::Resliced {
+            from: borders(old),
+            to: borders(new),
+        });
+    }
+    if (old.pivot_x, old.pivot_y) != (new.pivot_x, new.pivot_y) {
+        changes.push(SpriteFieldChange::PivotChanged {
+            from: (old.pivot_x, old.pivot_y),
+            to: (new.pivot_x, new.pivot_y),
+        });
+    }
+    let source = |s: &Sprite| SourceRef {
+        path: s.source_path.clone(),
+        left: s.source_left,
+        top: s.source_top,
+    };
+    if source(old) != source(new) {
+        changes.push(SpriteFieldChange::SourceChanged {
+            from: source(old),
+            to: source(new),
+        });
+    }
+
+    changes
+}
+
+impl AssetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.atlas.is_empty() && self.sprites.is_empty() && self.animations.is_empty()
+    }
+
+    /// 人が読むためのテキスト（追加は`+`、削除は`-`、変更は`~`から始まる行）
+    pub fn write_text(&self, sink: &mut (impl Write + ?Sized)) -> std::io::Result<()> {
+        if self.is_empty() {
+            return writeln!(sink, "no differences");
+        }
+
+        for c in self.atlas.iter() {
+            match c {
+                AtlasChange::Resized { from, to } => writeln!(
+                    sink,
+                    "atlas: resized {}x{} -> {}x{}",
+                    from.0, from.1, to.0, to.1
+                )?,
+                AtlasChange::Setting { name, from, to } => {
+                    writeln!(sink, "atlas: {name} {from} -> {to}")?
+                }
+            }
+        }
+
+        for c in self.sprites.iter() {
+            match c {
+                SpriteChange::Added { id, name } => writeln!(sink, "+ {name} ({id})")?,
+                SpriteChange::Removed { id, name } => writeln!(sink, "- {name} ({id})")?,
+                SpriteChange::Modified { id, name, changes } => {
+                    writeln!(sink, "~ {name} ({id})")?;
+                    for c in changes {
+                        write!(sink, "    ")?;
+                        match c {
+                            SpriteFieldChange::Renamed { from, to } => {
+                                writeln!(sink, "renamed: {from:?} -> {to:?}")?
+                            }
+                            SpriteFieldChange::Regrouped { from, to } => {
+                                writeln!(sink, "regrouped: {from:?} -> {to:?}")?
+                            }
+                            SpriteFieldChange::Moved { from, to } => writeln!(
+                                sink,
+                                "moved: ({}, {}) -> ({}, {})",
+                                from.0, from.1, to.0, to.1
+                            )?,
+                            SpriteFieldChange::Resized { from, to } => writeln!(
+                                sink,
+                                "resized: {}x{} -> {}x{}",
+                                from.0, from.1, to.0, to.1
+                            )?,
+                            SpriteFieldChange::Resliced { from, to } => writeln!(
+                                sink,
+                                "resliced: left={} top={} right={} bottom={} -> left={} top={} right={} bottom={}",
+                                from[0], from[1], from[2], from[3], to[0], to[1], to[2], to[3]
+                            )?,
+                            SpriteFieldChange::PivotChanged { from, to } => writeln!(
+                                sink,
+                                "pivot: ({}, {}) -> ({}, {})",
+                                from.0, from.1, to.0, to.1
+                            )?,
+                            SpriteFieldChange::SourceChanged { from, to } => writeln!(
+                                sink,
+                                "source: {} @ ({}, {}) -> {} @ ({}, {})",
+                                from.path.display(),
+                                from.left,
+                                from.top,
+                                to.path.display(),
+                                to.left,
+                                to.top
+                            )?,
+                        }
+                    }
+                }
+            }
+        }
+
+        for c in self.animations.iter() {
+            match c {
+                AnimationChange::Added { name } => writeln!(sink, "+ animation {name:?}")?,
+                AnimationChange::Removed { name } => writeln!(sink, "- animation {name:?}")?,
+                AnimationChange::Modified { name, changes } => {
+                    writeln!(sink, "~ animation {name:?}")?;
+                    for c in changes {
+                        write!(sink, "    ")?;
+                        match c {
+                            AnimationFieldChange::LoopModeChanged { from, to } => {
+                                writeln!(sink, "loop: {} -> {}", from.as_str(), to.as_str())?
+                            }
+                            AnimationFieldChange::FramesChanged { from, to } => {
+                                write!(sink, "frames:")?;
+                                write_frames_text(from, sink)?;
+                                write!(sink, " ->")?;
+                                write_frames_text(to, sink)?;
+                                writeln!(sink)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 機械可読なJSON（1行）
+    pub fn write_json(&self, sink: &mut (impl Write + ?Sized)) -> std::io::Result<()> {
+        write!(sink, "{{\"atlas\":[")?;
+        let mut sep = Separator::default();
+        for c in self.atlas.iter() {
+            sep.write(sink)?;
+            match c {
+                AtlasChange::Resized { from, to } => write!(
+                    sink,
+                    "{{\"kind\":\"resized\",\"from\":[{},{}],\"to\":[{},{}]}}",
+                    from.0, from.1, to.0, to.1
+                )?,
+                AtlasChange::Setting { name, from, to } => {
+                    write!(sink, "{{\"kind\":\"setting\",\"name\":\"{name}\",\"from\":")?;
+                    json_writer::write_string(sink, from)?;
+                    write!(sink, ",\"to\":")?;
+                    json_writer::write_string(sink, to)?;
+                    write!(sink, "}}")?;
+                }
+            }
+        }
+
+        write!(sink, "],\"sprites\":[")?;
+        let mut sep = Separator::default();
+        for c in self.sprites.iter() {
+            sep.write(sink)?;
+            let (kind, id, name) = match c {
+                SpriteChange::Added { id, name } => ("added", id, name),
+                SpriteChange::Removed { id, name } => ("removed", id, name),
+                SpriteChange::Modified { id, name, .. } => ("modified", id, name),
+            };
+            write!(sink, "{{\"kind\":\"{kind}\",\"id\":\"{id}\",\"name\":")?;
+            json_writer::write_string(sink, name)?;
+            if let SpriteChange::Modified { changes, .. } = c {
+                write!(sink, ",\"changes\":[")?;
+                let mut sep = Separator::default();
+                for c in changes {
+                    sep.write(sink)?;
+                    write_field_change_json(c, sink)?;
+                }
+                write!(sink, "]")?;
+            }
+            write!(sink, "}}")?;
+        }
+
+        write!(sink, "],\"animations\":[")?;
+        let mut sep = Separator::default();
+        for c in self.animations.iter() {
+            sep.write(sink)?;
+            let (kind, name) = match c {
+                AnimationChange::Added { name } => ("added", name),
+                AnimationChange::Removed { name } => ("removed", name),
+                AnimationChange::Modified { name, .. } => ("modified", name),
+            };
+            write!(sink, "{{\"kind\":\"{kind}\",\"name\":")?;
+            json_writer::write_string(sink, name)?;
+            if let AnimationChange::Modified { changes, .. } = c {
+                write!(sink, ",\"changes\":[")?;
+                let mut sep = Separator::default();
+                for c in changes {
+                    sep.write(sink)?;
+                    match c {
+                        AnimationFieldChange::LoopModeChanged { from, to } => write!(
+                            sink,
+                            "{{\"kind\":\"loop\",\"from\":\"{}\",\"to\":\"{}\"}}",
+                            from.as_str(),
+                            to.as_str()
+                        )?,
+                        AnimationFieldChange::FramesChanged { from, to } => {
+                            write!(sink, "{{\"kind\":\"frames\",\"from\":")?;
+                            write_frames_json(from, sink)?;
+                            write!(sink, ",\"to\":")?;
+                            write_frames_json(to, sink)?;
+                            write!(sink, "}}")?;
+                        }
+                    }
+                }
+                write!(sink, "]")?;
+            }
+            write!(sink, "}}")?;
+        }
+
+        writeln!(sink, "]}}")
+    }
+}
+
+/// ` <スプライトID>:<表示時間>ms`を並べる
+fn write_frames_text(
+    frames: &[AnimationFrame],
+    sink: &mut (impl Write + ?Sized),
+) -> std::io::Result<()> {
+    if frames.is_empty() {
+        return write!(sink, " (none)");
+    }
+
+    for f in frames {
+        write!(sink, " {}:{}ms", f.sprite_id, f.duration_ms)?;
+    }
+
+    Ok(())
+}
+
+fn write_frames_json(
+    frames: &[AnimationFrame],
+    sink: &mut (impl Write + ?Sized),
+) -> std::io::Result<()> {
+    write!(sink, "[")?;
+    let mut sep = Separator::default();
+    for f in frames {
+        sep.write(sink)?;
+        write!(
+            sink,
+            "{{\"sprite_id\":\"{}\",\"duration_ms\":{}}}",
+            f.sprite_id, f.duration_ms
+        )?;
+    }
+    write!(sink, "]")
+}
+
+fn write_field_change_json(
+    change: &SpriteFieldChange,
+    sink: &mut (impl Write + ?Sized),
+) -> std::io::Result<()> {
+    match change {
+        SpriteFieldChange::Renamed { from, to } | SpriteFieldChange::Regrouped { from, to } => {
+            let kind = if matches!(change, SpriteFieldChange::Renamed { .. }) {
+                "renamed"
+            } else {
+                "regrouped"
+            };
+            write!(sink, "{{\"kind\":\"{kind}\",\"from\":")?;
+            json_writer::write_string(sink, from)?;
+            write!(sink, ",\"to\":")?;
+            json_writer::write_string(sink, to)?;
+            write!(sink, "}}")
+        }
+        SpriteFieldChange::Moved { from, to } | SpriteFieldChange::Resized { from, to } => {
+            let kind = if matches!(change, SpriteFieldChange::Moved { .. }) {
+                "moved"
+            } else {
+                "resized"
+            };
+            write!(
+                sink,
+                "{{\"kind\":\"{kind}\",\"from\":[{},{}],\"to\":[{},{}]}}",
+                from.0, from.1, to.0, to.1
+            )
+        }
+        SpriteFieldChange::Resliced { from, to } => write!(
+            sink,
+            "{{\"kind\":\"resliced\",\"from\":[{},{},{},{}],\"to\":[{},{},{},{}]}}",
+            from[0], from[1], from[2], from[3], to[0], to[1], to[2], to[3]
+        ),
+        SpriteFieldChange::PivotChanged { from, to } => write!(
+            sink,
+            "{{\"kind\":\"pivot\",\"from\":[{},{}],\"to\":[{},{}]}}",
+            from.0, from.1, to.0, to.1
+        ),
+        SpriteFieldChange::SourceChanged { from, to } => {
+            write!(sink, "{{\"kind\":\"source\",\"from\":")?;
+            write_source_ref_json(from, sink)?;
+            write!(sink, ",\"to\":")?;
+            write_source_ref_json(to, sink)?;
+            write!(sink, "}}")
+        }
+    }
+}
+
+fn write_source_ref_json(
+    source: &SourceRef,
+    sink: &mut (impl Write + ?Sized),
+) -> std::io::Result<()> {
+    write!(sink, "{{\"path\":")?;
+    json_writer::write_string(sink, &source.path.to_string_lossy())?;
+    write!(sink, ",\"left\":{},\"top\":{}}}", source.left, source.top)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peridot::{AlphaMode, CompressionQuality, TextureCompression};
+
+    fn asset(animations: Vec<Animation>) -> SpriteAtlasAsset {
+        SpriteAtlasAsset {
+            sprites: Vec::new(),
+            animations,
+            width: 64,
+            height: 64,
+            alpha_mode: AlphaMode::Straight,
+            mip_levels: 1,
+            compression: TextureCompression::None,
+            compression_quality: CompressionQuality::Fast,
+        }
+    }
+
+    fn animation(name: &str, loop_mode: AnimationLoopMode, durations: &[u32]) -> Animation {
+        Animation {
+            name: name.into(),
+            loop_mode,
+            frames: durations
+                .iter()
+                .map(|&duration_ms| AnimationFrame {
+                    sprite_id: Uuid::from_u128(1),
+                    duration_ms,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn animations_are_matched_by_name() {
+        let old = asset(vec![
+            animation("idle", AnimationLoopMode::Loop, &[100]),
+            animation("jump", AnimationLoopMode::Once, &[50, 50]),
+            animation("walk", AnimationLoopMode::Loop, &[100, 100]),
+        ]);
+        let new = asset(vec![
+            animation("idle", AnimationLoopMode::Loop, &[100]),
+            animation("run", AnimationLoopMode::Loop, &[80]),
+            animation("walk", AnimationLoopMode::PingPong, &[100, 120]),
+        ]);
+
+        let d = diff(&old, &new);
+        assert!(!d.is_empty());
+        assert_eq!(
+            d.animations,
+            vec![
+                AnimationChange::Removed {
+                    name: "jump".into()
+                },
+                AnimationChange::Added { name: "run".into() },
+                AnimationChange::Modified {
+                    name: "walk".into(),
+                    changes: vec![
+                        AnimationFieldChange::LoopModeChanged {
+                            from: AnimationLoopMode::Loop,
+                            to: AnimationLoopMode::PingPong,
+                        },
+                        AnimationFieldChange::FramesChanged {
+                            from: old.animations[2].frames.clone(),
+                            to: new.animations[2].frames.clone(),
+                        },
+                    ],
+                },
+            ]
+        );
+    }
+}
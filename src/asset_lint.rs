@@ -0,0 +1,350 @@
+//! .psaの整合性チェック（ソース画像の有無と大きさ、IDと名前）
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+use crate::{
+    json_writer::{self, Separator},
+    peridot::{Sprite, SpriteAtlasAsset},
+    source_reader,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+impl Severity {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LintIssue {
+    #[error("sprite {name} ({id}): source {} does not exist", .path.display())]
+    MissingSource {
+        id: Uuid,
+        name: String,
+        path: PathBuf,
+    },
+    #[error("sprite {name} ({id}): cannot read the size of source {}", .path.display())]
+    UnreadableSource {
+        id: Uuid,
+        name: String,
+        path: PathBuf,
+    },
+    #[error(
+        "sprite {name} ({id}): stored region {}x{} at ({}, {}) does not match source {} ({}x{})",
+        .stored_size.0, .stored_size.1, .source_offset.0, .source_offset.1, .path.display(), .actual_size.0, .actual_size.1
+    )]
+    DimensionMismatch {
+        id: Uuid,
+        name: String,
+        path: PathBuf,
+        source_offset: (u32, u32),
+        stored_size: (u32, u32),
+        actual_size: (u32, u32),
+        /// ソースに合わせたときの大きさ（切り出し位置がソースの外にあれば0になる）
+        fitted_size: (u32, u32),
+    },
+    #[error("sprite id {0} appears more than once")]
+    DuplicateId(Uuid),
+    #[error("sprite id {id} is not in sorted order (after {previous})")]
+    UnsortedId { id: Uuid, previous: Uuid },
+    #[error("sprite name {name:?} is used by {} sprites", .ids.len())]
+    DuplicateName { name: String, ids: Vec<Uuid> },
+    #[error("sprite {0} has an empty name")]
+    EmptyName(Uuid),
+}
+impl LintIssue {
+    pub const fn severity(&self) -> Severity {
+        match self {
+            // 名前は書き出しやコード生成のときに困るだけなので警告にとどめる
+            Self::DuplicateName { .. } | Self::EmptyName(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    /// 機械可読な出力で使う種類の名前
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::MissingSource { .. } => "missing-source",
+            Self::UnreadableSource { .. } => "unreadable-source",
+            Self::DimensionMismatch { .. } => "dimension-mismatch",
+            Self::DuplicateId(_) => "duplicate-id",
+            Self::UnsortedId { .. } => "unsorted-id",
+            Self::DuplicateName { .. } => "duplicate-name",
+            Self::EmptyName(_) => "empty-name",
+        }
+    }
+
+    const fn id(&self) -> Option<&Uuid> {
+        match self {
+            Self::MissingSource { id, .. }
+            | Self::UnreadableSource { id, .. }
+            | Self::DimensionMismatch { id, .. }
+            | Self::DuplicateId(id)
+            | Self::UnsortedId { id, .. }
+            | Self::EmptyName(id) => Some(id),
+            Self::DuplicateName { .. } => None,
+        }
+    }
+
+    const fn path(&self) -> Option<&PathBuf> {
+        match self {
+            Self::MissingSource { path, .. }
+            | Self::UnreadableSource { path, .. }
+            | Self::DimensionMismatch { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// ソース画像の有無と大きさ以外も含めてすべて調べる
+pub fn lint(asset: &SpriteAtlasAsset) -> Vec<LintIssue> {
+    let mut issues = check_sources(&asset.sprites);
+
+    let mut seen_ids = HashSet::new();
+    let mut reported_ids = HashSet::new();
+    for (n, s) in asset.sprites.iter().enumerate() {
+        if !seen_ids.insert(s.id) {
+            if reported_ids.insert(s.id) {
+                issues.push(LintIssue::DuplicateId(s.id));
+            }
+        } else if n > 0 && asset.sprites[n - 1].id > s.id {
+            // Note: 書き出すときはIDの順に並んでいることが前提
+            issues.push(LintIssue::UnsortedId {
+                id: s.id,
+                previous: asset.sprites[n - 1].id,
+            });
+        }
+    }
+
+    let mut ids_by_name = BTreeMap::<&str, Vec<Uuid>>::new();
+    for s in asset.sprites.iter() {
+        if s.name.is_empty() {
+            issues.push(LintIssue::EmptyName(s.id));
+        } else {
+            ids_by_name.entry(&s.name).or_default().push(s.id);
+        }
+    }
+    for (name, ids) in ids_by_name {
+        if ids.len() > 1 {
+            issues.push(LintIssue::DuplicateName {
+                name: name.to_owned(),
+                ids,
+            });
+        }
+    }
+
+    issues
+}
+
+/// ソース画像の大きさは、切り出す領域がはみ出していないかだけを調べる
+///
+/// Note: ファイル全体を使っているのか(0, 0)から一部を切り出しているのかは.psaに残っていないので、
+/// ソースが大きくなった場合は区別できない。一部だけ使っているものを全体に広げてしまわないように、どちらも切り出しとして扱う
+///
+/// 画像のヘッダを読むので、読み込んだときはUIスレッドでなくバックグラウンドで呼ぶ
+pub fn check_sources(sprites: &[Sprite]) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+
+    // 同じ画像を何度も読まないようにする
+    let mut dimensions = HashMap::<&Path, Option<(u32, u32)>>::new();
+    for s in sprites.iter() {
+        // Note: アプリ本体と同じく相対パスはカレントディレクトリからのものとして扱う
+        let path = &s.source_path;
+        if !path.exists() {
+            issues.push(LintIssue::MissingSource {
+                id: s.id,
+                name: s.name.clone(),
+                path: path.clone(),
+            });
+            continue;
+        }
+        let Some((width, height)) = *dimensions
+            .entry(&s.source_path)
+            .or_insert_with(|| source_reader::read_dimensions(path))
+        else {
+            issues.push(LintIssue::UnreadableSource {
+                id: s.id,
+                name: s.name.clone(),
+                path: path.clone(),
+            });
+            continue;
+        };
+
+        let fitted_size = (
+            s.width.min(width.saturating_sub(s.source_left)),
+            s.height.min(height.saturating_sub(s.source_top)),
+        );
+        if fitted_size != (s.width, s.height) {
+            issues.push(LintIssue::DimensionMismatch {
+                id: s.id,
+                name: s.name.clone(),
+                path: path.clone(),
+                source_offset: (s.source_left, s.source_top),
+                stored_size: (s.width, s.height),
+                actual_size: (width, height),
+                fitted_size,
+            });
+        }
+    }
+
+    issues
+}
+
+pub fn write_text(issues: &[LintIssue], sink: &mut (impl Write + ?Sized)) -> std::io::Result<()> {
+    for i in issues {
+        writeln!(sink, "{}: [{}] {i}", i.severity().as_str(), i.code())?;
+    }
+
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity() == Severity::Error)
+        .count();
+    writeln!(
+        sink,
+        "{errors} error(s), {} warning(s)",
+        issues.len() - errors
+    )
+}
+
+/// 機械可読なJSON（1行）
+pub fn write_json(issues: &[LintIssue], sink: &mut (impl Write + ?Sized)) -> std::io::Result<()> {
+    write!(sink, "{{\"issues\":[")?;
+    let mut sep = Separator::default();
+    for i in issues {
+        sep.write(sink)?;
+        write!(
+            sink,
+            "{{\"severity\":\"{}\",\"code\":\"{}\"",
+            i.severity().as_str(),
+            i.code()
+        )?;
+        if let Some(id) = i.id() {
+            write!(sink, ",\"id\":\"{id}\"")?;
+        }
+        if let LintIssue::DuplicateName { ids, .. } = i {
+            write!(sink, ",\"ids\":[")?;
+            let mut sep = Separator::default();
+            for id in ids {
+                sep.write(sink)?;
+                write!(sink, "\"{id}\"")?;
+            }
+            write!(sink, "]")?;
+        }
+        if let Some(path) = i.path() {
+            write!(sink, ",\"path\":")?;
+            json_writer::write_string(sink, &path.to_string_lossy())?;
+        }
+        write!(sink, ",\"message\":")?;
+        json_writer::write_string(sink, &i.to_string())?;
+        write!(sink, "}}")?;
+    }
+
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity() == Severity::Error)
+        .count();
+    writeln!(
+        sink,
+        "],\"errors\":{errors},\"warnings\":{}}}",
+        issues.len() - errors
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peridot::{AlphaMode, CompressionQuality, TextureCompression};
+
+    fn asset_with_sprite(source_path: PathBuf, width: u32, height: u32) -> SpriteAtlasAsset {
+        SpriteAtlasAsset {
+            sprites: vec![Sprite {
+                id: Uuid::from_u128(1),
+                name: "cell".into(),
+                source_path,
+                source_left: 0,
+                source_top: 0,
+                width,
+                height,
+                left: 0,
+                top: 0,
+                border_left: 0,
+                border_top: 0,
+                border_right: 0,
+                border_bottom: 0,
+                pivot_x: 0.5,
+                pivot_y: 0.5,
+                group: String::new(),
+            }],
+            animations: Vec::new(),
+            width: 64,
+            height: 64,
+            alpha_mode: AlphaMode::Straight,
+            mip_levels: 1,
+            compression: TextureCompression::None,
+            compression_quality: CompressionQuality::Fast,
+        }
+    }
+
+    fn dimension_mismatches(width: u32, height: u32) -> Vec<((u32, u32), (u32, u32))> {
+        let path = std::env::temp_dir().join(format!(
+            "asset_lint_{}_{width}x{height}.png",
+            std::process::id()
+        ));
+        image::RgbaImage::new(16, 8).save(&path).unwrap();
+        let issues = lint(&asset_with_sprite(path.clone(), width, height));
+        std::fs::remove_file(&path).unwrap();
+
+        issues
+            .into_iter()
+            .filter_map(|i| match i {
+                LintIssue::DimensionMismatch {
+                    stored_size,
+                    fitted_size,
+                    ..
+                } => Some((stored_size, fitted_size)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lone_cell_at_origin_is_not_a_mismatch() {
+        assert!(dimension_mismatches(4, 4).is_empty());
+        assert!(dimension_mismatches(16, 8).is_empty());
+    }
+
+    #[test]
+    fn region_outside_of_shrunk_source_is_a_mismatch() {
+        assert_eq!(dimension_mismatches(20, 8), vec![((20, 8), (16, 8))]);
+    }
+
+    #[test]
+    fn check_sources_reports_only_source_issues() {
+        let mut asset = asset_with_sprite(PathBuf::from("asset_lint_missing.png"), 4, 4);
+        asset.sprites[0].name = String::new();
+        asset.sprites.push(asset.sprites[0].clone());
+
+        let issues = check_sources(&asset.sprites);
+        assert_eq!(issues.len(), 2);
+        assert!(
+            issues
+                .iter()
+                .all(|i| matches!(i, LintIssue::MissingSource { .. }))
+        );
+        // Note: IDや名前の重複はlintでだけ調べる
+        assert!(lint(&asset).len() > issues.len());
+    }
+}
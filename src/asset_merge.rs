@@ -0,0 +1,630 @@
+//! 同時に編集された.psaの3方向マージ（スプライトはUUIDで、アニメーションは名前で対応付ける）
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write as _,
+};
+
+use uuid::Uuid;
+
+use crate::peridot::{Animation, Sprite, SpriteAtlasAsset};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+impl core::fmt::Display for Side {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Ours => "ours",
+            Self::Theirs => "theirs",
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MergeConflict {
+    #[error("sprite {name} ({id}): both sides changed {}", .fields.join(", "))]
+    SpriteFields {
+        id: Uuid,
+        name: String,
+        fields: Vec<&'static str>,
+    },
+    #[error("sprite {name} ({id}): deleted in {deleted_in} but changed in the other")]
+    ModifyDelete {
+        id: Uuid,
+        name: String,
+        deleted_in: Side,
+    },
+    #[error("sprite {name} ({id}): added differently on both sides")]
+    BothAdded { id: Uuid, name: String },
+    #[error("atlas {0}: changed differently on both sides")]
+    Atlas(&'static str),
+    #[error("animation {0}: changed differently on both sides")]
+    Animation(String),
+    #[error("animation {animation}: refers to sprite {sprite_id} which is removed by the merge")]
+    MissingFrameSprite { animation: String, sprite_id: Uuid },
+}
+
+fn format_conflicts(conflicts: &[MergeConflict]) -> String {
+    let mut s = String::new();
+    for c in conflicts {
+        let _ = write!(s, "\n  {c}");
+    }
+
+    s
+}
+
+fn format_overlaps(overlaps: &[(String, String)]) -> String {
+    let mut s = String::new();
+    for (a, b) in overlaps {
+        let _ = write!(s, "\n  {a} and {b}");
+    }
+
+    s
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MergeError {
+    #[error("merge conflicts:{}", format_conflicts(.0))]
+    Conflicts(Vec<MergeConflict>),
+    #[error("merged layout introduces overlapping sprites:{}", format_overlaps(.0))]
+    Overlaps(Vec<(String, String)>),
+}
+
+/// 片方だけが変わっていればそちらを、両方同じように変わっていればそれを取る
+fn merge3<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if ours == theirs || theirs == base {
+        Some(ours.clone())
+    } else if ours == base {
+        Some(theirs.clone())
+    } else {
+        None
+    }
+}
+
+/// `base`から`ours`と`theirs`へそれぞれ編集されたものをマージする
+///
+/// どちらか片方だけの変更は自動で取り込む。スプライトは項目（名前、位置、大きさなど）ごとにマージするので、
+/// 片方で移動してもう片方で名前を変えたようなものは衝突しない
+pub fn merge(
+    base: &SpriteAtlasAsset,
+    ours: &SpriteAtlasAsset,
+    theirs: &SpriteAtlasAsset,
+) -> Result<SpriteAtlasAsset, MergeError> {
+    let mut conflicts = Vec::new();
+
+    let alpha_mode = merge_setting(
+        "alpha_mode",
+        [base.alpha_mode, ours.alpha_mode, theirs.alpha_mode],
+        &mut conflicts,
+    );
+    let mip_levels = merge_setting(
+        "mip_levels",
+        [base.mip_levels, ours.mip_levels, theirs.mip_levels],
+        &mut conflicts,
+    );
+    let compression = merge_setting(
+        "compression",
+        [base.compression, ours.compression, theirs.compression],
+        &mut conflicts,
+    );
+    let compression_quality = merge_setting(
+        "compression_quality",
+        [
+            base.compression_quality,
+            ours.compression_quality,
+            theirs.compression_quality,
+        ],
+        &mut conflicts,
+    );
+    // Note: アトラスの大きさはスプライトを追加したときに広がるものなので、両方で変わっていたら大きいほうを取る
+    // （マージしたスプライトが入りきるかは後で確かめる）
+    let width =
+        merge3(&base.width, &ours.width, &theirs.width).unwrap_or(ours.width.max(theirs.width));
+    let height = merge3(&base.height, &ours.height, &theirs.height)
+        .unwrap_or(ours.height.max(theirs.height));
+
+    let sprites = merge_sprites(base, ours, theirs, &mut conflicts);
+    let animations = merge_animations(base, ours, theirs, &sprites, &mut conflicts);
+    // Note: 移動と大きさの変更が別々に取り込まれると、どちらのアトラスにも収まらないことがある
+    if sprites.iter().any(|s| s.left + s.width > width) {
+        conflicts.push(MergeConflict::Atlas("width"));
+    }
+    if sprites.iter().any(|s| s.top + s.height > height) {
+        conflicts.push(MergeConflict::Atlas("height"));
+    }
+    if !conflicts.is_empty() {
+        return Err(MergeError::Conflicts(conflicts));
+    }
+
+    // どちらにもなかった重なりができていたら失敗にする
+    let existing = overlapping_pairs(&ours.sprites)
+        .into_iter()
+        .chain(overlapping_pairs(&theirs.sprites))
+        .collect::<HashSet<_>>();
+    let name_of = |id: Uuid| {
+        sprites
+            .iter()
+            .find(|s| s.id == id)
+            .map_or_else(String::new, |s| format!("{} ({id})", s.name))
+    };
+    let mut overlaps = overlapping_pairs(&sprites)
+        .into_iter()
+        .filter(|p| !existing.contains(p))
+        .collect::<Vec<_>>();
+    if !overlaps.is_empty() {
+        overlaps.sort();
+        return Err(MergeError::Overlaps(
+            overlaps
+                .into_iter()
+                .map(|(a, b)| (name_of(a), name_of(b)))
+                .collect(),
+        ));
+    }
+
+    Ok(SpriteAtlasAsset {
+        sprites,
+        animations,
+        width,
+        height,
+        alpha_mode,
+        mip_levels,
+        compression,
+        compression_quality,
+    })
+}
+
+/// アトラスの設定のマージ（base, ours, theirsの順に渡す。衝突したらoursの値のまま）
+fn merge_setting<T: PartialEq + Copy>(
+    name: &'static str,
+    [base, ours, theirs]: [T; 3],
+    conflicts: &mut Vec<MergeConflict>,
+) -> T {
+    merge3(&base, &ours, &theirs).unwrap_or_else(|| {
+        conflicts.push(MergeConflict::Atlas(name));
+        ours
+    })
+}
+
+/// 項目ごとのマージ（衝突した項目はoursの値のまま名前を記録する）
+struct SpriteFieldMerge<'s> {
+    base: &'s Sprite,
+    ours: &'s Sprite,
+    theirs: &'s Sprite,
+    merged: Sprite,
+    conflicting_fields: Vec<&'static str>,
+}
+impl SpriteFieldMerge<'_> {
+    fn field<T: PartialEq + Clone>(
+        &mut self,
+        name: &'static str,
+        get: impl Fn(&Sprite) -> T,
+        set: impl FnOnce(&mut Sprite, T),
+    ) {
+        match merge3(&get(self.base), &get(self.ours), &get(self.theirs)) {
+            Some(v) => set(&mut self.merged, v),
+            None => self.conflicting_fields.push(name),
+        }
+    }
+}
+
+fn merge_sprites(
+    base: &SpriteAtlasAsset,
+    ours: &SpriteAtlasAsset,
+    theirs: &SpriteAtlasAsset,
+    conflicts: &mut Vec<MergeConflict>,
+) -> Vec<Sprite> {
+    let mut entries = BTreeMap::<Uuid, [Option<&Sprite>; 3]>::new();
+    for (n, a) in [base, ours, theirs].into_iter().enumerate() {
+        for s in a.sprites.iter() {
+            entries.entry(s.id).or_default()[n] = Some(s);
+        }
+    }
+
+    let mut merged = Vec::with_capacity(entries.len());
+    for (id, e) in entries {
+        match e {
+            [Some(b), Some(o), Some(t)] => {
+                let mut m = SpriteFieldMerge {
+                    base: b,
+                    ours: o,
+                    theirs: t,
+                    merged: o.clone(),
+                    conflicting_fields: Vec::new(),
+                };
+                m.field("name", |s| s.name.clone(), |s, v| s.name = v);
+                m.field("group", |s| s.group.clone(), |s, v| s.group = v);
+                m.field(
+                    "position",
+                    |s| (s.left, s.top),
+                    |s, (l, t)| (s.left, s.top) = (l, t),
+                );
+                m.field(
+                    "size",
+                    |s| (s.width, s.height),
+                    |s, (w, h)| (s.width, s.height) = (w, h),
+                );
+                m.field(
+                    "slice",
+                    |s| [s.border_left, s.border_top, s.border_right, s.border_bottom],
+                    |s, [l, t, r, b]| {
+                        (s.border_left, s.border_top, s.border_right, s.border_bottom) =
+                            (l, t, r, b)
+                    },
+                );
+                m.field(
+                    "pivot",
+                    |s| (s.pivot_x, s.pivot_y),
+                    |s, (x, y)| (s.pivot_x, s.pivot_y) = (x, y),
+                );
+                m.field(
+                    "source",
+                    |s| (s.source_path.clone(), s.source_left, s.source_top),
+                    |s, (p, l, t)| (s.source_path, s.source_left, s.source_top) = (p, l, t),
+                );
+
+                if !m.conflicting_fields.is_empty() {
+                    conflicts.push(MergeConflict::SpriteFields {
+                        id,
+                        name: o.name.clone(),
+                        fields: m.conflicting_fields,
+                    });
+                }
+                merged.push(m.merged);
+            }
+            [b, o, t] => match merge3(&b, &o, &t) {
+                Some(s) => merged.extend(s.cloned()),
+                None => {
+                    let name = o.or(t).or(b).map_or_else(String::new, |s| s.name.clone());
+                    conflicts.push(match (b, o) {
+                        (None, _) => MergeConflict::BothAdded { id, name },
+                        (Some(_), None) => MergeConflict::ModifyDelete {
+                            id,
+                            name,
+                            deleted_in: Side::Ours,
+                        },
+                        (Some(_), Some(_)) => MergeConflict::ModifyDelete {
+                            id,
+                            name,
+                            deleted_in: Side::Theirs,
+                        },
+                    });
+                }
+            },
+        }
+    }
+
+    // Note: BTreeMapの順に作っているので、書き出すときの前提（IDの順）になっている
+    merged
+}
+
+fn merge_animations(
+    base: &SpriteAtlasAsset,
+    ours: &SpriteAtlasAsset,
+    theirs: &SpriteAtlasAsset,
+    merged_sprites: &[Sprite],
+    conflicts: &mut Vec<MergeConflict>,
+) -> Vec<Animation> {
+    let find =
+        |a: &'_ SpriteAtlasAsset, name: &str| a.animations.iter().find(|x| x.name == name).cloned();
+
+    let mut names = ours
+        .animations
+        .iter()
+        .map(|x| x.name.clone())
+        .collect::<Vec<_>>();
+    for x in theirs.animations.iter() {
+        if !names.contains(&x.name) {
+            names.push(x.name.clone());
+        }
+    }
+
+    let sprite_ids = merged_sprites.iter().map(|s| s.id).collect::<HashSet<_>>();
+    let mut merged = Vec::with_capacity(names.len());
+    for name in names {
+        let Some(a) = merge3(&find(base, &name), &find(ours, &name), &find(theirs, &name)) else {
+            conflicts.push(MergeConflict::Animation(name));
+            continue;
+        };
+        let Some(a) = a else {
+            // 削除された
+            continue;
+        };
+
+        for f in a.frames.iter() {
+            if !sprite_ids.contains(&f.sprite_id) {
+                conflicts.push(MergeConflict::MissingFrameSprite {
+                    animation: a.name.clone(),
+                    sprite_id: f.sprite_id,
+                });
+            }
+        }
+        merged.push(a);
+    }
+    // Note: 書き出すときは名前の順に並んでいることが前提
+    merged.sort_by(|a, b| a.name.cmp(&b.name));
+
+    merged
+}
+
+/// 重なっているスプライトのIDの組（小さいほうが先）
+fn overlapping_pairs(sprites: &[Sprite]) -> Vec<(Uuid, Uuid)> {
+    let sprites = sprites
+        .iter()
+        .filter(|s| s.width > 0 && s.height > 0)
+        .collect::<Vec<_>>();
+
+    let mut pairs = Vec::new();
+    for (n, a) in sprites.iter().enumerate() {
+        for b in sprites[n + 1..].iter() {
+            if a.left < b.left + b.width
+                && b.left < a.left + a.width
+                && a.top < b.top + b.height
+                && b.top < a.top + a.height
+            {
+                pairs.push((a.id.min(b.id), a.id.max(b.id)));
+            }
+        }
+    }
+
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peridot::{
+        AlphaMode, AnimationFrame, AnimationLoopMode, CompressionQuality, TextureCompression,
+    };
+
+    fn sprite(id: u128, name: &str, left: u32, top: u32) -> Sprite {
+        Sprite {
+            id: Uuid::from_u128(id),
+            name: name.into(),
+            source_path: format!("{name}.png").into(),
+            source_left: 0,
+            source_top: 0,
+            width: 16,
+            height: 16,
+            left,
+            top,
+            border_left: 0,
+            border_top: 0,
+            border_right: 0,
+            border_bottom: 0,
+            pivot_x: 0.5,
+            pivot_y: 0.5,
+            group: String::new(),
+        }
+    }
+
+    fn animation(name: &str, sprite_ids: &[u128]) -> Animation {
+        Animation {
+            name: name.into(),
+            loop_mode: AnimationLoopMode::Loop,
+            frames: sprite_ids
+                .iter()
+                .map(|&id| AnimationFrame {
+                    sprite_id: Uuid::from_u128(id),
+                    duration_ms: 100,
+                })
+                .collect(),
+        }
+    }
+
+    fn asset(sprites: Vec<Sprite>, animations: Vec<Animation>) -> SpriteAtlasAsset {
+        SpriteAtlasAsset {
+            sprites,
+            animations,
+            width: 64,
+            height: 64,
+            alpha_mode: AlphaMode::Straight,
+            mip_levels: 1,
+            compression: TextureCompression::None,
+            compression_quality: CompressionQuality::Fast,
+        }
+    }
+
+    fn conflicts(
+        base: &SpriteAtlasAsset,
+        ours: &SpriteAtlasAsset,
+        theirs: &SpriteAtlasAsset,
+    ) -> Vec<MergeConflict> {
+        match merge(base, ours, theirs) {
+            Err(MergeError::Conflicts(c)) => c,
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(_) => panic!("merged without conflicts"),
+        }
+    }
+
+    #[test]
+    fn merge3_takes_the_changed_side() {
+        assert_eq!(merge3(&1, &1, &1), Some(1));
+        assert_eq!(merge3(&1, &2, &1), Some(2));
+        assert_eq!(merge3(&1, &1, &3), Some(3));
+        assert_eq!(merge3(&1, &2, &2), Some(2));
+        assert_eq!(merge3(&1, &2, &3), None);
+    }
+
+    #[test]
+    fn sprite_fields_are_merged_separately() {
+        let base = asset(vec![sprite(1, "hero", 0, 0)], Vec::new());
+        let ours = asset(vec![sprite(1, "hero", 32, 0)], Vec::new());
+        let theirs = asset(vec![sprite(1, "player", 0, 0)], Vec::new());
+
+        let merged = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(merged.sprites, [sprite(1, "player", 32, 0)]);
+    }
+
+    #[test]
+    fn same_field_changed_on_both_sides_conflicts() {
+        let base = asset(vec![sprite(1, "hero", 0, 0)], Vec::new());
+        let ours = asset(vec![sprite(1, "hero", 32, 0)], Vec::new());
+        let theirs = asset(vec![sprite(1, "player", 0, 32)], Vec::new());
+
+        assert!(matches!(
+            &conflicts(&base, &ours, &theirs)[..],
+            [MergeConflict::SpriteFields { fields, .. }] if fields == &["position"]
+        ));
+    }
+
+    #[test]
+    fn deleted_and_modified_sprite_conflicts() {
+        let base = asset(vec![sprite(1, "hero", 0, 0)], Vec::new());
+        let deleted = asset(Vec::new(), Vec::new());
+        let renamed = asset(vec![sprite(1, "player", 0, 0)], Vec::new());
+
+        assert!(matches!(
+            &conflicts(&base, &deleted, &renamed)[..],
+            [MergeConflict::ModifyDelete {
+                deleted_in: Side::Ours,
+                ..
+            }]
+        ));
+        assert!(matches!(
+            &conflicts(&base, &renamed, &deleted)[..],
+            [MergeConflict::ModifyDelete {
+                deleted_in: Side::Theirs,
+                ..
+            }]
+        ));
+
+        // 変更がなければ削除が取り込まれる
+        let merged = merge(&base, &base, &deleted).unwrap();
+        assert!(merged.sprites.is_empty());
+    }
+
+    #[test]
+    fn sprite_added_on_both_sides() {
+        let base = asset(Vec::new(), Vec::new());
+        let ours = asset(vec![sprite(1, "hero", 0, 0)], Vec::new());
+        let theirs = asset(vec![sprite(1, "hero", 16, 0)], Vec::new());
+
+        assert!(matches!(
+            &conflicts(&base, &ours, &theirs)[..],
+            [MergeConflict::BothAdded { name, .. }] if name == "hero"
+        ));
+
+        // 同じものなら1つになる
+        let merged = merge(&base, &ours, &ours).unwrap();
+        assert_eq!(merged.sprites, [sprite(1, "hero", 0, 0)]);
+    }
+
+    #[test]
+    fn animations_are_merged_by_name() {
+        let sprites = || vec![sprite(1, "a", 0, 0), sprite(2, "b", 16, 0)];
+        let base = asset(
+            sprites(),
+            vec![animation("idle", &[1]), animation("run", &[1, 2])],
+        );
+        let ours = asset(
+            sprites(),
+            vec![
+                animation("idle", &[1]),
+                animation("run", &[1, 2]),
+                animation("walk", &[2, 1]),
+            ],
+        );
+        let theirs = asset(sprites(), vec![animation("idle", &[1, 2])]);
+
+        let merged = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(
+            merged.animations,
+            [animation("idle", &[1, 2]), animation("walk", &[2, 1])]
+        );
+
+        let ours = asset(sprites(), vec![animation("idle", &[2])]);
+        assert!(matches!(
+            &conflicts(&base, &ours, &theirs)[..],
+            [MergeConflict::Animation(name)] if name == "idle"
+        ));
+    }
+
+    #[test]
+    fn animation_frames_must_refer_merged_sprites() {
+        let base = asset(
+            vec![sprite(1, "a", 0, 0), sprite(2, "b", 16, 0)],
+            Vec::new(),
+        );
+        let ours = asset(
+            vec![sprite(1, "a", 0, 0), sprite(2, "b", 16, 0)],
+            vec![animation("idle", &[1, 2])],
+        );
+        let theirs = asset(vec![sprite(1, "a", 0, 0)], Vec::new());
+
+        assert!(matches!(
+            &conflicts(&base, &ours, &theirs)[..],
+            [MergeConflict::MissingFrameSprite { animation, sprite_id }]
+                if animation == "idle" && *sprite_id == Uuid::from_u128(2)
+        ));
+    }
+
+    #[test]
+    fn new_overlaps_are_rejected() {
+        let base = asset(vec![sprite(1, "a", 0, 0)], Vec::new());
+        let ours = asset(
+            vec![sprite(1, "a", 0, 0), sprite(2, "b", 16, 0)],
+            Vec::new(),
+        );
+        let theirs = asset(
+            vec![sprite(1, "a", 0, 0), sprite(3, "c", 24, 0)],
+            Vec::new(),
+        );
+
+        assert!(matches!(
+            merge(&base, &ours, &theirs),
+            Err(MergeError::Overlaps(pairs)) if pairs.len() == 1
+        ));
+
+        // もともと重なっていたものはそのまま
+        let base = asset(vec![sprite(1, "a", 0, 0), sprite(2, "b", 8, 0)], Vec::new());
+        let theirs = asset(vec![sprite(1, "a", 0, 0), sprite(2, "c", 8, 0)], Vec::new());
+        assert!(merge(&base, &base, &theirs).is_ok());
+    }
+
+    #[test]
+    fn atlas_settings() {
+        let base = asset(vec![sprite(1, "a", 0, 0)], Vec::new());
+        let mut ours = asset(
+            vec![sprite(1, "a", 0, 0), sprite(2, "b", 64, 0)],
+            Vec::new(),
+        );
+        ours.width = 128;
+        ours.mip_levels = 2;
+        let mut theirs = asset(
+            vec![sprite(1, "a", 0, 0), sprite(3, "c", 0, 128)],
+            Vec::new(),
+        );
+        theirs.height = 256;
+        theirs.width = 32;
+
+        // 大きさは広いほうを取る
+        let merged = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(
+            (merged.width, merged.height, merged.mip_levels),
+            (128, 256, 2)
+        );
+
+        theirs.mip_levels = 3;
+        assert!(matches!(
+            &conflicts(&base, &ours, &theirs)[..],
+            [MergeConflict::Atlas("mip_levels")]
+        ));
+    }
+
+    #[test]
+    fn merged_sprites_must_fit_in_the_atlas() {
+        let base = asset(vec![sprite(1, "a", 0, 0)], Vec::new());
+        let ours = asset(vec![sprite(1, "a", 48, 0)], Vec::new());
+        let mut resized = sprite(1, "a", 0, 0);
+        resized.width = 32;
+        let theirs = asset(vec![resized], Vec::new());
+
+        assert!(matches!(
+            &conflicts(&base, &ours, &theirs)[..],
+            [MergeConflict::Atlas("width")]
+        ));
+    }
+}
@@ -0,0 +1,652 @@
+//! アトラス画像のサンプル形式と合成
+//!
+//! ソース画像の精度（8bit/16bit/浮動小数点）とアルファの持ち方を保ったままプレビューや書き出しに回すためのもの
+
+use std::{collections::HashMap, path::Path};
+
+use image::{DynamicImage, ImageBuffer, ImageDecoder, Pixel};
+
+use crate::peridot::{AlphaMode, SpriteAtlasAsset};
+
+/// サンプル形式（精度の低い順）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SampleFormat {
+    Unorm8,
+    Unorm16,
+    /// OpenEXR/Radiance HDR（値はリニア）
+    Float32,
+}
+impl SampleFormat {
+    pub const fn of(color_type: image::ColorType) -> Self {
+        match color_type {
+            image::ColorType::L16
+            | image::ColorType::La16
+            | image::ColorType::Rgb16
+            | image::ColorType::Rgba16 => Self::Unorm16,
+            image::ColorType::Rgb32F | image::ColorType::Rgba32F => Self::Float32,
+            _ => Self::Unorm8,
+        }
+    }
+
+    /// ヘッダだけ読んで判定する
+    pub fn probe(path: &Path) -> image::ImageResult<Self> {
+        let decoder = image::ImageReader::open(path)?
+            .with_guessed_format()?
+            .into_decoder()?;
+
+        Ok(Self::of(decoder.color_type()))
+    }
+
+    /// 8bitより精度が高いか（プレビューのアトラスを浮動小数点にする必要があるか）
+    pub const fn is_high_precision(self) -> bool {
+        !matches!(self, Self::Unorm8)
+    }
+
+    /// 書き出すときのページ画像の拡張子
+    pub const fn page_extension(self) -> &'static str {
+        match self {
+            Self::Unorm8 | Self::Unorm16 => "png",
+            // Note: PNGには浮動小数点のサンプル形式がないのでEXRで書く
+            Self::Float32 => "exr",
+        }
+    }
+}
+
+/// ソース画像のアルファの持ち方（形式から決める）
+///
+/// OpenEXRは仕様上premultiplied、PNGはstraight（Radiance HDRはアルファがないのでどちらでも同じ）
+pub fn source_alpha_mode(path: &Path) -> AlphaMode {
+    match image::ImageFormat::from_path(path) {
+        Ok(image::ImageFormat::OpenExr) => AlphaMode::Premultiplied,
+        _ => AlphaMode::Straight,
+    }
+}
+
+/// アルファの持ち方を変換する（元の精度のRGBA画像になる）
+pub fn convert_alpha_mode(image: &DynamicImage, from: AlphaMode, to: AlphaMode) -> DynamicImage {
+    match SampleFormat::of(image.color()) {
+        SampleFormat::Unorm8 => {
+            let mut image = image.to_rgba8();
+            convert_unorm_alpha_mode(&mut image, u8::MAX, from, to);
+            DynamicImage::ImageRgba8(image)
+        }
+        SampleFormat::Unorm16 => {
+            let mut image = image.to_rgba16();
+            convert_unorm_alpha_mode(&mut image, u16::MAX, from, to);
+            DynamicImage::ImageRgba16(image)
+        }
+        SampleFormat::Float32 => {
+            let mut image = image.to_rgba32f();
+            convert_float_alpha_mode(&mut image, from, to);
+            DynamicImage::ImageRgba32F(image)
+        }
+    }
+}
+
+/// Note: 整数のまま丸めるので、premultipliedからstraightに戻すとアルファが小さいところの色は元に戻らない
+fn convert_unorm_alpha_mode<S>(rgba_samples: &mut [S], max: S, from: AlphaMode, to: AlphaMode)
+where
+    S: Copy + Into<u64> + TryFrom<u64>,
+{
+    if from == to {
+        return;
+    }
+
+    let max: u64 = max.into();
+    for p in rgba_samples.chunks_exact_mut(4) {
+        let a: u64 = p[3].into();
+        for c in p[..3].iter_mut() {
+            let v: u64 = (*c).into();
+            let converted = match to {
+                AlphaMode::Premultiplied => (v * a + max / 2) / max,
+                AlphaMode::Straight if a == 0 => 0,
+                AlphaMode::Straight => ((v * max + a / 2) / a).min(max),
+            };
+            // Note: maxで切り詰めているので必ず収まる
+            *c = S::try_from(converted).ok().unwrap();
+        }
+    }
+}
+
+/// Note: HDRでは色が1を超えうるので、straightに戻すときも切り詰めない
+fn convert_float_alpha_mode(image: &mut image::Rgba32FImage, from: AlphaMode, to: AlphaMode) {
+    if from == to {
+        return;
+    }
+
+    for p in image.pixels_mut() {
+        let a = p.0[3];
+        for c in p.0[..3].iter_mut() {
+            *c = match to {
+                AlphaMode::Premultiplied => *c * a,
+                AlphaMode::Straight if a == 0.0 => 0.0,
+                AlphaMode::Straight => *c / a,
+            };
+        }
+    }
+}
+
+/// 画面に表示するための形式に変換する
+///
+/// 8bit/16bitのものはそのまま（sRGBとみなす）、浮動小数点のものはリニアなのでsRGBのカーブをかける。
+/// 1を超える値はそのまま残すので、浮動小数点のテクスチャに入れる場合はHDRの情報が失われない
+pub fn for_display(image: &DynamicImage) -> DynamicImage {
+    if SampleFormat::of(image.color()) != SampleFormat::Float32 {
+        return image.clone();
+    }
+
+    let mut image = image.to_rgba32f();
+    for p in image.pixels_mut() {
+        for c in p.0[..3].iter_mut() {
+            *c = linear_to_srgb(*c);
+        }
+    }
+
+    DynamicImage::ImageRgba32F(image)
+}
+
+fn linear_to_srgb(x: f32) -> f32 {
+    if x <= 0.0031308 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// EXRに書くときはリニアにそろえる（8bit/16bitのものはsRGBとみなして戻す）
+fn to_linear_rgba32f(image: &DynamicImage) -> image::Rgba32FImage {
+    let mut converted = image.to_rgba32f();
+    if SampleFormat::of(image.color()) != SampleFormat::Float32 {
+        for p in converted.pixels_mut() {
+            for c in p.0[..3].iter_mut() {
+                *c = srgb_to_linear(*c);
+            }
+        }
+    }
+
+    converted
+}
+
+/// 8bitのsRGBにする（ブロック圧縮の入力用）
+pub fn to_srgb_rgba8(image: &DynamicImage, alpha_mode: AlphaMode) -> image::RgbaImage {
+    if SampleFormat::of(image.color()) != SampleFormat::Float32 {
+        return image.to_rgba8();
+    }
+
+    // Note: premultipliedのときはリニアのまま掛けてあるので、一度外してからsRGBにする
+    let straight = for_display(&convert_alpha_mode(image, alpha_mode, AlphaMode::Straight));
+    convert_alpha_mode(
+        &DynamicImage::ImageRgba8(straight.to_rgba8()),
+        AlphaMode::Straight,
+        alpha_mode,
+    )
+    .to_rgba8()
+}
+
+/// 16bitの画像の色をsRGBからリニアにする
+///
+/// 16bitにはsRGBのテクスチャ形式がないので、コンテナに入れるときはリニアにしておく
+pub fn linearize_unorm16(image: &DynamicImage, alpha_mode: AlphaMode) -> DynamicImage {
+    let mut straight = convert_alpha_mode(image, alpha_mode, AlphaMode::Straight).to_rgba16();
+    for p in straight.pixels_mut() {
+        for c in p.0[..3].iter_mut() {
+            *c = (srgb_to_linear(*c as f32 / u16::MAX as f32) * u16::MAX as f32).round() as u16;
+        }
+    }
+
+    convert_alpha_mode(
+        &DynamicImage::ImageRgba16(straight),
+        AlphaMode::Straight,
+        alpha_mode,
+    )
+}
+
+/// f32を半精度浮動小数点数のビット列にする（最近接偶数丸め）
+pub fn f32_to_f16_bits(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // inf/nan
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let e = exponent - 127 + 15;
+    if e >= 0x1f {
+        // 表せないほど大きいのでinf
+        return sign | 0x7c00;
+    }
+    if e <= 0 {
+        // 非正規化数（小さすぎるものは0）
+        if e < -10 {
+            return sign;
+        }
+        let m = mantissa | 0x80_0000;
+        let shift = (14 - e) as u32;
+
+        return sign | round_half_even(m >> shift, m & ((1 << shift) - 1), 1 << (shift - 1)) as u16;
+    }
+
+    // Note: 仮数部の繰り上がりはそのまま指数部に伝わる（最大値を超えたらinfになる）
+    sign | round_half_even(
+        ((e as u32) << 10) | (mantissa >> 13),
+        mantissa & 0x1fff,
+        0x1000,
+    ) as u16
+}
+
+const fn round_half_even(truncated: u32, remainder: u32, halfway: u32) -> u32 {
+    if remainder > halfway || (remainder == halfway && truncated & 1 == 1) {
+        truncated + 1
+    } else {
+        truncated
+    }
+}
+
+/// スプライトをアセットの配置どおりに並べた1枚の画像を作る
+///
+/// 画像の形式はソース画像のうちいちばん精度の高いものに合わせ、アルファはアセットの設定に合わせる
+pub fn compose(asset: &SpriteAtlasAsset) -> image::ImageResult<DynamicImage> {
+    // 同じソース画像から何枚も切り出すことが多いので一度だけ読む
+    let mut sources = HashMap::new();
+    for s in asset.sprites.iter() {
+        if !sources.contains_key(s.source_path.as_path()) {
+            sources.insert(s.source_path.as_path(), image::open(&s.source_path)?);
+        }
+    }
+    let format = sources
+        .values()
+        .map(|x| SampleFormat::of(x.color()))
+        .max()
+        .unwrap_or(SampleFormat::Unorm8);
+
+    Ok(match format {
+        SampleFormat::Unorm8 => {
+            DynamicImage::ImageRgba8(compose_with(asset, &sources, |x, from| {
+                let mut x = x.to_rgba8();
+                convert_unorm_alpha_mode(&mut x, u8::MAX, from, asset.alpha_mode);
+                x
+            }))
+        }
+        SampleFormat::Unorm16 => {
+            DynamicImage::ImageRgba16(compose_with(asset, &sources, |x, from| {
+                let mut x = x.to_rgba16();
+                convert_unorm_alpha_mode(&mut x, u16::MAX, from, asset.alpha_mode);
+                x
+            }))
+        }
+        SampleFormat::Float32 => {
+            DynamicImage::ImageRgba32F(compose_with(asset, &sources, |x, from| {
+                // Note: premultipliedにするときはリニアにしてから掛ける
+                let mut x = to_linear_rgba32f(x);
+                convert_float_alpha_mode(&mut x, from, asset.alpha_mode);
+                x
+            }))
+        }
+    })
+}
+
+fn compose_with<P: Pixel>(
+    asset: &SpriteAtlasAsset,
+    sources: &HashMap<&Path, DynamicImage>,
+    convert: impl Fn(&DynamicImage, AlphaMode) -> ImageBuffer<P, Vec<P::Subpixel>>,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let mut page = ImageBuffer::new(asset.width, asset.height);
+    for s in asset.sprites.iter() {
+        let source = &sources[s.source_path.as_path()];
+        let cropped = convert(
+            &source.crop_imm(s.source_left, s.source_top, s.width, s.height),
+            source_alpha_mode(&s.source_path),
+        );
+
+        // Note: ページからはみ出した分は切り捨てられる
+        image::imageops::replace(&mut page, &cropped, s.left as _, s.top as _);
+    }
+
+    page
+}
+
+/// ミップマップを作るときの縮小フィルタ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MipFilter {
+    /// 2x2の平均
+    Box,
+    /// Kaiser窓をかけたsinc（ボックスよりぼやけにくい）
+    Kaiser,
+}
+impl MipFilter {
+    /// 隣のスプライトがにじまないようにあける隙間（いちばん小さいレベルのテクセル数）
+    ///
+    /// Note: 1段縮小するごとに2x2の外側へ`reach`テクセル読むので、ベースレベルまでたどると広がりは`reach`セル未満に収まる。
+    /// いちばん小さいレベルをバイリニアで読んでもにじまないように、さらに1テクセル足しておく
+    pub const fn gutter(self) -> u32 {
+        let reach = match self {
+            Self::Box => 0,
+            Self::Kaiser => KAISER_RADIUS as u32 - 1,
+        };
+
+        reach + 1
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "box" => Some(Self::Box),
+            "kaiser" => Some(Self::Kaiser),
+            _ => None,
+        }
+    }
+}
+
+/// ベースレベルを含めて`levels`枚のミップマップを作る（1x1になったらそこで止める）
+///
+/// 縮小はリニアかつpremultipliedで行い、各レベルは`page`と同じ形式・アルファの持ち方で返す
+pub fn mip_chain(
+    page: &DynamicImage,
+    levels: u32,
+    alpha_mode: AlphaMode,
+    filter: MipFilter,
+) -> Vec<DynamicImage> {
+    let format = SampleFormat::of(page.color());
+    // Note: sRGBのまま平均すると暗くなり、straightのまま平均すると透明な部分の色がにじみ出る
+    let mut work = to_linear_rgba32f(&convert_alpha_mode(page, alpha_mode, AlphaMode::Straight));
+    convert_float_alpha_mode(&mut work, AlphaMode::Straight, AlphaMode::Premultiplied);
+
+    let mut chain = vec![page.clone()];
+    for _ in 1..levels {
+        if work.width() == 1 && work.height() == 1 {
+            break;
+        }
+
+        work = match filter {
+            MipFilter::Box => downsample_box(&work),
+            MipFilter::Kaiser => downsample_kaiser(&work),
+        };
+        chain.push(from_linear_premultiplied(&work, format, alpha_mode));
+    }
+
+    chain
+}
+
+fn from_linear_premultiplied(
+    image: &image::Rgba32FImage,
+    format: SampleFormat,
+    alpha_mode: AlphaMode,
+) -> DynamicImage {
+    let mut image = image.clone();
+    if format == SampleFormat::Float32 {
+        convert_float_alpha_mode(&mut image, AlphaMode::Premultiplied, alpha_mode);
+        return DynamicImage::ImageRgba32F(image);
+    }
+
+    convert_float_alpha_mode(&mut image, AlphaMode::Premultiplied, AlphaMode::Straight);
+    for p in image.pixels_mut() {
+        for c in p.0[..3].iter_mut() {
+            *c = linear_to_srgb(c.clamp(0.0, 1.0));
+        }
+    }
+    let image = DynamicImage::ImageRgba32F(image);
+
+    convert_alpha_mode(
+        &match format {
+            SampleFormat::Unorm16 => DynamicImage::ImageRgba16(image.to_rgba16()),
+            _ => DynamicImage::ImageRgba8(image.to_rgba8()),
+        },
+        AlphaMode::Straight,
+        alpha_mode,
+    )
+}
+
+fn downsample_box(image: &image::Rgba32FImage) -> image::Rgba32FImage {
+    let (width, height) = ((image.width() / 2).max(1), (image.height() / 2).max(1));
+
+    image::Rgba32FImage::from_fn(width, height, |x, y| {
+        // Note: 1ピクセル幅の方向はそのまま使う
+        let (x0, x1) = (
+            (x * 2).min(image.width() - 1),
+            (x * 2 + 1).min(image.width() - 1),
+        );
+        let (y0, y1) = (
+            (y * 2).min(image.height() - 1),
+            (y * 2 + 1).min(image.height() - 1),
+        );
+        let mut sum = [0.0f32; 4];
+        for p in [(x0, y0), (x1, y0), (x0, y1), (x1, y1)] {
+            for (s, c) in sum.iter_mut().zip(image.get_pixel(p.0, p.1).0) {
+                *s += c;
+            }
+        }
+
+        image::Rgba(sum.map(|s| s * 0.25))
+    })
+}
+
+/// Kaiser窓の半径（縮小前のテクセル単位）と形のパラメータ
+const KAISER_RADIUS: f32 = 3.0;
+const KAISER_ALPHA: f32 = 4.0;
+
+fn downsample_kaiser(image: &image::Rgba32FImage) -> image::Rgba32FImage {
+    let weights = kaiser_weights();
+
+    // 横と縦に分けてかける
+    let horizontal = if image.width() > 1 {
+        image::Rgba32FImage::from_fn(image.width() / 2, image.height(), |x, y| {
+            filter_taps(&weights, x, image.width(), |sx| *image.get_pixel(sx, y))
+        })
+    } else {
+        image.clone()
+    };
+    if horizontal.height() > 1 {
+        image::Rgba32FImage::from_fn(horizontal.width(), horizontal.height() / 2, |x, y| {
+            filter_taps(&weights, y, horizontal.height(), |sy| {
+                *horizontal.get_pixel(x, sy)
+            })
+        })
+    } else {
+        horizontal
+    }
+}
+
+/// 縮小後のテクセル中心からの距離が-2.5, -1.5, ..., 2.5のテクセルにかける重み（合計1）
+fn kaiser_weights() -> Vec<f32> {
+    let tap_count = (KAISER_RADIUS * 2.0) as usize;
+    let weights = (0..tap_count)
+        .map(|n| {
+            let d = n as f32 + 0.5 - KAISER_RADIUS;
+            // 半分に縮小するので、縮小後のナイキスト周波数で切る
+            sinc(d * 0.5) * kaiser_window(d / KAISER_RADIUS)
+        })
+        .collect::<Vec<_>>();
+    let sum = weights.iter().sum::<f32>();
+
+    weights.into_iter().map(|w| w / sum).collect()
+}
+
+fn filter_taps(
+    weights: &[f32],
+    dst: u32,
+    src_length: u32,
+    sample: impl Fn(u32) -> image::Rgba<f32>,
+) -> image::Rgba<f32> {
+    let first = (dst * 2) as i64 + 1 - weights.len() as i64 / 2;
+    let mut sum = [0.0f32; 4];
+    for (n, w) in weights.iter().enumerate() {
+        // 端はクランプ
+        let src = (first + n as i64).clamp(0, src_length as i64 - 1) as u32;
+        for (s, c) in sum.iter_mut().zip(sample(src).0) {
+            *s += c * w;
+        }
+    }
+
+    // Note: sincの負の部分で範囲外に出ることがあるので戻しておく（色はHDRがあるので上は切らない）
+    let [r, g, b, a] = sum;
+    image::Rgba([r.max(0.0), g.max(0.0), b.max(0.0), a.clamp(0.0, 1.0)])
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let px = core::f32::consts::PI * x;
+        px.sin() / px
+    }
+}
+
+fn kaiser_window(t: f32) -> f32 {
+    bessel_i0(KAISER_ALPHA * (1.0 - t * t).max(0.0).sqrt()) / bessel_i0(KAISER_ALPHA)
+}
+
+/// 第1種変形ベッセル関数I0（級数展開）
+fn bessel_i0(x: f32) -> f32 {
+    let (mut sum, mut term, mut k) = (1.0f32, 1.0f32, 1.0f32);
+    loop {
+        term *= (x * 0.5 / k) * (x * 0.5 / k);
+        sum += term;
+        if term < sum * 1e-7 {
+            break sum;
+        }
+        k += 1.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peridot::{CompressionQuality, Sprite, TextureCompression};
+
+    fn rgba8(p: [u8; 4]) -> DynamicImage {
+        DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(p)))
+    }
+
+    fn rgba32f(p: [f32; 4]) -> DynamicImage {
+        DynamicImage::ImageRgba32F(image::Rgba32FImage::from_pixel(1, 1, image::Rgba(p)))
+    }
+
+    #[test]
+    fn unorm8_straight_to_premultiplied() {
+        let converted = convert_alpha_mode(
+            &rgba8([255, 128, 0, 128]),
+            AlphaMode::Straight,
+            AlphaMode::Premultiplied,
+        );
+        assert_eq!(converted.to_rgba8().get_pixel(0, 0).0, [128, 64, 0, 128]);
+    }
+
+    #[test]
+    fn unorm8_round_trip() {
+        let premultiplied = convert_alpha_mode(
+            &rgba8([255, 128, 0, 128]),
+            AlphaMode::Straight,
+            AlphaMode::Premultiplied,
+        );
+        let straight = convert_alpha_mode(
+            &premultiplied,
+            AlphaMode::Premultiplied,
+            AlphaMode::Straight,
+        );
+        assert_eq!(straight.to_rgba8().get_pixel(0, 0).0, [255, 128, 0, 128]);
+    }
+
+    #[test]
+    fn unorm16_straight_to_premultiplied() {
+        let mut samples = [u16::MAX, 32768, 0, 32768];
+        convert_unorm_alpha_mode(
+            &mut samples,
+            u16::MAX,
+            AlphaMode::Straight,
+            AlphaMode::Premultiplied,
+        );
+        assert_eq!(samples, [32768, 16384, 0, 32768]);
+    }
+
+    #[test]
+    fn zero_alpha_clears_color() {
+        for (from, to) in [
+            (AlphaMode::Straight, AlphaMode::Premultiplied),
+            (AlphaMode::Premultiplied, AlphaMode::Straight),
+        ] {
+            let converted = convert_alpha_mode(&rgba8([200, 100, 50, 0]), from, to);
+            assert_eq!(converted.to_rgba8().get_pixel(0, 0).0, [0, 0, 0, 0]);
+
+            let converted = convert_alpha_mode(&rgba32f([2.0, 1.0, 0.5, 0.0]), from, to);
+            assert_eq!(
+                converted.to_rgba32f().get_pixel(0, 0).0,
+                [0.0, 0.0, 0.0, 0.0]
+            );
+        }
+    }
+
+    #[test]
+    fn float_keeps_values_above_one() {
+        let premultiplied = convert_alpha_mode(
+            &rgba32f([4.0, 2.0, 0.5, 0.5]),
+            AlphaMode::Straight,
+            AlphaMode::Premultiplied,
+        );
+        assert_eq!(
+            premultiplied.to_rgba32f().get_pixel(0, 0).0,
+            [2.0, 1.0, 0.25, 0.5]
+        );
+
+        let straight = convert_alpha_mode(
+            &premultiplied,
+            AlphaMode::Premultiplied,
+            AlphaMode::Straight,
+        );
+        assert_eq!(
+            straight.to_rgba32f().get_pixel(0, 0).0,
+            [4.0, 2.0, 0.5, 0.5]
+        );
+    }
+
+    #[test]
+    fn compose_premultiplies_straight_sources() {
+        let path =
+            std::env::temp_dir().join(format!("atlas_image_compose_{}.png", std::process::id()));
+        rgba8([255, 128, 0, 128]).save(&path).unwrap();
+
+        let asset = SpriteAtlasAsset {
+            sprites: vec![Sprite {
+                id: uuid::Uuid::from_u128(1),
+                name: "dot".into(),
+                source_path: path.clone(),
+                source_left: 0,
+                source_top: 0,
+                width: 1,
+                height: 1,
+                left: 1,
+                top: 2,
+                border_left: 0,
+                border_top: 0,
+                border_right: 0,
+                border_bottom: 0,
+                pivot_x: 0.5,
+                pivot_y: 0.5,
+                group: String::new(),
+            }],
+            animations: Vec::new(),
+            width: 4,
+            height: 4,
+            alpha_mode: AlphaMode::Premultiplied,
+            mip_levels: 1,
+            compression: TextureCompression::None,
+            compression_quality: CompressionQuality::Fast,
+        };
+        let composed = compose(&asset);
+        std::fs::remove_file(&path).unwrap();
+
+        let composed = composed.unwrap().to_rgba8();
+        assert_eq!(composed.dimensions(), (4, 4));
+        assert_eq!(composed.get_pixel(1, 2).0, [128, 64, 0, 128]);
+        assert_eq!(composed.get_pixel(0, 0).0, [0, 0, 0, 0]);
+    }
+}
@@ -1,30 +1,373 @@
 use std::{
-    path::PathBuf,
+    cell::Cell,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
     sync::{
         Arc,
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
     },
     thread::JoinHandle,
 };
 
-use crossbeam::{
-    channel::TryRecvError,
-    deque::{Injector, Worker},
-};
+use crossbeam::{channel::TryRecvError, deque::Injector};
+use parking_lot::{Condvar, Mutex};
 
-use crate::native_wrapper::NativeEvent;
+use crate::{
+    native_wrapper::NativeEvent,
+    region_detect::{self, DetectedRegion, RegionDetectParams},
+};
 
-pub enum BackgroundWork {
+pub enum BackgroundWorkKind {
     LoadSpriteSource(PathBuf, Box<dyn FnMut(PathBuf, image::DynamicImage) + Send>),
+    DetectSpriteRegions(
+        PathBuf,
+        RegionDetectParams,
+        Box<dyn FnMut(PathBuf, Vec<DetectedRegion>, image::DynamicImage) + Send>,
+    ),
+    /// 任意の処理（ラベルは進捗表示に使う）
+    Job(
+        String,
+        Box<dyn FnOnce(&BackgroundJobContext) -> BackgroundJobResult + Send>,
+    ),
+}
+
+pub type BackgroundJobResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// 優先度の高いものから順に処理される（同じ優先度の中ではキューに入れた順）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackgroundWorkPriority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+impl BackgroundWorkPriority {
+    const COUNT: usize = 3;
+}
+
+/// キューに入れた作業を取り消すためのトークン
+///
+/// 同じトークンを持つ作業は`cancel`でまとめて取り消される（処理中のものは結果を捨てる）
+#[derive(Clone)]
+pub struct BackgroundWorkCancellationToken(Arc<AtomicBool>);
+impl Default for BackgroundWorkCancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl BackgroundWorkCancellationToken {
+    pub fn new() -> Self {
+        Self(Arc::new(AtomicBool::new(false)))
+    }
+
+    #[inline]
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackgroundJobGroupId(u64);
+
+struct BackgroundJobGroupState {
+    id: BackgroundJobGroupId,
+    label: String,
+    /// メンバーごとの進捗
+    progress: Mutex<Vec<f32>>,
+}
+
+/// まとめて1つの進捗として表示する作業のグループ
+///
+/// 全体の進捗はメンバーの進捗の平均になる（メンバーは全部キューに入れてから処理が進むようにすること）
+#[derive(Clone)]
+pub struct BackgroundJobGroup(Arc<BackgroundJobGroupState>);
+impl BackgroundJobGroup {
+    pub fn new(label: impl Into<String>) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        Self(Arc::new(BackgroundJobGroupState {
+            id: BackgroundJobGroupId(NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+            label: label.into(),
+            progress: Mutex::new(Vec::new()),
+        }))
+    }
+
+    fn add_member(&self) -> BackgroundJobGroupMember {
+        let mut progress = self.0.progress.lock();
+        progress.push(0.0);
+
+        BackgroundJobGroupMember {
+            group: self.0.clone(),
+            index: progress.len() - 1,
+        }
+    }
+}
+
+struct BackgroundJobGroupMember {
+    group: Arc<BackgroundJobGroupState>,
+    index: usize,
+}
+impl BackgroundJobGroupMember {
+    fn set_progress(&self, progress: f32, view_feedback_sender: &ViewFeedbackSender) {
+        let mut p = self.group.progress.lock();
+        p[self.index] = progress;
+        let total = p.iter().sum::<f32>() / p.len() as f32;
+
+        // Note: 別のスレッドから送られた値と順番が入れ替わらないようにロックを持ったまま送る
+        view_feedback_sender.send(BackgroundWorkerViewFeedback::GroupProgress(
+            self.group.id,
+            self.group.label.clone(),
+            total,
+        ));
+    }
+}
+
+pub struct BackgroundWork {
+    kind: BackgroundWorkKind,
+    priority: BackgroundWorkPriority,
+    cancellation_token: Option<BackgroundWorkCancellationToken>,
+    group_member: Option<BackgroundJobGroupMember>,
+}
+impl BackgroundWork {
+    pub fn new(kind: BackgroundWorkKind) -> Self {
+        Self {
+            kind,
+            priority: BackgroundWorkPriority::default(),
+            cancellation_token: None,
+            group_member: None,
+        }
+    }
+
+    pub fn job(
+        label: impl Into<String>,
+        f: impl FnOnce(&BackgroundJobContext) -> BackgroundJobResult + Send + 'static,
+    ) -> Self {
+        Self::new(BackgroundWorkKind::Job(label.into(), Box::new(f)))
+    }
+
+    pub fn with_priority(self, priority: BackgroundWorkPriority) -> Self {
+        Self { priority, ..self }
+    }
+
+    pub fn with_cancellation_token(self, token: BackgroundWorkCancellationToken) -> Self {
+        Self {
+            cancellation_token: Some(token),
+            ..self
+        }
+    }
+
+    pub fn with_group(self, group: &BackgroundJobGroup) -> Self {
+        Self {
+            group_member: Some(group.add_member()),
+            ..self
+        }
+    }
+}
+
+/// Jobの処理中に進捗を報告したり取り消しを確認したりするためのもの
+pub struct BackgroundJobContext<'a> {
+    worker_index: usize,
+    view_feedback_sender: &'a ViewFeedbackSender,
+    cancellation_token: Option<&'a BackgroundWorkCancellationToken>,
+    group_member: Option<&'a BackgroundJobGroupMember>,
+    last_reported_progress: Cell<f32>,
+}
+impl BackgroundJobContext<'_> {
+    /// 進捗（0.0〜1.0）を報告する
+    pub fn report_progress(&self, progress: f32) {
+        let progress = progress.clamp(0.0, 1.0);
+        // Note: 細かく呼ばれても表示が追いつかないので1%未満の変化は送らない
+        if (progress - self.last_reported_progress.get()).abs() < 0.01 {
+            return;
+        }
+        self.last_reported_progress.set(progress);
+
+        self.view_feedback_sender
+            .send(BackgroundWorkerViewFeedback::Progress(
+                self.worker_index,
+                progress,
+            ));
+        if let Some(m) = self.group_member {
+            m.set_progress(progress, self.view_feedback_sender);
+        }
+    }
+
+    /// 取り消されていたら途中で打ち切ってよい
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation_token.is_some_and(|x| x.is_cancelled())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackgroundWorkError {
+    #[error("failed to load {}: {}", .0.display(), .1)]
+    LoadImage(PathBuf, image::ImageError),
+    #[error("{0} failed: {1}")]
+    Job(String, Box<dyn std::error::Error + Send + Sync>),
+    #[error("{0} panicked: {1}")]
+    Panicked(String, String),
 }
 
 pub enum BackgroundWorkerViewFeedback {
-    BeginWork(usize, String),
+    BeginWork(usize, String, Option<BackgroundJobGroupId>),
+    /// 処理中の作業の進捗（0.0〜1.0）
+    Progress(usize, f32),
     EndWork(usize),
+    /// 作業が失敗して終わった（EndWorkの代わりに送られる）
+    Failed(usize, BackgroundWorkError),
+    /// グループ全体の進捗（メンバーが全部終わったら1.0）
+    GroupProgress(BackgroundJobGroupId, String, f32),
+}
+
+pub struct BackgroundTaskStatus {
+    pub label: String,
+    /// 進捗を報告しない作業ではNone
+    pub progress: Option<f32>,
+    pub group: Option<BackgroundJobGroupId>,
+}
+
+pub struct BackgroundJobGroupStatus {
+    pub id: BackgroundJobGroupId,
+    pub label: String,
+    pub progress: f32,
+}
+
+/// UIスレッド側で持つ作業の状況（`BackgroundWorkerViewFeedback`を順に適用して作る）
+pub struct BackgroundWorkerStatus {
+    pub workers: Vec<Option<BackgroundTaskStatus>>,
+    /// 終わっていないグループ
+    pub groups: Vec<BackgroundJobGroupStatus>,
+}
+impl BackgroundWorkerStatus {
+    pub fn new(worker_count: usize) -> Self {
+        Self {
+            workers: core::iter::repeat_with(|| None)
+                .take(worker_count)
+                .collect(),
+            groups: Vec::new(),
+        }
+    }
+
+    pub fn apply(&mut self, vf: &BackgroundWorkerViewFeedback) {
+        match *vf {
+            BackgroundWorkerViewFeedback::BeginWork(n, ref label, group) => {
+                self.workers[n] = Some(BackgroundTaskStatus {
+                    label: label.clone(),
+                    progress: None,
+                    group,
+                });
+            }
+            BackgroundWorkerViewFeedback::Progress(n, progress) => {
+                if let Some(ref mut t) = self.workers[n] {
+                    t.progress = Some(progress);
+                }
+            }
+            BackgroundWorkerViewFeedback::EndWork(n)
+            | BackgroundWorkerViewFeedback::Failed(n, _) => {
+                self.workers[n] = None;
+            }
+            BackgroundWorkerViewFeedback::GroupProgress(id, ref label, progress) => {
+                let existing = self.groups.iter().position(|g| g.id == id);
+                match existing {
+                    Some(x) if progress >= 1.0 => {
+                        self.groups.remove(x);
+                    }
+                    Some(x) => {
+                        self.groups[x].progress = progress;
+                    }
+                    None if progress >= 1.0 => (),
+                    None => self.groups.push(BackgroundJobGroupStatus {
+                        id,
+                        label: label.clone(),
+                        progress,
+                    }),
+                }
+            }
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        !self.groups.is_empty() || self.workers.iter().any(Option::is_some)
+    }
+
+    /// 全体の進捗（何もしていなければNone）
+    ///
+    /// グループはまとめて1つ、グループに入っていない作業は1つずつ数えて平均する（進捗を報告しない作業は0とみなす）
+    pub fn overall_progress(&self) -> Option<f32> {
+        let (count, sum) = self
+            .groups
+            .iter()
+            .map(|g| g.progress)
+            .chain(
+                self.workers
+                    .iter()
+                    .flatten()
+                    .filter(|t| t.group.is_none())
+                    .map(|t| t.progress.unwrap_or(0.0)),
+            )
+            .fold((0, 0.0), |(c, s), p| (c + 1, s + p));
+
+        (count > 0).then(|| sum / count as f32)
+    }
+}
+
+struct WorkQueue {
+    queues: [Injector<BackgroundWork>; BackgroundWorkPriority::COUNT],
+    idle_lock: Mutex<()>,
+    wakeup: Condvar,
+}
+impl WorkQueue {
+    fn new() -> Self {
+        Self {
+            queues: core::array::from_fn(|_| Injector::new()),
+            idle_lock: Mutex::new(()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    fn push(&self, work: BackgroundWork) {
+        self.queues[work.priority as usize].push(work);
+
+        // Note: 待機に入ろうとしているスレッドとすれ違わないようにロックを取ってから起こす
+        let _g = self.idle_lock.lock();
+        self.wakeup.notify_one();
+    }
+
+    fn pop(&self) -> Option<BackgroundWork> {
+        self.queues.iter().find_map(|q| {
+            core::iter::repeat_with(|| q.steal())
+                .find(|x| !x.is_retry())
+                .and_then(|x| x.success())
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queues.iter().all(|q| q.is_empty())
+    }
+
+    /// 作業が入るかteardownされるまで待つ
+    fn park(&self, teardown_signal: &AtomicBool) {
+        let mut g = self.idle_lock.lock();
+        if teardown_signal.load(Ordering::Acquire) || !self.is_empty() {
+            return;
+        }
+
+        self.wakeup.wait(&mut g);
+    }
+
+    fn wake_all(&self) {
+        let _g = self.idle_lock.lock();
+        self.wakeup.notify_all();
+    }
 }
 
 #[derive(Clone)]
-pub struct BackgroundWorkerEnqueueAccess(Arc<Injector<BackgroundWork>>);
+pub struct BackgroundWorkerEnqueueAccess(Arc<WorkQueue>);
 impl BackgroundWorkerEnqueueAccess {
     #[inline]
     pub fn enqueue(&self, work: BackgroundWork) {
@@ -38,7 +381,7 @@ impl BackgroundWorkerEnqueueAccess {
 }
 
 #[derive(Clone)]
-pub struct BackgroundWorkerEnqueueWeakAccess(std::sync::Weak<Injector<BackgroundWork>>);
+pub struct BackgroundWorkerEnqueueWeakAccess(std::sync::Weak<WorkQueue>);
 impl BackgroundWorkerEnqueueWeakAccess {
     #[inline]
     pub fn upgrade(&self) -> Option<BackgroundWorkerEnqueueAccess> {
@@ -46,9 +389,116 @@ impl BackgroundWorkerEnqueueWeakAccess {
     }
 }
 
+struct ViewFeedbackSender {
+    sender: crossbeam::channel::Sender<BackgroundWorkerViewFeedback>,
+    ui_thread_wakeup_event: Arc<NativeEvent>,
+}
+impl ViewFeedbackSender {
+    fn send(&self, vf: BackgroundWorkerViewFeedback) {
+        match self.sender.send(vf) {
+            Ok(()) => (),
+            Err(e) => {
+                tracing::warn!({?e}, "sending view feedback failed");
+            }
+        }
+        self.ui_thread_wakeup_event.signal();
+    }
+}
+
+fn load_image(path: &Path) -> Result<image::DynamicImage, BackgroundWorkError> {
+    image::open(path).map_err(|e| BackgroundWorkError::LoadImage(path.to_owned(), e))
+}
+
+/// panicのペイロードから表示用のメッセージを取り出す
+fn panic_message(payload: &(dyn core::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        (*s).to_owned()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::from("unknown panic")
+    }
+}
+
+fn process(n: usize, work: BackgroundWork, view_feedback_sender: &ViewFeedbackSender) {
+    let BackgroundWork {
+        kind,
+        cancellation_token,
+        group_member,
+        ..
+    } = work;
+    let is_cancelled = || {
+        cancellation_token
+            .as_ref()
+            .is_some_and(|x| x.is_cancelled())
+    };
+    let group_id = group_member.as_ref().map(|m| m.group.id);
+
+    // Note: 取り消されたものや失敗したものもグループの中では終わったものとして扱う
+    let finish = || {
+        if let Some(ref m) = group_member {
+            m.set_progress(1.0, view_feedback_sender);
+        }
+    };
+
+    if is_cancelled() {
+        // 始まる前に取り消された
+        finish();
+        return;
+    }
+
+    let label = match kind {
+        BackgroundWorkKind::LoadSpriteSource(ref path, _) => format!("Loading {}", path.display()),
+        BackgroundWorkKind::DetectSpriteRegions(ref path, _, _) => {
+            format!("Detecting sprites in {}", path.display())
+        }
+        BackgroundWorkKind::Job(ref label, _) => label.clone(),
+    };
+    view_feedback_sender.send(BackgroundWorkerViewFeedback::BeginWork(
+        n,
+        label.clone(),
+        group_id,
+    ));
+
+    // Note: 作業やコールバックがpanicしてもワーカースレッドを止めないように、失敗として報告する
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| match kind {
+        BackgroundWorkKind::LoadSpriteSource(path, mut on_complete) => {
+            load_image(&path).map(|img| {
+                if !is_cancelled() {
+                    on_complete(path, img);
+                }
+            })
+        }
+        BackgroundWorkKind::DetectSpriteRegions(path, params, mut on_complete) => load_image(&path)
+            .map(|img| {
+                if !is_cancelled() {
+                    let regions = region_detect::detect(&img, &params);
+                    if !is_cancelled() {
+                        on_complete(path, regions, img);
+                    }
+                }
+            }),
+        BackgroundWorkKind::Job(label, f) => f(&BackgroundJobContext {
+            worker_index: n,
+            view_feedback_sender,
+            cancellation_token: cancellation_token.as_ref(),
+            group_member: group_member.as_ref(),
+            last_reported_progress: Cell::new(0.0),
+        })
+        .map_err(|e| BackgroundWorkError::Job(label, e)),
+    }))
+    .unwrap_or_else(|e| Err(BackgroundWorkError::Panicked(label, panic_message(&*e))));
+
+    match result {
+        Ok(()) => view_feedback_sender.send(BackgroundWorkerViewFeedback::EndWork(n)),
+        Err(e) => view_feedback_sender.send(BackgroundWorkerViewFeedback::Failed(n, e)),
+    }
+    finish();
+}
+
 pub struct BackgroundWorker {
     join_handles: Vec<JoinHandle<()>>,
-    work_queue: Arc<Injector<BackgroundWork>>,
+    work_queue: Arc<WorkQueue>,
     teardown_signal: Arc<AtomicBool>,
     view_feedback_receiver: crossbeam::channel::Receiver<BackgroundWorkerViewFeedback>,
 }
@@ -57,68 +507,27 @@ impl BackgroundWorker {
         let worker_count = std::thread::available_parallelism()
             .unwrap_or(unsafe { core::num::NonZero::new_unchecked(4) })
             .get();
-        let work_queue = Injector::new();
-        let (mut join_handles, mut local_queues, mut stealers) = (
-            Vec::with_capacity(worker_count),
-            Vec::with_capacity(worker_count),
-            Vec::with_capacity(worker_count),
-        );
-        for _ in 0..worker_count {
-            let local_queue = Worker::new_fifo();
-            stealers.push(local_queue.stealer());
-            local_queues.push(local_queue);
-        }
-        let stealers = Arc::new(stealers);
-        let work_queue = Arc::new(work_queue);
+        let work_queue = Arc::new(WorkQueue::new());
         let teardown_signal = Arc::new(AtomicBool::new(false));
         let (view_feedback_sender, view_feedback_receiver) = crossbeam::channel::unbounded();
-        for (n, local_queue) in local_queues.into_iter().enumerate() {
+        let mut join_handles = Vec::with_capacity(worker_count);
+        for n in 0..worker_count {
             join_handles.push(
                 std::thread::Builder::new()
                     .name(format!("Background Worker #{}", n + 1))
                     .spawn({
-                        let stealers = stealers.clone();
                         let work_queue = work_queue.clone();
                         let teardown_signal = teardown_signal.clone();
-                        let view_feedback_sender = view_feedback_sender.clone();
-                        let ui_thread_wakeup_event = ui_thread_wakeup_event.clone();
+                        let view_feedback_sender = ViewFeedbackSender {
+                            sender: view_feedback_sender.clone(),
+                            ui_thread_wakeup_event: ui_thread_wakeup_event.clone(),
+                        };
 
                         move || {
                             while !teardown_signal.load(Ordering::Acquire) {
-                                let next = local_queue.pop().or_else(|| {
-                                    core::iter::repeat_with(|| {
-                                        work_queue.steal_batch_and_pop(&local_queue).or_else(|| {
-                                            stealers.iter().map(|x| x.steal()).collect()
-                                        })
-                                    })
-                                    .find(|x| !x.is_retry())
-                                    .and_then(|x| x.success())
-                                });
-
-                                match next {
-                                    Some(BackgroundWork::LoadSpriteSource(path, mut on_complete)) => {
-                                        match view_feedback_sender.send(BackgroundWorkerViewFeedback::BeginWork(n, format!("Loading {}", path.display()))) {
-                                            Ok(()) => (),
-                                            Err(e) => {
-                                                tracing::warn!({?e}, "sending view feedback failed");
-                                            }
-                                        }
-                                        ui_thread_wakeup_event.signal();
-                                        let img = image::open(&path).unwrap();
-                                        on_complete(path, img);
-                                        match view_feedback_sender.send(BackgroundWorkerViewFeedback::EndWork(n)) {
-                                            Ok(()) => (),
-                                            Err(e) => {
-                                                tracing::warn!({?e}, "sending view feedback failed");
-                                            }
-                                        }
-                                        ui_thread_wakeup_event.signal();
-                                    }
-                                    None => {
-                                        // wait for new event
-                                        // TODO: 一旦sleep(1)する（本当はparkとかしてあげたほうがいい）
-                                        std::thread::yield_now();
-                                    }
+                                match work_queue.pop() {
+                                    Some(work) => process(n, work, &view_feedback_sender),
+                                    None => work_queue.park(&teardown_signal),
                                 }
                             }
                         }
@@ -165,6 +574,7 @@ impl BackgroundWorker {
 
     pub fn teardown(self) {
         self.teardown_signal.store(true, Ordering::Release);
+        self.work_queue.wake_all();
         for x in self.join_handles {
             x.join().unwrap();
         }
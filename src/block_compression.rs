@@ -0,0 +1,1080 @@
+//! CPUでのテクスチャのブロック圧縮（BC1/BC3/BC7/ETC2 RGBA8）
+//!
+//! 入力はRGBA8で、4x4テクセルのブロックを左上から行順に並べたバイト列を出力する
+//! 幅や高さが4の倍数でないときは端のテクセルを繰り返して埋める
+
+use image::RgbaImage;
+
+use crate::peridot::{CompressionQuality, SpriteAtlasAsset, TextureCompression};
+
+#[derive(Debug, thiserror::Error)]
+pub enum LayoutError {
+    #[error("atlas is empty")]
+    EmptyAtlas,
+    #[error("atlas size {0}x{1} is not a multiple of the block size")]
+    UnalignedAtlasSize(u32, u32),
+    #[error("sprites {0} and {1} share a compression block")]
+    SharedBlock(String, String),
+}
+
+/// アトラスの大きさがブロックの倍数で、どのブロックにも2つ以上のスプライトが入っていないか確かめる
+///
+/// スプライトの位置や大きさ自体はそろっていなくてもいい（隙間が透明のまま圧縮される）
+pub fn validate_layout(asset: &SpriteAtlasAsset) -> Result<(), LayoutError> {
+    // Note: 0はどのブロックの大きさの倍数にもなるので先に弾く
+    if asset.width == 0 || asset.height == 0 {
+        return Err(LayoutError::EmptyAtlas);
+    }
+
+    let block_size = asset.compression.block_size();
+    if !asset.width.is_multiple_of(block_size) || !asset.height.is_multiple_of(block_size) {
+        return Err(LayoutError::UnalignedAtlasSize(asset.width, asset.height));
+    }
+
+    let block_rects = asset
+        .sprites
+        .iter()
+        .filter(|s| s.width > 0 && s.height > 0)
+        .map(|s| {
+            (
+                s,
+                s.left / block_size,
+                s.top / block_size,
+                (s.left + s.width).div_ceil(block_size),
+                (s.top + s.height).div_ceil(block_size),
+            )
+        })
+        .collect::<Vec<_>>();
+    for (n, &(a, al, at, ar, ab)) in block_rects.iter().enumerate() {
+        for &(b, bl, bt, br, bb) in &block_rects[n + 1..] {
+            if al < br && bl < ar && at < bb && bt < ab {
+                return Err(LayoutError::SharedBlock(a.name.clone(), b.name.clone()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// ブロックの中のテクセル（行順）
+type Texels = [[u8; 4]; 16];
+
+/// 1ブロック（圧縮しないなら1テクセル）のバイト数
+pub const fn block_bytes(compression: TextureCompression) -> u32 {
+    match compression {
+        TextureCompression::None => 4,
+        TextureCompression::Bc1 => 8,
+        TextureCompression::Bc3 | TextureCompression::Bc7 | TextureCompression::Etc2 => 16,
+    }
+}
+
+pub fn compress(
+    image: &RgbaImage,
+    compression: TextureCompression,
+    quality: CompressionQuality,
+) -> Vec<u8> {
+    if compression == TextureCompression::None {
+        return image.as_raw().clone();
+    }
+    if image.width() == 0 || image.height() == 0 {
+        // ブロックが1つもない
+        return Vec::new();
+    }
+
+    let (block_width, block_height) = (image.width().div_ceil(4), image.height().div_ceil(4));
+    let row_bytes = (block_width * block_bytes(compression)) as usize;
+    let mut compressed = vec![0; row_bytes * block_height as usize];
+
+    // ブロックごとに独立しているので行単位でスレッドに分ける
+    let threads = std::thread::available_parallelism().map_or(1, |x| x.get());
+    let rows_per_thread = (block_height as usize).div_ceil(threads).max(1);
+    std::thread::scope(|s| {
+        for (n, rows) in compressed
+            .chunks_mut(row_bytes * rows_per_thread)
+            .enumerate()
+        {
+            s.spawn(move || {
+                for (r, row) in rows.chunks_mut(row_bytes).enumerate() {
+                    let by = (n * rows_per_thread + r) as u32;
+                    for (bx, block) in row
+                        .chunks_mut(block_bytes(compression) as usize)
+                        .enumerate()
+                    {
+                        let texels = block_texels(image, bx as u32 * 4, by * 4);
+                        encode_block(&texels, compression, quality, block);
+                    }
+                }
+            });
+        }
+    });
+
+    compressed
+}
+
+fn block_texels(image: &RgbaImage, left: u32, top: u32) -> Texels {
+    core::array::from_fn(|n| {
+        let (x, y) = (left + n as u32 % 4, top + n as u32 / 4);
+
+        image
+            .get_pixel(x.min(image.width() - 1), y.min(image.height() - 1))
+            .0
+    })
+}
+
+fn encode_block(
+    texels: &Texels,
+    compression: TextureCompression,
+    quality: CompressionQuality,
+    sink: &mut [u8],
+) {
+    match compression {
+        TextureCompression::None => unreachable!("not block-compressed"),
+        TextureCompression::Bc1 => sink.copy_from_slice(&encode_bc1(texels, quality, true)),
+        TextureCompression::Bc3 => {
+            sink[..8].copy_from_slice(&encode_bc3_alpha(texels, quality));
+            // Note: BC3の色ブロックは常に4色として解釈される
+            sink[8..].copy_from_slice(&encode_bc1(texels, quality, false));
+        }
+        TextureCompression::Bc7 => sink.copy_from_slice(&encode_bc7(texels, quality)),
+        TextureCompression::Etc2 => {
+            sink[..8].copy_from_slice(&encode_eac_alpha(texels, quality));
+            sink[8..].copy_from_slice(&encode_etc1(texels, quality));
+        }
+    }
+}
+
+/// 最小二乗法で端点を合わせ直すときの繰り返し回数
+const fn refine_iterations(quality: CompressionQuality) -> usize {
+    match quality {
+        CompressionQuality::Fast => 0,
+        CompressionQuality::High => 2,
+    }
+}
+
+fn squared_distance<const N: usize>(a: &[f32; N], b: &[f32; N]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// 主成分の軸上で両端にある点を端点にする
+fn principal_endpoints<const N: usize>(points: &[[f32; N]]) -> ([f32; N], [f32; N]) {
+    let count = points.len() as f32;
+    let mean: [f32; N] = core::array::from_fn(|c| points.iter().map(|p| p[c]).sum::<f32>() / count);
+    let mut covariance = [[0.0f32; N]; N];
+    for p in points {
+        for (i, row) in covariance.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x += (p[i] - mean[i]) * (p[j] - mean[j]);
+            }
+        }
+    }
+
+    // べき乗法（ブロックは16テクセルしかないので数回で十分）
+    let mut axis = [1.0f32; N];
+    for _ in 0..8 {
+        let next: [f32; N] = core::array::from_fn(|i| {
+            covariance[i]
+                .iter()
+                .zip(axis.iter())
+                .map(|(c, a)| c * a)
+                .sum()
+        });
+        let length = next.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if length < 1e-6 {
+            // 全部同じ色
+            return (mean, mean);
+        }
+        axis = next.map(|x| x / length);
+    }
+
+    let (mut min, mut max) = (f32::MAX, f32::MIN);
+    for p in points {
+        let t = p
+            .iter()
+            .zip(mean.iter())
+            .zip(axis.iter())
+            .map(|((p, m), a)| (p - m) * a)
+            .sum::<f32>();
+        min = min.min(t);
+        max = max.max(t);
+    }
+
+    (
+        core::array::from_fn(|c| (mean[c] + axis[c] * min).clamp(0.0, 255.0)),
+        core::array::from_fn(|c| (mean[c] + axis[c] * max).clamp(0.0, 255.0)),
+    )
+}
+
+/// 各点の補間の重み（0なら1つ目の端点、1なら2つ目の端点）から最小二乗法で端点を求める
+fn fit_endpoints<const N: usize>(
+    points: &[[f32; N]],
+    weights: impl Iterator<Item = f32>,
+) -> Option<([f32; N], [f32; N])> {
+    let (mut aa, mut ab, mut bb) = (0.0f32, 0.0f32, 0.0f32);
+    let (mut ax, mut bx) = ([0.0f32; N], [0.0f32; N]);
+    for (p, w) in points.iter().zip(weights) {
+        let (a, b) = (1.0 - w, w);
+        aa += a * a;
+        ab += a * b;
+        bb += b * b;
+        for c in 0..N {
+            ax[c] += a * p[c];
+            bx[c] += b * p[c];
+        }
+    }
+
+    let det = aa * bb - ab * ab;
+    if det.abs() < 1e-6 {
+        // 全部同じ重みなので決まらない
+        return None;
+    }
+
+    Some((
+        core::array::from_fn(|c| ((bb * ax[c] - ab * bx[c]) / det).clamp(0.0, 255.0)),
+        core::array::from_fn(|c| ((aa * bx[c] - ab * ax[c]) / det).clamp(0.0, 255.0)),
+    ))
+}
+
+/// 端点から符号化を試して誤差の小さいものを選ぶ
+///
+/// `encode`は符号化した結果と誤差と各点の重みを返す
+fn search_endpoints<const N: usize, B: Copy>(
+    points: &[[f32; N]],
+    quality: CompressionQuality,
+    encode: impl Fn(&[f32; N], &[f32; N]) -> (B, f32, [f32; 16]),
+) -> B {
+    let (e0, e1) = principal_endpoints(points);
+    let (mut best, mut best_error, mut weights) = encode(&e0, &e1);
+    for _ in 0..refine_iterations(quality) {
+        let Some((e0, e1)) = fit_endpoints(points, weights.iter().copied()) else {
+            break;
+        };
+        let (block, error, w) = encode(&e0, &e1);
+        if error >= best_error {
+            break;
+        }
+
+        (best, best_error, weights) = (block, error, w);
+    }
+
+    best
+}
+
+fn rgb565(color: &[f32; 3]) -> u16 {
+    let r = (color[0] * 31.0 / 255.0).round() as u16;
+    let g = (color[1] * 63.0 / 255.0).round() as u16;
+    let b = (color[2] * 31.0 / 255.0).round() as u16;
+
+    (r << 11) | (g << 5) | b
+}
+
+fn expand_rgb565(color: u16) -> [f32; 3] {
+    let (r, g, b) = (color >> 11, (color >> 5) & 0x3f, color & 0x1f);
+
+    [
+        ((r << 3) | (r >> 2)) as f32,
+        ((g << 2) | (g >> 4)) as f32,
+        ((b << 3) | (b >> 2)) as f32,
+    ]
+}
+
+/// `transparent_mode`のときはアルファが128未満のテクセルを透明（3色モード）にする
+fn encode_bc1(texels: &Texels, quality: CompressionQuality, transparent_mode: bool) -> [u8; 8] {
+    let transparent_mode = transparent_mode && texels.iter().any(|t| t[3] < 128);
+    let is_opaque = |t: &[u8; 4]| !transparent_mode || t[3] >= 128;
+    let points = texels
+        .iter()
+        .filter(|t| is_opaque(t))
+        .map(|t| [t[0] as f32, t[1] as f32, t[2] as f32])
+        .collect::<Vec<_>>();
+    if points.is_empty() {
+        // 全部透明
+        return [0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff];
+    }
+
+    search_endpoints(&points, quality, |e0, e1| {
+        let (mut c0, mut c1) = (rgb565(e0), rgb565(e1));
+        // Note: c0 > c1なら4色、c0 <= c1なら3色+透明として解釈される
+        if (c0 < c1) != transparent_mode && c0 != c1 {
+            core::mem::swap(&mut c0, &mut c1);
+        }
+        let (a, b) = (expand_rgb565(c0), expand_rgb565(c1));
+        let palette: [([f32; 3], f32); 4] = if transparent_mode || c0 == c1 {
+            [
+                (a, 0.0),
+                (b, 1.0),
+                (core::array::from_fn(|c| ((a[c] + b[c]) / 2.0).floor()), 0.5),
+                ([0.0; 3], 0.0),
+            ]
+        } else {
+            [
+                (a, 0.0),
+                (b, 1.0),
+                (
+                    core::array::from_fn(|c| ((a[c] * 2.0 + b[c]) / 3.0).floor()),
+                    1.0 / 3.0,
+                ),
+                (
+                    core::array::from_fn(|c| ((a[c] + b[c] * 2.0) / 3.0).floor()),
+                    2.0 / 3.0,
+                ),
+            ]
+        };
+        let candidates = if transparent_mode || c0 == c1 { 3 } else { 4 };
+
+        let (mut indices, mut error) = (0u32, 0.0f32);
+        let mut weights = [0.0f32; 16];
+        let mut opaque_count = 0;
+        for (n, t) in texels.iter().enumerate() {
+            if !is_opaque(t) {
+                indices |= 3 << (n * 2);
+                continue;
+            }
+
+            let p = [t[0] as f32, t[1] as f32, t[2] as f32];
+            let (i, e) = palette[..candidates]
+                .iter()
+                .map(|(c, _)| squared_distance(c, &p))
+                .enumerate()
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap();
+            indices |= (i as u32) << (n * 2);
+            error += e;
+            // 重みは端点の入れ替え前の向きにそろえる
+            let w = palette[i].1;
+            weights[opaque_count] = if rgb565(e0) == c0 { w } else { 1.0 - w };
+            opaque_count += 1;
+        }
+
+        let mut block = [0u8; 8];
+        block[0..2].copy_from_slice(&c0.to_le_bytes());
+        block[2..4].copy_from_slice(&c1.to_le_bytes());
+        block[4..8].copy_from_slice(&indices.to_le_bytes());
+
+        (block, error, weights)
+    })
+}
+
+fn encode_bc3_alpha(texels: &Texels, quality: CompressionQuality) -> [u8; 8] {
+    let alphas = texels.map(|t| t[3]);
+    let (min, max) = (*alphas.iter().min().unwrap(), *alphas.iter().max().unwrap());
+
+    let mut candidates = vec![(max, min)];
+    if quality == CompressionQuality::High {
+        // 0と255を別に持てる6段階のモードも試す
+        let inner = alphas.iter().copied().filter(|&a| a != 0 && a != u8::MAX);
+        candidates.push((inner.clone().min().unwrap_or(0), inner.max().unwrap_or(0)));
+    }
+
+    candidates
+        .into_iter()
+        .map(|(a0, a1)| encode_bc3_alpha_with(&alphas, a0, a1))
+        .min_by_key(|&(_, error)| error)
+        .unwrap()
+        .0
+}
+
+fn encode_bc3_alpha_with(alphas: &[u8; 16], a0: u8, a1: u8) -> ([u8; 8], u32) {
+    let (a, b) = (a0 as u32, a1 as u32);
+    let palette: [u32; 8] = if a0 > a1 {
+        core::array::from_fn(|i| match i {
+            0 => a,
+            1 => b,
+            _ => ((8 - i as u32) * a + (i as u32 - 1) * b) / 7,
+        })
+    } else {
+        core::array::from_fn(|i| match i {
+            0 => a,
+            1 => b,
+            6 => 0,
+            7 => 255,
+            _ => ((6 - i as u32) * a + (i as u32 - 1) * b) / 5,
+        })
+    };
+
+    let (mut indices, mut error) = (0u64, 0u32);
+    for (n, &x) in alphas.iter().enumerate() {
+        let (i, e) = palette
+            .iter()
+            .map(|&p| p.abs_diff(x as u32).pow(2))
+            .enumerate()
+            .min_by_key(|&(_, e)| e)
+            .unwrap();
+        indices |= (i as u64) << (n * 3);
+        error += e;
+    }
+
+    let mut block = [0u8; 8];
+    block[0] = a0;
+    block[1] = a1;
+    block[2..8].copy_from_slice(&indices.to_le_bytes()[..6]);
+
+    (block, error)
+}
+
+/// BC7の4bitインデックスの補間の重み（/64）
+const BC7_WEIGHTS4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// 7bit + P-bitで表せるもっとも近い値にする（P-bitは4チャンネルで共有）
+fn quantize_bc7_mode6_endpoint(e: &[f32; 4], p: u32) -> [u32; 4] {
+    core::array::from_fn(|c| ((e[c] - p as f32) / 2.0).round().clamp(0.0, 127.0) as u32 * 2 + p)
+}
+
+fn nearest_p_bit(e: &[f32; 4]) -> u32 {
+    [0, 1]
+        .into_iter()
+        .min_by(|&a, &b| {
+            let error =
+                |p| squared_distance(&quantize_bc7_mode6_endpoint(e, p).map(|x| x as f32), e);
+
+            error(a).total_cmp(&error(b))
+        })
+        .unwrap()
+}
+
+/// BC7はモード6（1サブセット、RGBA 7bit + P-bit、4bitインデックス）だけで符号化する
+fn encode_bc7(texels: &Texels, quality: CompressionQuality) -> [u8; 16] {
+    let points = texels.map(|t| t.map(|x| x as f32));
+
+    search_endpoints(&points, quality, |e0, e1| {
+        // Note: 端点ごとにいちばん近いP-bitを選ぶだけだと、単色のときに0と255を同時に表せない
+        let p_bits = match quality {
+            CompressionQuality::Fast => vec![(nearest_p_bit(e0), nearest_p_bit(e1))],
+            CompressionQuality::High => vec![(0, 0), (0, 1), (1, 0), (1, 1)],
+        };
+
+        p_bits
+            .into_iter()
+            .map(|(p0, p1)| {
+                encode_bc7_mode6(
+                    &points,
+                    quantize_bc7_mode6_endpoint(e0, p0),
+                    quantize_bc7_mode6_endpoint(e1, p1),
+                )
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap()
+    })
+}
+
+fn encode_bc7_mode6(
+    points: &[[f32; 4]; 16],
+    mut q0: [u32; 4],
+    mut q1: [u32; 4],
+) -> ([u8; 16], f32, [f32; 16]) {
+    let palette: [[f32; 4]; 16] = core::array::from_fn(|i| {
+        core::array::from_fn(|c| {
+            (((64 - BC7_WEIGHTS4[i]) * q0[c] + BC7_WEIGHTS4[i] * q1[c] + 32) >> 6) as f32
+        })
+    });
+
+    let (mut indices, mut error) = ([0u32; 16], 0.0f32);
+    for (n, p) in points.iter().enumerate() {
+        let (i, e) = palette
+            .iter()
+            .map(|c| squared_distance(c, p))
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        indices[n] = i as _;
+        error += e;
+    }
+    let weights = indices.map(|i| BC7_WEIGHTS4[i as usize] as f32 / 64.0);
+
+    // Note: 先頭のテクセルのインデックスは最上位ビットを省略するので0～7に収める
+    if indices[0] >= 8 {
+        core::mem::swap(&mut q0, &mut q1);
+        indices = indices.map(|i| 15 - i);
+    }
+
+    let mut bits = BitWriter::default();
+    bits.push(1 << 6, 7);
+    for c in 0..4 {
+        bits.push(q0[c] >> 1, 7);
+        bits.push(q1[c] >> 1, 7);
+    }
+    bits.push(q0[0] & 1, 1);
+    bits.push(q1[0] & 1, 1);
+    bits.push(indices[0], 3);
+    for &i in &indices[1..] {
+        bits.push(i, 4);
+    }
+
+    (bits.0.to_le_bytes(), error, weights)
+}
+
+/// 下位ビットから順に詰める
+#[derive(Default)]
+struct BitWriter(u128, u32);
+impl BitWriter {
+    fn push(&mut self, value: u32, bits: u32) {
+        self.0 |= (value as u128) << self.1;
+        self.1 += bits;
+    }
+}
+
+/// ETC1の輝度の修正値の表（a, b: インデックスが0..3のとき+a, +b, -a, -b）
+const ETC1_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// ETC1のサブブロック（2x4か4x2）に入るテクセル（行順のインデックス）
+fn etc1_subblock(flip: bool, second: bool) -> impl Iterator<Item = usize> {
+    (0..16).filter(move |n| {
+        let (x, y) = (n % 4, n / 4);
+        let v = if flip { y } else { x };
+
+        (v >= 2) == second
+    })
+}
+
+/// サブブロックをベースの色で符号化したときの誤差と表の番号と各テクセルのインデックス
+fn encode_etc1_subblock(
+    texels: &Texels,
+    flip: bool,
+    second: bool,
+    base: [i32; 3],
+) -> (u32, u32, [u32; 16]) {
+    ETC1_MODIFIERS
+        .iter()
+        .enumerate()
+        .map(|(table, &[a, b])| {
+            let (mut error, mut indices) = (0u32, [0u32; 16]);
+            for n in etc1_subblock(flip, second) {
+                let (i, e) = [a, b, -a, -b]
+                    .into_iter()
+                    .map(|m| {
+                        (0..3)
+                            .map(|c| {
+                                (base[c] + m)
+                                    .clamp(0, 255)
+                                    .abs_diff(texels[n][c] as i32)
+                                    .pow(2)
+                            })
+                            .sum::<u32>()
+                    })
+                    .enumerate()
+                    .min_by_key(|&(_, e)| e)
+                    .unwrap();
+                indices[n] = i as _;
+                error += e;
+            }
+
+            (error, table as u32, indices)
+        })
+        .min_by_key(|&(e, _, _)| e)
+        .unwrap()
+}
+
+/// ETC2のRGBはETC1互換のindividual/differentialモードだけで符号化する
+fn encode_etc1(texels: &Texels, quality: CompressionQuality) -> [u8; 8] {
+    // 高品質のときはベースの色を明るさ方向に少しずらしたものも試す
+    let shifts: &[i32] = match quality {
+        CompressionQuality::Fast => &[0],
+        CompressionQuality::High => &[0, -1, 1],
+    };
+
+    // (誤差, 64bitのブロック)
+    let mut best = (u32::MAX, 0u64);
+    for flip in [false, true] {
+        let averages = [false, true].map(|second| {
+            let (mut sum, mut count) = ([0.0f32; 3], 0.0f32);
+            for n in etc1_subblock(flip, second) {
+                for c in 0..3 {
+                    sum[c] += texels[n][c] as f32;
+                }
+                count += 1.0;
+            }
+
+            sum.map(|x| x / count)
+        });
+
+        // differential: 5bit + 3bitの差分、individual: 4bitずつ
+        let mut found_differential = false;
+        for differential in [true, false] {
+            if !differential && found_differential && quality == CompressionQuality::Fast {
+                continue;
+            }
+
+            let bits = if differential { 5 } else { 4 };
+            let max = (1 << bits) - 1;
+            let expand = |x: i32| (x << (8 - bits)) | (x >> (2 * bits - 8));
+            // Note: サブブロックごとの誤差は独立しているので先に計算しておく
+            let candidates = [false, true].map(|second| {
+                let average = averages[second as usize];
+                shifts
+                    .iter()
+                    .map(|&s| {
+                        let base = average
+                            .map(|x| ((x * max as f32 / 255.0).round() as i32 + s).clamp(0, max));
+
+                        (
+                            base,
+                            encode_etc1_subblock(texels, flip, second, base.map(expand)),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+            for (b0, (e0, t0, i0)) in candidates[0].iter() {
+                for (b1, (e1, t1, i1)) in candidates[1].iter() {
+                    let d: [i32; 3] = core::array::from_fn(|c| b1[c] - b0[c]);
+                    if differential && d.iter().any(|d| !(-4..=3).contains(d)) {
+                        continue;
+                    }
+                    found_differential |= differential;
+                    if e0 + e1 >= best.0 {
+                        continue;
+                    }
+
+                    let mut colors = 0u64;
+                    for c in 0..3 {
+                        let byte = if differential {
+                            (b0[c] as u64) << 3 | (d[c] as u64 & 0x7)
+                        } else {
+                            (b0[c] as u64) << 4 | b1[c] as u64
+                        };
+                        colors |= byte << (56 - c * 8);
+                    }
+                    best = (
+                        e0 + e1,
+                        colors | etc1_indices_and_flags(*t0, *t1, differential, flip, i0, i1),
+                    );
+                }
+            }
+        }
+    }
+
+    best.1.to_be_bytes()
+}
+
+/// ETC1ブロックの下位40bit（表の番号、diff/flipビット、インデックス）
+fn etc1_indices_and_flags(
+    table0: u32,
+    table1: u32,
+    differential: bool,
+    flip: bool,
+    indices0: &[u32; 16],
+    indices1: &[u32; 16],
+) -> u64 {
+    let mut bits = (table0 as u64) << 37
+        | (table1 as u64) << 34
+        | (differential as u64) << 33
+        | (flip as u64) << 32;
+    for n in 0..16 {
+        let i = indices0[n] | indices1[n];
+        // Note: インデックスは列順で、上位ビットと下位ビットが別の16bitに入る
+        let (x, y) = (n % 4, n / 4);
+        let position = x * 4 + y;
+        bits |= ((i >> 1) as u64) << (16 + position) | ((i & 1) as u64) << position;
+    }
+
+    bits
+}
+
+/// EACの修正値の表（インデックス0..7）
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+fn encode_eac_alpha(texels: &Texels, quality: CompressionQuality) -> [u8; 8] {
+    let alphas = texels.map(|t| t[3] as i32);
+    let (min, max) = (*alphas.iter().min().unwrap(), *alphas.iter().max().unwrap());
+    let (multiplier_shifts, base_shifts): (&[i32], &[i32]) = match quality {
+        CompressionQuality::Fast => (&[0], &[0]),
+        CompressionQuality::High => (&[-1, 0, 1], &[-2, -1, 0, 1, 2]),
+    };
+
+    // (誤差, 64bitのブロック)
+    let mut best = (u32::MAX, 0u64);
+    for (table, modifiers) in EAC_MODIFIERS.iter().enumerate() {
+        let (low, high) = (modifiers[3], modifiers[7]);
+        let multiplier = ((max - min) as f32 / (high - low) as f32).round() as i32;
+        for &ms in multiplier_shifts {
+            // Note: 0倍はRGBA8のアルファでは使わない
+            let multiplier = (multiplier + ms).clamp(1, 15);
+            // 表の範囲の中心をアルファの範囲の中心に合わせる
+            let base = ((min + max) as f32 / 2.0 - (low + high) as f32 * multiplier as f32 / 2.0)
+                .round() as i32;
+            for &bs in base_shifts {
+                let base = (base + bs).clamp(0, 255);
+
+                let (mut error, mut indices) = (0u32, 0u64);
+                for (n, &a) in alphas.iter().enumerate() {
+                    let (i, e) = modifiers
+                        .iter()
+                        .map(|m| (base + m * multiplier).clamp(0, 255).abs_diff(a).pow(2))
+                        .enumerate()
+                        .min_by_key(|&(_, e)| e)
+                        .unwrap();
+                    let (x, y) = (n % 4, n / 4);
+                    indices |= (i as u64) << (45 - (x * 4 + y) * 3);
+                    error += e;
+                }
+                if error < best.0 {
+                    best = (
+                        error,
+                        (base as u64) << 56
+                            | (multiplier as u64) << 52
+                            | (table as u64) << 48
+                            | indices,
+                    );
+                }
+            }
+        }
+    }
+
+    best.1.to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_bc1_colors(block: &[u8], four_colors_only: bool) -> Texels {
+        let (c0, c1) = (
+            u16::from_le_bytes([block[0], block[1]]),
+            u16::from_le_bytes([block[2], block[3]]),
+        );
+        let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+        let (a, b) = (expand_rgb565(c0), expand_rgb565(c1));
+        let rgba = |c: [f32; 3]| [c[0] as u8, c[1] as u8, c[2] as u8, 255];
+        let palette = if c0 > c1 || four_colors_only {
+            [
+                rgba(a),
+                rgba(b),
+                rgba(core::array::from_fn(|c| (a[c] * 2.0 + b[c]) / 3.0)),
+                rgba(core::array::from_fn(|c| (a[c] + b[c] * 2.0) / 3.0)),
+            ]
+        } else {
+            [
+                rgba(a),
+                rgba(b),
+                rgba(core::array::from_fn(|c| (a[c] + b[c]) / 2.0)),
+                [0; 4],
+            ]
+        };
+
+        core::array::from_fn(|n| palette[(indices >> (n * 2)) as usize & 3])
+    }
+
+    fn decode_bc3_alpha(block: &[u8]) -> [u8; 16] {
+        let (a, b) = (block[0] as u32, block[1] as u32);
+        let palette: [u32; 8] = if a > b {
+            core::array::from_fn(|i| match i {
+                0 => a,
+                1 => b,
+                _ => ((8 - i as u32) * a + (i as u32 - 1) * b) / 7,
+            })
+        } else {
+            core::array::from_fn(|i| match i {
+                0 => a,
+                1 => b,
+                6 => 0,
+                7 => 255,
+                _ => ((6 - i as u32) * a + (i as u32 - 1) * b) / 5,
+            })
+        };
+        let mut bits = [0u8; 8];
+        bits[..6].copy_from_slice(&block[2..8]);
+        let indices = u64::from_le_bytes(bits);
+
+        core::array::from_fn(|n| palette[(indices >> (n * 3)) as usize & 7] as u8)
+    }
+
+    fn decode_bc7_mode6(block: &[u8]) -> Texels {
+        let bits = u128::from_le_bytes(block.try_into().unwrap());
+        let mut cursor = 0;
+        let mut read = |n: u32| {
+            let v = (bits >> cursor) as u32 & ((1 << n) - 1);
+            cursor += n;
+            v
+        };
+        assert_eq!(read(7), 1 << 6, "mode 6");
+        let mut endpoints = [[0u32; 4]; 2];
+        for c in 0..4 {
+            endpoints[0][c] = read(7) << 1;
+            endpoints[1][c] = read(7) << 1;
+        }
+        let (p0, p1) = (read(1), read(1));
+        endpoints[0] = endpoints[0].map(|x| x | p0);
+        endpoints[1] = endpoints[1].map(|x| x | p1);
+        let indices: [u32; 16] = core::array::from_fn(|n| read(if n == 0 { 3 } else { 4 }));
+
+        indices.map(|i| {
+            let w = BC7_WEIGHTS4[i as usize];
+            core::array::from_fn(|c| {
+                (((64 - w) * endpoints[0][c] + w * endpoints[1][c] + 32) >> 6) as u8
+            })
+        })
+    }
+
+    /// ETC1互換のモード（individual/differential）だけ読む
+    fn decode_etc1(block: &[u8]) -> [[u8; 3]; 16] {
+        let bits = u64::from_be_bytes(block.try_into().unwrap());
+        let field = |shift: u32, len: u32| ((bits >> shift) & ((1 << len) - 1)) as i32;
+        let (differential, flip) = (field(33, 1) == 1, field(32, 1) == 1);
+        let bases: [[i32; 3]; 2] = if differential {
+            let b0: [i32; 3] = core::array::from_fn(|c| field(59 - c as u32 * 8, 5));
+            let b1: [i32; 3] = core::array::from_fn(|c| {
+                let d = field(56 - c as u32 * 8, 3);
+                b0[c] + if d >= 4 { d - 8 } else { d }
+            });
+            assert!(
+                b1.iter().all(|x| (0..32).contains(x)),
+                "T/H/planar modes are not emitted"
+            );
+
+            [b0, b1].map(|b| b.map(|x| (x << 3) | (x >> 2)))
+        } else {
+            [
+                core::array::from_fn(|c| field(60 - c as u32 * 8, 4) * 17),
+                core::array::from_fn(|c| field(56 - c as u32 * 8, 4) * 17),
+            ]
+        };
+        let tables = [field(37, 3), field(34, 3)];
+
+        core::array::from_fn(|n| {
+            let (x, y) = (n % 4, n / 4);
+            let second = if flip { y >= 2 } else { x >= 2 } as usize;
+            let p = (x * 4 + y) as u32;
+            let index = (field(16 + p, 1) << 1) | field(p, 1);
+            let [a, b] = ETC1_MODIFIERS[tables[second] as usize];
+            let m = [a, b, -a, -b][index as usize];
+
+            bases[second].map(|c| (c + m).clamp(0, 255) as u8)
+        })
+    }
+
+    fn decode_eac_alpha(block: &[u8]) -> [u8; 16] {
+        let bits = u64::from_be_bytes(block.try_into().unwrap());
+        let (base, multiplier, table) = (
+            (bits >> 56) as i32,
+            ((bits >> 52) & 0xf) as i32,
+            ((bits >> 48) & 0xf) as usize,
+        );
+
+        core::array::from_fn(|n| {
+            let (x, y) = (n % 4, n / 4);
+            let i = (bits >> (45 - (x * 4 + y) * 3)) as usize & 7;
+
+            (base + EAC_MODIFIERS[table][i] * multiplier).clamp(0, 255) as u8
+        })
+    }
+
+    fn decode_block(block: &[u8], compression: TextureCompression) -> Texels {
+        match compression {
+            TextureCompression::None => unreachable!("not block-compressed"),
+            TextureCompression::Bc1 => decode_bc1_colors(block, false),
+            TextureCompression::Bc3 => {
+                let alphas = decode_bc3_alpha(&block[..8]);
+                let mut texels = decode_bc1_colors(&block[8..], true);
+                for (t, a) in texels.iter_mut().zip(alphas) {
+                    t[3] = a;
+                }
+
+                texels
+            }
+            TextureCompression::Bc7 => decode_bc7_mode6(block),
+            TextureCompression::Etc2 => {
+                let alphas = decode_eac_alpha(&block[..8]);
+                let colors = decode_etc1(&block[8..]);
+
+                core::array::from_fn(|n| {
+                    let [r, g, b] = colors[n];
+                    [r, g, b, alphas[n]]
+                })
+            }
+        }
+    }
+
+    fn decode(data: &[u8], width: u32, height: u32, compression: TextureCompression) -> RgbaImage {
+        let block_width = width.div_ceil(4);
+        let mut image = RgbaImage::new(width, height);
+        for (n, block) in data
+            .chunks_exact(block_bytes(compression) as usize)
+            .enumerate()
+        {
+            let (bx, by) = (n as u32 % block_width, n as u32 / block_width);
+            for (t, texel) in decode_block(block, compression).into_iter().enumerate() {
+                let (x, y) = (bx * 4 + t as u32 % 4, by * 4 + t as u32 / 4);
+                if x < width && y < height {
+                    image.put_pixel(x, y, image::Rgba(texel));
+                }
+            }
+        }
+
+        image
+    }
+
+    const COMPRESSIONS: [TextureCompression; 4] = [
+        TextureCompression::Bc1,
+        TextureCompression::Bc3,
+        TextureCompression::Bc7,
+        TextureCompression::Etc2,
+    ];
+    const QUALITIES: [CompressionQuality; 2] = [CompressionQuality::Fast, CompressionQuality::High];
+
+    /// 圧縮してから戻したときのチャンネルごとの最大の誤差
+    fn round_trip_error(
+        image: &RgbaImage,
+        compression: TextureCompression,
+        quality: CompressionQuality,
+    ) -> u8 {
+        let data = compress(image, compression, quality);
+        assert_eq!(
+            data.len(),
+            (image.width().div_ceil(4) * image.height().div_ceil(4) * block_bytes(compression))
+                as usize
+        );
+        let decoded = decode(&data, image.width(), image.height(), compression);
+
+        image
+            .pixels()
+            .zip(decoded.pixels())
+            .flat_map(|(a, b)| (0..4).map(move |c| a.0[c].abs_diff(b.0[c])))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn solid_color_round_trip() {
+        // 端のブロックが半端になる大きさ
+        let image = RgbaImage::from_pixel(6, 5, image::Rgba([200, 100, 50, 255]));
+        for compression in COMPRESSIONS {
+            for quality in QUALITIES {
+                let error = round_trip_error(&image, compression, quality);
+                assert!(error <= 4, "{compression:?} {quality:?}: {error}");
+            }
+        }
+    }
+
+    #[test]
+    fn two_color_round_trip() {
+        // ブロックごとに左半分と右半分で色を変える
+        let image = RgbaImage::from_fn(8, 8, |x, _| {
+            if x % 4 < 2 {
+                image::Rgba([240, 32, 16, 255])
+            } else {
+                image::Rgba([16, 64, 224, 255])
+            }
+        });
+        for compression in COMPRESSIONS {
+            for quality in QUALITIES {
+                let error = round_trip_error(&image, compression, quality);
+                assert!(error <= 8, "{compression:?} {quality:?}: {error}");
+            }
+        }
+    }
+
+    #[test]
+    fn alpha_gradient_round_trip() {
+        let image = RgbaImage::from_fn(16, 4, |x, _| image::Rgba([128, 128, 128, x as u8 * 17]));
+        for compression in [
+            TextureCompression::Bc3,
+            TextureCompression::Bc7,
+            TextureCompression::Etc2,
+        ] {
+            for quality in QUALITIES {
+                let error = round_trip_error(&image, compression, quality);
+                assert!(error <= 6, "{compression:?} {quality:?}: {error}");
+            }
+        }
+
+        // BC1は1bitのアルファなので、半分より薄いところが透明になる
+        for quality in QUALITIES {
+            let data = compress(&image, TextureCompression::Bc1, quality);
+            let decoded = decode(&data, 16, 4, TextureCompression::Bc1);
+            for (a, b) in image.pixels().zip(decoded.pixels()) {
+                assert_eq!(b.0[3], if a.0[3] < 128 { 0 } else { 255 });
+                if b.0[3] == 255 {
+                    assert!((0..3).all(|c| a.0[c].abs_diff(b.0[c]) <= 8));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_image_has_no_blocks() {
+        for compression in COMPRESSIONS {
+            assert!(
+                compress(&RgbaImage::new(0, 0), compression, CompressionQuality::Fast).is_empty()
+            );
+            assert!(
+                compress(&RgbaImage::new(8, 0), compression, CompressionQuality::Fast).is_empty()
+            );
+        }
+    }
+
+    fn asset(width: u32, height: u32, sprites: &[(u32, u32, u32, u32)]) -> SpriteAtlasAsset {
+        SpriteAtlasAsset {
+            sprites: sprites
+                .iter()
+                .enumerate()
+                .map(|(n, &(left, top, width, height))| crate::peridot::Sprite {
+                    id: uuid::Uuid::from_u128(n as _),
+                    name: format!("s{n}"),
+                    source_path: "a.png".into(),
+                    source_left: 0,
+                    source_top: 0,
+                    width,
+                    height,
+                    left,
+                    top,
+                    border_left: 0,
+                    border_top: 0,
+                    border_right: 0,
+                    border_bottom: 0,
+                    pivot_x: 0.5,
+                    pivot_y: 0.5,
+                    group: String::new(),
+                })
+                .collect(),
+            animations: Vec::new(),
+            width,
+            height,
+            alpha_mode: crate::peridot::AlphaMode::Straight,
+            mip_levels: 1,
+            compression: TextureCompression::Bc7,
+            compression_quality: CompressionQuality::Fast,
+        }
+    }
+
+    #[test]
+    fn layout_is_validated() {
+        assert!(validate_layout(&asset(16, 16, &[(0, 0, 4, 4), (4, 0, 3, 8)])).is_ok());
+        assert!(matches!(
+            validate_layout(&asset(0, 0, &[])),
+            Err(LayoutError::EmptyAtlas)
+        ));
+        assert!(matches!(
+            validate_layout(&asset(18, 16, &[])),
+            Err(LayoutError::UnalignedAtlasSize(18, 16))
+        ));
+        assert!(matches!(
+            validate_layout(&asset(16, 16, &[(0, 0, 5, 4), (5, 0, 4, 4)])),
+            Err(LayoutError::SharedBlock(..))
+        ));
+    }
+}
@@ -0,0 +1,451 @@
+//! Command line subcommands(ウィンドウを開かずに処理する)
+
+use std::{
+    ffi::OsString,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    asset_diff, asset_lint, asset_merge,
+    atlas_image::{self, MipFilter, SampleFormat},
+    block_compression, gdx_atlas,
+    grid_slice::{self, GridSliceParams},
+    peridot, rust_codegen, sprite_packing,
+    texture_container::{self, ContainerFormat},
+};
+
+const USAGE: &str = "\
+usage:
+  peridot-sprite-atlas-visualizer export-atlas <input.psa> <output.atlas> [--mip-format dds|ktx2|png] [--mip-filter box|kaiser]
+  peridot-sprite-atlas-visualizer pack <input.psa> <mip_levels> <output.psa>
+  peridot-sprite-atlas-visualizer diff <old.psa> <new.psa> [--json]
+  peridot-sprite-atlas-visualizer merge <base.psa> <ours.psa> <theirs.psa> [<output.psa>]
+  peridot-sprite-atlas-visualizer lint <input.psa> [--json] [--deny-warnings]
+  peridot-sprite-atlas-visualizer import-atlas <input.atlas> <output.psa>
+  peridot-sprite-atlas-visualizer gen-rust <input.psa> <output.rs>
+  peridot-sprite-atlas-visualizer slice-grid <sheet.png> <cell_width> <cell_height> <margin> <spacing> <output.psa>";
+
+/// サブコマンドが指定されていればそれを実行して終了コードを返す
+pub fn try_run(args: impl IntoIterator<Item = OsString>) -> Option<i32> {
+    let mut args = args.into_iter().skip(1);
+    let subcommand = args.next()?;
+    let args = args.collect::<Vec<_>>();
+
+    let r = match subcommand.to_str() {
+        Some("export-atlas") => export_atlas(&args),
+        Some("import-atlas") => import_atlas(&args),
+        Some("gen-rust") => gen_rust(&args),
+        Some("slice-grid") => slice_grid(&args),
+        Some("pack") => pack(&args),
+        Some("diff") => diff(&args),
+        Some("merge") => merge(&args),
+        Some("lint") => lint(&args),
+        _ => {
+            eprintln!("unknown subcommand: {}\n{USAGE}", subcommand.display());
+            return Some(2);
+        }
+    };
+
+    match r {
+        Ok(()) => Some(0),
+        Err(CommandError::Usage) => {
+            eprintln!("{USAGE}");
+            Some(2)
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            Some(1)
+        }
+    }
+}
+
+fn read_psa(path: &PathBuf) -> Result<peridot::SpriteAtlasAsset, CommandError> {
+    Ok(peridot::SpriteAtlasAsset::read(
+        &mut std::io::BufReader::new(std::fs::File::open(path)?),
+    )?)
+}
+
+/// Note: BufWriterは捨てるときの書き込みの失敗を報告しないので、明示的にflushする
+fn write_psa(path: &Path, asset: &peridot::SpriteAtlasAsset) -> Result<(), CommandError> {
+    let mut sink = std::io::BufWriter::new(std::fs::File::create(path)?);
+    asset.write(&mut sink)?;
+    sink.flush()?;
+
+    Ok(())
+}
+
+fn parse_number_arg(arg: &OsString, name: &'static str) -> Result<u32, CommandError> {
+    arg.to_str()
+        .and_then(|x| x.parse().ok())
+        .ok_or(CommandError::InvalidNumber(name))
+}
+
+/// ミップマップ付き・ブロック圧縮したページの書き出し先
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MipOutput {
+    Container(ContainerFormat),
+    /// レベルごとに別の画像ファイル（`{名前}_mip{レベル}.png`など）
+    SeparateFiles,
+}
+
+fn export_atlas(args: &[OsString]) -> Result<(), CommandError> {
+    let [input, output, options @ ..] = args else {
+        return Err(CommandError::Usage);
+    };
+    let (input, output) = (PathBuf::from(input), PathBuf::from(output));
+
+    let mut mip_output = MipOutput::Container(ContainerFormat::Dds);
+    let mut mip_filter = MipFilter::Kaiser;
+    for option in options.chunks(2) {
+        let [name, value] = option else {
+            return Err(CommandError::Usage);
+        };
+        let value = value.to_str().ok_or(CommandError::Usage)?;
+        match name.to_str() {
+            Some("--mip-format") => {
+                mip_output = match value {
+                    "png" => MipOutput::SeparateFiles,
+                    _ => MipOutput::Container(
+                        ContainerFormat::parse(value).ok_or(CommandError::Usage)?,
+                    ),
+                };
+            }
+            Some("--mip-filter") => {
+                mip_filter = MipFilter::parse(value).ok_or(CommandError::Usage)?;
+            }
+            _ => return Err(CommandError::Usage),
+        }
+    }
+
+    let asset = read_psa(&input)?;
+    let compressed = asset.compression != peridot::TextureCompression::None;
+    if compressed {
+        if mip_output == MipOutput::SeparateFiles {
+            return Err(CommandError::CompressedSeparateFiles);
+        }
+        block_compression::validate_layout(&asset)?;
+    }
+    let use_container = asset.mip_levels > 1 || compressed;
+
+    // ページ画像は.atlasと同名で、ソース画像の精度に合わせて16bit PNGかEXRとして出力する
+    let page = atlas_image::compose(&asset)?;
+    let page_extension = SampleFormat::of(page.color()).page_extension();
+    let page_path = match mip_output {
+        // Note: コンテナに入れるときは.atlasからはコンテナを参照する
+        MipOutput::Container(f) if use_container => output.with_extension(f.extension()),
+        _ => output.with_extension(page_extension),
+    };
+    let page_name = page_path
+        .file_name()
+        .ok_or(CommandError::Usage)?
+        .to_string_lossy();
+
+    let mut sink = std::io::BufWriter::new(std::fs::File::create(&output)?);
+    gdx_atlas::write(&asset, &page_name, &mut sink)?;
+    sink.flush()?;
+
+    if !use_container {
+        page.save(&page_path)?;
+        return Ok(());
+    }
+
+    let levels = atlas_image::mip_chain(&page, asset.mip_levels, asset.alpha_mode, mip_filter);
+    match mip_output {
+        MipOutput::Container(f) => {
+            let mut sink = std::io::BufWriter::new(std::fs::File::create(&page_path)?);
+            texture_container::write(
+                f,
+                &levels,
+                asset.alpha_mode,
+                asset.compression,
+                asset.compression_quality,
+                &mut sink,
+            )?;
+            sink.flush()?;
+        }
+        MipOutput::SeparateFiles => {
+            let stem = output
+                .file_stem()
+                .ok_or(CommandError::Usage)?
+                .to_string_lossy();
+            page.save(&page_path)?;
+            for (n, l) in levels.iter().enumerate().skip(1) {
+                l.save(output.with_file_name(format!("{stem}_mip{n}.{page_extension}")))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn import_atlas(args: &[OsString]) -> Result<(), CommandError> {
+    let [input, output] = args else {
+        return Err(CommandError::Usage);
+    };
+    let (input, output) = (PathBuf::from(input), PathBuf::from(output));
+
+    let source_dir = input
+        .parent()
+        .map_or_else(PathBuf::new, |p| p.to_path_buf());
+    let asset = gdx_atlas::read(
+        &mut std::io::BufReader::new(std::fs::File::open(&input)?),
+        &source_dir,
+    )?;
+
+    write_psa(&output, &asset)?;
+
+    Ok(())
+}
+
+fn gen_rust(args: &[OsString]) -> Result<(), CommandError> {
+    let [input, output] = args else {
+        return Err(CommandError::Usage);
+    };
+    let (input, output) = (PathBuf::from(input), PathBuf::from(output));
+
+    let asset = read_psa(&input)?;
+    let source_name = input
+        .file_name()
+        .ok_or(CommandError::Usage)?
+        .to_string_lossy();
+
+    // 生成に失敗したときに中途半端なファイルが残らないように一旦メモリ上に書き出す
+    let mut code = Vec::new();
+    rust_codegen::write(&asset, &source_name, &mut code)?;
+    std::fs::write(&output, code)?;
+
+    Ok(())
+}
+
+fn slice_grid(args: &[OsString]) -> Result<(), CommandError> {
+    let [sheet, cell_width, cell_height, margin, spacing, output] = args else {
+        return Err(CommandError::Usage);
+    };
+    let params = GridSliceParams {
+        cell_width: parse_number_arg(cell_width, "cell_width")?,
+        cell_height: parse_number_arg(cell_height, "cell_height")?,
+        margin: parse_number_arg(margin, "margin")?,
+        spacing: parse_number_arg(spacing, "spacing")?,
+    };
+    let (sheet, output) = (std::path::absolute(sheet)?, PathBuf::from(output));
+
+    let cells = grid_slice::non_empty_cells(&image::open(&sheet)?, &params);
+
+    // 既存のアセットがあればそこに追加する
+    let mut asset = if output.exists() {
+        read_psa(&output)?
+    } else {
+        peridot::SpriteAtlasAsset {
+            sprites: Vec::new(),
+            animations: Vec::new(),
+            width: 32,
+            height: 32,
+            alpha_mode: peridot::AlphaMode::Straight,
+            mip_levels: 1,
+            compression: peridot::TextureCompression::None,
+            compression_quality: peridot::CompressionQuality::Fast,
+        }
+    };
+    let stem = sheet
+        .file_stem()
+        .ok_or(CommandError::Usage)?
+        .to_string_lossy();
+    // 同じシートから取り込み済みなら続きの番号にする
+    let first_index =
+        grid_slice::next_name_index(&stem, asset.sprites.iter().map(|x| x.name.as_str()));
+    // 置いてあるスプライトと重ならないように、空いているところへ置く
+    let layout = sprite_packing::place_around(
+        &asset
+            .sprites
+            .iter()
+            .map(|x| [x.left, x.top, x.width, x.height])
+            .collect::<Vec<_>>(),
+        &vec![(params.cell_width, params.cell_height); cells.len()],
+        asset.width.max(asset.height),
+        AppState::MAX_ATLAS_SIZE,
+    )?;
+    for (c, (left, top)) in cells.iter().zip(layout.positions) {
+        asset.sprites.push(peridot::Sprite {
+            id: Uuid::new_v4(),
+            name: format!("{stem}_{}", first_index + c.index),
+            source_path: sheet.clone(),
+            source_left: c.left,
+            source_top: c.top,
+            width: params.cell_width,
+            height: params.cell_height,
+            left,
+            top,
+            border_left: 0,
+            border_top: 0,
+            border_right: 0,
+            border_bottom: 0,
+            pivot_x: peridot::Sprite::DEFAULT_PIVOT.0,
+            pivot_y: peridot::Sprite::DEFAULT_PIVOT.1,
+            group: String::new(),
+        });
+
+        // Power of Twoに丸める（AppState::add_spritesと同じ）
+        asset.width = asset
+            .width
+            .max(left + params.cell_width)
+            .next_power_of_two();
+        asset.height = asset
+            .height
+            .max(top + params.cell_height)
+            .next_power_of_two();
+    }
+    asset.sprites.sort_by_key(|x| x.id);
+
+    write_psa(&output, &asset)?;
+    println!("{} sprites added", cells.len());
+
+    Ok(())
+}
+
+fn pack(args: &[OsString]) -> Result<(), CommandError> {
+    let [input, mip_levels, output] = args else {
+        return Err(CommandError::Usage);
+    };
+    let mip_levels = parse_number_arg(mip_levels, "mip_levels")?;
+    if !(1..=peridot::MAX_MIP_LEVELS).contains(&mip_levels) {
+        return Err(CommandError::InvalidNumber("mip_levels"));
+    }
+    let (input, output) = (PathBuf::from(input), PathBuf::from(output));
+
+    let mut asset = read_psa(&input)?;
+    let layout = sprite_packing::pack(
+        &asset
+            .sprites
+            .iter()
+            .map(|x| (x.width, x.height))
+            .collect::<Vec<_>>(),
+        mip_levels,
+        asset.compression.block_size(),
+        AppState::MAX_ATLAS_SIZE,
+    )?;
+    for (x, (left, top)) in asset.sprites.iter_mut().zip(layout.positions) {
+        x.left = left;
+        x.top = top;
+    }
+    asset.width = layout.size;
+    asset.height = layout.size;
+    asset.mip_levels = mip_levels;
+
+    write_psa(&output, &asset)?;
+    println!("packed into {0}x{0}", layout.size);
+
+    Ok(())
+}
+
+fn diff(args: &[OsString]) -> Result<(), CommandError> {
+    let (old, new, json) = match args {
+        [old, new] => (old, new, false),
+        [old, new, flag] if flag == "--json" => (old, new, true),
+        _ => return Err(CommandError::Usage),
+    };
+
+    let d = asset_diff::diff(
+        &read_psa(&PathBuf::from(old))?,
+        &read_psa(&PathBuf::from(new))?,
+    );
+    let mut sink = std::io::stdout().lock();
+    if json {
+        d.write_json(&mut sink)?;
+    } else {
+        d.write_text(&mut sink)?;
+    }
+
+    Ok(())
+}
+
+/// git merge driverとして`merge %O %A %B`の形で使える（出力先を省略するとoursに書き戻す）
+///
+/// 衝突したときは何も書き換えずに失敗する
+fn merge(args: &[OsString]) -> Result<(), CommandError> {
+    let (base, ours, theirs, output) = match args {
+        [base, ours, theirs] => (base, ours, theirs, ours),
+        [base, ours, theirs, output] => (base, ours, theirs, output),
+        _ => return Err(CommandError::Usage),
+    };
+
+    let merged = asset_merge::merge(
+        &read_psa(&PathBuf::from(base))?,
+        &read_psa(&PathBuf::from(ours))?,
+        &read_psa(&PathBuf::from(theirs))?,
+    )?;
+
+    // 書き込みの途中で失敗してoursが壊れないように一旦メモリ上に書き出す
+    let mut content = Vec::new();
+    merged.write(&mut content)?;
+    std::fs::write(output, content)?;
+
+    Ok(())
+}
+
+/// CIで使えるようにエラー（`--deny-warnings`のときは警告も）があれば失敗する
+fn lint(args: &[OsString]) -> Result<(), CommandError> {
+    let Some((input, flags)) = args.split_first() else {
+        return Err(CommandError::Usage);
+    };
+    let (mut json, mut deny_warnings) = (false, false);
+    for f in flags {
+        match f.to_str() {
+            Some("--json") => json = true,
+            Some("--deny-warnings") => deny_warnings = true,
+            _ => return Err(CommandError::Usage),
+        }
+    }
+
+    let issues = asset_lint::lint(&read_psa(&PathBuf::from(input))?);
+    let mut sink = std::io::stdout().lock();
+    if json {
+        asset_lint::write_json(&issues, &mut sink)?;
+    } else {
+        asset_lint::write_text(&issues, &mut sink)?;
+    }
+
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity() == asset_lint::Severity::Error)
+        .count();
+    let warnings = issues.len() - errors;
+    if errors > 0 || (deny_warnings && warnings > 0) {
+        return Err(CommandError::Lint { errors, warnings });
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+enum CommandError {
+    #[error("invalid arguments")]
+    Usage,
+    #[error("invalid number: {0}")]
+    InvalidNumber(&'static str),
+    #[error("block-compressed textures must be written to dds or ktx2")]
+    CompressedSeparateFiles,
+    #[error("lint failed: {errors} error(s), {warnings} warning(s)")]
+    Lint { errors: usize, warnings: usize },
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[error(transparent)]
+    PsaRead(#[from] peridot::SpriteAtlasAssetReadError),
+    #[error(transparent)]
+    AtlasRead(#[from] gdx_atlas::ReadError),
+    #[error(transparent)]
+    RustCodegen(#[from] rust_codegen::GenerateError),
+    #[error(transparent)]
+    Pack(#[from] sprite_packing::PackError),
+    #[error(transparent)]
+    TextureContainer(#[from] texture_container::WriteError),
+    #[error(transparent)]
+    BlockLayout(#[from] block_compression::LayoutError),
+    #[error(transparent)]
+    Merge(#[from] asset_merge::MergeError),
+}
@@ -0,0 +1,891 @@
+use std::{cell::Cell, rc::Rc};
+
+use windows::{
+    Foundation::Size,
+    UI::Composition::{
+        AnimationIterationBehavior, CompositionBitmapInterpolationMode,
+        CompositionEffectSourceParameter, CompositionStretch, CompositionSurfaceBrush,
+        ContainerVisual, SpriteVisual, VisualCollection,
+    },
+    Win32::Graphics::Direct2D::{Common::D2D_RECT_F, D2D1_INTERPOLATION_MODE_NEAREST_NEIGHBOR},
+};
+use windows_core::{Interface, h};
+use windows_numerics::{Vector2, Vector3};
+
+use crate::{
+    AppHitTestTreeManager, D2D1_COLOR_F_WHITE, PresenterInitContext, ViewInitContext,
+    ViewWorkerEnqueueWeakAccess,
+    app_state::{AnimationPreview, AppState},
+    atlas_image,
+    bg_worker::{
+        BackgroundWork, BackgroundWorkKind, BackgroundWorkPriority,
+        BackgroundWorkerEnqueueWeakAccess,
+    },
+    color_factory::ui_color_from_websafe_hex_rgb_with_alpha,
+    composition_element_builder::{
+        CompositionMaskBrushParams, CompositionNineGridBrushParams, CompositionSurfaceBrushParams,
+        ContainerVisualParams, SpriteVisualParams,
+    },
+    coordinate::dip_to_pixels,
+    create_instant_effect_brush,
+    effect_builder::{ColorSourceEffectParams, CompositeEffectParams, GaussianBlurEffectParams},
+    hittest::{
+        HitTestTreeActionHandler, HitTestTreeData, HitTestTreeManager, HitTestTreeRef,
+        PointerActionArgs,
+    },
+    input::EventContinueControl,
+    peridot::{AlphaMode, AnimationLoopMode},
+    subsystem::Subsystem,
+    surface_helper::{create_bitmap_from_rgba, draw_2d},
+    timespan_helper::timespan_ms,
+};
+
+const fn loop_mode_label(mode: AnimationLoopMode) -> &'static str {
+    match mode {
+        AnimationLoopMode::Once => "1回",
+        AnimationLoopMode::Loop => "ループ",
+        AnimationLoopMode::PingPong => "往復",
+    }
+}
+
+struct AnimationPaneView {
+    root: ContainerVisual,
+    preview_window: ContainerVisual,
+    preview_strip: SpriteVisual,
+    preview_strip_brush: CompositionSurfaceBrush,
+    rows: ContainerVisual,
+    hover_highlight: SpriteVisual,
+    active_highlight: SpriteVisual,
+    ht_root: HitTestTreeRef,
+    ht_row_area: HitTestTreeRef,
+    row_count: Cell<usize>,
+    dpi: f32,
+}
+impl AnimationPaneView {
+    const CORNER_RADIUS: f32 = 12.0;
+    const BLUR_AMOUNT: f32 = 27.0;
+    const SURFACE_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb_with_alpha(0xfff, 24);
+    const PREVIEW_BG_COLOR: windows::UI::Color =
+        ui_color_from_websafe_hex_rgb_with_alpha(0x000, 64);
+    const HOVER_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb_with_alpha(0xccc, 32);
+    const ACTIVE_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb_with_alpha(0x4af, 64);
+    const SPACING: f32 = 8.0;
+    const PADDING: f32 = 12.0;
+    const WIDTH: f32 = 240.0;
+    const PREVIEW_HEIGHT: f32 = 160.0;
+    /// プレビュー用のサーフェスの一辺の最大（D3D11で扱える最大のテクスチャサイズ）
+    const MAX_PREVIEW_SURFACE_SIZE: u32 = 16384;
+    const ROW_HEIGHT: f32 = 20.0;
+    const ROWS_TOP: f32 = Self::PADDING + Self::PREVIEW_HEIGHT + Self::SPACING;
+    /// アニメーション一覧の前に並んでいる操作用の行の数
+    const ACTION_ROW_COUNT: usize = 3;
+
+    fn new(init: &mut ViewInitContext) -> Self {
+        let frame_surface = init
+            .subsystem
+            .rounded_rect_mask_surface(init.dpi, Self::CORNER_RADIUS)
+            .unwrap();
+
+        let root = ContainerVisualParams::new()
+            .width(init.dip_to_pixels(Self::WIDTH))
+            .expand_height()
+            .left(init.dip_to_pixels(-Self::WIDTH - Self::SPACING))
+            .relative_horizontal_offset_adjustment(1.0)
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+
+        let bg_base_brush = create_instant_effect_brush(
+            init.subsystem,
+            &CompositeEffectParams::new(&[
+                GaussianBlurEffectParams::new(
+                    &CompositionEffectSourceParameter::Create(h!("source")).unwrap(),
+                )
+                .blur_amount_px(Self::BLUR_AMOUNT)
+                .instantiate()
+                .unwrap()
+                .cast()
+                .unwrap(),
+                ColorSourceEffectParams {
+                    color: Some(Self::SURFACE_COLOR),
+                }
+                .instantiate()
+                .unwrap()
+                .cast()
+                .unwrap(),
+            ])
+            .instantiate()
+            .unwrap(),
+            &[(
+                h!("source"),
+                init.subsystem
+                    .compositor
+                    .CreateBackdropBrush()
+                    .unwrap()
+                    .cast()
+                    .unwrap(),
+            )],
+        )
+        .unwrap();
+        let bg = SpriteVisualParams::new(
+            &CompositionMaskBrushParams {
+                source: &bg_base_brush,
+                mask: &CompositionNineGridBrushParams::new(
+                    &CompositionSurfaceBrushParams::new(&frame_surface)
+                        .stretch(CompositionStretch::Fill)
+                        .instantiate(&init.subsystem.compositor)
+                        .unwrap(),
+                )
+                .insets(init.dip_to_pixels(Self::CORNER_RADIUS))
+                .instantiate(&init.subsystem.compositor)
+                .unwrap(),
+            }
+            .instantiate(&init.subsystem.compositor)
+            .unwrap(),
+        )
+        .expand()
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+
+        let preview_bg = SpriteVisualParams::new(
+            &init
+                .subsystem
+                .compositor
+                .CreateColorBrushWithColor(Self::PREVIEW_BG_COLOR)
+                .unwrap(),
+        )
+        .offset_xy(Vector2 {
+            X: init.dip_to_pixels(Self::PADDING),
+            Y: init.dip_to_pixels(Self::PADDING),
+        })
+        .size(Vector2 {
+            X: init.dip_to_pixels(Self::WIDTH - Self::PADDING * 2.0),
+            Y: init.dip_to_pixels(Self::PREVIEW_HEIGHT),
+        })
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+
+        // 1枚に横並びにしたフレームをずらして表示する（フレームの切り替えはComposition側のアニメーションで行う）
+        let preview_window = ContainerVisualParams::new()
+            .anchor_point(Vector2 { X: 0.5, Y: 0.5 })
+            .offset_xy(Vector2 {
+                X: init.dip_to_pixels(Self::WIDTH * 0.5),
+                Y: init.dip_to_pixels(Self::PADDING + Self::PREVIEW_HEIGHT * 0.5),
+            })
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+        preview_window
+            .SetClip(&init.subsystem.compositor.CreateInsetClip().unwrap())
+            .unwrap();
+        let preview_strip_brush = init.subsystem.compositor.CreateSurfaceBrush().unwrap();
+        // ドット絵がぼやけないようにする
+        preview_strip_brush
+            .SetBitmapInterpolationMode(CompositionBitmapInterpolationMode::NearestNeighbor)
+            .unwrap();
+        let preview_strip = SpriteVisualParams::new(&preview_strip_brush)
+            .opacity(0.0)
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+        preview_window
+            .Children()
+            .unwrap()
+            .InsertAtTop(&preview_strip)
+            .unwrap();
+
+        let hover_highlight = SpriteVisualParams::new(
+            &init
+                .subsystem
+                .compositor
+                .CreateColorBrushWithColor(Self::HOVER_COLOR)
+                .unwrap(),
+        )
+        .size(Vector2 {
+            X: init.dip_to_pixels(Self::WIDTH - Self::PADDING * 2.0),
+            Y: init.dip_to_pixels(Self::ROW_HEIGHT),
+        })
+        .opacity(0.0)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+        let active_highlight = SpriteVisualParams::new(
+            &init
+                .subsystem
+                .compositor
+                .CreateColorBrushWithColor(Self::ACTIVE_COLOR)
+                .unwrap(),
+        )
+        .size(Vector2 {
+            X: init.dip_to_pixels(Self::WIDTH - Self::PADDING * 2.0),
+            Y: init.dip_to_pixels(Self::ROW_HEIGHT),
+        })
+        .opacity(0.0)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+        let rows = ContainerVisualParams::new()
+            .expand()
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+
+        let children = root.Children().unwrap();
+        children.InsertAtTop(&bg).unwrap();
+        children.InsertAtTop(&preview_bg).unwrap();
+        children.InsertAtTop(&preview_window).unwrap();
+        children.InsertAtTop(&active_highlight).unwrap();
+        children.InsertAtTop(&hover_highlight).unwrap();
+        children.InsertAtTop(&rows).unwrap();
+
+        let ht_root = init.ht.borrow_mut().alloc(HitTestTreeData {
+            left: -Self::WIDTH - Self::SPACING,
+            top: 0.0,
+            left_adjustment_factor: 1.0,
+            top_adjustment_factor: 0.0,
+            width: Self::WIDTH,
+            height: -Self::SPACING,
+            width_adjustment_factor: 0.0,
+            height_adjustment_factor: 1.0,
+            parent: None,
+            children: Vec::new(),
+            focusable: false,
+            action_handler: None,
+        });
+        let ht_row_area = init.ht.borrow_mut().alloc(HitTestTreeData {
+            left: Self::PADDING,
+            top: Self::ROWS_TOP,
+            left_adjustment_factor: 0.0,
+            top_adjustment_factor: 0.0,
+            width: -Self::PADDING * 2.0,
+            height: -Self::ROWS_TOP - Self::PADDING,
+            width_adjustment_factor: 1.0,
+            height_adjustment_factor: 1.0,
+            parent: None,
+            children: Vec::new(),
+            focusable: false,
+            action_handler: None,
+        });
+        init.ht.borrow_mut().add_child(ht_root, ht_row_area);
+
+        Self {
+            root,
+            preview_window,
+            preview_strip,
+            preview_strip_brush,
+            rows,
+            hover_highlight,
+            active_highlight,
+            ht_root,
+            ht_row_area,
+            row_count: Cell::new(0),
+            dpi: init.dpi,
+        }
+    }
+
+    fn mount(
+        &self,
+        children: &VisualCollection,
+        ht: &mut AppHitTestTreeManager,
+        ht_parent: HitTestTreeRef,
+    ) {
+        children.InsertAtTop(&self.root).unwrap();
+        ht.add_child(ht_parent, self.ht_root);
+    }
+
+    fn set_top(&self, ht: &mut AppHitTestTreeManager, top: f32) {
+        self.root
+            .SetOffset(Vector3 {
+                X: dip_to_pixels(-Self::WIDTH - Self::SPACING, self.dpi),
+                Y: dip_to_pixels(top, self.dpi),
+                Z: 0.0,
+            })
+            .unwrap();
+        self.root
+            .SetSize(Vector2 {
+                X: dip_to_pixels(Self::WIDTH, self.dpi),
+                Y: dip_to_pixels(-top - Self::SPACING, self.dpi),
+            })
+            .unwrap();
+        ht.get_mut(self.ht_root).top = top;
+        ht.get_mut(self.ht_root).height = -top - Self::SPACING;
+    }
+
+    const fn row_top(index: usize) -> f32 {
+        Self::ROWS_TOP + index as f32 * Self::ROW_HEIGHT
+    }
+
+    fn row_index_at(&self, local_y: f32) -> Option<usize> {
+        let index = (local_y / Self::ROW_HEIGHT).trunc();
+        if 0.0 <= index && index < self.row_count.get() as f32 {
+            Some(index as usize)
+        } else {
+            None
+        }
+    }
+
+    fn set_rows(&self, labels: &[String], subsystem: &Subsystem) {
+        let children = self.rows.Children().unwrap();
+        children.RemoveAll().unwrap();
+
+        for (n, l) in labels.iter().enumerate() {
+            let tl = subsystem
+                .new_text_layout_unrestricted(l, &subsystem.default_ui_format)
+                .unwrap();
+            let mut tm = core::mem::MaybeUninit::uninit();
+            unsafe {
+                tl.GetMetrics(tm.as_mut_ptr()).unwrap();
+            }
+            let tm = unsafe { tm.assume_init() };
+            let surface = subsystem
+                .gen_text_surface(self.dpi, &tl, &D2D1_COLOR_F_WHITE)
+                .unwrap();
+
+            let label = SpriteVisualParams::new(
+                &CompositionSurfaceBrushParams::new(&surface)
+                    .instantiate(&subsystem.compositor)
+                    .unwrap(),
+            )
+            .size(Vector2 {
+                X: dip_to_pixels(tm.width, self.dpi),
+                Y: dip_to_pixels(tm.height, self.dpi),
+            })
+            .offset_xy(Vector2 {
+                X: dip_to_pixels(Self::PADDING + 8.0, self.dpi),
+                Y: dip_to_pixels(
+                    Self::row_top(n) + (Self::ROW_HEIGHT - tm.height) * 0.5,
+                    self.dpi,
+                ),
+            })
+            .instantiate(&subsystem.compositor)
+            .unwrap();
+            children.InsertAtTop(&label).unwrap();
+        }
+
+        self.row_count.set(labels.len());
+    }
+
+    fn locate_highlight(&self, highlight: &SpriteVisual, index: Option<usize>) {
+        let Some(index) = index else {
+            highlight.SetOpacity(0.0).unwrap();
+            return;
+        };
+
+        highlight
+            .SetOffset(Vector3 {
+                X: dip_to_pixels(Self::PADDING, self.dpi),
+                Y: dip_to_pixels(Self::row_top(index), self.dpi),
+                Z: 0.0,
+            })
+            .unwrap();
+        highlight.SetOpacity(1.0).unwrap();
+    }
+
+    fn set_hover_row(&self, index: Option<usize>) {
+        self.locate_highlight(&self.hover_highlight, index);
+    }
+
+    fn set_active_row(&self, index: Option<usize>) {
+        self.locate_highlight(&self.active_highlight, index);
+    }
+
+    fn set_preview(&self, preview: &AnimationPreview, subsystem: &Subsystem) {
+        self.preview_strip.StopAnimation(h!("Offset")).unwrap();
+
+        let frame_count = preview.frames.len() as u32;
+        let images = preview
+            .frames
+            .iter()
+            .filter_map(|x| x.image.as_ref())
+            .collect::<Vec<_>>();
+        let cell_width = images.iter().map(|x| x.width()).max().unwrap_or(0);
+        let cell_height = images.iter().map(|x| x.height()).max().unwrap_or(0);
+        if cell_width == 0 || cell_height == 0 {
+            self.preview_strip.SetOpacity(0.0).unwrap();
+            return;
+        }
+
+        // Note: 横一列だとすぐにサーフェスの最大サイズを超えるので、収まる数ごとに折り返して並べる
+        let columns = (Self::MAX_PREVIEW_SURFACE_SIZE / cell_width).min(frame_count);
+        let rows = if columns == 0 {
+            0
+        } else {
+            frame_count.div_ceil(columns)
+        };
+        if columns == 0 || rows * cell_height > Self::MAX_PREVIEW_SURFACE_SIZE {
+            tracing::warn!(
+                { cell_width, cell_height, frame_count },
+                "animation preview is too large to display"
+            );
+            self.clear_preview();
+            return;
+        }
+
+        let surface = match subsystem.new_2d_drawing_surface(Size {
+            Width: (cell_width * columns) as _,
+            Height: (cell_height * rows) as _,
+        }) {
+            Ok(x) => x,
+            Err(e) => {
+                tracing::warn!({ %e }, "failed to create animation preview surface");
+                self.clear_preview();
+                return;
+            }
+        };
+        let r = draw_2d(&surface, |dc, offset| {
+            unsafe {
+                dc.Clear(None);
+            }
+
+            // Note: 画像のないフレームも位置は詰めない（アニメーションはフレームの番号で位置を決める）
+            for (n, img) in preview
+                .frames
+                .iter()
+                .enumerate()
+                .filter_map(|(n, x)| Some((n, x.image.as_ref()?)))
+            {
+                let bitmap = create_bitmap_from_rgba(dc, img)?;
+
+                // セルの中央に置く
+                let (column, row) = (n as u32 % columns, n as u32 / columns);
+                let left = offset.x as f32
+                    + (column * cell_width) as f32
+                    + ((cell_width - img.width()) / 2) as f32;
+                let top = offset.y as f32
+                    + (row * cell_height) as f32
+                    + ((cell_height - img.height()) / 2) as f32;
+                unsafe {
+                    dc.DrawBitmap(
+                        &bitmap,
+                        Some(&D2D_RECT_F {
+                            left,
+                            top,
+                            right: left + img.width() as f32,
+                            bottom: top + img.height() as f32,
+                        }),
+                        1.0,
+                        D2D1_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
+                        None,
+                        None,
+                    );
+                }
+            }
+
+            Ok::<_, windows_core::Error>(())
+        });
+        if let Err(e) = r {
+            tracing::warn!({ %e }, "failed to draw animation preview");
+            self.clear_preview();
+            return;
+        }
+        self.preview_strip_brush.SetSurface(&surface).unwrap();
+
+        // プレビュー領域に収まる最大の倍率（拡大するときは整数倍にしてドットの大きさをそろえる）
+        let area_width = dip_to_pixels(Self::WIDTH - Self::PADDING * 2.0, self.dpi);
+        let area_height = dip_to_pixels(Self::PREVIEW_HEIGHT, self.dpi);
+        let scale = (area_width / cell_width as f32).min(area_height / cell_height as f32);
+        let scale = if scale >= 1.0 { scale.floor() } else { scale };
+        let (frame_width, frame_height) = (cell_width as f32 * scale, cell_height as f32 * scale);
+
+        self.preview_window
+            .SetSize(Vector2 {
+                X: frame_width,
+                Y: frame_height,
+            })
+            .unwrap();
+        self.preview_strip
+            .SetSize(Vector2 {
+                X: (cell_width * columns) as _,
+                Y: (cell_height * rows) as _,
+            })
+            .unwrap();
+        self.preview_strip
+            .SetScale(Vector3 {
+                X: scale,
+                Y: scale,
+                Z: 1.0,
+            })
+            .unwrap();
+        self.preview_strip
+            .SetOffset(Vector3 {
+                X: 0.0,
+                Y: 0.0,
+                Z: 0.0,
+            })
+            .unwrap();
+        self.preview_strip.SetOpacity(1.0).unwrap();
+
+        if frame_count < 2 {
+            // 切り替えるものがない
+            return;
+        }
+
+        let mut sequence = (0..frame_count as usize).collect::<Vec<_>>();
+        if preview.loop_mode == AnimationLoopMode::PingPong {
+            // 両端のフレームは折り返しで2回続けて表示しない
+            sequence.extend((1..frame_count as usize - 1).rev());
+        }
+        // Note: 0msだとキーフレームの位置が重なるので最低1msにする
+        let durations = sequence
+            .iter()
+            .map(|&n| preview.frames[n].duration_ms.max(1))
+            .collect::<Vec<_>>();
+        let total_duration: u32 = durations.iter().sum();
+
+        let frame_offset = |n: usize| Vector3 {
+            X: -((n as u32 % columns) as f32 * frame_width),
+            Y: -((n as u32 / columns) as f32 * frame_height),
+            Z: 0.0,
+        };
+        let step = subsystem.compositor.CreateStepEasingFunction().unwrap();
+        // 区間の最後まで前のフレームを表示し続ける
+        step.SetIsFinalStepSingleFrame(true).unwrap();
+
+        let animation = subsystem
+            .compositor
+            .CreateVector3KeyFrameAnimation()
+            .unwrap();
+        animation
+            .InsertKeyFrame(0.0, frame_offset(sequence[0]))
+            .unwrap();
+        let mut elapsed = 0;
+        for n in 1..sequence.len() {
+            elapsed += durations[n - 1];
+            animation
+                .InsertKeyFrameWithEasingFunction(
+                    elapsed as f32 / total_duration as f32,
+                    frame_offset(sequence[n]),
+                    &step,
+                )
+                .unwrap();
+        }
+        animation
+            .InsertKeyFrameWithEasingFunction(1.0, frame_offset(*sequence.last().unwrap()), &step)
+            .unwrap();
+        animation.SetDuration(timespan_ms(total_duration)).unwrap();
+        match preview.loop_mode {
+            AnimationLoopMode::Once => {
+                animation
+                    .SetIterationBehavior(AnimationIterationBehavior::Count)
+                    .unwrap();
+                animation.SetIterationCount(1).unwrap();
+            }
+            AnimationLoopMode::Loop | AnimationLoopMode::PingPong => {
+                animation
+                    .SetIterationBehavior(AnimationIterationBehavior::Forever)
+                    .unwrap();
+            }
+        }
+        self.preview_strip
+            .StartAnimation(h!("Offset"), &animation)
+            .unwrap();
+    }
+
+    fn clear_preview(&self) {
+        self.preview_strip.StopAnimation(h!("Offset")).unwrap();
+        self.preview_strip.SetOpacity(0.0).unwrap();
+    }
+}
+
+struct AnimationPaneHitActionHandler {
+    view: Rc<AnimationPaneView>,
+    hover_row: Cell<Option<usize>>,
+    background_worker_enqueue_access: BackgroundWorkerEnqueueWeakAccess,
+    view_worker_enqueue_access: ViewWorkerEnqueueWeakAccess,
+}
+impl AnimationPaneHitActionHandler {
+    fn start_preview(&self, context: &mut AppState, index: usize) {
+        let Some(background_worker_enqueue_access) =
+            self.background_worker_enqueue_access.upgrade()
+        else {
+            // app teardown-ed
+            return;
+        };
+
+        for r in context.begin_animation_preview(index) {
+            let view_worker_enqueue_access = self.view_worker_enqueue_access.clone();
+
+            background_worker_enqueue_access.enqueue(
+                BackgroundWork::new(BackgroundWorkKind::LoadSpriteSource(
+                    r.source_path,
+                    Box::new(move |path, di| {
+                        let Some(view_worker_enqueue_access) = view_worker_enqueue_access.upgrade()
+                        else {
+                            // app teardown-ed
+                            return;
+                        };
+
+                        // Note: プレビューはD2Dのビットマップなので8bitに落とす（あとでアルファを掛けるのでstraightにそろえる）
+                        let image = atlas_image::for_display(&atlas_image::convert_alpha_mode(
+                            &di.crop_imm(r.source_left, r.source_top, r.width, r.height),
+                            atlas_image::source_alpha_mode(&path),
+                            AlphaMode::Straight,
+                        ))
+                        .to_rgba8();
+                        let (generation, frame_index) = (r.generation, r.frame_index);
+                        view_worker_enqueue_access.enqueue(move |app_state| {
+                            app_state.set_animation_preview_frame(generation, frame_index, image);
+                        });
+                    }),
+                ))
+                // Note: 再生を待っているので先に読み込む
+                .with_priority(BackgroundWorkPriority::High)
+                .with_cancellation_token(r.cancellation_token),
+            );
+        }
+    }
+}
+impl HitTestTreeActionHandler for AnimationPaneHitActionHandler {
+    type Context = AppState;
+
+    fn on_pointer_enter(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut Self::Context,
+        ht: &mut HitTestTreeManager<Self::Context>,
+        args: PointerActionArgs,
+    ) -> EventContinueControl {
+        if sender == self.view.ht_root {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_row_area {
+            let (_, local_y, _, _) = ht.translate_client_to_tree_local(
+                sender,
+                args.client_x,
+                args.client_y,
+                args.client_width,
+                args.client_height,
+            );
+            let index = self.view.row_index_at(local_y);
+            self.view.set_hover_row(index);
+            self.hover_row.set(index);
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_pointer_leave(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut Self::Context,
+        _ht: &mut HitTestTreeManager<Self::Context>,
+        _args: PointerActionArgs,
+    ) -> EventContinueControl {
+        if sender == self.view.ht_root {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_row_area {
+            self.view.set_hover_row(None);
+            self.hover_row.set(None);
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_pointer_move(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut Self::Context,
+        ht: &mut HitTestTreeManager<Self::Context>,
+        args: PointerActionArgs,
+    ) -> EventContinueControl {
+        if sender == self.view.ht_root {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_row_area {
+            let (_, local_y, _, _) = ht.translate_client_to_tree_local(
+                sender,
+                args.client_x,
+                args.client_y,
+                args.client_width,
+                args.client_height,
+            );
+            let index = self.view.row_index_at(local_y);
+            if self.hover_row.replace(index) != index {
+                self.view.set_hover_row(index);
+            }
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_pointer_down(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut Self::Context,
+        _ht: &mut HitTestTreeManager<Self::Context>,
+        _args: PointerActionArgs,
+    ) -> EventContinueControl {
+        if sender == self.view.ht_root || sender == self.view.ht_row_area {
+            // 下のグリッドのドラッグが始まらないようにする
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_click(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut Self::Context,
+        ht: &mut HitTestTreeManager<Self::Context>,
+        args: PointerActionArgs,
+    ) -> EventContinueControl {
+        if sender == self.view.ht_root {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_row_area {
+            let (_, local_y, _, _) = ht.translate_client_to_tree_local(
+                sender,
+                args.client_x,
+                args.client_y,
+                args.client_width,
+                args.client_height,
+            );
+            let previewing_index = context.animation_preview().map(|x| x.animation_index);
+
+            match self.view.row_index_at(local_y) {
+                Some(0) => {
+                    if let Some(n) = context.add_animation_from_selected_sprites() {
+                        self.start_preview(context, n);
+                    }
+                }
+                Some(1) => {
+                    if let Some(n) = previewing_index {
+                        context.cycle_animation_loop_mode(n);
+                    }
+                }
+                Some(2) => {
+                    if let Some(n) = previewing_index {
+                        context.remove_animation(n);
+                    }
+                }
+                Some(n) => {
+                    self.start_preview(context, n - AnimationPaneView::ACTION_ROW_COUNT);
+                }
+                None => (),
+            }
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
+    }
+}
+
+pub struct AnimationPanePresenter {
+    view: Rc<AnimationPaneView>,
+    _ht_action_handler: Rc<AnimationPaneHitActionHandler>,
+}
+impl AnimationPanePresenter {
+    pub fn new(init: &mut PresenterInitContext) -> Self {
+        let view = Rc::new(AnimationPaneView::new(&mut init.for_view));
+
+        init.app_state
+            .borrow_mut()
+            .register_animations_view_feedback({
+                let subsystem = Rc::downgrade(init.for_view.subsystem);
+                let view = Rc::downgrade(&view);
+
+                move |animations| {
+                    let Some(subsystem) = subsystem.upgrade() else {
+                        // app teardown-ed
+                        return;
+                    };
+                    let Some(view) = view.upgrade() else {
+                        // parent teardown-ed
+                        return;
+                    };
+
+                    let mut labels = vec![
+                        String::from("＋ 選択中のスプライトから作成"),
+                        String::from("ループの仕方を切り替え"),
+                        String::from("削除"),
+                    ];
+                    labels.extend(animations.iter().map(|x| {
+                        format!(
+                            "{} ({}フレーム, {})",
+                            x.name,
+                            x.frames.len(),
+                            loop_mode_label(x.loop_mode)
+                        )
+                    }));
+                    view.set_rows(&labels, &subsystem);
+                }
+            });
+        init.app_state
+            .borrow_mut()
+            .register_animation_preview_view_feedback({
+                let subsystem = Rc::downgrade(init.for_view.subsystem);
+                let view = Rc::downgrade(&view);
+
+                move |preview| {
+                    let Some(subsystem) = subsystem.upgrade() else {
+                        // app teardown-ed
+                        return;
+                    };
+                    let Some(view) = view.upgrade() else {
+                        // parent teardown-ed
+                        return;
+                    };
+
+                    let Some(preview) = preview else {
+                        view.set_active_row(None);
+                        view.clear_preview();
+                        return;
+                    };
+
+                    view.set_active_row(Some(
+                        AnimationPaneView::ACTION_ROW_COUNT + preview.animation_index,
+                    ));
+                    if preview.is_ready() {
+                        view.set_preview(preview, &subsystem);
+                    } else {
+                        view.clear_preview();
+                    }
+                }
+            });
+
+        let ht_action_handler = Rc::new(AnimationPaneHitActionHandler {
+            view: view.clone(),
+            hover_row: Cell::new(None),
+            background_worker_enqueue_access: init
+                .for_view
+                .background_worker_enqueue_access
+                .downgrade(),
+            view_worker_enqueue_access: init.view_worker_enqueue_access.clone(),
+        });
+        init.for_view
+            .ht
+            .borrow_mut()
+            .get_mut(view.ht_root)
+            .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
+        init.for_view
+            .ht
+            .borrow_mut()
+            .get_mut(view.ht_row_area)
+            .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
+
+        Self {
+            view,
+            _ht_action_handler: ht_action_handler,
+        }
+    }
+
+    pub fn mount(
+        &self,
+        children: &VisualCollection,
+        ht: &mut AppHitTestTreeManager,
+        ht_parent: HitTestTreeRef,
+    ) {
+        self.view.mount(children, ht, ht_parent);
+    }
+
+    pub fn set_top(&self, ht: &mut AppHitTestTreeManager, top: f32) {
+        self.view.set_top(ht, top);
+    }
+}
@@ -611,6 +611,7 @@ impl AppMenuButtonView {
             height_adjustment_factor: 0.0,
             parent: None,
             children: Vec::new(),
+            focusable: false,
             action_handler: None,
         });
 
@@ -659,6 +660,8 @@ impl AppMenuButtonView {
 struct AppHeaderBaseView {
     root: ContainerVisual,
     label: SpriteVisual,
+    progress_root: ContainerVisual,
+    progress_bar: SpriteVisual,
     label_brush: CompositionSurfaceBrush,
     label_text_format: IDWriteTextFormat,
     dwrite_factory: IDWriteFactory1,
@@ -669,6 +672,11 @@ struct AppHeaderBaseView {
     dpi: Cell<f32>,
 }
 impl AppHeaderBaseView {
+    const PROGRESS_HEIGHT: f32 = 2.0;
+    const PROGRESS_TRACK_COLOR: windows::UI::Color =
+        ui_color_from_websafe_hex_rgb_with_alpha(0xfff, 32);
+    const PROGRESS_BAR_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb(0x4af);
+
     fn new(init: &mut ViewInitContext, init_label: String) -> Self {
         let tl = init
             .subsystem
@@ -754,9 +762,43 @@ impl AppHeaderBaseView {
             .instantiate(&init.subsystem.compositor)
             .unwrap();
 
+        // Note: バックグラウンドの作業中だけ下端に出す
+        let progress_root = ContainerVisualParams::new()
+            .height(init.dip_to_pixels(Self::PROGRESS_HEIGHT))
+            .expand_width()
+            .relative_vertical_offset_adjustment(1.0)
+            .anchor_point(Vector2 { X: 0.0, Y: 1.0 })
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+        progress_root.SetOpacity(0.0).unwrap();
+        let progress_track = SpriteVisualParams::new(
+            &init
+                .subsystem
+                .compositor
+                .CreateColorBrushWithColor(Self::PROGRESS_TRACK_COLOR)
+                .unwrap(),
+        )
+        .expand()
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+        let progress_bar = SpriteVisualParams::new(
+            &init
+                .subsystem
+                .compositor
+                .CreateColorBrushWithColor(Self::PROGRESS_BAR_COLOR)
+                .unwrap(),
+        )
+        .relative_size_adjustment(Vector2 { X: 0.0, Y: 1.0 })
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+        let progress_children = progress_root.Children().unwrap();
+        progress_children.InsertAtTop(&progress_track).unwrap();
+        progress_children.InsertAtTop(&progress_bar).unwrap();
+
         let children = root.Children().unwrap();
         children.InsertAtTop(&bg).unwrap();
         children.InsertAtTop(&label).unwrap();
+        children.InsertAtTop(&progress_root).unwrap();
 
         let ht_root = init.ht.borrow_mut().alloc(HitTestTreeData {
             left: 0.0,
@@ -769,12 +811,15 @@ impl AppHeaderBaseView {
             height_adjustment_factor: 0.0,
             parent: None,
             children: Vec::new(),
+            focusable: false,
             action_handler: None,
         });
 
         Self {
             root,
             label,
+            progress_root,
+            progress_bar,
             label_brush,
             label_text_format: init.subsystem.default_ui_format.clone(),
             dwrite_factory: init.subsystem.dwrite_factory.clone(),
@@ -800,6 +845,21 @@ impl AppHeaderBaseView {
         ht.add_child(ht_parent, self.ht_root);
     }
 
+    /// バックグラウンドの作業全体の進捗（何もしていなければNone）
+    pub fn set_progress(&self, progress: Option<f32>) {
+        match progress {
+            Some(p) => {
+                self.progress_bar
+                    .SetRelativeSizeAdjustment(Vector2 { X: p, Y: 1.0 })
+                    .unwrap();
+                self.progress_root.SetOpacity(1.0).unwrap();
+            }
+            None => {
+                self.progress_root.SetOpacity(0.0).unwrap();
+            }
+        }
+    }
+
     pub fn set_label(&self, label: String) {
         if &label == &*self.current_label.borrow() {
             // かわらないのでなにもしない
@@ -1065,6 +1125,22 @@ impl AppHeaderPresenter {
                 }
             });
 
+        init.for_view
+            .background_worker_view_update_callback
+            .borrow_mut()
+            .push(Box::new({
+                let base_view = Rc::downgrade(&base_view);
+
+                move |status| {
+                    let Some(base_view) = base_view.upgrade() else {
+                        // parent teardown-ed
+                        return;
+                    };
+
+                    base_view.set_progress(status.overall_progress());
+                }
+            }));
+
         Self {
             base_view,
             close_button_view,
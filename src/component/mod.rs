@@ -1,2 +1,3 @@
+pub mod animation_pane;
 pub mod app_header;
 pub mod dnd_overlay;
@@ -0,0 +1,339 @@
+//! libGDX/Spine Texture Atlas(.atlas) Format
+
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+use crate::peridot::{AlphaMode, CompressionQuality, Sprite, SpriteAtlasAsset, TextureCompression};
+
+pub fn write(
+    asset: &SpriteAtlasAsset,
+    page_name: &str,
+    sink: &mut (impl Write + ?Sized),
+) -> std::io::Result<()> {
+    writeln!(sink, "{page_name}")?;
+    writeln!(sink, "size: {},{}", asset.width, asset.height)?;
+    if asset.mip_levels > 1 {
+        writeln!(sink, "filter: MipMapLinearLinear,Linear")?;
+    } else {
+        writeln!(sink, "filter: Linear,Linear")?;
+    }
+    if asset.alpha_mode == AlphaMode::Premultiplied {
+        writeln!(sink, "pma: true")?;
+    }
+
+    for s in asset.sprites.iter() {
+        // Note: TexturePackerと同じようにグループはディレクトリ風にリージョン名に含める
+        if s.group.is_empty() {
+            writeln!(sink, "{}", s.name)?;
+        } else {
+            writeln!(sink, "{}/{}", s.group, s.name)?;
+        }
+        writeln!(
+            sink,
+            "bounds: {},{},{},{}",
+            s.left, s.top, s.width, s.height
+        )?;
+        // Note: psaではトリミングも回転もしないので、元の大きさそのままで回転なしとして書く
+        writeln!(sink, "offsets: 0,0,{},{}", s.width, s.height)?;
+        writeln!(sink, "rotate: false")?;
+        if s.border_left != 0 || s.border_right != 0 || s.border_top != 0 || s.border_bottom != 0 {
+            writeln!(
+                sink,
+                "split: {},{},{},{}",
+                s.border_left, s.border_right, s.border_top, s.border_bottom
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 各リージョンはページ画像（`source_dir`以下にあるものとみなす）の`bounds`の位置から切り出すスプライトになる
+///
+/// リージョン名に`/`が含まれる場合は、最後の`/`より前をグループとして扱う
+pub fn read(
+    src: &mut (impl BufRead + ?Sized),
+    source_dir: &Path,
+) -> Result<SpriteAtlasAsset, ReadError> {
+    let mut sprites = Vec::new();
+    let mut page_size = None;
+    let mut alpha_mode = AlphaMode::Straight;
+    let mut page_started = false;
+    let mut page_path = PathBuf::new();
+    let mut current_region: Option<Region> = None;
+
+    for l in src.lines() {
+        let line = l?;
+        let l = line.trim();
+        if l.is_empty() {
+            // 空行でページが終わる
+            if let Some(r) = current_region.take() {
+                sprites.push(r.into_sprite(&page_path)?);
+            }
+            page_started = false;
+            continue;
+        }
+
+        let Some((key, value)) = l.split_once(':') else {
+            if !page_started {
+                // ページ名
+                if page_size.is_some() {
+                    return Err(ReadError::MultiplePages);
+                }
+                page_started = true;
+                page_path = source_dir.join(l);
+                continue;
+            }
+
+            // リージョン名
+            if let Some(r) = current_region.replace(Region::new(l.into())) {
+                sprites.push(r.into_sprite(&page_path)?);
+            }
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+
+        let Some(r) = current_region.as_mut() else {
+            // ページの属性
+            match key {
+                "size" => {
+                    let [w, h] = parse_values("size", value)?;
+                    page_size = Some((w, h));
+                }
+                "pma" if value == "true" => alpha_mode = AlphaMode::Premultiplied,
+                _ => (),
+            }
+            continue;
+        };
+
+        match key {
+            "bounds" => {
+                let [x, y, w, h] = parse_values("bounds", value)?;
+                (r.left, r.top, r.width, r.height) = (x, y, w, h);
+            }
+            // 旧形式
+            "xy" => {
+                let [x, y] = parse_values("xy", value)?;
+                (r.left, r.top) = (x, y);
+            }
+            "size" => {
+                let [w, h] = parse_values("size", value)?;
+                (r.width, r.height) = (w, h);
+            }
+            "split" => {
+                let [left, right, top, bottom] = parse_values("split", value)?;
+                r.split = [left, right, top, bottom];
+            }
+            "rotate" => {
+                r.rotated = !matches!(value, "false" | "0");
+            }
+            "index" => {
+                r.index = value
+                    .parse()
+                    .map_err(|e| ReadError::InvalidParamFormat("index", e))?;
+            }
+            // Note: offsets/orig/offsetはトリミング情報で、psaには対応する概念がないので読み捨てる
+            _ => (),
+        }
+    }
+    if let Some(r) = current_region.take() {
+        sprites.push(r.into_sprite(&page_path)?);
+    }
+
+    let (width, height) = page_size.ok_or(ReadError::MissingParam("size"))?;
+    sprites.sort_by_key(|x| x.id);
+
+    Ok(SpriteAtlasAsset {
+        sprites,
+        // Note: .atlasの連番リージョン(index)はアニメーションとしては取り込まない
+        animations: Vec::new(),
+        width,
+        height,
+        alpha_mode,
+        mip_levels: 1,
+        compression: TextureCompression::None,
+        compression_quality: CompressionQuality::Fast,
+    })
+}
+
+fn parse_values<const N: usize>(key: &'static str, value: &str) -> Result<[u32; N], ReadError> {
+    let mut values = [0; N];
+    let mut iter = value.split(',');
+    for v in values.iter_mut() {
+        *v = iter
+            .next()
+            .ok_or(ReadError::MissingParam(key))?
+            .trim()
+            .parse()
+            .map_err(|e| ReadError::InvalidParamFormat(key, e))?;
+    }
+
+    Ok(values)
+}
+
+struct Region {
+    name: String,
+    left: u32,
+    top: u32,
+    width: u32,
+    height: u32,
+    split: [u32; 4],
+    rotated: bool,
+    index: i32,
+}
+impl Region {
+    const fn new(name: String) -> Self {
+        Self {
+            name,
+            left: 0,
+            top: 0,
+            width: 0,
+            height: 0,
+            split: [0; 4],
+            rotated: false,
+            index: -1,
+        }
+    }
+
+    fn into_sprite(self, page_path: &Path) -> Result<Sprite, ReadError> {
+        if self.rotated {
+            return Err(ReadError::UnsupportedRotation(self.name));
+        }
+
+        let [border_left, border_right, border_top, border_bottom] = self.split;
+        // 連番リージョンは同名でindexだけが違うので、名前にindexを含めて区別する
+        let name = if self.index >= 0 {
+            format!("{}_{}", self.name, self.index)
+        } else {
+            self.name
+        };
+
+        // Note: .psaはカンマ区切りなので、グループのカンマは置き換えておく（sprite_group::derive_from_source_pathと同じ）
+        let (group, name) = match name.rsplit_once('/') {
+            Some((g, n)) => (g.replace(',', "_"), n.into()),
+            None => (String::new(), name),
+        };
+
+        Ok(Sprite {
+            id: Uuid::new_v4(),
+            source_path: page_path.to_path_buf(),
+            name,
+            source_left: self.left,
+            source_top: self.top,
+            width: self.width,
+            height: self.height,
+            left: self.left,
+            top: self.top,
+            border_left,
+            border_top,
+            border_right,
+            border_bottom,
+            // Note: .atlasにはピボットの情報がない
+            pivot_x: Sprite::DEFAULT_PIVOT.0,
+            pivot_y: Sprite::DEFAULT_PIVOT.1,
+            group,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReadError {
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+    #[error("multiple pages are not supported")]
+    MultiplePages,
+    #[error("rotated region is not supported: {0}")]
+    UnsupportedRotation(String),
+    #[error("missing {0}")]
+    MissingParam(&'static str),
+    #[error("invalid param format({0}): {1}")]
+    InvalidParamFormat(&'static str, std::num::ParseIntError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite(id: u128, name: &str, group: &str, rect: [u32; 4], split: [u32; 4]) -> Sprite {
+        let [left, top, width, height] = rect;
+        let [border_left, border_right, border_top, border_bottom] = split;
+
+        Sprite {
+            id: Uuid::from_u128(id),
+            name: name.into(),
+            source_path: PathBuf::from("atlas/page.png"),
+            source_left: left,
+            source_top: top,
+            width,
+            height,
+            left,
+            top,
+            border_left,
+            border_top,
+            border_right,
+            border_bottom,
+            pivot_x: Sprite::DEFAULT_PIVOT.0,
+            pivot_y: Sprite::DEFAULT_PIVOT.1,
+            group: group.into(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let asset = SpriteAtlasAsset {
+            sprites: vec![
+                sprite(1, "button", "ui/common", [0, 0, 32, 16], [4, 4, 2, 2]),
+                sprite(2, "hero", "", [32, 0, 16, 24], [0; 4]),
+            ],
+            animations: Vec::new(),
+            width: 64,
+            height: 32,
+            alpha_mode: AlphaMode::Premultiplied,
+            mip_levels: 1,
+            compression: TextureCompression::None,
+            compression_quality: CompressionQuality::Fast,
+        };
+        let mut buf = Vec::new();
+        write(&asset, "page.png", &mut buf).unwrap();
+
+        let read = read(&mut &buf[..], Path::new("atlas")).unwrap();
+        assert_eq!((read.width, read.height), (64, 32));
+        assert_eq!(read.alpha_mode, AlphaMode::Premultiplied);
+        // IDは読み込むたびに振り直されるので、名前の順に並べて比べる
+        let mut sprites = read.sprites;
+        sprites.sort_by(|a, b| a.name.cmp(&b.name));
+        for (r, s) in sprites.iter().zip(asset.sprites.iter()) {
+            assert_eq!(
+                Sprite {
+                    id: s.id,
+                    ..r.clone()
+                },
+                *s
+            );
+        }
+        assert_eq!(sprites.len(), asset.sprites.len());
+    }
+
+    #[test]
+    fn comma_in_region_path_is_replaced() {
+        let src = "page.png\nsize: 16,16\nchars,old/hero\nbounds: 0,0,8,8\n";
+        let asset = read(&mut src.as_bytes(), Path::new(".")).unwrap();
+        assert_eq!(asset.sprites[0].group, "chars_old");
+        assert_eq!(asset.sprites[0].name, "hero");
+
+        asset.write(&mut Vec::new()).unwrap();
+    }
+
+    #[test]
+    fn rotated_region_is_rejected() {
+        let src = "page.png\nsize: 16,16\nhero\nbounds: 0,0,8,8\nrotate: 90\n";
+        assert!(matches!(
+            read(&mut src.as_bytes(), Path::new(".")),
+            Err(ReadError::UnsupportedRotation(name)) if name == "hero"
+        ));
+    }
+}
@@ -0,0 +1,141 @@
+//! Grid slicing of uniform sprite sheets
+
+use image::{GenericImageView, Pixel, Primitive};
+
+#[derive(Debug, Clone, Copy)]
+pub struct GridSliceParams {
+    pub cell_width: u32,
+    pub cell_height: u32,
+    /// シート外周の余白
+    pub margin: u32,
+    /// セル同士の間隔
+    pub spacing: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GridCell {
+    /// 行優先で数えたセルの番号（空のセルも数える）
+    pub index: u32,
+    pub left: u32,
+    pub top: u32,
+}
+
+/// 完全に透明ではないセルを列挙する
+pub fn non_empty_cells(image: &impl GenericImageView, params: &GridSliceParams) -> Vec<GridCell> {
+    if params.cell_width == 0 || params.cell_height == 0 {
+        return Vec::new();
+    }
+
+    let (width, height) = image.dimensions();
+    let columns = cell_count(width, params.cell_width, params.margin, params.spacing);
+    let rows = cell_count(height, params.cell_height, params.margin, params.spacing);
+
+    let mut cells = Vec::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            let left = params.margin + column * (params.cell_width + params.spacing);
+            let top = params.margin + row * (params.cell_height + params.spacing);
+
+            let view = image.view(left, top, params.cell_width, params.cell_height);
+            if view.pixels().all(|(_, _, p)| is_transparent(&p)) {
+                continue;
+            }
+
+            cells.push(GridCell {
+                index: row * columns + column,
+                left,
+                top,
+            });
+        }
+    }
+
+    cells
+}
+
+/// `{stem}_{n}`の形の名前のうち、いちばん大きいnの次（ひとつもなければ0）
+///
+/// 同じシートから何度か取り込んでも名前がぶつからないように、続きの番号を振るためのもの
+pub fn next_name_index<'a>(stem: &str, names: impl IntoIterator<Item = &'a str>) -> u32 {
+    names
+        .into_iter()
+        .filter_map(|x| x.strip_prefix(stem)?.strip_prefix('_')?.parse::<u32>().ok())
+        .max()
+        .map_or(0, |n| n + 1)
+}
+
+/// はみ出さずに並べられるセルの数
+const fn cell_count(length: u32, cell_length: u32, margin: u32, spacing: u32) -> u32 {
+    let Some(usable) = length.checked_sub(margin * 2) else {
+        return 0;
+    };
+    if usable < cell_length {
+        return 0;
+    }
+
+    (usable - cell_length) / (cell_length + spacing) + 1
+}
+
+#[inline]
+fn is_transparent<P: Pixel>(p: &P) -> bool {
+    // Note: アルファチャンネルがない画像は常に不透明扱いになる
+    p.to_rgba().0[3] == P::Subpixel::DEFAULT_MIN_VALUE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 4x3セル（8x8、余白2、間隔1）のシートで、`opaque`のセルだけ左上に1ピクセル描く
+    fn sheet(opaque: &[(u32, u32)]) -> image::RgbaImage {
+        let mut image = image::RgbaImage::new(2 * 2 + 4 * 8 + 3, 2 * 2 + 3 * 8 + 2);
+        for &(column, row) in opaque {
+            image.put_pixel(2 + column * 9, 2 + row * 9, image::Rgba([255, 0, 0, 1]));
+        }
+
+        image
+    }
+
+    const PARAMS: GridSliceParams = GridSliceParams {
+        cell_width: 8,
+        cell_height: 8,
+        margin: 2,
+        spacing: 1,
+    };
+
+    #[test]
+    fn only_non_empty_cells_are_listed() {
+        let cells = non_empty_cells(&sheet(&[(0, 0), (3, 0), (1, 2)]), &PARAMS);
+        assert_eq!(
+            cells
+                .iter()
+                .map(|c| (c.index, c.left, c.top))
+                .collect::<Vec<_>>(),
+            [(0, 2, 2), (3, 29, 2), (9, 11, 20)]
+        );
+    }
+
+    #[test]
+    fn partial_cells_are_not_counted() {
+        // 右端と下端に1ピクセルずつたりない
+        assert_eq!(cell_count(2 * 2 + 4 * 8 + 3 - 1, 8, 2, 1), 3);
+        assert_eq!(cell_count(2 * 2 + 4 * 8 + 3, 8, 2, 1), 4);
+        assert_eq!(cell_count(3, 8, 2, 1), 0);
+        assert_eq!(cell_count(11, 8, 2, 1), 0);
+        assert_eq!(cell_count(12, 8, 2, 1), 1);
+
+        let zero = GridSliceParams {
+            cell_width: 0,
+            ..PARAMS
+        };
+        assert!(non_empty_cells(&sheet(&[(0, 0)]), &zero).is_empty());
+    }
+
+    #[test]
+    fn name_index_continues_after_existing() {
+        assert_eq!(next_name_index("hero", []), 0);
+        assert_eq!(
+            next_name_index("hero", ["hero_0", "hero_7", "hero_x", "heroes_9", "hero"]),
+            8
+        );
+    }
+}
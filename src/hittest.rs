@@ -1,15 +1,31 @@
 use std::{collections::BTreeSet, rc::Rc};
 
-use windows::Win32::{Foundation::HWND, UI::WindowsAndMessaging::HCURSOR};
-
-use crate::input::EventContinueControl;
+use crate::input::{
+    CursorHandle, EventContinueControl, KeyCode, ModifierKeys, PointerButton, WindowHandle,
+};
 
+#[derive(Clone, Copy)]
 pub struct PointerActionArgs {
-    pub hwnd: HWND,
+    pub window: WindowHandle,
     pub client_x: f32,
     pub client_y: f32,
     pub client_width: f32,
     pub client_height: f32,
+    pub modifiers: ModifierKeys,
+}
+
+/// ホイールの回転量（1.0で1ノッチ）
+///
+/// yは奥に回したときに正、xは右に倒したときに正
+#[derive(Clone, Copy, Debug)]
+pub struct WheelDelta {
+    pub x: f32,
+    pub y: f32,
+}
+
+pub struct KeyActionArgs {
+    pub window: WindowHandle,
+    pub key: KeyCode,
 }
 
 pub trait HitTestTreeActionHandler {
@@ -21,7 +37,7 @@ pub trait HitTestTreeActionHandler {
     }
 
     #[allow(unused_variables)]
-    fn cursor(&self, sender: HitTestTreeRef, context: &mut Self::Context) -> Option<HCURSOR> {
+    fn cursor(&self, sender: HitTestTreeRef, context: &mut Self::Context) -> Option<CursorHandle> {
         None
     }
 
@@ -90,6 +106,108 @@ pub trait HitTestTreeActionHandler {
     ) -> EventContinueControl {
         EventContinueControl::empty()
     }
+
+    /// 左ボタンのダブルクリック（1回目と2回目の`on_click`のあとに来る）
+    #[allow(unused_variables)]
+    fn on_double_click(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut Self::Context,
+        ht: &mut HitTestTreeManager<Self::Context>,
+        args: PointerActionArgs,
+    ) -> EventContinueControl {
+        EventContinueControl::empty()
+    }
+
+    #[allow(unused_variables)]
+    fn on_aux_pointer_down(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut Self::Context,
+        ht: &mut HitTestTreeManager<Self::Context>,
+        args: PointerActionArgs,
+        button: PointerButton,
+    ) -> EventContinueControl {
+        EventContinueControl::empty()
+    }
+
+    #[allow(unused_variables)]
+    fn on_aux_pointer_up(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut Self::Context,
+        ht: &mut HitTestTreeManager<Self::Context>,
+        args: PointerActionArgs,
+        button: PointerButton,
+    ) -> EventContinueControl {
+        EventContinueControl::empty()
+    }
+
+    #[allow(unused_variables)]
+    fn on_aux_click(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut Self::Context,
+        ht: &mut HitTestTreeManager<Self::Context>,
+        args: PointerActionArgs,
+        button: PointerButton,
+    ) -> EventContinueControl {
+        EventContinueControl::empty()
+    }
+
+    #[allow(unused_variables)]
+    fn on_wheel(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut Self::Context,
+        ht: &mut HitTestTreeManager<Self::Context>,
+        args: PointerActionArgs,
+        delta: WheelDelta,
+    ) -> EventContinueControl {
+        EventContinueControl::empty()
+    }
+
+    /// `focus_visible`はキーボード操作でフォーカスされたとき（フォーカスの表示を出すべきとき）にtrue
+    #[allow(unused_variables)]
+    fn on_focus(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut Self::Context,
+        ht: &mut HitTestTreeManager<Self::Context>,
+        focus_visible: bool,
+    ) {
+    }
+
+    #[allow(unused_variables)]
+    fn on_blur(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut Self::Context,
+        ht: &mut HitTestTreeManager<Self::Context>,
+    ) {
+    }
+
+    #[allow(unused_variables)]
+    fn on_key_down(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut Self::Context,
+        ht: &mut HitTestTreeManager<Self::Context>,
+        args: KeyActionArgs,
+    ) -> EventContinueControl {
+        EventContinueControl::empty()
+    }
+
+    #[allow(unused_variables)]
+    fn on_char(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut Self::Context,
+        ht: &mut HitTestTreeManager<Self::Context>,
+        ch: char,
+    ) -> EventContinueControl {
+        EventContinueControl::empty()
+    }
 }
 
 pub struct HitTestTreeData<ActionContext> {
@@ -103,6 +221,8 @@ pub struct HitTestTreeData<ActionContext> {
     pub height_adjustment_factor: f32,
     pub parent: Option<HitTestTreeRef>,
     pub children: Vec<HitTestTreeRef>,
+    /// キーボードフォーカスを受け取れるか（Tabでの移動対象になる）
+    pub focusable: bool,
     pub action_handler:
         Option<std::rc::Weak<dyn HitTestTreeActionHandler<Context = ActionContext>>>,
 }
@@ -117,19 +237,27 @@ impl<ActionContext> HitTestTreeData<ActionContext> {
     }
 }
 
-#[repr(transparent)]
+/// ノードへの参照
+///
+/// 解放されたノードの場所は再利用されるので、世代番号で古い参照を見分ける
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub struct HitTestTreeRef(usize);
+pub struct HitTestTreeRef {
+    index: usize,
+    generation: u32,
+}
 
 pub struct HitTestTreeManager<ActionContext> {
-    pub entities: Vec<HitTestTreeData<ActionContext>>,
-    pub free: BTreeSet<usize>,
+    entities: Vec<HitTestTreeData<ActionContext>>,
+    /// entitiesと同じ並びで、解放されるたびに1増える
+    generations: Vec<u32>,
+    free: BTreeSet<usize>,
 }
 impl<ActionContext> HitTestTreeManager<ActionContext> {
     #[inline]
     pub fn new() -> Self {
         Self {
             entities: Vec::new(),
+            generations: Vec::new(),
             free: BTreeSet::new(),
         }
     }
@@ -137,50 +265,145 @@ impl<ActionContext> HitTestTreeManager<ActionContext> {
     pub fn alloc(&mut self, data: HitTestTreeData<ActionContext>) -> HitTestTreeRef {
         if let Some(f) = self.free.pop_first() {
             self.entities[f] = data;
-            return HitTestTreeRef(f);
+            return HitTestTreeRef {
+                index: f,
+                generation: self.generations[f],
+            };
         }
 
         self.entities.push(data);
-        HitTestTreeRef(self.entities.len() - 1)
+        self.generations.push(0);
+        HitTestTreeRef {
+            index: self.entities.len() - 1,
+            generation: 0,
+        }
     }
 
+    /// 参照先がまだ解放されていないか
     #[inline]
-    pub fn free(&mut self, index: HitTestTreeRef) {
-        self.free.insert(index.0);
+    pub fn is_alive(&self, index: HitTestTreeRef) -> bool {
+        self.generations.get(index.index) == Some(&index.generation)
     }
 
-    pub fn free_rec(&mut self, index: HitTestTreeRef) {
-        let mut stack = vec![index];
-        while !stack.is_empty() {
-            for x in core::mem::replace(&mut stack, Vec::new()) {
-                stack.extend(self.entities[x.0].children.iter().copied());
+    #[inline]
+    fn check(&self, index: HitTestTreeRef) -> Result<usize, HitTestTreeError> {
+        if !self.is_alive(index) {
+            return Err(HitTestTreeError::StaleRef(index));
+        }
 
-                self.free(x);
-            }
+        Ok(index.index)
+    }
+
+    /// ノードを解放する
+    ///
+    /// 親からは切り離され、子は親なしになる（子自体は解放されない）
+    pub fn free(&mut self, index: HitTestTreeRef) -> Result<(), HitTestTreeError> {
+        let n = self.check(index)?;
+
+        self.remove_child(index);
+        for c in core::mem::take(&mut self.entities[n].children) {
+            self.entities[c.index].parent = None;
+        }
+        // Note: 古い参照からアクセスできないようにハンドラも外しておく
+        self.entities[n].action_handler = None;
+        self.generations[n] = self.generations[n].wrapping_add(1);
+        self.free.insert(n);
+
+        Ok(())
+    }
+
+    /// ノードを子孫ごと解放する（親からは切り離される）
+    pub fn free_rec(&mut self, index: HitTestTreeRef) -> Result<(), HitTestTreeError> {
+        self.check(index)?;
+        self.remove_child(index);
+
+        let mut stack = vec![index];
+        while let Some(x) = stack.pop() {
+            stack.extend(self.entities[x.index].children.iter().copied());
+            self.free(x)?;
         }
+
+        Ok(())
     }
 
+    #[inline]
+    pub fn try_get(
+        &self,
+        index: HitTestTreeRef,
+    ) -> Result<&HitTestTreeData<ActionContext>, HitTestTreeError> {
+        Ok(&self.entities[self.check(index)?])
+    }
+
+    #[inline]
+    pub fn try_get_mut(
+        &mut self,
+        index: HitTestTreeRef,
+    ) -> Result<&mut HitTestTreeData<ActionContext>, HitTestTreeError> {
+        let n = self.check(index)?;
+
+        Ok(&mut self.entities[n])
+    }
+
+    /// 解放済みのノードを参照するとpanicする
     #[inline]
     pub fn get(&self, index: HitTestTreeRef) -> &HitTestTreeData<ActionContext> {
-        &self.entities[index.0]
+        match self.try_get(index) {
+            Ok(x) => x,
+            Err(e) => panic!("{e}"),
+        }
     }
 
+    /// 解放済みのノードを参照するとpanicする
     #[inline]
     pub fn get_mut(&mut self, index: HitTestTreeRef) -> &mut HitTestTreeData<ActionContext> {
-        &mut self.entities[index.0]
+        match self.try_get_mut(index) {
+            Ok(x) => x,
+            Err(e) => panic!("{e}"),
+        }
     }
 
+    /// 解放済みのノードを指定するとpanicする
     pub fn add_child(&mut self, parent: HitTestTreeRef, child: HitTestTreeRef) {
-        self.entities[parent.0].children.push(child);
-        self.entities[child.0].parent = Some(parent);
+        // Note: 別の親についたままだとその親のchildrenに残ってしまうので先に外す
+        self.remove_child(child);
+
+        self.get_mut(parent).children.push(child);
+        self.get_mut(child).parent = Some(parent);
     }
 
+    /// 解放済みのノードを指定するとpanicする
     pub fn remove_child(&mut self, child: HitTestTreeRef) {
-        let Some(p) = self.entities[child.0].parent.take() else {
+        let Some(p) = self.get_mut(child).parent.take() else {
             return;
         };
 
-        self.entities[p.0].children.retain(|&x| x != child);
+        self.get_mut(p).children.retain(|&x| x != child);
+    }
+
+    /// フォーカスを受け取れるノードをTabでの移動順（木の行きがけ順）に並べる
+    ///
+    /// ヒットしない（`hit_active`がfalseの）ノードの下は見ない
+    pub fn collect_focusable(
+        &self,
+        context: &ActionContext,
+        root: HitTestTreeRef,
+    ) -> Vec<HitTestTreeRef> {
+        let mut result = Vec::new();
+        let mut stack = vec![root];
+        while let Some(x) = stack.pop() {
+            let e = self.get(x);
+            if !e.action_handler().is_none_or(|a| a.hit_active(x, context)) {
+                continue;
+            }
+
+            if e.focusable {
+                result.push(x);
+            }
+            // 先頭の子から見るように逆順で積む
+            stack.extend(e.children.iter().rev().copied());
+        }
+
+        result
     }
 
     pub fn dump(&self, root: HitTestTreeRef) {
@@ -193,10 +416,11 @@ impl<ActionContext> HitTestTreeManager<ActionContext> {
                 print!("  ");
             }
 
-            let e = &this.entities[x.0];
+            let e = this.get(x);
             println!(
-                "#{}: (x{}+{}, x{}+{}) size (x{}+{}, x{}+{})",
-                x.0,
+                "#{}@{}: (x{}+{}, x{}+{}) size (x{}+{}, x{}+{})",
+                x.index,
+                x.generation,
                 e.left_adjustment_factor,
                 e.left,
                 e.top_adjustment_factor,
@@ -223,7 +447,7 @@ impl<ActionContext> HitTestTreeManager<ActionContext> {
         client_width: f32,
         client_height: f32,
     ) -> (f32, f32, f32, f32) {
-        let e = &self.entities[x.0];
+        let e = self.get(x);
         match e.parent {
             None => {
                 // parent = clientなので直接計算する
@@ -271,7 +495,7 @@ impl<ActionContext> HitTestTreeManager<ActionContext> {
         parent_global_width: f32,
         parent_global_height: f32,
     ) -> Option<HitTestTreeRef> {
-        let e = &self.entities[x.0];
+        let e = self.get(x);
         if !e
             .action_handler
             .as_ref()
@@ -320,3 +544,83 @@ impl<ActionContext> HitTestTreeManager<ActionContext> {
         }
     }
 }
+
+#[derive(Debug, thiserror::Error)]
+pub enum HitTestTreeError {
+    #[error("stale or invalid hit test tree ref: {0:?}")]
+    StaleRef(HitTestTreeRef),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node() -> HitTestTreeData<()> {
+        HitTestTreeData {
+            left: 0.0,
+            top: 0.0,
+            left_adjustment_factor: 0.0,
+            top_adjustment_factor: 0.0,
+            width: 0.0,
+            height: 0.0,
+            width_adjustment_factor: 1.0,
+            height_adjustment_factor: 1.0,
+            parent: None,
+            children: Vec::new(),
+            focusable: false,
+            action_handler: None,
+        }
+    }
+
+    #[test]
+    fn stale_ref_after_free_and_realloc() {
+        let mut ht = HitTestTreeManager::new();
+        let old = ht.alloc(node());
+        ht.free(old).unwrap();
+        let new = ht.alloc(node());
+
+        // 同じ場所が再利用されても古い参照からは触れない
+        assert_eq!(old.index, new.index);
+        assert!(!ht.is_alive(old));
+        assert!(matches!(
+            ht.try_get(old),
+            Err(HitTestTreeError::StaleRef(r)) if r == old
+        ));
+        assert!(matches!(ht.free(old), Err(HitTestTreeError::StaleRef(_))));
+        assert!(ht.try_get(new).is_ok());
+    }
+
+    #[test]
+    fn free_rec_detaches_from_parent() {
+        let mut ht = HitTestTreeManager::new();
+        let root = ht.alloc(node());
+        let child = ht.alloc(node());
+        let grandchild = ht.alloc(node());
+        let sibling = ht.alloc(node());
+        ht.add_child(root, child);
+        ht.add_child(child, grandchild);
+        ht.add_child(root, sibling);
+
+        ht.free_rec(child).unwrap();
+
+        assert!(!ht.is_alive(child));
+        assert!(!ht.is_alive(grandchild));
+        assert_eq!(ht.get(root).children, [sibling]);
+        assert_eq!(ht.get(sibling).parent, Some(root));
+    }
+
+    #[test]
+    fn add_child_reparents() {
+        let mut ht = HitTestTreeManager::new();
+        let first = ht.alloc(node());
+        let second = ht.alloc(node());
+        let child = ht.alloc(node());
+
+        ht.add_child(first, child);
+        ht.add_child(second, child);
+
+        assert!(ht.get(first).children.is_empty());
+        assert_eq!(ht.get(second).children, [child]);
+        assert_eq!(ht.get(child).parent, Some(second));
+    }
+}
@@ -1,17 +1,12 @@
+use std::time::{Duration, Instant};
+
 use bitflags::bitflags;
-use windows::{
-    Foundation::Size,
-    Win32::{
-        Foundation::HWND,
-        UI::{
-            Input::KeyboardAndMouse::{ReleaseCapture, SetCapture},
-            WindowsAndMessaging::HCURSOR,
-        },
-    },
-};
 use windows_numerics::Vector2;
 
-use crate::hittest::{HitTestTreeManager, HitTestTreeRef, PointerActionArgs};
+use crate::hittest::{
+    HitTestTreeActionHandler, HitTestTreeManager, HitTestTreeRef, KeyActionArgs, PointerActionArgs,
+    WheelDelta,
+};
 
 const CLICK_DETECTION_MAX_DISTNACE: f32 = 4.0;
 
@@ -22,39 +17,230 @@ bitflags! {
         const CAPTURE_ELEMENT = 1 << 1;
         const RELEASE_CAPTURE_ELEMENT = 1 << 2;
         const RECOMPUTE_POINTER_ENTER = 1 << 3;
+        /// キーボードフォーカスを外す
+        const RELEASE_FOCUS = 1 << 4;
     }
 }
 
+bitflags! {
+    /// ポインタ操作時に押されていた修飾キー
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct ModifierKeys: u8 {
+        const SHIFT = 1 << 0;
+        const CONTROL = 1 << 1;
+        const ALT = 1 << 2;
+    }
+}
+
+/// 左ボタン以外のボタン
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PointerButton {
+    Right,
+    Middle,
+}
+
 pub enum PointerFocusState {
     None,
     Entering(HitTestTreeRef),
     Capturing(HitTestTreeRef),
 }
 
-pub struct PointerInputManager {
+/// ウィンドウのハンドル（Win32ではHWND）
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WindowHandle(pub *mut core::ffi::c_void);
+
+/// マウスカーソルのハンドル（Win32ではHCURSOR）
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CursorHandle(pub *mut core::ffi::c_void);
+
+/// クライアント領域の大きさ（DIP）
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ClientSize {
+    pub width: f32,
+    pub height: f32,
+}
+
+/// キーコード（値はWin32の仮想キーコードと同じ）
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyCode(pub u16);
+impl KeyCode {
+    pub const TAB: Self = Self(0x09);
+    pub const RETURN: Self = Self(0x0d);
+    pub const ESCAPE: Self = Self(0x1b);
+    pub const SPACE: Self = Self(0x20);
+    pub const UP: Self = Self(0x26);
+    pub const DOWN: Self = Self(0x28);
+}
+
+/// ポインタ入力の処理で必要になるプラットフォーム側の機能
+///
+/// ウィンドウなしで入力を再生するときは差し替える（`tests/input_replay.rs`を参照）
+pub trait InputPlatform {
+    fn set_capture(&self, window: WindowHandle);
+    fn release_capture(&self);
+    fn now(&self) -> Instant;
+    fn double_click_interval(&self) -> Duration;
+}
+
+#[cfg(windows)]
+pub use self::win32::Win32InputPlatform;
+
+#[cfg(windows)]
+mod win32 {
+    use std::time::{Duration, Instant};
+
+    use windows::{
+        Foundation::Size,
+        Win32::{
+            Foundation::HWND,
+            UI::{
+                Input::KeyboardAndMouse::{
+                    GetDoubleClickTime, ReleaseCapture, SetCapture, VIRTUAL_KEY,
+                },
+                WindowsAndMessaging::HCURSOR,
+            },
+        },
+    };
+
+    use super::{ClientSize, CursorHandle, InputPlatform, KeyCode, WindowHandle};
+
+    impl From<HWND> for WindowHandle {
+        fn from(value: HWND) -> Self {
+            Self(value.0)
+        }
+    }
+    impl From<WindowHandle> for HWND {
+        fn from(value: WindowHandle) -> Self {
+            Self(value.0)
+        }
+    }
+
+    impl From<HCURSOR> for CursorHandle {
+        fn from(value: HCURSOR) -> Self {
+            Self(value.0)
+        }
+    }
+    impl From<CursorHandle> for HCURSOR {
+        fn from(value: CursorHandle) -> Self {
+            Self(value.0)
+        }
+    }
+
+    impl From<Size> for ClientSize {
+        fn from(value: Size) -> Self {
+            Self {
+                width: value.Width,
+                height: value.Height,
+            }
+        }
+    }
+
+    impl From<VIRTUAL_KEY> for KeyCode {
+        fn from(value: VIRTUAL_KEY) -> Self {
+            Self(value.0)
+        }
+    }
+
+    pub struct Win32InputPlatform;
+    impl InputPlatform for Win32InputPlatform {
+        fn set_capture(&self, window: WindowHandle) {
+            unsafe {
+                SetCapture(window.into());
+            }
+        }
+
+        fn release_capture(&self) {
+            unsafe {
+                ReleaseCapture().expect("Failed to release captured pointer");
+            }
+        }
+
+        #[inline]
+        fn now(&self) -> Instant {
+            Instant::now()
+        }
+
+        fn double_click_interval(&self) -> Duration {
+            Duration::from_millis(unsafe { GetDoubleClickTime() } as _)
+        }
+    }
+}
+
+pub struct PointerInputManager<Platform: InputPlatform> {
+    platform: Platform,
     last_client_pointer_pos: Option<Vector2>,
     pointer_focus: PointerFocusState,
     click_base_client_pointer_pos: Option<Vector2>,
+    aux_click_base: Option<(PointerButton, Vector2)>,
+    /// 直前のクリック（ダブルクリック判定用）
+    last_click: Option<(Instant, Vector2)>,
+    double_click_interval: Duration,
 }
-impl PointerInputManager {
+#[cfg(windows)]
+impl PointerInputManager<Win32InputPlatform> {
     pub fn new() -> Self {
+        Self::with_platform(Win32InputPlatform)
+    }
+}
+impl<Platform: InputPlatform> PointerInputManager<Platform> {
+    pub fn with_platform(platform: Platform) -> Self {
         Self {
+            // Note: 既定ではシステムの設定に合わせる
+            double_click_interval: platform.double_click_interval(),
+            platform,
             last_client_pointer_pos: None,
             pointer_focus: PointerFocusState::None,
             click_base_client_pointer_pos: None,
+            aux_click_base: None,
+            last_click: None,
+        }
+    }
+
+    pub const fn platform(&self) -> &Platform {
+        &self.platform
+    }
+
+    /// いまキャプチャ中の要素
+    pub fn capturing(&self) -> Option<HitTestTreeRef> {
+        match self.pointer_focus {
+            PointerFocusState::Capturing(tr) => Some(tr),
+            _ => None,
+        }
+    }
+
+    pub fn set_double_click_interval(&mut self, interval: Duration) {
+        self.double_click_interval = interval;
+    }
+
+    /// フォーカス中の要素が解放されていたら忘れる
+    fn forget_stale_pointer_focus<ActionContext>(
+        &mut self,
+        ht: &HitTestTreeManager<ActionContext>,
+    ) {
+        match self.pointer_focus {
+            PointerFocusState::Entering(tr) if !ht.is_alive(tr) => {
+                self.pointer_focus = PointerFocusState::None;
+            }
+            PointerFocusState::Capturing(tr) if !ht.is_alive(tr) => {
+                self.platform.release_capture();
+                self.pointer_focus = PointerFocusState::None;
+            }
+            _ => (),
         }
     }
 
     pub fn on_mouse_move<ActionContext>(
         &mut self,
-        hwnd: HWND,
+        window: WindowHandle,
         ht: &mut HitTestTreeManager<ActionContext>,
         action_context: &mut ActionContext,
         ht_root: HitTestTreeRef,
-        client_size: Size,
+        client_size: ClientSize,
         client_x: f32,
         client_y: f32,
+        modifiers: ModifierKeys,
     ) {
+        self.forget_stale_pointer_focus(ht);
         self.last_client_pointer_pos = Some(Vector2 {
             X: client_x,
             Y: client_y,
@@ -68,6 +254,13 @@ impl PointerInputManager {
                 self.click_base_client_pointer_pos = None;
             }
         }
+        if let Some((_, ref c)) = self.aux_click_base {
+            let d = (c.X - client_x).powi(2) + (c.Y - client_y).powi(2);
+
+            if d >= CLICK_DETECTION_MAX_DISTNACE * CLICK_DETECTION_MAX_DISTNACE {
+                self.aux_click_base = None;
+            }
+        }
 
         if let PointerFocusState::Capturing(tr) = self.pointer_focus {
             let _ = ht
@@ -79,11 +272,12 @@ impl PointerInputManager {
                         action_context,
                         ht,
                         PointerActionArgs {
-                            hwnd,
+                            window,
                             client_x,
                             client_y,
-                            client_width: client_size.Width,
-                            client_height: client_size.Height,
+                            client_width: client_size.width,
+                            client_height: client_size.height,
+                            modifiers,
                         },
                     )
                 });
@@ -98,28 +292,63 @@ impl PointerInputManager {
             client_y,
             0.0,
             0.0,
-            client_size.Width,
-            client_size.Height,
+            client_size.width,
+            client_size.height,
         );
-        if let PointerFocusState::Entering(tr) = self.pointer_focus {
-            if Some(tr) != new_hit {
-                // entering changed: leave and enter
+        // Note: Capturingは先に抜けているので、ここではEnteringかNoneのどちらか
+        let last_hit = match self.pointer_focus {
+            PointerFocusState::Entering(tr) => Some(tr),
+            _ => None,
+        };
+        if last_hit != new_hit {
+            // entering changed: leave and enter
+            let mut p = last_hit;
+            while let Some(tr) = p {
+                let t = ht.get(tr);
+                let next = t.parent;
+                let action_handler = t.action_handler();
+                let cont = action_handler.map_or(EventContinueControl::empty(), |a| {
+                    a.on_pointer_leave(
+                        tr,
+                        action_context,
+                        ht,
+                        PointerActionArgs {
+                            window,
+                            client_x,
+                            client_y,
+                            client_width: client_size.width,
+                            client_height: client_size.height,
+                            modifiers,
+                        },
+                    )
+                });
+                if cont.contains(EventContinueControl::STOP_PROPAGATION) {
+                    break;
+                }
+
+                // Note: ハンドラの中で親が解放されていることもある（以降のバブリングも同様）
+                p = next.filter(|&x| ht.is_alive(x));
+            }
+
+            // Note: leaveのハンドラの中で解放されていることもある
+            if let Some(tr) = new_hit.filter(|&x| ht.is_alive(x)) {
                 let mut p = Some(tr);
                 while let Some(tr) = p {
                     let t = ht.get(tr);
                     let next = t.parent;
                     let action_handler = t.action_handler();
                     let cont = action_handler.map_or(EventContinueControl::empty(), |a| {
-                        a.on_pointer_leave(
+                        a.on_pointer_enter(
                             tr,
                             action_context,
                             ht,
                             PointerActionArgs {
-                                hwnd,
+                                window,
                                 client_x,
                                 client_y,
-                                client_width: client_size.Width,
-                                client_height: client_size.Height,
+                                client_width: client_size.width,
+                                client_height: client_size.height,
+                                modifiers,
                             },
                         )
                     });
@@ -127,35 +356,7 @@ impl PointerInputManager {
                         break;
                     }
 
-                    p = next;
-                }
-
-                if let Some(tr) = new_hit {
-                    let mut p = Some(tr);
-                    while let Some(tr) = p {
-                        let t = ht.get(tr);
-                        let next = t.parent;
-                        let action_handler = t.action_handler();
-                        let cont = action_handler.map_or(EventContinueControl::empty(), |a| {
-                            a.on_pointer_enter(
-                                tr,
-                                action_context,
-                                ht,
-                                PointerActionArgs {
-                                    hwnd,
-                                    client_x,
-                                    client_y,
-                                    client_width: client_size.Width,
-                                    client_height: client_size.Height,
-                                },
-                            )
-                        });
-                        if cont.contains(EventContinueControl::STOP_PROPAGATION) {
-                            break;
-                        }
-
-                        p = next;
-                    }
+                    p = next.filter(|&x| ht.is_alive(x));
                 }
             }
         }
@@ -165,7 +366,7 @@ impl PointerInputManager {
             None => PointerFocusState::None,
         };
 
-        let mut p = new_hit;
+        let mut p = new_hit.filter(|&x| ht.is_alive(x));
         while let Some(tr) = p {
             let t = ht.get(tr);
             let next = t.parent;
@@ -176,43 +377,47 @@ impl PointerInputManager {
                     action_context,
                     ht,
                     PointerActionArgs {
-                        hwnd,
+                        window,
                         client_x,
                         client_y,
-                        client_width: client_size.Width,
-                        client_height: client_size.Height,
+                        client_width: client_size.width,
+                        client_height: client_size.height,
+                        modifiers,
                     },
                 )
             });
             if flags.contains(EventContinueControl::RECOMPUTE_POINTER_ENTER) {
                 self.on_mouse_move(
-                    hwnd,
+                    window,
                     ht,
                     action_context,
                     ht_root,
                     client_size,
                     client_x,
                     client_y,
+                    modifiers,
                 );
             }
             if flags.contains(EventContinueControl::STOP_PROPAGATION) {
                 break;
             }
 
-            p = next;
+            p = next.filter(|&x| ht.is_alive(x));
         }
     }
 
     pub fn on_mouse_left_down<ActionContext>(
         &mut self,
-        hwnd: HWND,
+        window: WindowHandle,
         ht: &mut HitTestTreeManager<ActionContext>,
         action_context: &mut ActionContext,
         ht_root: HitTestTreeRef,
-        client_size: Size,
+        client_size: ClientSize,
         client_x: f32,
         client_y: f32,
+        modifiers: ModifierKeys,
     ) {
+        self.forget_stale_pointer_focus(ht);
         self.click_base_client_pointer_pos = Some(Vector2 {
             X: client_x,
             Y: client_y,
@@ -229,38 +434,39 @@ impl PointerInputManager {
                                 action_context,
                                 ht,
                                 PointerActionArgs {
-                                    hwnd,
+                                    window,
                                     client_x,
                                     client_y,
-                                    client_width: client_size.Width,
-                                    client_height: client_size.Height,
+                                    client_width: client_size.width,
+                                    client_height: client_size.height,
+                                    modifiers,
                                 },
                             )
                         });
                 if flags.contains(EventContinueControl::RECOMPUTE_POINTER_ENTER) {
                     self.on_mouse_move(
-                        hwnd,
+                        window,
                         ht,
                         action_context,
                         ht_root,
                         client_size,
                         client_x,
                         client_y,
+                        modifiers,
                     );
                 }
                 if flags.contains(EventContinueControl::RELEASE_CAPTURE_ELEMENT) {
-                    unsafe {
-                        ReleaseCapture().expect("Failed to release captured pointer");
-                    }
+                    self.platform.release_capture();
                     self.pointer_focus = PointerFocusState::Entering(tr);
                     self.on_mouse_move(
-                        hwnd,
+                        window,
                         ht,
                         action_context,
                         ht_root,
                         client_size,
                         client_x,
                         client_y,
+                        modifiers,
                     );
                 }
             }
@@ -277,36 +483,36 @@ impl PointerInputManager {
                             action_context,
                             ht,
                             PointerActionArgs {
-                                hwnd,
+                                window,
                                 client_x,
                                 client_y,
-                                client_width: client_size.Width,
-                                client_height: client_size.Height,
+                                client_width: client_size.width,
+                                client_height: client_size.height,
+                                modifiers,
                             },
                         )
                     });
                     if flags.contains(EventContinueControl::RECOMPUTE_POINTER_ENTER) {
                         self.on_mouse_move(
-                            hwnd,
+                            window,
                             ht,
                             action_context,
                             ht_root,
                             client_size,
                             client_x,
                             client_y,
+                            modifiers,
                         );
                     }
                     if flags.contains(EventContinueControl::CAPTURE_ELEMENT) {
                         self.pointer_focus = PointerFocusState::Capturing(tr);
-                        unsafe {
-                            SetCapture(hwnd);
-                        }
+                        self.platform.set_capture(window);
                     }
                     if flags.contains(EventContinueControl::STOP_PROPAGATION) {
                         break;
                     }
 
-                    p = next;
+                    p = next.filter(|&x| ht.is_alive(x));
                 }
             }
             PointerFocusState::None => (),
@@ -315,22 +521,24 @@ impl PointerInputManager {
 
     pub fn on_mouse_left_up<ActionContext>(
         &mut self,
-        hwnd: HWND,
+        window: WindowHandle,
         ht: &mut HitTestTreeManager<ActionContext>,
         action_context: &mut ActionContext,
         ht_root: HitTestTreeRef,
-        client_size: Size,
+        client_size: ClientSize,
         client_x: f32,
         client_y: f32,
+        modifiers: ModifierKeys,
     ) {
         self.on_mouse_move(
-            hwnd,
+            window,
             ht,
             action_context,
             ht_root,
             client_size,
             client_x,
             client_y,
+            modifiers,
         );
 
         match self.pointer_focus {
@@ -344,38 +552,39 @@ impl PointerInputManager {
                                 action_context,
                                 ht,
                                 PointerActionArgs {
-                                    hwnd,
+                                    window,
                                     client_x,
                                     client_y,
-                                    client_width: client_size.Width,
-                                    client_height: client_size.Height,
+                                    client_width: client_size.width,
+                                    client_height: client_size.height,
+                                    modifiers,
                                 },
                             )
                         });
                 if flags.contains(EventContinueControl::RECOMPUTE_POINTER_ENTER) {
                     self.on_mouse_move(
-                        hwnd,
+                        window,
                         ht,
                         action_context,
                         ht_root,
                         client_size,
                         client_x,
                         client_y,
+                        modifiers,
                     );
                 }
                 if flags.contains(EventContinueControl::RELEASE_CAPTURE_ELEMENT) {
-                    unsafe {
-                        ReleaseCapture().expect("Failed to release captured pointer");
-                    }
+                    self.platform.release_capture();
                     self.pointer_focus = PointerFocusState::Entering(tr);
                     self.on_mouse_move(
-                        hwnd,
+                        window,
                         ht,
                         action_context,
                         ht_root,
                         client_size,
                         client_x,
                         client_y,
+                        modifiers,
                     );
                 }
             }
@@ -392,36 +601,36 @@ impl PointerInputManager {
                             action_context,
                             ht,
                             PointerActionArgs {
-                                hwnd,
+                                window,
                                 client_x,
                                 client_y,
-                                client_width: client_size.Width,
-                                client_height: client_size.Height,
+                                client_width: client_size.width,
+                                client_height: client_size.height,
+                                modifiers,
                             },
                         )
                     });
                     if flags.contains(EventContinueControl::RECOMPUTE_POINTER_ENTER) {
                         self.on_mouse_move(
-                            hwnd,
+                            window,
                             ht,
                             action_context,
                             ht_root,
                             client_size,
                             client_x,
                             client_y,
+                            modifiers,
                         );
                     }
                     if flags.contains(EventContinueControl::CAPTURE_ELEMENT) {
                         self.pointer_focus = PointerFocusState::Capturing(tr);
-                        unsafe {
-                            SetCapture(hwnd);
-                        }
+                        self.platform.set_capture(window);
                     }
                     if flags.contains(EventContinueControl::STOP_PROPAGATION) {
                         break;
                     }
 
-                    p = next;
+                    p = next.filter(|&x| ht.is_alive(x));
                 }
             }
             PointerFocusState::None => (),
@@ -439,38 +648,39 @@ impl PointerInputManager {
                                     action_context,
                                     ht,
                                     PointerActionArgs {
-                                        hwnd,
+                                        window,
                                         client_x,
                                         client_y,
-                                        client_width: client_size.Width,
-                                        client_height: client_size.Height,
+                                        client_width: client_size.width,
+                                        client_height: client_size.height,
+                                        modifiers,
                                     },
                                 )
                             });
                     if flags.contains(EventContinueControl::RECOMPUTE_POINTER_ENTER) {
                         self.on_mouse_move(
-                            hwnd,
+                            window,
                             ht,
                             action_context,
                             ht_root,
                             client_size,
                             client_x,
                             client_y,
+                            modifiers,
                         );
                     }
                     if flags.contains(EventContinueControl::RELEASE_CAPTURE_ELEMENT) {
-                        unsafe {
-                            ReleaseCapture().expect("Failed to release captured pointer");
-                        }
+                        self.platform.release_capture();
                         self.pointer_focus = PointerFocusState::Entering(tr);
                         self.on_mouse_move(
-                            hwnd,
+                            window,
                             ht,
                             action_context,
                             ht_root,
                             client_size,
                             client_x,
                             client_y,
+                            modifiers,
                         );
                     }
                 }
@@ -487,40 +697,290 @@ impl PointerInputManager {
                                 action_context,
                                 ht,
                                 PointerActionArgs {
-                                    hwnd,
+                                    window,
                                     client_x,
                                     client_y,
-                                    client_width: client_size.Width,
-                                    client_height: client_size.Height,
+                                    client_width: client_size.width,
+                                    client_height: client_size.height,
+                                    modifiers,
                                 },
                             )
                         });
                         if flags.contains(EventContinueControl::RECOMPUTE_POINTER_ENTER) {
                             self.on_mouse_move(
-                                hwnd,
+                                window,
                                 ht,
                                 action_context,
                                 ht_root,
                                 client_size,
                                 client_x,
                                 client_y,
+                                modifiers,
                             );
                         }
                         if flags.contains(EventContinueControl::CAPTURE_ELEMENT) {
                             self.pointer_focus = PointerFocusState::Capturing(tr);
-                            unsafe {
-                                SetCapture(hwnd);
-                            }
+                            self.platform.set_capture(window);
                         }
                         if flags.contains(EventContinueControl::STOP_PROPAGATION) {
                             break;
                         }
 
-                        p = next;
+                        p = next.filter(|&x| ht.is_alive(x));
                     }
                 }
                 PointerFocusState::None => (),
             }
+
+            let pos = Vector2 {
+                X: client_x,
+                Y: client_y,
+            };
+            let now = self.platform.now();
+            match self.last_click.take() {
+                Some((t, ref c))
+                    if now.duration_since(t) <= self.double_click_interval
+                        && (c.X - pos.X).powi(2) + (c.Y - pos.Y).powi(2)
+                            < CLICK_DETECTION_MAX_DISTNACE * CLICK_DETECTION_MAX_DISTNACE =>
+                {
+                    // Note: 3回目のクリックは新しい1回目として扱う（last_clickは空にしたまま）
+                    self.dispatch(
+                        ht,
+                        action_context,
+                        ht_root,
+                        PointerActionArgs {
+                            window,
+                            client_x,
+                            client_y,
+                            client_width: client_size.width,
+                            client_height: client_size.height,
+                            modifiers,
+                        },
+                        |a, tr, action_context, ht, args| {
+                            a.on_double_click(tr, action_context, ht, args)
+                        },
+                    );
+                }
+                _ => {
+                    self.last_click = Some((now, pos));
+                }
+            }
+        }
+    }
+
+    pub fn on_mouse_aux_down<ActionContext>(
+        &mut self,
+        window: WindowHandle,
+        ht: &mut HitTestTreeManager<ActionContext>,
+        action_context: &mut ActionContext,
+        ht_root: HitTestTreeRef,
+        client_size: ClientSize,
+        button: PointerButton,
+        client_x: f32,
+        client_y: f32,
+        modifiers: ModifierKeys,
+    ) {
+        self.forget_stale_pointer_focus(ht);
+        self.aux_click_base = Some((
+            button,
+            Vector2 {
+                X: client_x,
+                Y: client_y,
+            },
+        ));
+
+        self.dispatch(
+            ht,
+            action_context,
+            ht_root,
+            PointerActionArgs {
+                window,
+                client_x,
+                client_y,
+                client_width: client_size.width,
+                client_height: client_size.height,
+                modifiers,
+            },
+            |a, tr, action_context, ht, args| {
+                a.on_aux_pointer_down(tr, action_context, ht, args, button)
+            },
+        );
+    }
+
+    pub fn on_mouse_aux_up<ActionContext>(
+        &mut self,
+        window: WindowHandle,
+        ht: &mut HitTestTreeManager<ActionContext>,
+        action_context: &mut ActionContext,
+        ht_root: HitTestTreeRef,
+        client_size: ClientSize,
+        button: PointerButton,
+        client_x: f32,
+        client_y: f32,
+        modifiers: ModifierKeys,
+    ) {
+        self.on_mouse_move(
+            window,
+            ht,
+            action_context,
+            ht_root,
+            client_size,
+            client_x,
+            client_y,
+            modifiers,
+        );
+
+        let args = PointerActionArgs {
+            window,
+            client_x,
+            client_y,
+            client_width: client_size.width,
+            client_height: client_size.height,
+            modifiers,
+        };
+        self.dispatch(
+            ht,
+            action_context,
+            ht_root,
+            args,
+            |a, tr, action_context, ht, args| {
+                a.on_aux_pointer_up(tr, action_context, ht, args, button)
+            },
+        );
+
+        if self.aux_click_base.take().is_some_and(|(b, _)| b == button) {
+            self.dispatch(
+                ht,
+                action_context,
+                ht_root,
+                args,
+                |a, tr, action_context, ht, args| {
+                    a.on_aux_click(tr, action_context, ht, args, button)
+                },
+            );
+        }
+    }
+
+    pub fn on_mouse_wheel<ActionContext>(
+        &mut self,
+        window: WindowHandle,
+        ht: &mut HitTestTreeManager<ActionContext>,
+        action_context: &mut ActionContext,
+        ht_root: HitTestTreeRef,
+        client_size: ClientSize,
+        client_x: f32,
+        client_y: f32,
+        modifiers: ModifierKeys,
+        delta: WheelDelta,
+    ) {
+        self.forget_stale_pointer_focus(ht);
+
+        // Note: ホイールはポインタの移動を伴わないので、最後に入った要素にそのまま伝える
+        self.dispatch(
+            ht,
+            action_context,
+            ht_root,
+            PointerActionArgs {
+                window,
+                client_x,
+                client_y,
+                client_width: client_size.width,
+                client_height: client_size.height,
+                modifiers,
+            },
+            |a, tr, action_context, ht, args| a.on_wheel(tr, action_context, ht, args, delta),
+        );
+    }
+
+    /// キャプチャ中の要素か、ポインタが入っている要素から親に向かってイベントを伝える
+    fn dispatch<ActionContext>(
+        &mut self,
+        ht: &mut HitTestTreeManager<ActionContext>,
+        action_context: &mut ActionContext,
+        ht_root: HitTestTreeRef,
+        args: PointerActionArgs,
+        mut f: impl FnMut(
+            &dyn HitTestTreeActionHandler<Context = ActionContext>,
+            HitTestTreeRef,
+            &mut ActionContext,
+            &mut HitTestTreeManager<ActionContext>,
+            PointerActionArgs,
+        ) -> EventContinueControl,
+    ) {
+        let client_size = ClientSize {
+            width: args.client_width,
+            height: args.client_height,
+        };
+
+        match self.pointer_focus {
+            PointerFocusState::Capturing(tr) => {
+                let flags = ht
+                    .get(tr)
+                    .action_handler()
+                    .map_or(EventContinueControl::empty(), |a| {
+                        f(&*a, tr, action_context, ht, args)
+                    });
+                if flags.contains(EventContinueControl::RECOMPUTE_POINTER_ENTER) {
+                    self.on_mouse_move(
+                        args.window,
+                        ht,
+                        action_context,
+                        ht_root,
+                        client_size,
+                        args.client_x,
+                        args.client_y,
+                        args.modifiers,
+                    );
+                }
+                if flags.contains(EventContinueControl::RELEASE_CAPTURE_ELEMENT) {
+                    self.platform.release_capture();
+                    self.pointer_focus = PointerFocusState::Entering(tr);
+                    self.on_mouse_move(
+                        args.window,
+                        ht,
+                        action_context,
+                        ht_root,
+                        client_size,
+                        args.client_x,
+                        args.client_y,
+                        args.modifiers,
+                    );
+                }
+            }
+            PointerFocusState::Entering(tr) => {
+                // bubbling
+                let mut p = Some(tr);
+                while let Some(tr) = p {
+                    let t = ht.get(tr);
+                    let next = t.parent;
+                    let action_handler = t.action_handler();
+                    let flags = action_handler.map_or(EventContinueControl::empty(), |a| {
+                        f(&*a, tr, action_context, ht, args)
+                    });
+                    if flags.contains(EventContinueControl::RECOMPUTE_POINTER_ENTER) {
+                        self.on_mouse_move(
+                            args.window,
+                            ht,
+                            action_context,
+                            ht_root,
+                            client_size,
+                            args.client_x,
+                            args.client_y,
+                            args.modifiers,
+                        );
+                    }
+                    if flags.contains(EventContinueControl::CAPTURE_ELEMENT) {
+                        self.pointer_focus = PointerFocusState::Capturing(tr);
+                        self.platform.set_capture(args.window);
+                    }
+                    if flags.contains(EventContinueControl::STOP_PROPAGATION) {
+                        break;
+                    }
+
+                    p = next.filter(|&x| ht.is_alive(x));
+                }
+            }
+            PointerFocusState::None => (),
         }
     }
 
@@ -528,8 +988,14 @@ impl PointerInputManager {
         &self,
         ht: &HitTestTreeManager<ActionContext>,
         action_context: &mut ActionContext,
-    ) -> Option<HCURSOR> {
+    ) -> Option<CursorHandle> {
         match self.pointer_focus {
+            // Note: 解放済みの要素は次のポインタイベントで忘れるので、それまではなにもしない
+            PointerFocusState::Entering(tr) | PointerFocusState::Capturing(tr)
+                if !ht.is_alive(tr) =>
+            {
+                None
+            }
             PointerFocusState::Capturing(tr) => ht
                 .get(tr)
                 .action_handler()
@@ -546,7 +1012,7 @@ impl PointerInputManager {
                         return Some(c);
                     }
 
-                    p = t.parent;
+                    p = t.parent.filter(|&x| ht.is_alive(x));
                 }
 
                 // not processed
@@ -556,3 +1022,176 @@ impl PointerInputManager {
         }
     }
 }
+
+pub struct KeyboardFocusManager {
+    focus: Option<HitTestTreeRef>,
+}
+impl KeyboardFocusManager {
+    pub const fn new() -> Self {
+        Self { focus: None }
+    }
+
+    pub fn focus<ActionContext>(
+        &self,
+        ht: &HitTestTreeManager<ActionContext>,
+    ) -> Option<HitTestTreeRef> {
+        self.focus.filter(|&x| ht.is_alive(x))
+    }
+
+    pub fn set_focus<ActionContext>(
+        &mut self,
+        ht: &mut HitTestTreeManager<ActionContext>,
+        action_context: &mut ActionContext,
+        target: Option<HitTestTreeRef>,
+        focus_visible: bool,
+    ) {
+        let current = self.focus(ht);
+        if current == target && !focus_visible {
+            // 変化なし（キーボード操作のときはフォーカス表示を出し直すためにもう一度通知する）
+            return;
+        }
+
+        if let Some(tr) = current
+            && current != target
+            && let Some(a) = ht.get(tr).action_handler()
+        {
+            a.on_blur(tr, action_context, ht);
+        }
+
+        self.focus = target;
+        if let Some(tr) = target
+            && let Some(a) = ht.get(tr).action_handler()
+        {
+            a.on_focus(tr, action_context, ht, focus_visible);
+        }
+    }
+
+    /// Tab(`reverse`ならShift+Tab)でのフォーカス移動
+    pub fn move_focus<ActionContext>(
+        &mut self,
+        ht: &mut HitTestTreeManager<ActionContext>,
+        action_context: &mut ActionContext,
+        ht_root: HitTestTreeRef,
+        reverse: bool,
+    ) {
+        let order = ht.collect_focusable(action_context, ht_root);
+        if order.is_empty() {
+            return;
+        }
+
+        let next = match self
+            .focus(ht)
+            .and_then(|x| order.iter().position(|&o| o == x))
+        {
+            // 端まで行ったら反対側に戻る
+            Some(p) if reverse => order[(p + order.len() - 1) % order.len()],
+            Some(p) => order[(p + 1) % order.len()],
+            None if reverse => order[order.len() - 1],
+            None => order[0],
+        };
+        self.set_focus(ht, action_context, Some(next), true);
+    }
+
+    /// クリックされた要素（かその祖先）でフォーカスを受け取れるものにフォーカスを移す
+    pub fn on_mouse_left_down<ActionContext>(
+        &mut self,
+        ht: &mut HitTestTreeManager<ActionContext>,
+        action_context: &mut ActionContext,
+        ht_root: HitTestTreeRef,
+        client_size: ClientSize,
+        client_x: f32,
+        client_y: f32,
+    ) {
+        let mut p = ht.perform_test(
+            action_context,
+            ht_root,
+            client_x,
+            client_y,
+            0.0,
+            0.0,
+            client_size.width,
+            client_size.height,
+        );
+        while let Some(tr) = p {
+            if ht.get(tr).focusable {
+                break;
+            }
+
+            p = ht.get(tr).parent;
+        }
+
+        self.set_focus(ht, action_context, p, false);
+    }
+
+    /// 処理された場合はtrue
+    pub fn on_key_down<ActionContext>(
+        &mut self,
+        window: WindowHandle,
+        ht: &mut HitTestTreeManager<ActionContext>,
+        action_context: &mut ActionContext,
+        ht_root: HitTestTreeRef,
+        key: KeyCode,
+        shift: bool,
+    ) -> bool {
+        if key == KeyCode::TAB {
+            self.move_focus(ht, action_context, ht_root, shift);
+            return true;
+        }
+
+        self.dispatch(ht, action_context, |a, tr, action_context, ht| {
+            a.on_key_down(tr, action_context, ht, KeyActionArgs { window, key })
+        })
+    }
+
+    /// 処理された場合はtrue
+    pub fn on_char<ActionContext>(
+        &mut self,
+        ht: &mut HitTestTreeManager<ActionContext>,
+        action_context: &mut ActionContext,
+        ch: char,
+    ) -> bool {
+        if ch == '\t' {
+            // Tabはキーを押したときにフォーカス移動として処理済み
+            return true;
+        }
+
+        self.dispatch(ht, action_context, |a, tr, action_context, ht| {
+            a.on_char(tr, action_context, ht, ch)
+        })
+    }
+
+    /// フォーカス中の要素から親に向かってイベントを伝える
+    fn dispatch<ActionContext>(
+        &mut self,
+        ht: &mut HitTestTreeManager<ActionContext>,
+        action_context: &mut ActionContext,
+        mut f: impl FnMut(
+            &dyn HitTestTreeActionHandler<Context = ActionContext>,
+            HitTestTreeRef,
+            &mut ActionContext,
+            &mut HitTestTreeManager<ActionContext>,
+        ) -> EventContinueControl,
+    ) -> bool {
+        let mut p = self.focus(ht);
+        while let Some(tr) = p {
+            let t = ht.get(tr);
+            let next = t.parent;
+            let flags = t
+                .action_handler()
+                .map_or(EventContinueControl::empty(), |a| {
+                    f(&*a, tr, action_context, ht)
+                });
+            if flags.contains(EventContinueControl::RELEASE_FOCUS) {
+                self.set_focus(ht, action_context, None, false);
+            }
+            if flags.contains(EventContinueControl::STOP_PROPAGATION) {
+                return true;
+            }
+
+            // Note: ハンドラの中で親が解放されていることもある
+            p = next.filter(|&x| ht.is_alive(x));
+        }
+
+        false
+    }
+}
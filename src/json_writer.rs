@@ -0,0 +1,33 @@
+//! CLIの機械可読な出力用の最小限のJSON書き出し
+
+use std::io::Write;
+
+/// 文字列をJSONの文字列リテラルとして書き出す
+pub fn write_string(sink: &mut (impl Write + ?Sized), s: &str) -> std::io::Result<()> {
+    sink.write_all(b"\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => sink.write_all(b"\\\"")?,
+            '\\' => sink.write_all(b"\\\\")?,
+            '\n' => sink.write_all(b"\\n")?,
+            '\r' => sink.write_all(b"\\r")?,
+            '\t' => sink.write_all(b"\\t")?,
+            c if (c as u32) < 0x20 => write!(sink, "\\u{:04x}", c as u32)?,
+            c => write!(sink, "{c}")?,
+        }
+    }
+    sink.write_all(b"\"")
+}
+
+/// 区切りのカンマを2つ目以降の要素の前にだけ書くためのもの
+#[derive(Default)]
+pub struct Separator(bool);
+impl Separator {
+    pub fn write(&mut self, sink: &mut (impl Write + ?Sized)) -> std::io::Result<()> {
+        if core::mem::replace(&mut self.0, true) {
+            sink.write_all(b",")?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 use core::mem::MaybeUninit;
 use std::{
     cell::{Cell, RefCell},
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     ffi::OsString,
     os::windows::ffi::OsStringExt,
     path::PathBuf,
@@ -9,16 +9,21 @@ use std::{
     sync::Arc,
 };
 
-use app_state::{AppState, SpriteInfo};
+use app_state::{AppState, SpriteCandidate, SpriteInfo};
+use atlas_image::SampleFormat;
 use bg_worker::{
-    BackgroundWork, BackgroundWorker, BackgroundWorkerEnqueueAccess,
-    BackgroundWorkerEnqueueWeakAccess, BackgroundWorkerViewFeedback,
+    BackgroundJobGroup, BackgroundWork, BackgroundWorkCancellationToken, BackgroundWorkKind,
+    BackgroundWorkPriority, BackgroundWorker, BackgroundWorkerEnqueueAccess,
+    BackgroundWorkerEnqueueWeakAccess, BackgroundWorkerStatus, BackgroundWorkerViewFeedback,
 };
 use color_factory::{
     d2d1_color_f_from_hex_rgb, d2d1_color_f_from_websafe_hex_rgb, ui_color_from_hex_rgb,
     ui_color_from_websafe_hex_rgb, ui_color_from_websafe_hex_rgb_with_alpha,
 };
-use component::{app_header::AppHeaderPresenter, dnd_overlay::FileDragAndDropOverlayView};
+use component::{
+    animation_pane::AnimationPanePresenter, app_header::AppHeaderPresenter,
+    dnd_overlay::FileDragAndDropOverlayView,
+};
 use composition_element_builder::{
     CompositionMaskBrushParams, CompositionNineGridBrushParams, CompositionSurfaceBrushParams,
     ContainerVisualParams, SimpleImplicitAnimationParams, SimpleScalarAnimationParams,
@@ -30,12 +35,16 @@ use effect_builder::{
 };
 use hittest::HitTestTreeActionHandler;
 use hittest::*;
-use image::EncodableLayout;
 use input::*;
 use native_wrapper::NativeEvent;
 use parking_lot::RwLock;
+use peridot::AlphaMode;
+use quadtree::{QuadTree, QuadTreeRect};
+use sprite_filter::{SpriteFilter, SpriteFilterTarget};
+use sprite_group::{SpriteListEntry, SpriteListRow};
 use subsystem::Subsystem;
-use surface_helper::draw_2d;
+use surface_helper::{create_bitmap_from_rgba, draw_2d};
+use texture_allocator::{AtlasAllocError, AtlasRegion, TextureAtlasAllocator};
 use timespan_helper::timespan_ms;
 use windows::{
     Foundation::{Size, TimeSpan},
@@ -51,9 +60,9 @@ use windows::{
         Composition::{
             CompositionAnimationGroup, CompositionBrush, CompositionDrawingSurface,
             CompositionEasingFunction, CompositionEasingFunctionMode, CompositionEffectBrush,
-            CompositionEffectSourceParameter, CompositionPropertySet, CompositionStretch,
-            ContainerVisual, Desktop::DesktopWindowTarget, ScalarKeyFrameAnimation, SpriteVisual,
-            VisualCollection,
+            CompositionEffectSourceParameter, CompositionNineGridBrush, CompositionPropertySet,
+            CompositionStretch, Compositor, ContainerVisual, Desktop::DesktopWindowTarget,
+            ScalarKeyFrameAnimation, SpriteVisual, VisualCollection,
         },
     },
     Win32::{
@@ -64,10 +73,11 @@ use windows::{
                     D2D_POINT_2F, D2D_RECT_F, D2D_SIZE_F, D2D1_COLOR_F, D2D1_FIGURE_BEGIN_FILLED,
                     D2D1_FIGURE_END_CLOSED,
                 },
-                D2D1_DRAW_TEXT_OPTIONS_NONE, D2D1_ELLIPSE, D2D1_ROUNDED_RECT, ID2D1DeviceContext5,
+                D2D1_DRAW_TEXT_OPTIONS_NONE, D2D1_ELLIPSE,
+                D2D1_INTERPOLATION_MODE_NEAREST_NEIGHBOR, D2D1_ROUNDED_RECT, ID2D1DeviceContext5,
                 ID2D1Multithread,
             },
-            Direct3D::D3D_PRIMITIVE_TOPOLOGY_TRIANGLESTRIP,
+            Direct3D::{D3D_PRIMITIVE_TOPOLOGY_TRIANGLESTRIP, D3D11_SRV_DIMENSION_TEXTURE2DARRAY},
             Direct3D11::{
                 D3D11_BIND_CONSTANT_BUFFER, D3D11_BIND_SHADER_RESOURCE, D3D11_BIND_VERTEX_BUFFER,
                 D3D11_BLEND_DESC, D3D11_BLEND_INV_SRC_ALPHA, D3D11_BLEND_ONE, D3D11_BLEND_OP_ADD,
@@ -75,11 +85,13 @@ use windows::{
                 D3D11_CPU_ACCESS_WRITE, D3D11_FILTER_MIN_MAG_MIP_POINT, D3D11_INPUT_ELEMENT_DESC,
                 D3D11_INPUT_PER_INSTANCE_DATA, D3D11_INPUT_PER_VERTEX_DATA, D3D11_MAP_WRITE,
                 D3D11_MAP_WRITE_DISCARD, D3D11_RENDER_TARGET_BLEND_DESC, D3D11_SAMPLER_DESC,
-                D3D11_SUBRESOURCE_DATA, D3D11_TEXTURE_ADDRESS_CLAMP, D3D11_TEXTURE2D_DESC,
-                D3D11_USAGE_DEFAULT, D3D11_USAGE_DYNAMIC, D3D11_USAGE_IMMUTABLE,
-                D3D11_USAGE_STAGING, D3D11_VIEWPORT, ID3D11BlendState, ID3D11Buffer, ID3D11Device,
-                ID3D11DeviceContext, ID3D11InputLayout, ID3D11Multithread, ID3D11PixelShader,
-                ID3D11SamplerState, ID3D11ShaderResourceView, ID3D11Texture2D, ID3D11VertexShader,
+                D3D11_SHADER_RESOURCE_VIEW_DESC, D3D11_SHADER_RESOURCE_VIEW_DESC_0,
+                D3D11_SUBRESOURCE_DATA, D3D11_TEX2D_ARRAY_SRV, D3D11_TEXTURE_ADDRESS_CLAMP,
+                D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT, D3D11_USAGE_DYNAMIC,
+                D3D11_USAGE_IMMUTABLE, D3D11_USAGE_STAGING, D3D11_VIEWPORT, ID3D11BlendState,
+                ID3D11Buffer, ID3D11Device, ID3D11DeviceContext, ID3D11InputLayout,
+                ID3D11Multithread, ID3D11PixelShader, ID3D11SamplerState, ID3D11ShaderResourceView,
+                ID3D11Texture2D, ID3D11VertexShader,
             },
             DirectWrite::{DWRITE_FONT_WEIGHT_MEDIUM, DWRITE_TEXT_RANGE, IDWriteTextLayout1},
             Dwm::{
@@ -87,8 +99,10 @@ use windows::{
             },
             Dxgi::{
                 Common::{
-                    DXGI_ALPHA_MODE_IGNORE, DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_R8G8B8A8_UNORM,
-                    DXGI_FORMAT_R32G32_FLOAT, DXGI_FORMAT_R32G32B32A32_FLOAT, DXGI_SAMPLE_DESC,
+                    DXGI_ALPHA_MODE_IGNORE, DXGI_FORMAT, DXGI_FORMAT_B8G8R8A8_UNORM,
+                    DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_R16G16B16A16_FLOAT,
+                    DXGI_FORMAT_R32_UINT, DXGI_FORMAT_R32G32_FLOAT, DXGI_FORMAT_R32G32B32A32_FLOAT,
+                    DXGI_SAMPLE_DESC,
                 },
                 DXGI_PRESENT, DXGI_SCALING_STRETCH, DXGI_SWAP_CHAIN_DESC1,
                 DXGI_SWAP_CHAIN_FLAG_FRAME_LATENCY_WAITABLE_OBJECT, DXGI_SWAP_EFFECT_FLIP_DISCARD,
@@ -119,21 +133,24 @@ use windows::{
         UI::{
             Controls::MARGINS,
             HiDpi::GetDpiForWindow,
+            Input::KeyboardAndMouse::{GetKeyState, VIRTUAL_KEY, VK_CONTROL, VK_MENU, VK_SHIFT},
             Shell::{
                 CLSID_DragDropHelper, DragQueryFileW, HDROP, IDropTargetHelper,
                 IInitializeWithWindow,
             },
             WindowsAndMessaging::{
                 CW_USEDEFAULT, CreateWindowExW, DefWindowProcW, DispatchMessageW, GWLP_USERDATA,
-                GetClientRect, GetSystemMetrics, GetWindowLongPtrW, GetWindowRect, HCURSOR,
-                HTCLIENT, HTTOP, IDC_ARROW, IDC_SIZEWE, IDI_APPLICATION, LoadCursorW, LoadIconW,
+                GetClientRect, GetSystemMetrics, GetWindowLongPtrW, GetWindowRect, HTCLIENT, HTTOP,
+                IDC_ARROW, IDC_SIZEWE, IDI_APPLICATION, LoadCursorW, LoadIconW,
                 MsgWaitForMultipleObjects, NCCALCSIZE_PARAMS, PM_REMOVE, PeekMessageW,
                 PostQuitMessage, QS_ALLINPUT, RegisterClassExW, SM_CXSIZEFRAME, SM_CYSIZEFRAME,
                 SW_SHOW, SWP_FRAMECHANGED, SetCursor, SetWindowLongPtrW, SetWindowPos, ShowWindow,
-                TranslateMessage, WM_ACTIVATE, WM_CREATE, WM_DESTROY, WM_DPICHANGED,
-                WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MOUSEMOVE, WM_NCCALCSIZE, WM_NCHITTEST, WM_QUIT,
-                WM_SETCURSOR, WM_SIZE, WNDCLASS_STYLES, WNDCLASSEXW, WS_EX_APPWINDOW,
-                WS_EX_NOREDIRECTIONBITMAP, WS_EX_OVERLAPPEDWINDOW, WS_OVERLAPPEDWINDOW,
+                TranslateMessage, WHEEL_DELTA, WM_ACTIVATE, WM_CHAR, WM_CREATE, WM_DESTROY,
+                WM_DPICHANGED, WM_KEYDOWN, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN,
+                WM_MBUTTONUP, WM_MOUSEHWHEEL, WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_NCCALCSIZE,
+                WM_NCHITTEST, WM_QUIT, WM_RBUTTONDOWN, WM_RBUTTONUP, WM_SETCURSOR, WM_SIZE,
+                WNDCLASS_STYLES, WNDCLASSEXW, WS_EX_APPWINDOW, WS_EX_NOREDIRECTIONBITMAP,
+                WS_EX_OVERLAPPEDWINDOW, WS_OVERLAPPEDWINDOW,
             },
         },
     },
@@ -145,20 +162,38 @@ use windows_future::{AsyncOperationCompletedHandler, AsyncStatus};
 use windows_numerics::{Matrix3x2, Vector2, Vector3};
 
 mod app_state;
+mod asset_diff;
+mod asset_lint;
+mod asset_merge;
+mod atlas_image;
 mod bg_worker;
+mod block_compression;
+mod cli;
 mod color_factory;
 mod component;
 mod composition_element_builder;
 mod coordinate;
 mod effect_builder;
 mod extra_bindings;
+mod gdx_atlas;
+mod grid_slice;
 mod hittest;
 mod input;
+mod json_writer;
 mod native_wrapper;
 mod peridot;
+mod quadtree;
+mod region_detect;
+mod rust_codegen;
 mod source_reader;
+mod source_reconcile_dialog;
+mod sprite_filter;
+mod sprite_group;
+mod sprite_packing;
 mod subsystem;
 mod surface_helper;
+mod texture_allocator;
+mod texture_container;
 mod timespan_helper;
 
 type AppHitTestTreeManager = HitTestTreeManager<AppState>;
@@ -277,7 +312,7 @@ pub struct ViewInitContext<'r> {
     pub dpi: f32,
     pub background_worker_enqueue_access: &'r BackgroundWorkerEnqueueAccess,
     pub background_worker_view_update_callback:
-        &'r Rc<RefCell<Vec<Box<dyn FnMut(&[Option<String>])>>>>,
+        &'r Rc<RefCell<Vec<Box<dyn FnMut(&BackgroundWorkerStatus)>>>>,
 }
 impl ViewInitContext<'_> {
     #[inline(always)]
@@ -298,85 +333,354 @@ pub struct AtlasBaseGridRenderParams {
     pub grid_size: f32,
 }
 
-pub struct SimpleTextureAtlas {
+#[repr(C, align(16))]
+pub struct SpriteInstanceRenderParams {
+    pub pixel_size: [f32; 2],
+    pub offset: [f32; 2],
+    /// 0以外ならピクセルシェーダーでアルファを掛ける（アトラスがstraightのとき）
+    pub premultiply: u32,
+}
+
+/// アトラスのテクスチャに入れる画素の持ち方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteTexelEncoding {
+    pub high_precision: bool,
+    pub alpha_mode: AlphaMode,
+}
+
+/// スプライトのソース画像を詰め込むテクスチャ配列
+///
+/// 領域の割り当ては`TextureAtlasAllocator`で行い、ページが増えたら配列を作り直して中身を移す
+pub struct SpriteTextureAtlas {
     pub resource: ID3D11Texture2D,
     pub srv: ID3D11ShaderResourceView,
-    pub current_top: u32,
-    pub current_left: u32,
-    pub max_height: u32,
+    /// 8bitより精度の高いソースが来たらR16G16B16A16_FLOATにする（戻さない）
+    format: DXGI_FORMAT,
+    /// 書き出したときと同じ持ち方で入れておき、表示するときにシェーダーでそろえる
+    alpha_mode: AlphaMode,
+    allocator: TextureAtlasAllocator,
+    /// テクスチャ配列のスライス数（ページが減っても縮めない）
+    slice_count: u32,
+    /// (ソースパス, ソース内の切り出し矩形) => アトラス内の配置位置
+    placements: HashMap<(PathBuf, [u32; 4]), AtlasRegion>,
+    /// 中身を読み込み中の配置 => (読み込みのID, 読み込みの取り消し用トークン)
+    loading: HashMap<(PathBuf, [u32; 4]), (u64, BackgroundWorkCancellationToken)>,
+    next_load_id: u64,
 }
-impl SimpleTextureAtlas {
-    const SIZE: u32 = 4096;
+impl SpriteTextureAtlas {
+    const PAGE_SIZE: u32 = 4096;
+    const MAX_PAGES: u32 = 8;
 
     pub fn new(d3d11: &ID3D11Device) -> Self {
+        let (resource, srv) = Self::create_texture(d3d11, DXGI_FORMAT_R8G8B8A8_UNORM, 1);
+
+        Self {
+            resource,
+            srv,
+            format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            alpha_mode: AlphaMode::Straight,
+            allocator: TextureAtlasAllocator::new(Self::PAGE_SIZE, Self::MAX_PAGES),
+            slice_count: 1,
+            placements: HashMap::new(),
+            loading: HashMap::new(),
+            next_load_id: 0,
+        }
+    }
+
+    fn create_texture(
+        d3d11: &ID3D11Device,
+        format: DXGI_FORMAT,
+        slice_count: u32,
+    ) -> (ID3D11Texture2D, ID3D11ShaderResourceView) {
         let mut resource = core::mem::MaybeUninit::uninit();
         let mut srv = MaybeUninit::uninit();
         unsafe {
             d3d11
                 .CreateTexture2D(
                     &D3D11_TEXTURE2D_DESC {
-                        Width: Self::SIZE,
-                        Height: Self::SIZE,
+                        Width: Self::PAGE_SIZE,
+                        Height: Self::PAGE_SIZE,
                         MipLevels: 1,
-                        ArraySize: 1,
-                        Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+                        ArraySize: slice_count,
+                        Format: format,
                         SampleDesc: DXGI_SAMPLE_DESC {
                             Count: 1,
                             Quality: 0,
                         },
                         Usage: D3D11_USAGE_DEFAULT,
                         BindFlags: D3D11_BIND_SHADER_RESOURCE.0 as _,
-                        CPUAccessFlags: D3D11_CPU_ACCESS_WRITE.0 as _,
+                        CPUAccessFlags: 0,
                         MiscFlags: 0,
                     },
                     None,
                     Some(resource.as_mut_ptr()),
                 )
                 .unwrap();
+            // Note: スライスが1枚のときでもシェーダー側はTexture2DArrayなので明示的に配列のビューを作る
             d3d11
                 .CreateShaderResourceView(
                     resource.assume_init_ref().as_ref().unwrap(),
-                    None,
+                    Some(&D3D11_SHADER_RESOURCE_VIEW_DESC {
+                        Format: format,
+                        ViewDimension: D3D11_SRV_DIMENSION_TEXTURE2DARRAY,
+                        Anonymous: D3D11_SHADER_RESOURCE_VIEW_DESC_0 {
+                            Texture2DArray: D3D11_TEX2D_ARRAY_SRV {
+                                MostDetailedMip: 0,
+                                MipLevels: 1,
+                                FirstArraySlice: 0,
+                                ArraySize: slice_count,
+                            },
+                        },
+                    }),
                     Some(srv.as_mut_ptr()),
                 )
                 .unwrap();
         }
-        let resource = unsafe { resource.assume_init().unwrap() };
-        let srv = unsafe { srv.assume_init().unwrap() };
 
-        Self {
-            resource,
-            srv,
-            current_top: 0,
-            current_left: 0,
-            max_height: 0,
+        unsafe { (resource.assume_init().unwrap(), srv.assume_init().unwrap()) }
+    }
+
+    pub fn placement(&self, key: &(PathBuf, [u32; 4])) -> Option<&AtlasRegion> {
+        self.placements.get(key)
+    }
+
+    pub fn is_high_precision(&self) -> bool {
+        self.format == DXGI_FORMAT_R16G16B16A16_FLOAT
+    }
+
+    pub const fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    pub fn encoding(&self) -> SpriteTexelEncoding {
+        SpriteTexelEncoding {
+            high_precision: self.is_high_precision(),
+            alpha_mode: self.alpha_mode,
         }
     }
 
-    pub fn alloc(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
-        if (Self::SIZE - self.current_top) < height {
-            // 高さが足りない
-            return None;
+    /// テクスチャをR16G16B16A16_FLOATにする
+    pub fn promote_to_high_precision(&mut self, d3d11: &ID3D11Device) {
+        if self.is_high_precision() {
+            return;
         }
 
-        if width <= (Self::SIZE - self.current_left) {
-            // まだ入る
-            let o = (self.current_left, self.current_top);
-            self.current_left += width;
-            self.max_height = self.max_height.max(height);
-            Some(o)
-        } else {
-            // 改行が必要
-            if (Self::SIZE - self.current_top - self.max_height) < height {
-                // 改行すると入らなくなる
-                return None;
+        tracing::info!("sprite atlas promoted to R16G16B16A16_FLOAT");
+        self.reset(d3d11, DXGI_FORMAT_R16G16B16A16_FLOAT);
+    }
+
+    pub fn set_alpha_mode(&mut self, d3d11: &ID3D11Device, alpha_mode: AlphaMode) {
+        if self.alpha_mode == alpha_mode {
+            return;
+        }
+
+        self.alpha_mode = alpha_mode;
+        self.reset(d3d11, self.format);
+    }
+
+    /// 中身を作り直す
+    ///
+    /// Note: 形式の違うテクスチャ間ではコピーできないし、アルファの持ち方を変えると中身も変わるので、
+    /// 配置はすべて捨てる（呼び出し側で読み込み直すこと）
+    fn reset(&mut self, d3d11: &ID3D11Device, format: DXGI_FORMAT) {
+        let (resource, srv) = Self::create_texture(d3d11, format, 1);
+        self.resource = resource;
+        self.srv = srv;
+        self.format = format;
+        self.allocator = TextureAtlasAllocator::new(Self::PAGE_SIZE, Self::MAX_PAGES);
+        self.slice_count = 1;
+        self.placements.clear();
+        self.loading.clear();
+    }
+
+    /// テクスチャに書き込むデータと行ピッチ
+    pub fn texels(
+        encoding: SpriteTexelEncoding,
+        source_alpha_mode: AlphaMode,
+        image: &image::DynamicImage,
+    ) -> (Vec<u8>, u32) {
+        let image = atlas_image::for_display(&atlas_image::convert_alpha_mode(
+            image,
+            source_alpha_mode,
+            encoding.alpha_mode,
+        ));
+        if !encoding.high_precision {
+            return (image.to_rgba8().into_raw(), image.width() * 4);
+        }
+
+        let texels = image
+            .to_rgba32f()
+            .into_raw()
+            .into_iter()
+            .flat_map(|x| atlas_image::f32_to_f16_bits(x).to_ne_bytes())
+            .collect();
+
+        (texels, image.width() * 8)
+    }
+
+    /// 領域を割り当てる（すでに割り当て済みならその領域を返す）
+    ///
+    /// 2つ目の値は中身の読み込みが必要か（新しく割り当てたか、前の読み込みが取り消されたまま残っているか）。
+    /// 空きがなければ詰め直してから再度試すので、呼び出し側はD3D11のクリティカルセクションに入っていること
+    pub fn alloc(
+        &mut self,
+        d3d11: &ID3D11Device,
+        d3d11_context: &ID3D11DeviceContext,
+        key: (PathBuf, [u32; 4]),
+    ) -> Result<(AtlasRegion, bool), AtlasAllocError> {
+        if let Some(r) = self.placements.get(&key) {
+            let load_cancelled = self
+                .loading
+                .get(&key)
+                .is_some_and(|(_, t)| t.is_cancelled());
+            return Ok((*r, load_cancelled));
+        }
+
+        let [_, _, width, height] = key.1;
+        let r = match self.allocator.alloc(width, height) {
+            Ok(r) => r,
+            Err(AtlasAllocError::Full) => {
+                tracing::info!("sprite atlas is full; defragmenting");
+                self.defragment(d3d11, d3d11_context);
+                self.allocator.alloc(width, height)?
+            }
+            Err(e) => return Err(e),
+        };
+        self.grow(d3d11, d3d11_context);
+        self.placements.insert(key, r);
+
+        Ok((r, true))
+    }
+
+    /// `live`に含まれないものを解放する
+    pub fn retain(&mut self, live: &HashSet<(PathBuf, [u32; 4])>) {
+        let allocator = &mut self.allocator;
+        self.placements.retain(|k, r| {
+            if live.contains(k) {
+                return true;
+            }
+
+            allocator.free(r);
+            false
+        });
+        self.loading.retain(|k, _| live.contains(k));
+    }
+
+    /// 中身の読み込みを始めたことを記録して、読み込みのIDを返す（前の読み込みがあれば置き換える）
+    pub fn begin_load(
+        &mut self,
+        key: (PathBuf, [u32; 4]),
+        cancellation_token: BackgroundWorkCancellationToken,
+    ) -> u64 {
+        let id = self.next_load_id;
+        self.next_load_id += 1;
+        self.loading.insert(key, (id, cancellation_token));
+
+        id
+    }
+
+    /// 読み込みが終わったことを記録する（`id`がもう最新の読み込みでなければfalse）
+    pub fn finish_load(&mut self, key: &(PathBuf, [u32; 4]), id: u64) -> bool {
+        if self.loading.get(key).is_none_or(|&(x, _)| x != id) {
+            return false;
+        }
+
+        self.loading.remove(key);
+        true
+    }
+
+    /// 読み込めなかった（取り消された）ので配置を解放する
+    ///
+    /// 次に割り当てるときに新しく割り当てたものとして読み込み直される
+    pub fn abort_load(&mut self, key: &(PathBuf, [u32; 4]), id: u64) {
+        if !self.finish_load(key, id) {
+            // 読み込み直しが始まっているか、もう解放されている
+            return;
+        }
+
+        if let Some(r) = self.placements.remove(key) {
+            self.allocator.free(&r);
+        }
+    }
+
+    /// ページ数に合わせてテクスチャ配列を大きくする
+    fn grow(&mut self, d3d11: &ID3D11Device, d3d11_context: &ID3D11DeviceContext) {
+        let page_count = self.allocator.page_count();
+        if page_count <= self.slice_count {
+            return;
+        }
+
+        let (resource, srv) = Self::create_texture(d3d11, self.format, page_count);
+        for n in 0..self.slice_count {
+            unsafe {
+                d3d11_context.CopySubresourceRegion(&resource, n, 0, 0, 0, &self.resource, n, None);
+            }
+        }
+
+        tracing::info!(
+            { from = self.slice_count, to = page_count },
+            "sprite atlas pages grown"
+        );
+        self.resource = resource;
+        self.srv = srv;
+        self.slice_count = page_count;
+    }
+
+    /// 配置を詰め直して、中身を新しいテクスチャ配列に移す
+    fn defragment(&mut self, d3d11: &ID3D11Device, d3d11_context: &ID3D11DeviceContext) {
+        let (keys, regions): (Vec<_>, Vec<_>) =
+            self.placements.iter().map(|(k, r)| (k.clone(), *r)).unzip();
+        let Some(relocated) = self.allocator.defragment(&regions) else {
+            // 詰め直しても入らないので今のまま
+            return;
+        };
+
+        // Note: 詰め直しの結果が今の配列より多くのページを使うこともあるので、両方が入る大きさで作る
+        let slice_count = self.allocator.page_count().max(self.slice_count);
+        let (resource, srv) = Self::create_texture(d3d11, self.format, slice_count);
+        for ((k, old), new) in keys.into_iter().zip(regions).zip(relocated) {
+            unsafe {
+                d3d11_context.CopySubresourceRegion(
+                    &resource,
+                    new.page,
+                    new.left,
+                    new.top,
+                    0,
+                    &self.resource,
+                    old.page,
+                    Some(&D3D11_BOX {
+                        left: old.left,
+                        top: old.top,
+                        front: 0,
+                        right: old.left + old.width,
+                        bottom: old.top + old.height,
+                        back: 1,
+                    }),
+                );
             }
+            self.placements.insert(k, new);
+        }
+
+        self.resource = resource;
+        self.srv = srv;
+        self.slice_count = slice_count;
+    }
+}
 
-            let o = (self.current_left, self.current_top);
-            self.current_top += self.max_height;
-            self.current_left = 0;
-            self.max_height = height;
-            Some(o)
+/// 読み込み中のスプライトのソース
+///
+/// 読み込みが終わらないまま（取り消されたり失敗したりして）捨てられたら配置を解放する
+struct PendingSpriteLoad {
+    sprite_atlas: Arc<RwLock<SpriteTextureAtlas>>,
+    key: (PathBuf, [u32; 4]),
+    id: u64,
+    finished: bool,
+}
+impl Drop for PendingSpriteLoad {
+    fn drop(&mut self) {
+        if !self.finished {
+            tracing::info!({ path = ?self.key.0 }, "LoadSpriteComplete(aborted)");
+            self.sprite_atlas.write().abort_load(&self.key, self.id);
         }
     }
 }
@@ -385,6 +689,8 @@ impl SimpleTextureAtlas {
 pub struct SpriteInstance {
     pub pos_st: [f32; 4],
     pub uv_st: [f32; 4],
+    /// アトラスのページ（テクスチャ配列のスライス番号）
+    pub page: u32,
 }
 
 pub struct SpriteInstanceBuffer {
@@ -421,8 +727,10 @@ pub struct AtlasBaseGridView {
     resize_order: RwLock<Option<(u32, u32)>>,
     offset_pixels: RwLock<(f32, f32)>,
     background_worker_enqueue_access: BackgroundWorkerEnqueueWeakAccess,
-    simple_atlas: RwLock<SimpleTextureAtlas>,
-    sprite_source_offset: RwLock<HashMap<PathBuf, (u32, u32)>>,
+    // Note: 読み込みが終わったときにバックグラウンドのスレッドから書き込み先を引くので共有する
+    sprite_atlas: Arc<RwLock<SpriteTextureAtlas>>,
+    /// ソースの読み込みにつける、開いているドキュメントの取り消し用トークン
+    document_cancellation_token: RwLock<BackgroundWorkCancellationToken>,
     d3d11_device: ID3D11Device,
     d3d11_device_context: ID3D11DeviceContext,
     d3d11_mt: ID3D11Multithread,
@@ -589,7 +897,9 @@ impl AtlasBaseGridView {
         }
         let tex_sampler = unsafe { tex_sampler.assume_init().unwrap() };
 
-        let simple_atlas = RwLock::new(SimpleTextureAtlas::new(&init.subsystem.d3d11_device));
+        let sprite_atlas = Arc::new(RwLock::new(SpriteTextureAtlas::new(
+            &init.subsystem.d3d11_device,
+        )));
 
         let d3d11_mt: ID3D11Multithread = init.subsystem.d3d11_imm_context.cast().unwrap();
         unsafe {
@@ -644,6 +954,15 @@ impl AtlasBaseGridView {
                             InputSlotClass: D3D11_INPUT_PER_INSTANCE_DATA,
                             InstanceDataStepRate: 1,
                         },
+                        D3D11_INPUT_ELEMENT_DESC {
+                            SemanticName: s!("TEXCOORD"),
+                            SemanticIndex: 1,
+                            Format: DXGI_FORMAT_R32_UINT,
+                            InputSlot: 1,
+                            AlignedByteOffset: core::mem::offset_of!(SpriteInstance, page) as _,
+                            InputSlotClass: D3D11_INPUT_PER_INSTANCE_DATA,
+                            InstanceDataStepRate: 1,
+                        },
                     ],
                     &vsh_code,
                     Some(sprite_instance_input_layout.as_mut_ptr()),
@@ -823,12 +1142,13 @@ impl AtlasBaseGridView {
                 .d3d11_device
                 .CreateBuffer(
                     &D3D11_BUFFER_DESC {
-                        ByteWidth: core::mem::size_of::<[[f32; 2]; 2]>() as _,
+                        ByteWidth: core::mem::size_of::<SpriteInstanceRenderParams>() as _,
                         Usage: D3D11_USAGE_DYNAMIC,
                         BindFlags: D3D11_BIND_CONSTANT_BUFFER.0 as _,
                         CPUAccessFlags: D3D11_CPU_ACCESS_WRITE.0 as _,
                         MiscFlags: 0,
-                        StructureByteStride: core::mem::size_of::<[[f32; 2]; 2]>() as _,
+                        StructureByteStride: core::mem::size_of::<SpriteInstanceRenderParams>()
+                            as _,
                     },
                     None,
                     Some(texture_preview_cb.as_mut_ptr()),
@@ -892,8 +1212,8 @@ impl AtlasBaseGridView {
             resize_order: RwLock::new(None),
             offset_pixels: RwLock::new((0.0, 0.0)),
             background_worker_enqueue_access: init.background_worker_enqueue_access.downgrade(),
-            simple_atlas,
-            sprite_source_offset: RwLock::new(HashMap::new()),
+            sprite_atlas,
+            document_cancellation_token: RwLock::new(BackgroundWorkCancellationToken::new()),
             d3d11_device: init.subsystem.d3d11_device.clone(),
             d3d11_device_context: init.subsystem.d3d11_imm_context.clone(),
             d3d11_mt,
@@ -937,6 +1257,19 @@ impl AtlasBaseGridView {
         *self.atlas_bg_vertices_dirty.write() = true;
     }
 
+    /// Note: アトラスの中身は捨てられるので、このあとで`update_sprites`を呼んで読み込み直すこと
+    pub fn set_document_cancellation_token(&self, token: BackgroundWorkCancellationToken) {
+        *self.document_cancellation_token.write() = token;
+    }
+
+    pub fn set_alpha_mode(&self, alpha_mode: AlphaMode) {
+        let c = D3D11CriticalSectionGuard::enter(&self.d3d11_mt);
+        self.sprite_atlas
+            .write()
+            .set_alpha_mode(&self.d3d11_device, alpha_mode);
+        drop(c);
+    }
+
     pub fn update_sprite_offset(&self, index: usize, left_pixels: f32, top_pixels: f32) {
         let c = D3D11CriticalSectionGuard::enter(&self.d3d11_mt);
         let mut sprite_instance_buffer = self.sprite_instance_buffer.write();
@@ -1051,81 +1384,169 @@ impl AtlasBaseGridView {
                 .unwrap();
         }
         let mapped = unsafe { mapped.assume_init() };
-        for (n, x) in sprites.iter().enumerate() {
-            let (ox, oy) = match self
-                .sprite_source_offset
-                .write()
-                .entry(x.source_path.clone())
+        let mut atlas = self.sprite_atlas.write();
+        // 使われなくなったものを先に解放して空きを作っておく
+        atlas.retain(
+            &sprites
+                .iter()
+                .map(|x| {
+                    (
+                        x.source_path.clone(),
+                        [x.source_left, x.source_top, x.width, x.height],
+                    )
+                })
+                .collect(),
+        );
+        if !atlas.is_high_precision() {
+            // 新しく入るソースに16bitや浮動小数点のものがあればテクスチャの形式を上げる（ヘッダだけ見る）
+            let new_sources = sprites
+                .iter()
+                .filter(|x| {
+                    atlas
+                        .placement(&(
+                            x.source_path.clone(),
+                            [x.source_left, x.source_top, x.width, x.height],
+                        ))
+                        .is_none()
+                })
+                .map(|x| x.source_path.as_path())
+                .collect::<HashSet<_>>();
+            if new_sources
+                .into_iter()
+                .any(|p| SampleFormat::probe(p).is_ok_and(SampleFormat::is_high_precision))
             {
-                // ロード済み
-                std::collections::hash_map::Entry::Occupied(o) => *o.get(),
-                std::collections::hash_map::Entry::Vacant(e) => {
-                    let Some((ox, oy)) = self.simple_atlas.write().alloc(x.width, x.height) else {
-                        tracing::warn!("no suitable region(realloc or alloc page here...)");
-                        continue;
-                    };
-                    e.insert((ox, oy));
+                atlas.promote_to_high_precision(&self.d3d11_device);
+            }
+        }
+        let cancellation_token = self.document_cancellation_token.read().clone();
+        // Note: 途中でグループ全体が終わったことにならないように、メンバーをそろえてからキューに入れる
+        let load_group = BackgroundJobGroup::new("Loading sprites");
+        let mut loads = Vec::new();
+        // Note: 領域がたりないとallocの中で詰め直しが起きて先に割り当てたものも動くので、先に全部割り当ててから位置を書き込む
+        for x in sprites.iter() {
+            let key = (
+                x.source_path.clone(),
+                [x.source_left, x.source_top, x.width, x.height],
+            );
+            let (_, needs_load) = match atlas.alloc(
+                &self.d3d11_device,
+                &self.d3d11_device_context,
+                key.clone(),
+            ) {
+                Ok(x) => x,
+                Err(e) => {
+                    tracing::warn!({ ?e, path = ?x.source_path }, "no suitable region for sprite");
+                    continue;
+                }
+            };
 
-                    background_worker_enqueue_access.enqueue(BackgroundWork::LoadSpriteSource(
+            if needs_load {
+                // Note: 取り消されたり失敗したりしたら、PendingSpriteLoadが配置を解放して次の更新で読み込み直されるようにする
+                let mut pending = PendingSpriteLoad {
+                    sprite_atlas: self.sprite_atlas.clone(),
+                    id: atlas.begin_load(key.clone(), cancellation_token.clone()),
+                    key,
+                    finished: false,
+                };
+                loads.push(
+                    BackgroundWork::new(BackgroundWorkKind::LoadSpriteSource(
                         x.source_path.clone(),
                         Box::new({
                             let d3d11_device_context = self.d3d11_device_context.clone();
                             let d3d11_mt = self.d3d11_mt.clone();
-                            let simple_atlas_resource = self.simple_atlas.read().resource.clone();
-                            let (width, height) = (x.width, x.height);
 
                             move |path, di| {
-                                // TODO: HDR対応
-                                let img_formatted = di.to_rgba8();
+                                let [source_left, source_top, width, height] = pending.key.1;
+                                let cropped = di.crop_imm(source_left, source_top, width, height);
+                                if (cropped.width(), cropped.height()) != (width, height) {
+                                    // Note: 保存したあとにソースが小さくなっている。そのまま書き込むと確保した領域とずれて周りを壊すので読み込まない
+                                    tracing::warn!({?path, width, height}, "source is smaller than the sprite region");
+                                    return;
+                                }
+                                let encoding = pending.sprite_atlas.read().encoding();
+                                let (texels, row_pitch) = SpriteTextureAtlas::texels(
+                                    encoding,
+                                    atlas_image::source_alpha_mode(&path),
+                                    &cropped,
+                                );
+
+                                let c = D3D11CriticalSectionGuard::enter(&d3d11_mt);
+                                let mut atlas = pending.sprite_atlas.write();
+                                pending.finished = true;
+                                if !atlas.finish_load(&pending.key, pending.id) {
+                                    // 読み込み直しが始まっているか、もう解放されている
+                                    tracing::info!({?path}, "LoadSpriteComplete(discarded)");
+                                    return;
+                                }
+                                if atlas.encoding() != encoding {
+                                    // 変換中に持ち方が変わった（変えたときに読み込み直しが入っている）
+                                    tracing::info!({?path}, "LoadSpriteComplete(discarded)");
+                                    return;
+                                }
+                                // Note: 読み込み中に詰め直しで動いたり解放されたりしているかもしれないので、書き込む直前に配置を引き直す
+                                let Some(region) = atlas.placement(&pending.key).copied() else {
+                                    tracing::info!({?path}, "LoadSpriteComplete(discarded)");
+                                    return;
+                                };
                                 unsafe {
-                                    let c = D3D11CriticalSectionGuard::enter(&d3d11_mt);
-
                                     d3d11_device_context.UpdateSubresource(
-                                        &simple_atlas_resource,
-                                        0,
+                                        &atlas.resource,
+                                        region.page,
                                         Some(&D3D11_BOX {
-                                            left: ox,
-                                            top: oy,
+                                            left: region.left,
+                                            top: region.top,
                                             front: 0,
-                                            right: ox + width,
-                                            bottom: oy + height,
+                                            right: region.left + region.width,
+                                            bottom: region.top + region.height,
                                             back: 1,
                                         }),
-                                        img_formatted.as_bytes().as_ptr() as *const _,
-                                        img_formatted.width() * 4,
+                                        texels.as_ptr() as *const _,
+                                        row_pitch,
                                         0,
                                     );
-
-                                    drop(c);
                                 }
-                                tracing::info!({?path, ox, oy}, "LoadSpriteComplete");
+                                drop(atlas);
+                                drop(c);
+
+                                tracing::info!({?path, ?region}, "LoadSpriteComplete");
                             }
                         }),
-                    ));
-
-                    (ox, oy)
-                }
+                    ))
+                    .with_group(&load_group)
+                    .with_cancellation_token(cancellation_token.clone()),
+                );
+            }
+        }
+        for (n, x) in sprites.iter().enumerate() {
+            let (pos_st, uv_st, page) = match atlas.placement(&(
+                x.source_path.clone(),
+                [x.source_left, x.source_top, x.width, x.height],
+            )) {
+                Some(region) => (
+                    [x.width as f32, x.height as f32, x.left as f32, x.top as f32],
+                    [
+                        region.width as f32 / SpriteTextureAtlas::PAGE_SIZE as f32,
+                        region.height as f32 / SpriteTextureAtlas::PAGE_SIZE as f32,
+                        region.left as f32 / SpriteTextureAtlas::PAGE_SIZE as f32,
+                        region.top as f32 / SpriteTextureAtlas::PAGE_SIZE as f32,
+                    ],
+                    region.page,
+                ),
+                // 割り当てられなかった（警告は出してある）
+                // Note: インスタンスの数はスプライトの数のままなので、大きさ0にして描かれないようにする
+                None => ([0.0; 4], [0.0; 4], 0),
             };
 
             unsafe {
                 let instance_ptr = (mapped.pData as *mut SpriteInstance).add(n);
-                core::ptr::write(
-                    core::ptr::addr_of_mut!((*instance_ptr).pos_st),
-                    [x.width as f32, x.height as f32, x.left as f32, x.top as f32],
-                );
-                core::ptr::write(
-                    core::ptr::addr_of_mut!((*instance_ptr).uv_st),
-                    [
-                        x.width as f32 / SimpleTextureAtlas::SIZE as f32,
-                        x.height as f32 / SimpleTextureAtlas::SIZE as f32,
-                        ox as f32 / SimpleTextureAtlas::SIZE as f32,
-                        oy as f32 / SimpleTextureAtlas::SIZE as f32,
-                    ],
-                );
+                core::ptr::write(core::ptr::addr_of_mut!((*instance_ptr).pos_st), pos_st);
+                core::ptr::write(core::ptr::addr_of_mut!((*instance_ptr).uv_st), uv_st);
+                core::ptr::write(core::ptr::addr_of_mut!((*instance_ptr).page), page);
 
                 sprite_instance_buffer.is_dirty = true;
             }
         }
+        drop(atlas);
         unsafe {
             self.d3d11_device_context
                 .Unmap(&sprite_instance_buffer.staging, 0);
@@ -1133,6 +1554,10 @@ impl AtlasBaseGridView {
 
         sprite_instance_buffer.count = sprites.len();
         drop(c);
+
+        for w in loads {
+            background_worker_enqueue_access.enqueue(w);
+        }
     }
 
     pub fn set_offset(&self, offset_x: f32, offset_y: f32) {
@@ -1203,7 +1628,12 @@ impl AtlasBaseGridView {
         unsafe {
             core::ptr::write(
                 mapped.pData as _,
-                [[width_px as f32, height_px as f32], [offset_x, offset_y]],
+                SpriteInstanceRenderParams {
+                    pixel_size: [width_px as _, height_px as _],
+                    offset: [offset_x, offset_y],
+                    premultiply: (self.sprite_atlas.read().alpha_mode() == AlphaMode::Straight)
+                        as _,
+                },
             );
         }
         unsafe {
@@ -1304,7 +1734,9 @@ impl AtlasBaseGridView {
             self.d3d11_device_context
                 .VSSetConstantBuffers(0, Some(&[Some(self.texture_preview_cb.clone())]));
             self.d3d11_device_context
-                .PSSetShaderResources(0, Some(&[Some(self.simple_atlas.read().srv.clone())]));
+                .PSSetConstantBuffers(0, Some(&[Some(self.texture_preview_cb.clone())]));
+            self.d3d11_device_context
+                .PSSetShaderResources(0, Some(&[Some(self.sprite_atlas.read().srv.clone())]));
             self.d3d11_device_context
                 .PSSetSamplers(0, Some(&[Some(self.tex_sampler.clone())]));
             self.d3d11_device_context
@@ -1684,92 +2116,44 @@ impl SpriteAtlasBorderView {
     }
 }
 
-pub struct SpriteListToggleButtonView {
+/// 自動検出されたスプライト候補の枠表示（検出元の画像の上に重ねる）
+pub struct SpriteCandidatesView {
     root: ContainerVisual,
-    bg: SpriteVisual,
-    icon: SpriteVisual,
-    ht_root: HitTestTreeRef,
-    dpi: Cell<f32>,
-    hovering: Cell<bool>,
-    pressing: Cell<bool>,
+    sheet: SpriteVisual,
+    frames: ContainerVisual,
+    accepted_brush: CompositionNineGridBrush,
+    rejected_brush: CompositionNineGridBrush,
+    subsystem: Rc<Subsystem>,
 }
-impl SpriteListToggleButtonView {
-    const BUTTON_SIZE: f32 = 20.0;
-    const ICON_SIZE: f32 = 7.0;
-    const ICON_THICKNESS: f32 = 1.25;
-    const ICON_COLOR: D2D1_COLOR_F = d2d1_color_f_from_hex_rgb(0xf0f0f0);
-
-    pub fn new(init: &mut ViewInitContext) -> Self {
-        let button_size_px = init.dip_to_pixels(Self::BUTTON_SIZE);
-        let icon_size_px = init.dip_to_pixels(Self::ICON_SIZE);
+impl SpriteCandidatesView {
+    const ACCEPTED_COLOR: D2D1_COLOR_F = d2d1_color_f_from_websafe_hex_rgb(0x0f0);
+    const REJECTED_COLOR: D2D1_COLOR_F = d2d1_color_f_from_websafe_hex_rgb(0x888);
+    /// 配置済みのスプライトと見分けられるように検出元の画像は薄く表示する
+    const SHEET_OPACITY: f32 = 0.5;
 
-        let icon_surface = init
+    fn new_frame_brush(
+        init: &mut ViewInitContext,
+        color: &D2D1_COLOR_F,
+    ) -> CompositionNineGridBrush {
+        let frame_surface = init
             .subsystem
-            .new_2d_drawing_surface(size_sq(icon_size_px))
+            .new_2d_drawing_surface(Size {
+                Width: 4.0,
+                Height: 4.0,
+            })
             .unwrap();
-        draw_2d(&icon_surface, |dc, offset| {
-            unsafe {
-                dc.SetDpi(init.dpi, init.dpi);
-                dc.SetTransform(&Matrix3x2::translation(
-                    init.signed_pixels_to_dip(offset.x),
-                    init.signed_pixels_to_dip(offset.y),
-                ));
-            }
-
-            let brush = unsafe { dc.CreateSolidColorBrush(&Self::ICON_COLOR, None)? };
-
+        draw_2d(&frame_surface, |dc, offset| {
             unsafe {
                 dc.Clear(None);
-                dc.DrawLine(
-                    D2D_POINT_2F {
-                        x: Self::ICON_SIZE * 0.4,
-                        y: 0.0,
-                    },
-                    D2D_POINT_2F {
-                        x: 0.0,
-                        y: Self::ICON_SIZE * 0.5,
-                    },
-                    &brush,
-                    Self::ICON_THICKNESS,
-                    None,
-                );
-                dc.DrawLine(
-                    D2D_POINT_2F {
-                        x: 0.0,
-                        y: Self::ICON_SIZE * 0.5,
-                    },
-                    D2D_POINT_2F {
-                        x: Self::ICON_SIZE * 0.4,
-                        y: Self::ICON_SIZE,
-                    },
-                    &brush,
-                    Self::ICON_THICKNESS,
-                    None,
-                );
-                dc.DrawLine(
-                    D2D_POINT_2F {
-                        x: Self::ICON_SIZE,
-                        y: 0.0,
-                    },
-                    D2D_POINT_2F {
-                        x: Self::ICON_SIZE * 0.6,
-                        y: Self::ICON_SIZE * 0.5,
-                    },
-                    &brush,
-                    Self::ICON_THICKNESS,
-                    None,
-                );
-                dc.DrawLine(
-                    D2D_POINT_2F {
-                        x: Self::ICON_SIZE * 0.6,
-                        y: Self::ICON_SIZE * 0.5,
-                    },
-                    D2D_POINT_2F {
-                        x: Self::ICON_SIZE,
-                        y: Self::ICON_SIZE,
+                dc.DrawRectangle(
+                    &D2D_RECT_F {
+                        left: offset.x as f32 + 0.5,
+                        top: offset.y as f32 + 0.5,
+                        right: offset.x as f32 + 4.0 - 0.5,
+                        bottom: offset.y as f32 + 4.0 - 0.5,
                     },
-                    &brush,
-                    Self::ICON_THICKNESS,
+                    &dc.CreateSolidColorBrush(color, None)?,
+                    1.0,
                     None,
                 );
             }
@@ -1778,319 +2162,291 @@ impl SpriteListToggleButtonView {
         })
         .unwrap();
 
-        let frame_surface = init
-            .subsystem
-            .new_2d_drawing_surface(Size {
-                Width: button_size_px,
-                Height: button_size_px,
-            })
-            .unwrap();
-        draw_2d(&frame_surface, |dc, offset| {
-            unsafe {
-                dc.SetDpi(init.dpi, init.dpi);
-                dc.SetTransform(&Matrix3x2::translation(
-                    init.signed_pixels_to_dip(offset.x),
-                    init.signed_pixels_to_dip(offset.y),
-                ));
-
-                dc.Clear(None);
-                dc.FillEllipse(
-                    &D2D1_ELLIPSE {
-                        point: D2D_POINT_2F {
-                            x: Self::BUTTON_SIZE * 0.5,
-                            y: Self::BUTTON_SIZE * 0.5,
-                        },
-                        radiusX: Self::BUTTON_SIZE * 0.5,
-                        radiusY: Self::BUTTON_SIZE * 0.5,
-                    },
-                    &dc.CreateSolidColorBrush(&D2D1_COLOR_F_WHITE, None)?,
-                );
-            }
-
-            Ok::<_, windows_core::Error>(())
-        })
-        .unwrap();
+        CompositionNineGridBrushParams::new(
+            &CompositionSurfaceBrushParams::new(&frame_surface)
+                .stretch(CompositionStretch::Fill)
+                .instantiate(&init.subsystem.compositor)
+                .unwrap(),
+        )
+        .insets(1.0)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap()
+    }
 
+    pub fn new(init: &mut ViewInitContext) -> Self {
         let root = ContainerVisualParams::new()
-            .size_sq(button_size_px)
-            .relative_offset_adjustment_xy(Vector2 { X: 1.0, Y: 0.0 })
-            .offset_xy(Vector2 {
-                X: -button_size_px - init.dip_to_pixels(8.0),
-                Y: init.dip_to_pixels(8.0),
-            })
             .instantiate(&init.subsystem.compositor)
             .unwrap();
-        let bg = SpriteVisualParams::new(
-            &CompositionSurfaceBrushParams::new(&frame_surface)
-                .instantiate(&init.subsystem.compositor)
-                .unwrap(),
-        )
-        .expand()
-        .opacity(0.0)
-        .instantiate(&init.subsystem.compositor)
-        .unwrap();
-        let icon = SpriteVisualParams::new(
-            &CompositionSurfaceBrushParams::new(&icon_surface)
-                .instantiate(&init.subsystem.compositor)
-                .unwrap(),
-        )
-        .size_sq(icon_size_px)
-        .relative_offset_adjustment_xy(Vector2 { X: 0.5, Y: 0.5 })
-        .anchor_point(Vector2 { X: 0.5, Y: 0.5 })
-        .instantiate(&init.subsystem.compositor)
-        .unwrap();
-
-        let children = root.Children().unwrap();
-        children.InsertAtTop(&bg).unwrap();
-        children.InsertAtTop(&icon).unwrap();
-
-        let implicit_easing_animation = SimpleImplicitAnimationParams::new(
-            &init
-                .subsystem
-                .compositor
-                .CreateCubicBezierEasingFunction(
-                    Vector2 { X: 0.5, Y: 0.0 },
-                    Vector2 { X: 0.5, Y: 1.0 },
-                )
-                .unwrap(),
-            h!("Opacity"),
-            timespan_ms(100),
-        )
-        .instantiate_scalar(&init.subsystem.compositor)
-        .unwrap();
-        let implicit_move_animation = SimpleImplicitAnimationParams::new(
-            &init
-                .subsystem
-                .compositor
-                .CreateCubicBezierEasingFunction(
-                    Vector2 { X: 0.5, Y: 0.0 },
-                    Vector2 { X: 0.5, Y: 1.0 },
-                )
-                .unwrap(),
-            h!("Offset"),
-            SpriteListPaneView::TRANSITION_DURATION,
-        )
-        .instantiate_vector3(&init.subsystem.compositor)
-        .unwrap();
-
-        let bg_implicit_animations = init
-            .subsystem
-            .compositor
-            .CreateImplicitAnimationCollection()
-            .unwrap();
-        bg_implicit_animations
-            .Insert(h!("Opacity"), &implicit_easing_animation)
-            .unwrap();
-        bg.SetImplicitAnimations(&bg_implicit_animations).unwrap();
-
-        let root_implicit_animations = init
-            .subsystem
-            .compositor
-            .CreateImplicitAnimationCollection()
-            .unwrap();
-        root_implicit_animations
-            .Insert(h!("Offset"), &implicit_move_animation)
-            .unwrap();
-        root.SetImplicitAnimations(&root_implicit_animations)
+        let sheet = init.subsystem.compositor.CreateSpriteVisual().unwrap();
+        sheet.SetOpacity(Self::SHEET_OPACITY).unwrap();
+        let frames = ContainerVisualParams::new()
+            .instantiate(&init.subsystem.compositor)
             .unwrap();
 
-        let ht_root = init.ht.borrow_mut().alloc(HitTestTreeData {
-            left: -Self::BUTTON_SIZE - 8.0,
-            top: 8.0,
-            left_adjustment_factor: 1.0,
-            top_adjustment_factor: 0.0,
-            width: Self::BUTTON_SIZE,
-            height: Self::BUTTON_SIZE,
-            width_adjustment_factor: 0.0,
-            height_adjustment_factor: 0.0,
-            parent: None,
-            children: Vec::new(),
-            action_handler: None,
-        });
+        let children = root.Children().unwrap();
+        children.InsertAtTop(&sheet).unwrap();
+        children.InsertAtTop(&frames).unwrap();
 
         Self {
             root,
-            icon,
-            bg,
-            ht_root,
-            dpi: Cell::new(init.dpi),
-            hovering: Cell::new(false),
-            pressing: Cell::new(false),
+            sheet,
+            frames,
+            accepted_brush: Self::new_frame_brush(init, &Self::ACCEPTED_COLOR),
+            rejected_brush: Self::new_frame_brush(init, &Self::REJECTED_COLOR),
+            subsystem: init.subsystem.clone(),
         }
     }
 
-    pub fn mount(
-        &self,
-        children: &VisualCollection,
-        ht: &mut AppHitTestTreeManager,
-        ht_parent: HitTestTreeRef,
-    ) {
+    pub fn mount(&self, children: &VisualCollection) {
         children.InsertAtTop(&self.root).unwrap();
-        ht.add_child(ht_parent, self.ht_root);
-    }
-
-    fn update_bg_opacity(&self) {
-        match (self.hovering.get(), self.pressing.get()) {
-            (false, _) => {
-                self.bg.SetOpacity(0.0).unwrap();
-            }
-            (true, false) => {
-                self.bg.SetOpacity(0.15).unwrap();
-            }
-            (true, true) => {
-                self.bg.SetOpacity(0.3).unwrap();
-            }
-        }
     }
 
-    pub fn on_hover(&self) {
-        self.hovering.set(true);
-        self.update_bg_opacity();
-    }
+    pub fn set_candidates(&self, candidates: &[SpriteCandidate]) {
+        let children = self.frames.Children().unwrap();
+        children.RemoveAll().unwrap();
 
-    pub fn on_press(&self) {
-        self.pressing.set(true);
-        self.update_bg_opacity();
+        for c in candidates {
+            let frame = SpriteVisualParams::new(if c.accepted {
+                &self.accepted_brush
+            } else {
+                &self.rejected_brush
+            })
+            .offset_xy(Vector2 {
+                X: c.left as _,
+                Y: c.top as _,
+            })
+            .size(Vector2 {
+                X: c.width as _,
+                Y: c.height as _,
+            })
+            .instantiate(&self.subsystem.compositor)
+            .unwrap();
+            children.InsertAtTop(&frame).unwrap();
+        }
     }
 
-    pub fn on_release(&self) {
-        self.pressing.set(false);
-        self.update_bg_opacity();
-    }
+    pub fn set_sheet(&self, sheet: Option<&image::RgbaImage>) {
+        let Some(sheet) = sheet else {
+            self.sheet.SetBrush(None::<&CompositionBrush>).unwrap();
+            return;
+        };
 
-    pub fn on_hover_leave(&self) {
-        self.hovering.set(false);
-        self.pressing.set(false);
-        self.update_bg_opacity();
-    }
+        // Note: 大きすぎてサーフェスを作れないときは枠だけ表示する
+        let surface = match self.subsystem.new_2d_drawing_surface(Size {
+            Width: sheet.width() as _,
+            Height: sheet.height() as _,
+        }) {
+            Ok(x) => x,
+            Err(e) => {
+                tracing::warn!({ %e, width = sheet.width(), height = sheet.height() }, "failed to create candidate sheet surface");
+                self.sheet.SetBrush(None::<&CompositionBrush>).unwrap();
+                return;
+            }
+        };
+        if let Err(e) = draw_2d(&surface, |dc, offset| {
+            let bitmap = create_bitmap_from_rgba(dc, sheet)?;
+            unsafe {
+                dc.Clear(None);
+                dc.DrawBitmap(
+                    &bitmap,
+                    Some(&D2D_RECT_F {
+                        left: offset.x as f32,
+                        top: offset.y as f32,
+                        right: offset.x as f32 + sheet.width() as f32,
+                        bottom: offset.y as f32 + sheet.height() as f32,
+                    }),
+                    1.0,
+                    D2D1_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
+                    None,
+                    None,
+                );
+            }
 
-    pub fn transit_hidden(&self, ht: &mut AppHitTestTreeManager) {
-        let dpi = self.dpi.get();
+            Ok::<_, windows_core::Error>(())
+        }) {
+            tracing::warn!({ %e }, "failed to draw candidate sheet");
+            self.sheet.SetBrush(None::<&CompositionBrush>).unwrap();
+            return;
+        }
 
-        self.icon
-            .SetScale(Vector3 {
-                X: -1.0,
-                Y: 1.0,
-                Z: 1.0,
+        self.sheet
+            .SetBrush(
+                &CompositionSurfaceBrushParams::new(&surface)
+                    .instantiate(&self.subsystem.compositor)
+                    .unwrap(),
+            )
+            .unwrap();
+        self.sheet
+            .SetSize(Vector2 {
+                X: sheet.width() as _,
+                Y: sheet.height() as _,
             })
             .unwrap();
+    }
+
+    pub fn set_view_offset(&self, offset_x_pixels: f32, offset_y_pixels: f32) {
         self.root
             .SetOffset(Vector3 {
-                X: dip_to_pixels(8.0 + SpriteListPaneView::SPACING, dpi),
-                Y: dip_to_pixels(8.0, dpi),
+                X: -offset_x_pixels,
+                Y: -offset_y_pixels,
                 Z: 0.0,
             })
             .unwrap();
-        ht.get_mut(self.ht_root).left = 8.0 + SpriteListPaneView::SPACING;
     }
+}
 
-    pub fn transit_shown(&self, ht: &mut AppHitTestTreeManager) {
-        let dpi = self.dpi.get();
+/// 検索にマッチしたスプライトの枠表示
+pub struct SpriteFilterMatchesView {
+    root: ContainerVisual,
+    frame_brush: CompositionNineGridBrush,
+    compositor: Compositor,
+    sprites: RefCell<Vec<((u32, u32, u32, u32), SpriteFilterTarget)>>,
+    filter: RefCell<Option<SpriteFilter>>,
+}
+impl SpriteFilterMatchesView {
+    const COLOR: D2D1_COLOR_F = d2d1_color_f_from_websafe_hex_rgb(0xff0);
 
-        self.icon
-            .SetScale(Vector3 {
-                X: 1.0,
-                Y: 1.0,
-                Z: 1.0,
-            })
+    pub fn new(init: &mut ViewInitContext) -> Self {
+        let root = ContainerVisualParams::new()
+            .instantiate(&init.subsystem.compositor)
             .unwrap();
+
+        Self {
+            root,
+            frame_brush: SpriteCandidatesView::new_frame_brush(init, &Self::COLOR),
+            compositor: init.subsystem.compositor.clone(),
+            sprites: RefCell::new(Vec::new()),
+            filter: RefCell::new(None),
+        }
+    }
+
+    pub fn mount(&self, children: &VisualCollection) {
+        children.InsertAtTop(&self.root).unwrap();
+    }
+
+    pub fn set_sprites(&self, sprites: &[SpriteInfo]) {
+        *self.sprites.borrow_mut() = sprites
+            .iter()
+            .map(|x| {
+                (
+                    (x.left, x.top, x.width, x.height),
+                    SpriteFilterTarget::from_sprite(x),
+                )
+            })
+            .collect();
+        self.update();
+    }
+
+    pub fn set_filter(&self, filter: Option<SpriteFilter>) {
+        *self.filter.borrow_mut() = filter;
+        self.update();
+    }
+
+    fn update(&self) {
+        let children = self.root.Children().unwrap();
+        children.RemoveAll().unwrap();
+
+        let Some(ref filter) = *self.filter.borrow() else {
+            // 検索していない
+            return;
+        };
+        for &((left, top, width, height), ref target) in self.sprites.borrow().iter() {
+            if !filter.matches(target) {
+                continue;
+            }
+
+            let frame = SpriteVisualParams::new(&self.frame_brush)
+                .offset_xy(Vector2 {
+                    X: left as _,
+                    Y: top as _,
+                })
+                .size(Vector2 {
+                    X: width as _,
+                    Y: height as _,
+                })
+                .instantiate(&self.compositor)
+                .unwrap();
+            children.InsertAtTop(&frame).unwrap();
+        }
+    }
+
+    pub fn set_view_offset(&self, offset_x_pixels: f32, offset_y_pixels: f32) {
         self.root
             .SetOffset(Vector3 {
-                X: dip_to_pixels(-Self::BUTTON_SIZE - 8.0, dpi),
-                Y: dip_to_pixels(8.0, dpi),
+                X: -offset_x_pixels,
+                Y: -offset_y_pixels,
                 Z: 0.0,
             })
             .unwrap();
-        ht.get_mut(self.ht_root).left = -Self::BUTTON_SIZE - 8.0;
     }
 }
 
-pub struct SpriteListCellView {
+/// 選択中のスプライトのピボット位置を示す十字マーカー
+pub struct SpritePivotMarkerView {
     root: ContainerVisual,
-    bg: SpriteVisual,
-    bg_select: SpriteVisual,
-    label: SpriteVisual,
-    top: Cell<f32>,
-    dpi: Cell<f32>,
+    marker: SpriteVisual,
 }
-impl SpriteListCellView {
-    const FRAME_TEX_SIZE: f32 = 24.0;
-    const CORNER_RADIUS: f32 = 8.0;
-    const CELL_HEIGHT: f32 = 20.0;
-    const LABEL_COLOR: D2D1_COLOR_F = D2D1_COLOR_F_WHITE;
-    const BG_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb_with_alpha(0xccc, 32);
-    const ACTIVE_BG_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb_with_alpha(0x4af, 64);
+impl SpritePivotMarkerView {
+    const SIZE: f32 = 13.0;
+    const THICKNESS: f32 = 1.0;
+    const COLOR: D2D1_COLOR_F = d2d1_color_f_from_hex_rgb(0xff00ff);
+    /// ドラッグを開始できるマーカーからの距離
+    pub const GRAB_RADIUS: f32 = 6.0;
 
-    fn gen_frame_tex(subsystem: &Subsystem, dpi: f32) -> CompositionDrawingSurface {
-        let s = subsystem
+    pub fn new(init: &mut ViewInitContext) -> Self {
+        let surface = init
+            .subsystem
             .new_2d_drawing_surface(Size {
-                Width: dip_to_pixels(Self::FRAME_TEX_SIZE, dpi),
-                Height: dip_to_pixels(Self::FRAME_TEX_SIZE, dpi),
+                Width: init.dip_to_pixels(Self::SIZE),
+                Height: init.dip_to_pixels(Self::SIZE),
             })
             .unwrap();
-        draw_2d(&s, |dc, offset| {
+        draw_2d(&surface, |dc, offset| {
             unsafe {
-                dc.SetDpi(dpi, dpi);
+                dc.SetDpi(init.dpi, init.dpi);
                 dc.SetTransform(&Matrix3x2::translation(
-                    signed_pixels_to_dip(offset.x, dpi),
-                    signed_pixels_to_dip(offset.y, dpi),
+                    init.signed_pixels_to_dip(offset.x),
+                    init.signed_pixels_to_dip(offset.y),
                 ));
-
-                dc.Clear(None);
-                dc.FillRoundedRectangle(
-                    &D2D1_ROUNDED_RECT {
-                        rect: D2D_RECT_F {
-                            left: 0.0,
-                            top: 0.0,
-                            right: Self::FRAME_TEX_SIZE,
-                            bottom: Self::FRAME_TEX_SIZE,
-                        },
-                        radiusX: Self::CORNER_RADIUS,
-                        radiusY: Self::CORNER_RADIUS,
-                    },
-                    &dc.CreateSolidColorBrush(&D2D1_COLOR_F_WHITE, None)?,
-                );
             }
 
-            Ok::<_, windows_core::Error>(())
-        })
-        .unwrap();
-
-        s
-    }
-
-    pub fn new(init: &mut ViewInitContext, label: &str, init_top: f32) -> Self {
-        let frame_tex = Self::gen_frame_tex(init.subsystem, init.dpi);
+            let brush = unsafe { dc.CreateSolidColorBrush(&Self::COLOR, None)? };
+            let center = Self::SIZE * 0.5;
 
-        let tl = init
-            .subsystem
-            .new_text_layout_unrestricted(label, &init.subsystem.default_ui_format)
-            .unwrap();
-        let mut tm = core::mem::MaybeUninit::uninit();
-        unsafe {
-            tl.GetMetrics(tm.as_mut_ptr()).unwrap();
-        }
-        let tm = unsafe { tm.assume_init() };
-        let label_surface = init
-            .subsystem
-            .new_2d_drawing_surface(Size {
-                Width: init.dip_to_pixels(tm.width),
-                Height: init.dip_to_pixels(tm.height),
-            })
-            .unwrap();
-        draw_2d(&label_surface, |dc, offset| {
             unsafe {
-                dc.SetDpi(init.dpi, init.dpi);
-
                 dc.Clear(None);
-                dc.DrawTextLayout(
+                dc.DrawLine(
+                    D2D_POINT_2F { x: center, y: 0.0 },
                     D2D_POINT_2F {
-                        x: init.signed_pixels_to_dip(offset.x),
-                        y: init.signed_pixels_to_dip(offset.y),
+                        x: center,
+                        y: Self::SIZE,
                     },
-                    &tl,
-                    &dc.CreateSolidColorBrush(&Self::LABEL_COLOR, None)?,
-                    D2D1_DRAW_TEXT_OPTIONS_NONE,
+                    &brush,
+                    Self::THICKNESS,
+                    None,
+                );
+                dc.DrawLine(
+                    D2D_POINT_2F { x: 0.0, y: center },
+                    D2D_POINT_2F {
+                        x: Self::SIZE,
+                        y: center,
+                    },
+                    &brush,
+                    Self::THICKNESS,
+                    None,
+                );
+                dc.DrawEllipse(
+                    &D2D1_ELLIPSE {
+                        point: D2D_POINT_2F {
+                            x: center,
+                            y: center,
+                        },
+                        radiusX: center * 0.5,
+                        radiusY: center * 0.5,
+                    },
+                    &brush,
+                    Self::THICKNESS,
+                    None,
                 );
             }
 
@@ -2098,198 +2454,143 @@ impl SpriteListCellView {
         })
         .unwrap();
 
-        let bg_base_brush = CompositionNineGridBrushParams::new(
-            &CompositionSurfaceBrushParams::new(&frame_tex)
-                .stretch(CompositionStretch::Fill)
+        let marker = SpriteVisualParams::new(
+            &CompositionSurfaceBrushParams::new(&surface)
                 .instantiate(&init.subsystem.compositor)
                 .unwrap(),
         )
-        .insets(init.dip_to_pixels(Self::CORNER_RADIUS))
+        .size_sq(init.dip_to_pixels(Self::SIZE))
+        .anchor_point(Vector2 { X: 0.5, Y: 0.5 })
         .instantiate(&init.subsystem.compositor)
         .unwrap();
+        marker.SetOpacity(0.0).unwrap();
 
         let root = ContainerVisualParams::new()
-            .size(Vector2 {
-                X: init.dip_to_pixels(
-                    -SpriteListPaneView::CELL_AREA_PADDINGS.right
-                        - SpriteListPaneView::CELL_AREA_PADDINGS.left,
-                ),
-                Y: init.dip_to_pixels(Self::CELL_HEIGHT),
-            })
-            .expand_width()
-            .offset_xy(Vector2 {
-                X: init.dip_to_pixels(SpriteListPaneView::CELL_AREA_PADDINGS.left),
-                Y: init.dip_to_pixels(init_top),
-            })
             .instantiate(&init.subsystem.compositor)
             .unwrap();
-        let bg = SpriteVisualParams::new(
-            &create_instant_effect_brush(
-                &init.subsystem,
-                &TintEffectParams {
-                    source: &CompositionEffectSourceParameter::Create(h!("source")).unwrap(),
-                    color: Some(Self::BG_COLOR),
-                }
-                .instantiate()
-                .unwrap(),
-                &[(h!("source"), bg_base_brush.cast().unwrap())],
-            )
-            .unwrap(),
-        )
-        .expand()
-        .opacity(0.0)
-        .instantiate(&init.subsystem.compositor)
-        .unwrap();
-        let bg_select = SpriteVisualParams::new(
-            &create_instant_effect_brush(
-                &init.subsystem,
-                &TintEffectParams {
-                    source: &CompositionEffectSourceParameter::Create(h!("source")).unwrap(),
-                    color: Some(Self::ACTIVE_BG_COLOR),
-                }
-                .instantiate()
-                .unwrap(),
-                &[(h!("source"), bg_base_brush.cast().unwrap())],
-            )
-            .unwrap(),
-        )
-        .expand()
-        .opacity(0.0)
-        .instantiate(&init.subsystem.compositor)
-        .unwrap();
-        let label = SpriteVisualParams::new(
-            &CompositionSurfaceBrushParams::new(&label_surface)
-                .instantiate(&init.subsystem.compositor)
-                .unwrap(),
-        )
-        .size(Vector2 {
-            X: init.dip_to_pixels(tm.width),
-            Y: init.dip_to_pixels(tm.height),
-        })
-        .offset_xy(Vector2 {
-            X: init.dip_to_pixels(8.0),
-            Y: init.dip_to_pixels(-tm.height * 0.5),
-        })
-        .relative_vertical_offset_adjustment(0.5)
-        .instantiate(&init.subsystem.compositor)
-        .unwrap();
-
-        let children = root.Children().unwrap();
-        children.InsertAtTop(&bg_select).unwrap();
-        children.InsertAtTop(&bg).unwrap();
-        children.InsertAtTop(&label).unwrap();
-
-        let opacity_transition = SimpleImplicitAnimationParams::new(
-            &init
-                .subsystem
-                .compositor
-                .CreateCubicBezierEasingFunction(
-                    Vector2 { X: 0.5, Y: 0.0 },
-                    Vector2 { X: 0.5, Y: 1.0 },
-                )
-                .unwrap(),
-            h!("Opacity"),
-            timespan_ms(100),
-        )
-        .instantiate_scalar(&init.subsystem.compositor)
-        .unwrap();
-
-        let bg_implicit_animations = init
-            .subsystem
-            .compositor
-            .CreateImplicitAnimationCollection()
-            .unwrap();
-        bg_implicit_animations
-            .Insert(h!("Opacity"), &opacity_transition)
-            .unwrap();
-        bg.SetImplicitAnimations(&bg_implicit_animations).unwrap();
-        bg_select
-            .SetImplicitAnimations(&bg_implicit_animations)
-            .unwrap();
+        root.Children().unwrap().InsertAtTop(&marker).unwrap();
 
-        Self {
-            root,
-            bg,
-            bg_select,
-            label,
-            top: Cell::new(init_top),
-            dpi: Cell::new(init.dpi),
-        }
+        Self { root, marker }
     }
 
     pub fn mount(&self, children: &VisualCollection) {
         children.InsertAtTop(&self.root).unwrap();
     }
 
-    pub fn unmount(&self) {
-        self.root
-            .Parent()
-            .unwrap()
-            .Children()
-            .unwrap()
-            .Remove(&self.root)
+    /// アトラス上のピクセル位置にマーカーを表示する
+    pub fn show_at(&self, x_pixels: f32, y_pixels: f32) {
+        self.marker
+            .SetOffset(Vector3 {
+                X: x_pixels,
+                Y: y_pixels,
+                Z: 0.0,
+            })
             .unwrap();
+        self.marker.SetOpacity(1.0).unwrap();
     }
 
-    pub fn on_hover(&self) {
-        self.bg.SetOpacity(1.0).unwrap();
-    }
-
-    pub fn on_leave(&self) {
-        self.bg.SetOpacity(0.0).unwrap();
-    }
-
-    pub fn on_select(&self) {
-        self.bg_select.SetOpacity(1.0).unwrap();
-    }
-
-    pub fn on_deselect(&self) {
-        self.bg_select.SetOpacity(0.0).unwrap();
+    pub fn hide(&self) {
+        self.marker.SetOpacity(0.0).unwrap();
     }
 
-    pub fn set_top(&self, top: f32) {
-        let dpi = self.dpi.get();
-
+    pub fn set_view_offset(&self, offset_x_pixels: f32, offset_y_pixels: f32) {
         self.root
             .SetOffset(Vector3 {
-                X: dip_to_pixels(SpriteListPaneView::CELL_AREA_PADDINGS.left, dpi),
-                Y: dip_to_pixels(top, dpi),
+                X: -offset_x_pixels,
+                Y: -offset_y_pixels,
                 Z: 0.0,
             })
             .unwrap();
-        self.top.set(top);
     }
+}
 
-    pub fn set_name(&self, name: &str, subsystem: &Subsystem) {
-        let dpi = self.dpi.get();
+pub struct SpriteListToggleButtonView {
+    root: ContainerVisual,
+    bg: SpriteVisual,
+    icon: SpriteVisual,
+    ht_root: HitTestTreeRef,
+    dpi: Cell<f32>,
+    hovering: Cell<bool>,
+    pressing: Cell<bool>,
+}
+impl SpriteListToggleButtonView {
+    const BUTTON_SIZE: f32 = 20.0;
+    const ICON_SIZE: f32 = 7.0;
+    const ICON_THICKNESS: f32 = 1.25;
+    const ICON_COLOR: D2D1_COLOR_F = d2d1_color_f_from_hex_rgb(0xf0f0f0);
 
-        let tl = subsystem
-            .new_text_layout_unrestricted(name, &subsystem.default_ui_format)
+    pub fn new(init: &mut ViewInitContext) -> Self {
+        let button_size_px = init.dip_to_pixels(Self::BUTTON_SIZE);
+        let icon_size_px = init.dip_to_pixels(Self::ICON_SIZE);
+
+        let icon_surface = init
+            .subsystem
+            .new_2d_drawing_surface(size_sq(icon_size_px))
             .unwrap();
-        let mut tm = core::mem::MaybeUninit::uninit();
-        unsafe {
-            tl.GetMetrics(tm.as_mut_ptr()).unwrap();
-        }
-        let tm = unsafe { tm.assume_init() };
-        let label_surface = subsystem
-            .new_2d_drawing_surface(Size {
-                Width: dip_to_pixels(tm.width, dpi),
-                Height: dip_to_pixels(tm.height, dpi),
-            })
-            .unwrap();
-        draw_2d(&label_surface, |dc, offset| {
+        draw_2d(&icon_surface, |dc, offset| {
             unsafe {
-                dc.SetDpi(dpi, dpi);
+                dc.SetDpi(init.dpi, init.dpi);
+                dc.SetTransform(&Matrix3x2::translation(
+                    init.signed_pixels_to_dip(offset.x),
+                    init.signed_pixels_to_dip(offset.y),
+                ));
+            }
+
+            let brush = unsafe { dc.CreateSolidColorBrush(&Self::ICON_COLOR, None)? };
 
+            unsafe {
                 dc.Clear(None);
-                dc.DrawTextLayout(
+                dc.DrawLine(
                     D2D_POINT_2F {
-                        x: signed_pixels_to_dip(offset.x, dpi),
-                        y: signed_pixels_to_dip(offset.y, dpi),
+                        x: Self::ICON_SIZE * 0.4,
+                        y: 0.0,
                     },
-                    &tl,
-                    &dc.CreateSolidColorBrush(&Self::LABEL_COLOR, None)?,
-                    D2D1_DRAW_TEXT_OPTIONS_NONE,
+                    D2D_POINT_2F {
+                        x: 0.0,
+                        y: Self::ICON_SIZE * 0.5,
+                    },
+                    &brush,
+                    Self::ICON_THICKNESS,
+                    None,
+                );
+                dc.DrawLine(
+                    D2D_POINT_2F {
+                        x: 0.0,
+                        y: Self::ICON_SIZE * 0.5,
+                    },
+                    D2D_POINT_2F {
+                        x: Self::ICON_SIZE * 0.4,
+                        y: Self::ICON_SIZE,
+                    },
+                    &brush,
+                    Self::ICON_THICKNESS,
+                    None,
+                );
+                dc.DrawLine(
+                    D2D_POINT_2F {
+                        x: Self::ICON_SIZE,
+                        y: 0.0,
+                    },
+                    D2D_POINT_2F {
+                        x: Self::ICON_SIZE * 0.6,
+                        y: Self::ICON_SIZE * 0.5,
+                    },
+                    &brush,
+                    Self::ICON_THICKNESS,
+                    None,
+                );
+                dc.DrawLine(
+                    D2D_POINT_2F {
+                        x: Self::ICON_SIZE * 0.6,
+                        y: Self::ICON_SIZE * 0.5,
+                    },
+                    D2D_POINT_2F {
+                        x: Self::ICON_SIZE,
+                        y: Self::ICON_SIZE,
+                    },
+                    &brush,
+                    Self::ICON_THICKNESS,
+                    None,
                 );
             }
 
@@ -2297,194 +2598,327 @@ impl SpriteListCellView {
         })
         .unwrap();
 
-        self.label
-            .SetBrush(
-                &CompositionSurfaceBrushParams::new(&label_surface)
-                    .instantiate(&subsystem.compositor)
-                    .unwrap(),
-            )
-            .unwrap();
-        self.label
-            .SetSize(Vector2 {
-                X: dip_to_pixels(tm.width, dpi),
-                Y: dip_to_pixels(tm.height, dpi),
+        let frame_surface = init
+            .subsystem
+            .new_2d_drawing_surface(Size {
+                Width: button_size_px,
+                Height: button_size_px,
             })
             .unwrap();
-    }
-}
+        draw_2d(&frame_surface, |dc, offset| {
+            unsafe {
+                dc.SetDpi(init.dpi, init.dpi);
+                dc.SetTransform(&Matrix3x2::translation(
+                    init.signed_pixels_to_dip(offset.x),
+                    init.signed_pixels_to_dip(offset.y),
+                ));
 
-pub struct SpriteListPaneView {
-    root: ContainerVisual,
-    ht_root: HitTestTreeRef,
-    ht_adjust_area: HitTestTreeRef,
-    ht_cell_area: HitTestTreeRef,
-    composition_properties: CompositionPropertySet,
-    hide_animation: ScalarKeyFrameAnimation,
-    show_animation: ScalarKeyFrameAnimation,
-    dpi: f32,
-    top: Cell<f32>,
-    width: Cell<f32>,
-}
-impl SpriteListPaneView {
-    const CORNER_RADIUS: f32 = 12.0;
-    const FRAME_TEX_SIZE: f32 = 32.0;
-    const BLUR_AMOUNT: f32 = 27.0;
-    const SURFACE_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb_with_alpha(0xfff, 24);
-    const SPACING: f32 = 8.0;
-    const ADJUST_AREA_THICKNESS: f32 = 4.0;
-    const INIT_WIDTH: f32 = 280.0;
-    const HEADER_LABEL_MAIN_COLOR: D2D1_COLOR_F = d2d1_color_f_from_websafe_hex_rgb(0xfff);
-    const HEADER_LABEL_SHADOW_COLOR: D2D1_COLOR_F = d2d1_color_f_from_websafe_hex_rgb(0x111);
-    const TRANSITION_DURATION: TimeSpan = timespan_ms(250);
-    const CELL_AREA_PADDINGS: RectDIP = RectDIP {
-        left: 16.0,
-        top: 32.0,
-        right: 16.0,
-        bottom: 16.0,
-    };
+                dc.Clear(None);
+                dc.FillEllipse(
+                    &D2D1_ELLIPSE {
+                        point: D2D_POINT_2F {
+                            x: Self::BUTTON_SIZE * 0.5,
+                            y: Self::BUTTON_SIZE * 0.5,
+                        },
+                        radiusX: Self::BUTTON_SIZE * 0.5,
+                        radiusY: Self::BUTTON_SIZE * 0.5,
+                    },
+                    &dc.CreateSolidColorBrush(&D2D1_COLOR_F_WHITE, None)?,
+                );
+            }
 
-    pub fn new(init: &mut ViewInitContext) -> Self {
-        let frame_surface = init
-            .subsystem
-            .rounded_rect_mask_surface(init.dpi, Self::CORNER_RADIUS)
-            .unwrap();
+            Ok::<_, windows_core::Error>(())
+        })
+        .unwrap();
 
         let root = ContainerVisualParams::new()
-            .left(init.dip_to_pixels(8.0))
-            .width(init.dip_to_pixels(Self::INIT_WIDTH))
-            .expand_height()
+            .size_sq(button_size_px)
+            .relative_offset_adjustment_xy(Vector2 { X: 1.0, Y: 0.0 })
+            .offset_xy(Vector2 {
+                X: -button_size_px - init.dip_to_pixels(8.0),
+                Y: init.dip_to_pixels(8.0),
+            })
             .instantiate(&init.subsystem.compositor)
             .unwrap();
-
-        let bg_base_brush = create_instant_effect_brush(
-            init.subsystem,
-            &CompositeEffectParams::new(&[
-                GaussianBlurEffectParams::new(
-                    &CompositionEffectSourceParameter::Create(h!("source")).unwrap(),
-                )
-                .blur_amount_px(Self::BLUR_AMOUNT)
-                .instantiate()
-                .unwrap()
-                .cast()
+        let bg = SpriteVisualParams::new(
+            &CompositionSurfaceBrushParams::new(&frame_surface)
+                .instantiate(&init.subsystem.compositor)
                 .unwrap(),
-                ColorSourceEffectParams {
-                    color: Some(Self::SURFACE_COLOR),
-                }
-                .instantiate()
-                .unwrap()
-                .cast()
+        )
+        .expand()
+        .opacity(0.0)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+        let icon = SpriteVisualParams::new(
+            &CompositionSurfaceBrushParams::new(&icon_surface)
+                .instantiate(&init.subsystem.compositor)
                 .unwrap(),
-            ])
-            .instantiate()
-            .unwrap(),
-            &[(
-                h!("source"),
-                init.subsystem
-                    .compositor
-                    .CreateBackdropBrush()
-                    .unwrap()
-                    .cast()
-                    .unwrap(),
-            )],
         )
+        .size_sq(icon_size_px)
+        .relative_offset_adjustment_xy(Vector2 { X: 0.5, Y: 0.5 })
+        .anchor_point(Vector2 { X: 0.5, Y: 0.5 })
+        .instantiate(&init.subsystem.compositor)
         .unwrap();
 
-        let bg = SpriteVisualParams::new(
-            &CompositionMaskBrushParams {
-                source: &bg_base_brush,
-                mask: &CompositionNineGridBrushParams::new(
-                    &CompositionSurfaceBrushParams::new(&frame_surface)
-                        .stretch(CompositionStretch::Fill)
-                        .instantiate(&init.subsystem.compositor)
-                        .unwrap(),
+        let children = root.Children().unwrap();
+        children.InsertAtTop(&bg).unwrap();
+        children.InsertAtTop(&icon).unwrap();
+
+        let implicit_easing_animation = SimpleImplicitAnimationParams::new(
+            &init
+                .subsystem
+                .compositor
+                .CreateCubicBezierEasingFunction(
+                    Vector2 { X: 0.5, Y: 0.0 },
+                    Vector2 { X: 0.5, Y: 1.0 },
                 )
-                .insets(init.dip_to_pixels(Self::CORNER_RADIUS))
-                .instantiate(&init.subsystem.compositor)
                 .unwrap(),
-            }
-            .instantiate(&init.subsystem.compositor)
-            .unwrap(),
+            h!("Opacity"),
+            timespan_ms(100),
         )
-        .expand()
-        .instantiate(&init.subsystem.compositor)
+        .instantiate_scalar(&init.subsystem.compositor)
+        .unwrap();
+        let implicit_move_animation = SimpleImplicitAnimationParams::new(
+            &init
+                .subsystem
+                .compositor
+                .CreateCubicBezierEasingFunction(
+                    Vector2 { X: 0.5, Y: 0.0 },
+                    Vector2 { X: 0.5, Y: 1.0 },
+                )
+                .unwrap(),
+            h!("Offset"),
+            SpriteListPaneView::TRANSITION_DURATION,
+        )
+        .instantiate_vector3(&init.subsystem.compositor)
         .unwrap();
 
-        let tl = init
+        let bg_implicit_animations = init
             .subsystem
-            .new_text_layout_unrestricted("Sprites", &init.subsystem.default_ui_format)
+            .compositor
+            .CreateImplicitAnimationCollection()
             .unwrap();
-        unsafe {
-            tl.SetFontWeight(
-                DWRITE_FONT_WEIGHT_MEDIUM,
-                DWRITE_TEXT_RANGE {
-                    startPosition: 0,
-                    length: 7,
-                },
-            )
+        bg_implicit_animations
+            .Insert(h!("Opacity"), &implicit_easing_animation)
             .unwrap();
-            tl.cast::<IDWriteTextLayout1>()
-                .unwrap()
-                .SetCharacterSpacing(
-                    0.2,
-                    0.2,
-                    0.1,
-                    DWRITE_TEXT_RANGE {
-                        startPosition: 0,
-                        length: 7,
-                    },
-                )
-                .unwrap();
+        bg.SetImplicitAnimations(&bg_implicit_animations).unwrap();
+
+        let root_implicit_animations = init
+            .subsystem
+            .compositor
+            .CreateImplicitAnimationCollection()
+            .unwrap();
+        root_implicit_animations
+            .Insert(h!("Offset"), &implicit_move_animation)
+            .unwrap();
+        root.SetImplicitAnimations(&root_implicit_animations)
+            .unwrap();
+
+        let ht_root = init.ht.borrow_mut().alloc(HitTestTreeData {
+            left: -Self::BUTTON_SIZE - 8.0,
+            top: 8.0,
+            left_adjustment_factor: 1.0,
+            top_adjustment_factor: 0.0,
+            width: Self::BUTTON_SIZE,
+            height: Self::BUTTON_SIZE,
+            width_adjustment_factor: 0.0,
+            height_adjustment_factor: 0.0,
+            parent: None,
+            children: Vec::new(),
+            focusable: false,
+            action_handler: None,
+        });
+
+        Self {
+            root,
+            icon,
+            bg,
+            ht_root,
+            dpi: Cell::new(init.dpi),
+            hovering: Cell::new(false),
+            pressing: Cell::new(false),
         }
-        let mut tm = core::mem::MaybeUninit::uninit();
-        unsafe {
-            tl.GetMetrics(tm.as_mut_ptr()).unwrap();
+    }
+
+    pub fn mount(
+        &self,
+        children: &VisualCollection,
+        ht: &mut AppHitTestTreeManager,
+        ht_parent: HitTestTreeRef,
+    ) {
+        children.InsertAtTop(&self.root).unwrap();
+        ht.add_child(ht_parent, self.ht_root);
+    }
+
+    fn update_bg_opacity(&self) {
+        match (self.hovering.get(), self.pressing.get()) {
+            (false, _) => {
+                self.bg.SetOpacity(0.0).unwrap();
+            }
+            (true, false) => {
+                self.bg.SetOpacity(0.15).unwrap();
+            }
+            (true, true) => {
+                self.bg.SetOpacity(0.3).unwrap();
+            }
         }
-        let tm = unsafe { tm.assume_init() };
-        let header_surface = init
-            .subsystem
+    }
+
+    pub fn on_hover(&self) {
+        self.hovering.set(true);
+        self.update_bg_opacity();
+    }
+
+    pub fn on_press(&self) {
+        self.pressing.set(true);
+        self.update_bg_opacity();
+    }
+
+    pub fn on_release(&self) {
+        self.pressing.set(false);
+        self.update_bg_opacity();
+    }
+
+    pub fn on_hover_leave(&self) {
+        self.hovering.set(false);
+        self.pressing.set(false);
+        self.update_bg_opacity();
+    }
+
+    pub fn transit_hidden(&self, ht: &mut AppHitTestTreeManager) {
+        let dpi = self.dpi.get();
+
+        self.icon
+            .SetScale(Vector3 {
+                X: -1.0,
+                Y: 1.0,
+                Z: 1.0,
+            })
+            .unwrap();
+        self.root
+            .SetOffset(Vector3 {
+                X: dip_to_pixels(8.0 + SpriteListPaneView::SPACING, dpi),
+                Y: dip_to_pixels(8.0, dpi),
+                Z: 0.0,
+            })
+            .unwrap();
+        ht.get_mut(self.ht_root).left = 8.0 + SpriteListPaneView::SPACING;
+    }
+
+    pub fn transit_shown(&self, ht: &mut AppHitTestTreeManager) {
+        let dpi = self.dpi.get();
+
+        self.icon
+            .SetScale(Vector3 {
+                X: 1.0,
+                Y: 1.0,
+                Z: 1.0,
+            })
+            .unwrap();
+        self.root
+            .SetOffset(Vector3 {
+                X: dip_to_pixels(-Self::BUTTON_SIZE - 8.0, dpi),
+                Y: dip_to_pixels(8.0, dpi),
+                Z: 0.0,
+            })
+            .unwrap();
+        ht.get_mut(self.ht_root).left = -Self::BUTTON_SIZE - 8.0;
+    }
+}
+
+pub struct SpriteListCellView {
+    root: ContainerVisual,
+    bg: SpriteVisual,
+    bg_select: SpriteVisual,
+    label: SpriteVisual,
+    /// 同じ文字列で再描画しないためのキャッシュ
+    label_text: RefCell<String>,
+    top: Cell<f32>,
+    dpi: Cell<f32>,
+}
+impl SpriteListCellView {
+    const FRAME_TEX_SIZE: f32 = 24.0;
+    const CORNER_RADIUS: f32 = 8.0;
+    const CELL_HEIGHT: f32 = 20.0;
+    const LABEL_LEFT: f32 = 8.0;
+    /// グループの階層1段あたりの字下げ幅
+    const INDENT: f32 = 12.0;
+    /// グループ見出しの左端の、クリックで折りたたみを切り替える部分の幅
+    const GROUP_TOGGLE_WIDTH: f32 = 16.0;
+    const LABEL_COLOR: D2D1_COLOR_F = D2D1_COLOR_F_WHITE;
+    const BG_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb_with_alpha(0xccc, 32);
+    const ACTIVE_BG_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb_with_alpha(0x4af, 64);
+
+    fn gen_frame_tex(subsystem: &Subsystem, dpi: f32) -> CompositionDrawingSurface {
+        let s = subsystem
             .new_2d_drawing_surface(Size {
-                Width: init.dip_to_pixels(tm.width),
-                Height: init.dip_to_pixels(tm.height),
+                Width: dip_to_pixels(Self::FRAME_TEX_SIZE, dpi),
+                Height: dip_to_pixels(Self::FRAME_TEX_SIZE, dpi),
             })
             .unwrap();
-        draw_2d(&header_surface, |dc, offset| {
+        draw_2d(&s, |dc, offset| {
             unsafe {
-                dc.SetDpi(init.dpi, init.dpi);
+                dc.SetDpi(dpi, dpi);
+                dc.SetTransform(&Matrix3x2::translation(
+                    signed_pixels_to_dip(offset.x, dpi),
+                    signed_pixels_to_dip(offset.y, dpi),
+                ));
 
                 dc.Clear(None);
-                dc.DrawTextLayout(
-                    D2D_POINT_2F {
-                        x: init.signed_pixels_to_dip(offset.x),
-                        y: init.signed_pixels_to_dip(offset.y),
+                dc.FillRoundedRectangle(
+                    &D2D1_ROUNDED_RECT {
+                        rect: D2D_RECT_F {
+                            left: 0.0,
+                            top: 0.0,
+                            right: Self::FRAME_TEX_SIZE,
+                            bottom: Self::FRAME_TEX_SIZE,
+                        },
+                        radiusX: Self::CORNER_RADIUS,
+                        radiusY: Self::CORNER_RADIUS,
                     },
-                    &tl,
-                    &dc.CreateSolidColorBrush(&Self::HEADER_LABEL_MAIN_COLOR, None)?,
-                    D2D1_DRAW_TEXT_OPTIONS_NONE,
+                    &dc.CreateSolidColorBrush(&D2D1_COLOR_F_WHITE, None)?,
                 );
             }
 
             Ok::<_, windows_core::Error>(())
         })
         .unwrap();
-        let header_surface_w = init
+
+        s
+    }
+
+    pub fn new(init: &mut ViewInitContext, label: &str, init_top: f32) -> Self {
+        let label_text = RefCell::new(label.to_owned());
+        let frame_tex = Self::gen_frame_tex(init.subsystem, init.dpi);
+
+        let tl = init
+            .subsystem
+            .new_text_layout_unrestricted(label, &init.subsystem.default_ui_format)
+            .unwrap();
+        let mut tm = core::mem::MaybeUninit::uninit();
+        unsafe {
+            tl.GetMetrics(tm.as_mut_ptr()).unwrap();
+        }
+        let tm = unsafe { tm.assume_init() };
+        let label_surface = init
             .subsystem
             .new_2d_drawing_surface(Size {
-                Width: init.dip_to_pixels(tm.width + 18.0),
-                Height: init.dip_to_pixels(tm.height + 18.0),
+                Width: init.dip_to_pixels(tm.width),
+                Height: init.dip_to_pixels(tm.height),
             })
             .unwrap();
-        draw_2d(&header_surface_w, |dc, offset| {
+        draw_2d(&label_surface, |dc, offset| {
             unsafe {
                 dc.SetDpi(init.dpi, init.dpi);
 
                 dc.Clear(None);
                 dc.DrawTextLayout(
                     D2D_POINT_2F {
-                        x: init.signed_pixels_to_dip(offset.x) + 9.0,
-                        y: init.signed_pixels_to_dip(offset.y) + 9.0,
+                        x: init.signed_pixels_to_dip(offset.x),
+                        y: init.signed_pixels_to_dip(offset.y),
                     },
                     &tl,
-                    &dc.CreateSolidColorBrush(&Self::HEADER_LABEL_SHADOW_COLOR, None)?,
+                    &dc.CreateSolidColorBrush(&Self::LABEL_COLOR, None)?,
                     D2D1_DRAW_TEXT_OPTIONS_NONE,
                 );
             }
@@ -2493,166 +2927,123 @@ impl SpriteListPaneView {
         })
         .unwrap();
 
-        let header = SpriteVisualParams::new(
-            &CompositionSurfaceBrushParams::new(&header_surface)
+        let bg_base_brush = CompositionNineGridBrushParams::new(
+            &CompositionSurfaceBrushParams::new(&frame_tex)
+                .stretch(CompositionStretch::Fill)
                 .instantiate(&init.subsystem.compositor)
                 .unwrap(),
         )
-        .size(Vector2 {
-            X: init.dip_to_pixels(tm.width),
-            Y: init.dip_to_pixels(tm.height),
-        })
-        .offset_xy(Vector2 {
-            X: init.dip_to_pixels(-tm.width * 0.5),
-            Y: init.dip_to_pixels(Self::CORNER_RADIUS),
-        })
-        .relative_horizontal_offset_adjustment(0.5)
+        .insets(init.dip_to_pixels(Self::CORNER_RADIUS))
         .instantiate(&init.subsystem.compositor)
         .unwrap();
-        let header_bg = SpriteVisualParams::new(
+
+        // Note: セルの表示領域（SpriteListPaneView::cell_scroll_root）からの相対位置
+        let root = ContainerVisualParams::new()
+            .height(init.dip_to_pixels(Self::CELL_HEIGHT))
+            .expand_width()
+            .top(init.dip_to_pixels(init_top))
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+        let bg = SpriteVisualParams::new(
             &create_instant_effect_brush(
-                init.subsystem,
-                &GaussianBlurEffectParams::new(
-                    &CompositionEffectSourceParameter::Create(h!("source")).unwrap(),
-                )
-                .blur_amount_px(18.0)
+                &init.subsystem,
+                &TintEffectParams {
+                    source: &CompositionEffectSourceParameter::Create(h!("source")).unwrap(),
+                    color: Some(Self::BG_COLOR),
+                }
                 .instantiate()
                 .unwrap(),
-                &[(
-                    h!("source"),
-                    CompositionSurfaceBrushParams::new(&header_surface_w)
-                        .instantiate(&init.subsystem.compositor)
-                        .unwrap()
-                        .cast()
-                        .unwrap(),
-                )],
+                &[(h!("source"), bg_base_brush.cast().unwrap())],
+            )
+            .unwrap(),
+        )
+        .expand()
+        .opacity(0.0)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+        let bg_select = SpriteVisualParams::new(
+            &create_instant_effect_brush(
+                &init.subsystem,
+                &TintEffectParams {
+                    source: &CompositionEffectSourceParameter::Create(h!("source")).unwrap(),
+                    color: Some(Self::ACTIVE_BG_COLOR),
+                }
+                .instantiate()
+                .unwrap(),
+                &[(h!("source"), bg_base_brush.cast().unwrap())],
             )
             .unwrap(),
         )
+        .expand()
+        .opacity(0.0)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+        let label = SpriteVisualParams::new(
+            &CompositionSurfaceBrushParams::new(&label_surface)
+                .instantiate(&init.subsystem.compositor)
+                .unwrap(),
+        )
         .size(Vector2 {
-            X: init.dip_to_pixels(tm.width + 18.0),
-            Y: init.dip_to_pixels(tm.height + 18.0),
+            X: init.dip_to_pixels(tm.width),
+            Y: init.dip_to_pixels(tm.height),
         })
         .offset_xy(Vector2 {
-            X: init.dip_to_pixels(-(tm.width + 18.0) * 0.5),
-            Y: init.dip_to_pixels(Self::CORNER_RADIUS - 9.0),
+            X: init.dip_to_pixels(Self::LABEL_LEFT),
+            Y: init.dip_to_pixels(-tm.height * 0.5),
         })
-        .relative_horizontal_offset_adjustment(0.5)
+        .relative_vertical_offset_adjustment(0.5)
         .instantiate(&init.subsystem.compositor)
         .unwrap();
 
         let children = root.Children().unwrap();
+        children.InsertAtTop(&bg_select).unwrap();
         children.InsertAtTop(&bg).unwrap();
-        children.InsertAtTop(&header_bg).unwrap();
-        children.InsertAtTop(&header).unwrap();
+        children.InsertAtTop(&label).unwrap();
 
-        let composition_properties = init.subsystem.compositor.CreatePropertySet().unwrap();
-        composition_properties
-            .InsertScalar(h!("ShownRate"), 1.0)
-            .unwrap();
-        composition_properties
-            .InsertScalar(h!("DPI"), init.dpi)
-            .unwrap();
-        composition_properties
-            .InsertScalar(h!("TopOffset"), 0.0)
-            .unwrap();
+        let opacity_transition = SimpleImplicitAnimationParams::new(
+            &init
+                .subsystem
+                .compositor
+                .CreateCubicBezierEasingFunction(
+                    Vector2 { X: 0.5, Y: 0.0 },
+                    Vector2 { X: 0.5, Y: 1.0 },
+                )
+                .unwrap(),
+            h!("Opacity"),
+            timespan_ms(100),
+        )
+        .instantiate_scalar(&init.subsystem.compositor)
+        .unwrap();
 
-        let offset_expr = format!(
-            "Vector3(-this.Target.Size.X - ({spc} * compositionProperties.DPI / 96.0) + (this.Target.Size.X + ({spc} * 2.0 * compositionProperties.DPI / 96.0)) * compositionProperties.ShownRate, compositionProperties.TopOffset * compositionProperties.DPI / 96.0, 0.0)",
-            spc = Self::SPACING
-        );
-        let root_offset = init
+        let bg_implicit_animations = init
             .subsystem
             .compositor
-            .CreateExpressionAnimationWithExpression(&offset_expr.into())
-            .unwrap();
-        root_offset
-            .SetExpressionReferenceParameter(h!("compositionProperties"), &composition_properties)
+            .CreateImplicitAnimationCollection()
             .unwrap();
-        root.StartAnimation(h!("Offset"), &root_offset).unwrap();
-
-        let easing = CompositionEasingFunction::CreateBackEasingFunction(
-            &init.subsystem.compositor,
-            CompositionEasingFunctionMode::Out,
-            0.1,
-        )
-        .unwrap();
-        let hide_animation = SimpleScalarAnimationParams::new(1.0, 0.0, &easing)
-            .duration(Self::TRANSITION_DURATION)
-            .instantiate(&init.subsystem.compositor)
+        bg_implicit_animations
+            .Insert(h!("Opacity"), &opacity_transition)
             .unwrap();
-        let show_animation = SimpleScalarAnimationParams::new(0.0, 1.0, &easing)
-            .duration(Self::TRANSITION_DURATION)
-            .instantiate(&init.subsystem.compositor)
+        bg.SetImplicitAnimations(&bg_implicit_animations).unwrap();
+        bg_select
+            .SetImplicitAnimations(&bg_implicit_animations)
             .unwrap();
 
-        let ht_root = init.ht.borrow_mut().alloc(HitTestTreeData {
-            left: Self::SPACING,
-            top: 0.0,
-            left_adjustment_factor: 0.0,
-            top_adjustment_factor: 0.0,
-            width: Self::INIT_WIDTH,
-            height: -Self::SPACING,
-            width_adjustment_factor: 0.0,
-            height_adjustment_factor: 1.0,
-            parent: None,
-            children: Vec::new(),
-            action_handler: None,
-        });
-        let ht_adjust_area = init.ht.borrow_mut().alloc(HitTestTreeData {
-            left: -Self::ADJUST_AREA_THICKNESS * 0.5,
-            top: 0.0,
-            left_adjustment_factor: 1.0,
-            top_adjustment_factor: 0.0,
-            width: Self::ADJUST_AREA_THICKNESS,
-            height: 0.0,
-            width_adjustment_factor: 0.0,
-            height_adjustment_factor: 1.0,
-            parent: None,
-            children: Vec::new(),
-            action_handler: None,
-        });
-        let ht_cell_area = init.ht.borrow_mut().alloc(HitTestTreeData {
-            left: Self::CELL_AREA_PADDINGS.left,
-            top: Self::CELL_AREA_PADDINGS.top,
-            left_adjustment_factor: 0.0,
-            top_adjustment_factor: 0.0,
-            width: -Self::CELL_AREA_PADDINGS.right - Self::CELL_AREA_PADDINGS.left,
-            height: -Self::CELL_AREA_PADDINGS.bottom - Self::CELL_AREA_PADDINGS.top,
-            width_adjustment_factor: 1.0,
-            height_adjustment_factor: 1.0,
-            parent: None,
-            children: Vec::new(),
-            action_handler: None,
-        });
-        init.ht.borrow_mut().add_child(ht_root, ht_cell_area);
-        init.ht.borrow_mut().add_child(ht_root, ht_adjust_area);
-
         Self {
             root,
-            ht_root,
-            ht_adjust_area,
-            ht_cell_area,
-            composition_properties,
-            hide_animation,
-            show_animation,
-            dpi: init.dpi,
-            top: Cell::new(0.0),
-            width: Cell::new(Self::INIT_WIDTH),
+            bg,
+            bg_select,
+            label,
+            label_text,
+            top: Cell::new(init_top),
+            dpi: Cell::new(init.dpi),
         }
     }
 
-    pub fn mount(
-        &self,
-        children: &VisualCollection,
-        ht: &mut AppHitTestTreeManager,
-        ht_parent: HitTestTreeRef,
-    ) {
+    pub fn mount(&self, children: &VisualCollection) {
         children.InsertAtTop(&self.root).unwrap();
-        ht.add_child(ht_parent, self.ht_root);
     }
 
-    pub fn unmount(&self, ht: &mut AppHitTestTreeManager) {
+    pub fn unmount(&self) {
         self.root
             .Parent()
             .unwrap()
@@ -2660,441 +3051,779 @@ impl SpriteListPaneView {
             .unwrap()
             .Remove(&self.root)
             .unwrap();
-        ht.remove_child(self.ht_root);
     }
 
-    pub fn shutdown(&self, ht: &mut AppHitTestTreeManager) {
-        ht.free_rec(self.ht_root);
+    pub fn on_hover(&self) {
+        self.bg.SetOpacity(1.0).unwrap();
     }
 
-    pub fn set_top(&self, ht: &mut AppHitTestTreeManager, top: f32) {
-        self.composition_properties
-            .InsertScalar(h!("TopOffset"), top)
-            .unwrap();
-        self.root
-            .SetSize(Vector2 {
-                X: dip_to_pixels(self.width.get(), self.dpi),
-                Y: dip_to_pixels(-top - Self::SPACING, self.dpi),
-            })
-            .unwrap();
-        ht.get_mut(self.ht_root).top = top;
-        ht.get_mut(self.ht_root).height = -top - Self::SPACING;
+    pub fn on_leave(&self) {
+        self.bg.SetOpacity(0.0).unwrap();
+    }
 
-        self.top.set(top);
+    pub fn on_select(&self) {
+        self.bg_select.SetOpacity(1.0).unwrap();
     }
 
-    pub fn set_width(&self, ht: &mut AppHitTestTreeManager, width: f32) {
+    pub fn on_deselect(&self) {
+        self.bg_select.SetOpacity(0.0).unwrap();
+    }
+
+    pub fn set_top(&self, top: f32) {
+        let dpi = self.dpi.get();
+
         self.root
-            .SetSize(Vector2 {
-                X: dip_to_pixels(width, self.dpi),
-                Y: dip_to_pixels(-self.top.get() - Self::SPACING, self.dpi),
+            .SetOffset(Vector3 {
+                X: 0.0,
+                Y: dip_to_pixels(top, dpi),
+                Z: 0.0,
             })
             .unwrap();
-        ht.get_mut(self.ht_root).width = width;
-
-        self.width.set(width);
+        self.top.set(top);
     }
 
-    pub fn transit_hidden(&self, ht: &mut AppHitTestTreeManager) {
-        self.composition_properties
-            .StartAnimation(h!("ShownRate"), &self.hide_animation)
-            .unwrap();
-        ht.get_mut(self.ht_root).left = -self.width.get();
+    pub fn set_visible(&self, visible: bool) {
+        self.root.SetIsVisible(visible).unwrap();
     }
 
-    pub fn transit_shown(&self, ht: &mut AppHitTestTreeManager) {
-        self.composition_properties
-            .StartAnimation(h!("ShownRate"), &self.show_animation)
+    pub fn set_indent(&self, depth: usize) {
+        let dpi = self.dpi.get();
+
+        let current = self.label.Offset().unwrap();
+        self.label
+            .SetOffset(Vector3 {
+                X: dip_to_pixels(Self::LABEL_LEFT + depth as f32 * Self::INDENT, dpi),
+                ..current
+            })
             .unwrap();
-        ht.get_mut(self.ht_root).left = Self::SPACING;
     }
-}
-
-pub struct SpriteListPaneHitActionHandler {
-    pub view: Rc<SpriteListPaneView>,
-    pub toggle_button_view: Rc<SpriteListToggleButtonView>,
-    pub cell_views: Rc<RefCell<Vec<SpriteListCellView>>>,
-    pub active_cell_index: Cell<Option<usize>>,
-    pub hidden: Cell<bool>,
-    adjust_drag_state: Cell<Option<(f32, f32)>>,
-}
-impl HitTestTreeActionHandler for SpriteListPaneHitActionHandler {
-    type Context = AppState;
 
-    fn cursor(&self, sender: HitTestTreeRef, _context: &mut AppState) -> Option<HCURSOR> {
-        if sender == self.view.ht_adjust_area && !self.hidden.get() {
-            // TODO: 必要そうならキャッシュする
-            return Some(unsafe { LoadCursorW(None, IDC_SIZEWE).unwrap() });
+    pub fn set_name(&self, name: &str, subsystem: &Subsystem) {
+        if *self.label_text.borrow() == name {
+            // 変化なし
+            return;
         }
+        name.clone_into(&mut self.label_text.borrow_mut());
 
-        None
-    }
+        let dpi = self.dpi.get();
 
-    fn on_pointer_enter(
-        &self,
-        sender: HitTestTreeRef,
-        _context: &mut AppState,
-        ht: &mut AppHitTestTreeManager,
-        args: PointerActionArgs,
-    ) -> EventContinueControl {
-        if sender == self.view.ht_root {
-            return EventContinueControl::STOP_PROPAGATION;
+        let tl = subsystem
+            .new_text_layout_unrestricted(name, &subsystem.default_ui_format)
+            .unwrap();
+        let mut tm = core::mem::MaybeUninit::uninit();
+        unsafe {
+            tl.GetMetrics(tm.as_mut_ptr()).unwrap();
         }
+        let tm = unsafe { tm.assume_init() };
+        let label_surface = subsystem
+            .new_2d_drawing_surface(Size {
+                Width: dip_to_pixels(tm.width, dpi),
+                Height: dip_to_pixels(tm.height, dpi),
+            })
+            .unwrap();
+        draw_2d(&label_surface, |dc, offset| {
+            unsafe {
+                dc.SetDpi(dpi, dpi);
 
-        if sender == self.toggle_button_view.ht_root {
-            self.toggle_button_view.on_hover();
-
-            return EventContinueControl::STOP_PROPAGATION;
-        }
-
-        if sender == self.view.ht_cell_area {
-            let (_, local_y, _, _) = ht.translate_client_to_tree_local(
-                sender,
-                args.client_x,
-                args.client_y,
-                args.client_width,
-                args.client_height,
-            );
-
-            let index = (local_y / SpriteListCellView::CELL_HEIGHT).trunc();
-            if 0.0 <= index && index < self.cell_views.borrow().len() as f32 {
-                let index = index as usize;
-                self.cell_views.borrow()[index].on_hover();
-                self.active_cell_index.set(Some(index));
-            } else {
-                self.active_cell_index.set(None);
+                dc.Clear(None);
+                dc.DrawTextLayout(
+                    D2D_POINT_2F {
+                        x: signed_pixels_to_dip(offset.x, dpi),
+                        y: signed_pixels_to_dip(offset.y, dpi),
+                    },
+                    &tl,
+                    &dc.CreateSolidColorBrush(&Self::LABEL_COLOR, None)?,
+                    D2D1_DRAW_TEXT_OPTIONS_NONE,
+                );
             }
 
-            return EventContinueControl::STOP_PROPAGATION;
-        }
+            Ok::<_, windows_core::Error>(())
+        })
+        .unwrap();
 
-        EventContinueControl::empty()
+        self.label
+            .SetBrush(
+                &CompositionSurfaceBrushParams::new(&label_surface)
+                    .instantiate(&subsystem.compositor)
+                    .unwrap(),
+            )
+            .unwrap();
+        self.label
+            .SetSize(Vector2 {
+                X: dip_to_pixels(tm.width, dpi),
+                Y: dip_to_pixels(tm.height, dpi),
+            })
+            .unwrap();
     }
+}
 
-    fn on_pointer_leave(
-        &self,
-        sender: HitTestTreeRef,
-        _context: &mut AppState,
-        _ht: &mut AppHitTestTreeManager,
-        _args: PointerActionArgs,
-    ) -> EventContinueControl {
-        if sender == self.view.ht_root {
-            return EventContinueControl::STOP_PROPAGATION;
-        }
-
-        if sender == self.toggle_button_view.ht_root {
-            self.toggle_button_view.on_hover_leave();
-
-            return EventContinueControl::STOP_PROPAGATION;
+/// スプライト一覧の検索欄
+pub struct SpriteListSearchBoxView {
+    root: ContainerVisual,
+    bg: SpriteVisual,
+    label: SpriteVisual,
+    caret: SpriteVisual,
+    ht_root: HitTestTreeRef,
+    ht_select_all: HitTestTreeRef,
+    dpi: f32,
+}
+impl SpriteListSearchBoxView {
+    const HEIGHT: f32 = 24.0;
+    const LABEL_LEFT: f32 = 8.0;
+    const CARET_HEIGHT: f32 = 14.0;
+    const BG_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb_with_alpha(0x000, 64);
+    const TEXT_COLOR: D2D1_COLOR_F = D2D1_COLOR_F_WHITE;
+    const PLACEHOLDER_COLOR: D2D1_COLOR_F = d2d1_color_f_from_websafe_hex_rgb(0x888);
+    const PLACEHOLDER: &'static str = "名前 / path: / w: / h: / has:slice";
+    const SELECT_ALL_LABEL: &'static str = "すべて選択";
+
+    fn render_text(
+        subsystem: &Subsystem,
+        dpi: f32,
+        text: &str,
+        color: &D2D1_COLOR_F,
+    ) -> (CompositionDrawingSurface, f32, f32) {
+        let tl = subsystem
+            .new_text_layout_unrestricted(text, &subsystem.default_ui_format)
+            .unwrap();
+        let mut tm = core::mem::MaybeUninit::uninit();
+        unsafe {
+            tl.GetMetrics(tm.as_mut_ptr()).unwrap();
         }
+        let tm = unsafe { tm.assume_init() };
+        let surface = subsystem
+            .new_2d_drawing_surface(Size {
+                Width: dip_to_pixels(tm.width, dpi),
+                Height: dip_to_pixels(tm.height, dpi),
+            })
+            .unwrap();
+        draw_2d(&surface, |dc, offset| {
+            unsafe {
+                dc.SetDpi(dpi, dpi);
 
-        if sender == self.view.ht_cell_area {
-            if let Some(x) = self.active_cell_index.replace(None) {
-                self.cell_views.borrow()[x].on_leave();
+                dc.Clear(None);
+                dc.DrawTextLayout(
+                    D2D_POINT_2F {
+                        x: signed_pixels_to_dip(offset.x, dpi),
+                        y: signed_pixels_to_dip(offset.y, dpi),
+                    },
+                    &tl,
+                    &dc.CreateSolidColorBrush(color, None)?,
+                    D2D1_DRAW_TEXT_OPTIONS_NONE,
+                );
             }
 
-            return EventContinueControl::STOP_PROPAGATION;
-        }
+            Ok::<_, windows_core::Error>(())
+        })
+        .unwrap();
 
-        EventContinueControl::empty()
+        (surface, tm.width, tm.height)
     }
 
-    fn on_pointer_down(
-        &self,
-        sender: HitTestTreeRef,
-        _context: &mut AppState,
-        _ht: &mut AppHitTestTreeManager,
-        args: PointerActionArgs,
-    ) -> EventContinueControl {
-        if sender == self.view.ht_root {
-            return EventContinueControl::STOP_PROPAGATION;
-        }
+    pub fn new(init: &mut ViewInitContext) -> Self {
+        let root = ContainerVisualParams::new()
+            .size(Vector2 {
+                X: init.dip_to_pixels(
+                    -SpriteListPaneView::CELL_AREA_PADDINGS.right
+                        - SpriteListPaneView::CELL_AREA_PADDINGS.left,
+                ),
+                Y: init.dip_to_pixels(Self::HEIGHT),
+            })
+            .expand_width()
+            .offset_xy(Vector2 {
+                X: init.dip_to_pixels(SpriteListPaneView::CELL_AREA_PADDINGS.left),
+                Y: init.dip_to_pixels(SpriteListPaneView::SEARCH_BOX_TOP),
+            })
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+        let bg = SpriteVisualParams::new(
+            &init
+                .subsystem
+                .compositor
+                .CreateColorBrushWithColor(Self::BG_COLOR)
+                .unwrap(),
+        )
+        .expand()
+        .opacity(0.5)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
 
-        if sender == self.view.ht_adjust_area && !self.hidden.get() {
-            self.adjust_drag_state
-                .set(Some((args.client_x, self.view.width.get())));
+        let (surface, w, h) = Self::render_text(
+            init.subsystem,
+            init.dpi,
+            Self::PLACEHOLDER,
+            &Self::PLACEHOLDER_COLOR,
+        );
+        let label = SpriteVisualParams::new(
+            &CompositionSurfaceBrushParams::new(&surface)
+                .instantiate(&init.subsystem.compositor)
+                .unwrap(),
+        )
+        .size(Vector2 {
+            X: init.dip_to_pixels(w),
+            Y: init.dip_to_pixels(h),
+        })
+        .offset_xy(Vector2 {
+            X: init.dip_to_pixels(Self::LABEL_LEFT),
+            Y: init.dip_to_pixels(-h * 0.5),
+        })
+        .relative_vertical_offset_adjustment(0.5)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
 
-            return EventContinueControl::CAPTURE_ELEMENT | EventContinueControl::STOP_PROPAGATION;
-        }
+        let caret = SpriteVisualParams::new(
+            &init
+                .subsystem
+                .compositor
+                .CreateColorBrushWithColor(ui_color_from_websafe_hex_rgb(0xfff))
+                .unwrap(),
+        )
+        .size(Vector2 {
+            X: init.dip_to_pixels(1.0),
+            Y: init.dip_to_pixels(Self::CARET_HEIGHT),
+        })
+        .offset_xy(Vector2 {
+            X: init.dip_to_pixels(Self::LABEL_LEFT),
+            Y: init.dip_to_pixels(-Self::CARET_HEIGHT * 0.5),
+        })
+        .relative_vertical_offset_adjustment(0.5)
+        .opacity(0.0)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
 
-        if sender == self.toggle_button_view.ht_root {
-            self.toggle_button_view.on_press();
+        let (surface, select_all_width, h) = Self::render_text(
+            init.subsystem,
+            init.dpi,
+            Self::SELECT_ALL_LABEL,
+            &Self::TEXT_COLOR,
+        );
+        let select_all_label = SpriteVisualParams::new(
+            &CompositionSurfaceBrushParams::new(&surface)
+                .instantiate(&init.subsystem.compositor)
+                .unwrap(),
+        )
+        .size(Vector2 {
+            X: init.dip_to_pixels(select_all_width),
+            Y: init.dip_to_pixels(h),
+        })
+        .offset_xy(Vector2 {
+            X: init.dip_to_pixels(-select_all_width - Self::LABEL_LEFT),
+            Y: init.dip_to_pixels(-h * 0.5),
+        })
+        .relative_offset_adjustment_xy(Vector2 { X: 1.0, Y: 0.5 })
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
 
-            return EventContinueControl::STOP_PROPAGATION;
-        }
+        let children = root.Children().unwrap();
+        children.InsertAtTop(&bg).unwrap();
+        children.InsertAtTop(&label).unwrap();
+        children.InsertAtTop(&caret).unwrap();
+        children.InsertAtTop(&select_all_label).unwrap();
 
-        EventContinueControl::empty()
+        let ht_root = init.ht.borrow_mut().alloc(HitTestTreeData {
+            left: SpriteListPaneView::CELL_AREA_PADDINGS.left,
+            top: SpriteListPaneView::SEARCH_BOX_TOP,
+            left_adjustment_factor: 0.0,
+            top_adjustment_factor: 0.0,
+            width: -SpriteListPaneView::CELL_AREA_PADDINGS.right
+                - SpriteListPaneView::CELL_AREA_PADDINGS.left,
+            height: Self::HEIGHT,
+            width_adjustment_factor: 1.0,
+            height_adjustment_factor: 0.0,
+            parent: None,
+            children: Vec::new(),
+            focusable: true,
+            action_handler: None,
+        });
+        let ht_select_all = init.ht.borrow_mut().alloc(HitTestTreeData {
+            left: -select_all_width - Self::LABEL_LEFT * 2.0,
+            top: 0.0,
+            left_adjustment_factor: 1.0,
+            top_adjustment_factor: 0.0,
+            width: select_all_width + Self::LABEL_LEFT * 2.0,
+            height: 0.0,
+            width_adjustment_factor: 0.0,
+            height_adjustment_factor: 1.0,
+            parent: None,
+            children: Vec::new(),
+            focusable: false,
+            action_handler: None,
+        });
+        init.ht.borrow_mut().add_child(ht_root, ht_select_all);
+
+        Self {
+            root,
+            bg,
+            label,
+            caret,
+            ht_root,
+            ht_select_all,
+            dpi: init.dpi,
+        }
     }
 
-    fn on_pointer_move(
+    pub fn mount(
         &self,
-        sender: HitTestTreeRef,
-        _context: &mut AppState,
+        children: &VisualCollection,
         ht: &mut AppHitTestTreeManager,
-        args: PointerActionArgs,
-    ) -> EventContinueControl {
-        if sender == self.view.ht_root {
-            return EventContinueControl::STOP_PROPAGATION;
-        }
+        ht_parent: HitTestTreeRef,
+    ) {
+        children.InsertAtTop(&self.root).unwrap();
+        ht.add_child(ht_parent, self.ht_root);
+    }
 
-        if sender == self.view.ht_adjust_area && !self.hidden.get() {
-            if let Some((base_x, base_width)) = self.adjust_drag_state.get() {
-                let new_width = (base_width + (args.client_x - base_x)).max(10.0);
-                self.view.set_width(ht, new_width);
-            }
+    pub fn set_query(&self, query: &str, subsystem: &Subsystem) {
+        let (surface, w, h) = if query.is_empty() {
+            Self::render_text(
+                subsystem,
+                self.dpi,
+                Self::PLACEHOLDER,
+                &Self::PLACEHOLDER_COLOR,
+            )
+        } else {
+            Self::render_text(subsystem, self.dpi, query, &Self::TEXT_COLOR)
+        };
 
-            return EventContinueControl::STOP_PROPAGATION;
-        }
+        self.label
+            .SetBrush(
+                &CompositionSurfaceBrushParams::new(&surface)
+                    .instantiate(&subsystem.compositor)
+                    .unwrap(),
+            )
+            .unwrap();
+        self.label
+            .SetSize(Vector2 {
+                X: dip_to_pixels(w, self.dpi),
+                Y: dip_to_pixels(h, self.dpi),
+            })
+            .unwrap();
 
-        if sender == self.view.ht_cell_area {
-            let (_, local_y, _, _) = ht.translate_client_to_tree_local(
-                sender,
-                args.client_x,
-                args.client_y,
-                args.client_width,
-                args.client_height,
-            );
-
-            let new_index = (local_y / SpriteListCellView::CELL_HEIGHT).trunc();
-            let new_index = if 0.0 <= new_index && new_index < self.cell_views.borrow().len() as f32
-            {
-                Some(new_index as usize)
-            } else {
-                None
-            };
+        // プレースホルダーのときはキャレットを先頭に置く
+        let caret_left = if query.is_empty() {
+            Self::LABEL_LEFT
+        } else {
+            Self::LABEL_LEFT + w
+        };
+        self.caret
+            .SetOffset(Vector3 {
+                X: dip_to_pixels(caret_left, self.dpi),
+                Y: dip_to_pixels(-Self::CARET_HEIGHT * 0.5, self.dpi),
+                Z: 0.0,
+            })
+            .unwrap();
+    }
 
-            if self.active_cell_index.get() != new_index {
-                // active changed
-                if let Some(n) = self.active_cell_index.replace(new_index) {
-                    self.cell_views.borrow()[n].on_leave();
-                }
+    pub fn set_active(&self, active: bool) {
+        self.caret
+            .SetOpacity(if active { 1.0 } else { 0.0 })
+            .unwrap();
+        self.bg.SetOpacity(if active { 1.0 } else { 0.5 }).unwrap();
+    }
+}
 
-                if let Some(n) = new_index {
-                    self.cell_views.borrow()[n].on_hover();
-                }
-            }
+pub struct SpriteListPaneView {
+    root: ContainerVisual,
+    /// スクロール位置に応じて動かすセルの親（表示領域からはみ出した部分はクリップされる）
+    cell_scroll_root: ContainerVisual,
+    scrollbar_thumb: SpriteVisual,
+    ht_root: HitTestTreeRef,
+    ht_adjust_area: HitTestTreeRef,
+    ht_cell_area: HitTestTreeRef,
+    ht_scrollbar: HitTestTreeRef,
+    composition_properties: CompositionPropertySet,
+    hide_animation: ScalarKeyFrameAnimation,
+    show_animation: ScalarKeyFrameAnimation,
+    dpi: f32,
+    top: Cell<f32>,
+    width: Cell<f32>,
+}
+impl SpriteListPaneView {
+    const CORNER_RADIUS: f32 = 12.0;
+    const FRAME_TEX_SIZE: f32 = 32.0;
+    const BLUR_AMOUNT: f32 = 27.0;
+    const SURFACE_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb_with_alpha(0xfff, 24);
+    const SPACING: f32 = 8.0;
+    const ADJUST_AREA_THICKNESS: f32 = 4.0;
+    const INIT_WIDTH: f32 = 280.0;
+    const SCROLLBAR_WIDTH: f32 = 4.0;
+    /// 細いので掴みやすいようにヒット領域は広めにとる
+    const SCROLLBAR_HIT_WIDTH: f32 = 12.0;
+    const SCROLLBAR_MIN_THUMB_HEIGHT: f32 = 16.0;
+    const SCROLLBAR_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb_with_alpha(0xfff, 96);
+    const HEADER_LABEL_MAIN_COLOR: D2D1_COLOR_F = d2d1_color_f_from_websafe_hex_rgb(0xfff);
+    const HEADER_LABEL_SHADOW_COLOR: D2D1_COLOR_F = d2d1_color_f_from_websafe_hex_rgb(0x111);
+    const TRANSITION_DURATION: TimeSpan = timespan_ms(250);
+    const SEARCH_BOX_TOP: f32 = 32.0;
+    const CELL_AREA_PADDINGS: RectDIP = RectDIP {
+        left: 16.0,
+        top: Self::SEARCH_BOX_TOP + SpriteListSearchBoxView::HEIGHT + 8.0,
+        right: 16.0,
+        bottom: 16.0,
+    };
 
-            return EventContinueControl::STOP_PROPAGATION;
-        }
+    pub fn new(init: &mut ViewInitContext) -> Self {
+        let frame_surface = init
+            .subsystem
+            .rounded_rect_mask_surface(init.dpi, Self::CORNER_RADIUS)
+            .unwrap();
 
-        EventContinueControl::empty()
-    }
+        let root = ContainerVisualParams::new()
+            .left(init.dip_to_pixels(8.0))
+            .width(init.dip_to_pixels(Self::INIT_WIDTH))
+            .expand_height()
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
 
-    fn on_pointer_up(
-        &self,
-        sender: HitTestTreeRef,
-        _context: &mut AppState,
-        ht: &mut AppHitTestTreeManager,
-        args: PointerActionArgs,
-    ) -> EventContinueControl {
-        if sender == self.view.ht_root {
-            return EventContinueControl::STOP_PROPAGATION;
-        }
+        let bg_base_brush = create_instant_effect_brush(
+            init.subsystem,
+            &CompositeEffectParams::new(&[
+                GaussianBlurEffectParams::new(
+                    &CompositionEffectSourceParameter::Create(h!("source")).unwrap(),
+                )
+                .blur_amount_px(Self::BLUR_AMOUNT)
+                .instantiate()
+                .unwrap()
+                .cast()
+                .unwrap(),
+                ColorSourceEffectParams {
+                    color: Some(Self::SURFACE_COLOR),
+                }
+                .instantiate()
+                .unwrap()
+                .cast()
+                .unwrap(),
+            ])
+            .instantiate()
+            .unwrap(),
+            &[(
+                h!("source"),
+                init.subsystem
+                    .compositor
+                    .CreateBackdropBrush()
+                    .unwrap()
+                    .cast()
+                    .unwrap(),
+            )],
+        )
+        .unwrap();
 
-        if sender == self.view.ht_adjust_area && !self.hidden.get() {
-            if let Some((base_x, base_width)) = self.adjust_drag_state.replace(None) {
-                let new_width = (base_width + (args.client_x - base_x)).max(10.0);
-                self.view.set_width(ht, new_width);
+        let bg = SpriteVisualParams::new(
+            &CompositionMaskBrushParams {
+                source: &bg_base_brush,
+                mask: &CompositionNineGridBrushParams::new(
+                    &CompositionSurfaceBrushParams::new(&frame_surface)
+                        .stretch(CompositionStretch::Fill)
+                        .instantiate(&init.subsystem.compositor)
+                        .unwrap(),
+                )
+                .insets(init.dip_to_pixels(Self::CORNER_RADIUS))
+                .instantiate(&init.subsystem.compositor)
+                .unwrap(),
             }
+            .instantiate(&init.subsystem.compositor)
+            .unwrap(),
+        )
+        .expand()
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
 
-            return EventContinueControl::RELEASE_CAPTURE_ELEMENT
-                | EventContinueControl::STOP_PROPAGATION;
-        }
-
-        if sender == self.toggle_button_view.ht_root {
-            self.toggle_button_view.on_release();
-
-            return EventContinueControl::STOP_PROPAGATION;
+        let tl = init
+            .subsystem
+            .new_text_layout_unrestricted("Sprites", &init.subsystem.default_ui_format)
+            .unwrap();
+        unsafe {
+            tl.SetFontWeight(
+                DWRITE_FONT_WEIGHT_MEDIUM,
+                DWRITE_TEXT_RANGE {
+                    startPosition: 0,
+                    length: 7,
+                },
+            )
+            .unwrap();
+            tl.cast::<IDWriteTextLayout1>()
+                .unwrap()
+                .SetCharacterSpacing(
+                    0.2,
+                    0.2,
+                    0.1,
+                    DWRITE_TEXT_RANGE {
+                        startPosition: 0,
+                        length: 7,
+                    },
+                )
+                .unwrap();
         }
-
-        EventContinueControl::empty()
-    }
-
-    fn on_click(
-        &self,
-        sender: HitTestTreeRef,
-        context: &mut AppState,
-        ht: &mut AppHitTestTreeManager,
-        args: PointerActionArgs,
-    ) -> EventContinueControl {
-        if sender == self.view.ht_root {
-            return EventContinueControl::STOP_PROPAGATION;
+        let mut tm = core::mem::MaybeUninit::uninit();
+        unsafe {
+            tl.GetMetrics(tm.as_mut_ptr()).unwrap();
         }
+        let tm = unsafe { tm.assume_init() };
+        let header_surface = init
+            .subsystem
+            .new_2d_drawing_surface(Size {
+                Width: init.dip_to_pixels(tm.width),
+                Height: init.dip_to_pixels(tm.height),
+            })
+            .unwrap();
+        draw_2d(&header_surface, |dc, offset| {
+            unsafe {
+                dc.SetDpi(init.dpi, init.dpi);
 
-        if sender == self.toggle_button_view.ht_root {
-            let hidden = !self.hidden.get();
-            self.hidden.set(hidden);
-
-            if hidden {
-                self.toggle_button_view.transit_hidden(ht);
-                self.view.transit_hidden(ht);
-            } else {
-                self.toggle_button_view.transit_shown(ht);
-                self.view.transit_shown(ht);
+                dc.Clear(None);
+                dc.DrawTextLayout(
+                    D2D_POINT_2F {
+                        x: init.signed_pixels_to_dip(offset.x),
+                        y: init.signed_pixels_to_dip(offset.y),
+                    },
+                    &tl,
+                    &dc.CreateSolidColorBrush(&Self::HEADER_LABEL_MAIN_COLOR, None)?,
+                    D2D1_DRAW_TEXT_OPTIONS_NONE,
+                );
             }
 
-            return EventContinueControl::STOP_PROPAGATION
-                | EventContinueControl::RECOMPUTE_POINTER_ENTER;
-        }
-
-        if sender == self.view.ht_cell_area {
-            let (_, local_y, _, _) = ht.translate_client_to_tree_local(
-                sender,
-                args.client_x,
-                args.client_y,
-                args.client_width,
-                args.client_height,
-            );
-
-            let click_index = (local_y / SpriteListCellView::CELL_HEIGHT).trunc();
-            let click_index =
-                if 0.0 <= click_index && click_index < self.cell_views.borrow().len() as f32 {
-                    Some(click_index as usize)
-                } else {
-                    None
-                };
+            Ok::<_, windows_core::Error>(())
+        })
+        .unwrap();
+        let header_surface_w = init
+            .subsystem
+            .new_2d_drawing_surface(Size {
+                Width: init.dip_to_pixels(tm.width + 18.0),
+                Height: init.dip_to_pixels(tm.height + 18.0),
+            })
+            .unwrap();
+        draw_2d(&header_surface_w, |dc, offset| {
+            unsafe {
+                dc.SetDpi(init.dpi, init.dpi);
 
-            if let Some(x) = click_index {
-                context.select_sprite(x);
+                dc.Clear(None);
+                dc.DrawTextLayout(
+                    D2D_POINT_2F {
+                        x: init.signed_pixels_to_dip(offset.x) + 9.0,
+                        y: init.signed_pixels_to_dip(offset.y) + 9.0,
+                    },
+                    &tl,
+                    &dc.CreateSolidColorBrush(&Self::HEADER_LABEL_SHADOW_COLOR, None)?,
+                    D2D1_DRAW_TEXT_OPTIONS_NONE,
+                );
             }
 
-            return EventContinueControl::STOP_PROPAGATION;
-        }
-
-        EventContinueControl::empty()
-    }
-}
+            Ok::<_, windows_core::Error>(())
+        })
+        .unwrap();
 
-pub struct SpriteListPanePresenter {
-    view: Rc<SpriteListPaneView>,
-    _ht_action_handler: Rc<SpriteListPaneHitActionHandler>,
-}
-impl SpriteListPanePresenter {
-    pub fn new(init: &mut PresenterInitContext) -> Self {
-        let view = Rc::new(SpriteListPaneView::new(&mut init.for_view));
-        let toggle_button_view = Rc::new(SpriteListToggleButtonView::new(&mut init.for_view));
-        let mut sprite_list_contents = Vec::new();
-        let sprite_list_cells = Rc::new(RefCell::new(Vec::new()));
+        let header = SpriteVisualParams::new(
+            &CompositionSurfaceBrushParams::new(&header_surface)
+                .instantiate(&init.subsystem.compositor)
+                .unwrap(),
+        )
+        .size(Vector2 {
+            X: init.dip_to_pixels(tm.width),
+            Y: init.dip_to_pixels(tm.height),
+        })
+        .offset_xy(Vector2 {
+            X: init.dip_to_pixels(-tm.width * 0.5),
+            Y: init.dip_to_pixels(Self::CORNER_RADIUS),
+        })
+        .relative_horizontal_offset_adjustment(0.5)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+        let header_bg = SpriteVisualParams::new(
+            &create_instant_effect_brush(
+                init.subsystem,
+                &GaussianBlurEffectParams::new(
+                    &CompositionEffectSourceParameter::Create(h!("source")).unwrap(),
+                )
+                .blur_amount_px(18.0)
+                .instantiate()
+                .unwrap(),
+                &[(
+                    h!("source"),
+                    CompositionSurfaceBrushParams::new(&header_surface_w)
+                        .instantiate(&init.subsystem.compositor)
+                        .unwrap()
+                        .cast()
+                        .unwrap(),
+                )],
+            )
+            .unwrap(),
+        )
+        .size(Vector2 {
+            X: init.dip_to_pixels(tm.width + 18.0),
+            Y: init.dip_to_pixels(tm.height + 18.0),
+        })
+        .offset_xy(Vector2 {
+            X: init.dip_to_pixels(-(tm.width + 18.0) * 0.5),
+            Y: init.dip_to_pixels(Self::CORNER_RADIUS - 9.0),
+        })
+        .relative_horizontal_offset_adjustment(0.5)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
 
-        toggle_button_view.mount(
-            &view.root.Children().unwrap(),
-            &mut init.for_view.ht.borrow_mut(),
-            view.ht_root,
-        );
+        let children = root.Children().unwrap();
+        children.InsertAtTop(&bg).unwrap();
+        children.InsertAtTop(&header_bg).unwrap();
+        children.InsertAtTop(&header).unwrap();
 
-        init.app_state.borrow_mut().register_sprites_view_feedback({
-            let subsystem = Rc::downgrade(init.for_view.subsystem);
-            let ht = Rc::downgrade(init.for_view.ht);
-            let view = Rc::downgrade(&view);
-            let sprite_list_cells = Rc::downgrade(&sprite_list_cells);
-            let background_worker_enqueue_access =
-                init.for_view.background_worker_enqueue_access.downgrade();
-            let background_worker_view_update_callback =
-                Rc::downgrade(init.for_view.background_worker_view_update_callback);
+        let composition_properties = init.subsystem.compositor.CreatePropertySet().unwrap();
+        composition_properties
+            .InsertScalar(h!("ShownRate"), 1.0)
+            .unwrap();
+        composition_properties
+            .InsertScalar(h!("DPI"), init.dpi)
+            .unwrap();
+        composition_properties
+            .InsertScalar(h!("TopOffset"), 0.0)
+            .unwrap();
 
-            move |sprites| {
-                let Some(subsystem) = subsystem.upgrade() else {
-                    // app teardown-ed
-                    return;
-                };
-                let Some(background_worker_enqueue_access) =
-                    background_worker_enqueue_access.upgrade()
-                else {
-                    // app teardown-ed
-                    return;
-                };
-                let Some(background_worker_view_update_callback) =
-                    background_worker_view_update_callback.upgrade()
-                else {
-                    // app teardown-ed
-                    return;
-                };
-                let Some(ht) = ht.upgrade() else {
-                    // parent teardown-ed
-                    return;
-                };
-                let Some(view) = view.upgrade() else {
-                    // parent teardown-ed
-                    return;
-                };
-                let Some(sprite_list_cells) = sprite_list_cells.upgrade() else {
-                    // parent teardown-ed
-                    return;
-                };
+        let offset_expr = format!(
+            "Vector3(-this.Target.Size.X - ({spc} * compositionProperties.DPI / 96.0) + (this.Target.Size.X + ({spc} * 2.0 * compositionProperties.DPI / 96.0)) * compositionProperties.ShownRate, compositionProperties.TopOffset * compositionProperties.DPI / 96.0, 0.0)",
+            spc = Self::SPACING
+        );
+        let root_offset = init
+            .subsystem
+            .compositor
+            .CreateExpressionAnimationWithExpression(&offset_expr.into())
+            .unwrap();
+        root_offset
+            .SetExpressionReferenceParameter(h!("compositionProperties"), &composition_properties)
+            .unwrap();
+        root.StartAnimation(h!("Offset"), &root_offset).unwrap();
 
-                sprite_list_contents.clear();
-                sprite_list_contents.extend(sprites.iter().map(|x| (x.name.clone(), x.selected)));
-                let visible_contents = &sprite_list_contents[..];
-                for (n, &(ref c, sel)) in visible_contents.iter().enumerate() {
-                    if sprite_list_cells.borrow().len() == n {
-                        // create new one
-                        let new_cell = SpriteListCellView::new(
-                            &mut ViewInitContext {
-                                subsystem: &subsystem,
-                                ht: &ht,
-                                dpi: view.dpi,
-                                background_worker_enqueue_access: &background_worker_enqueue_access,
-                                background_worker_view_update_callback:
-                                    &background_worker_view_update_callback,
-                            },
-                            &c,
-                            SpriteListPaneView::CELL_AREA_PADDINGS.top
-                                + n as f32 * SpriteListCellView::CELL_HEIGHT,
-                        );
-                        new_cell.mount(&view.root.Children().unwrap());
-                        if sel {
-                            new_cell.on_select();
-                        }
-                        sprite_list_cells.borrow_mut().push(new_cell);
-                        continue;
-                    }
+        let easing = CompositionEasingFunction::CreateBackEasingFunction(
+            &init.subsystem.compositor,
+            CompositionEasingFunctionMode::Out,
+            0.1,
+        )
+        .unwrap();
+        let hide_animation = SimpleScalarAnimationParams::new(1.0, 0.0, &easing)
+            .duration(Self::TRANSITION_DURATION)
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+        let show_animation = SimpleScalarAnimationParams::new(0.0, 1.0, &easing)
+            .duration(Self::TRANSITION_DURATION)
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
 
-                    sprite_list_cells.borrow()[n].set_name(&c, &subsystem);
-                    sprite_list_cells.borrow()[n].set_top(
-                        SpriteListPaneView::CELL_AREA_PADDINGS.top
-                            + n as f32 * SpriteListCellView::CELL_HEIGHT,
-                    );
-                    if sel {
-                        sprite_list_cells.borrow()[n].on_select();
-                    } else {
-                        sprite_list_cells.borrow()[n].on_deselect();
-                    }
-                }
-            }
+        let ht_root = init.ht.borrow_mut().alloc(HitTestTreeData {
+            left: Self::SPACING,
+            top: 0.0,
+            left_adjustment_factor: 0.0,
+            top_adjustment_factor: 0.0,
+            width: Self::INIT_WIDTH,
+            height: -Self::SPACING,
+            width_adjustment_factor: 0.0,
+            height_adjustment_factor: 1.0,
+            parent: None,
+            children: Vec::new(),
+            focusable: false,
+            action_handler: None,
         });
-
-        let ht_action_handler = Rc::new(SpriteListPaneHitActionHandler {
-            view: view.clone(),
-            toggle_button_view: toggle_button_view.clone(),
-            cell_views: sprite_list_cells,
-            active_cell_index: Cell::new(None),
-            hidden: Cell::new(false),
-            adjust_drag_state: Cell::new(None),
+        let ht_adjust_area = init.ht.borrow_mut().alloc(HitTestTreeData {
+            left: -Self::ADJUST_AREA_THICKNESS * 0.5,
+            top: 0.0,
+            left_adjustment_factor: 1.0,
+            top_adjustment_factor: 0.0,
+            width: Self::ADJUST_AREA_THICKNESS,
+            height: 0.0,
+            width_adjustment_factor: 0.0,
+            height_adjustment_factor: 1.0,
+            parent: None,
+            children: Vec::new(),
+            focusable: false,
+            action_handler: None,
         });
-        init.for_view
-            .ht
-            .borrow_mut()
-            .get_mut(view.ht_root)
-            .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
-        init.for_view
-            .ht
-            .borrow_mut()
-            .get_mut(view.ht_adjust_area)
-            .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
-        init.for_view
-            .ht
-            .borrow_mut()
-            .get_mut(view.ht_cell_area)
-            .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
-        init.for_view
-            .ht
-            .borrow_mut()
-            .get_mut(toggle_button_view.ht_root)
-            .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
+        let ht_cell_area = init.ht.borrow_mut().alloc(HitTestTreeData {
+            left: Self::CELL_AREA_PADDINGS.left,
+            top: Self::CELL_AREA_PADDINGS.top,
+            left_adjustment_factor: 0.0,
+            top_adjustment_factor: 0.0,
+            width: -Self::CELL_AREA_PADDINGS.right - Self::CELL_AREA_PADDINGS.left,
+            height: -Self::CELL_AREA_PADDINGS.bottom - Self::CELL_AREA_PADDINGS.top,
+            width_adjustment_factor: 1.0,
+            height_adjustment_factor: 1.0,
+            parent: None,
+            children: Vec::new(),
+            focusable: true,
+            action_handler: None,
+        });
+        let ht_scrollbar = init.ht.borrow_mut().alloc(HitTestTreeData {
+            left: -Self::SCROLLBAR_HIT_WIDTH,
+            top: 0.0,
+            left_adjustment_factor: 1.0,
+            top_adjustment_factor: 0.0,
+            width: Self::SCROLLBAR_HIT_WIDTH,
+            height: 0.0,
+            width_adjustment_factor: 0.0,
+            height_adjustment_factor: 1.0,
+            parent: None,
+            children: Vec::new(),
+            focusable: false,
+            action_handler: None,
+        });
+        init.ht.borrow_mut().add_child(ht_root, ht_cell_area);
+        init.ht.borrow_mut().add_child(ht_root, ht_adjust_area);
+        init.ht.borrow_mut().add_child(ht_cell_area, ht_scrollbar);
+
+        let cell_area = ContainerVisualParams::new()
+            .offset_xy(Vector2 {
+                X: init.dip_to_pixels(Self::CELL_AREA_PADDINGS.left),
+                Y: init.dip_to_pixels(Self::CELL_AREA_PADDINGS.top),
+            })
+            .size(Vector2 {
+                X: init
+                    .dip_to_pixels(-Self::CELL_AREA_PADDINGS.right - Self::CELL_AREA_PADDINGS.left),
+                Y: init
+                    .dip_to_pixels(-Self::CELL_AREA_PADDINGS.bottom - Self::CELL_AREA_PADDINGS.top),
+            })
+            .expand()
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+        cell_area
+            .SetClip(&init.subsystem.compositor.CreateInsetClip().unwrap())
+            .unwrap();
+        let cell_scroll_root = ContainerVisualParams::new()
+            .expand_width()
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+        let scrollbar_thumb = SpriteVisualParams::new(
+            &init
+                .subsystem
+                .compositor
+                .CreateColorBrushWithColor(Self::SCROLLBAR_COLOR)
+                .unwrap(),
+        )
+        .width(init.dip_to_pixels(Self::SCROLLBAR_WIDTH))
+        .left(init.dip_to_pixels(-Self::SCROLLBAR_WIDTH))
+        .relative_horizontal_offset_adjustment(1.0)
+        .opacity(0.0)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+        cell_area
+            .Children()
+            .unwrap()
+            .InsertAtTop(&cell_scroll_root)
+            .unwrap();
+        cell_area
+            .Children()
+            .unwrap()
+            .InsertAtTop(&scrollbar_thumb)
+            .unwrap();
+        root.Children().unwrap().InsertAtTop(&cell_area).unwrap();
 
         Self {
-            view,
-            _ht_action_handler: ht_action_handler,
+            root,
+            cell_scroll_root,
+            scrollbar_thumb,
+            ht_root,
+            ht_adjust_area,
+            ht_cell_area,
+            ht_scrollbar,
+            composition_properties,
+            hide_animation,
+            show_animation,
+            dpi: init.dpi,
+            top: Cell::new(0.0),
+            width: Cell::new(Self::INIT_WIDTH),
         }
     }
 
@@ -3104,719 +3833,2087 @@ impl SpriteListPanePresenter {
         ht: &mut AppHitTestTreeManager,
         ht_parent: HitTestTreeRef,
     ) {
-        self.view.mount(children, ht, ht_parent);
+        children.InsertAtTop(&self.root).unwrap();
+        ht.add_child(ht_parent, self.ht_root);
     }
 
     pub fn unmount(&self, ht: &mut AppHitTestTreeManager) {
-        self.view.unmount(ht);
-    }
-
-    pub fn shutdown(&self, ht: &mut AppHitTestTreeManager) {
-        self.view.shutdown(ht);
-    }
-
-    pub fn set_top(&self, ht: &mut AppHitTestTreeManager, top: f32) {
-        self.view.set_top(ht, top);
-    }
-}
-
-struct AppMenuEntryView {
-    root: ContainerVisual,
-    bg: SpriteVisual,
-    ht_root: HitTestTreeRef,
-    dpi: Cell<f32>,
-    hovering: Cell<bool>,
-    active: Cell<bool>,
-}
-impl AppMenuEntryView {
-    const HEIGHT: f32 = 24.0;
-    const ICON_SIZE: f32 = 20.0;
-    const ICON_LEFT_OFFSET: f32 = 8.0;
-    const ICON_LABEL_GAP: f32 = 4.0;
-
-    pub fn new(init: &mut ViewInitContext, icon_path: &str, label: &str) -> (Self, f32) {
-        let icon_path_w = icon_path
-            .encode_utf16()
-            .chain(core::iter::once(0))
-            .collect::<Vec<_>>();
-        let icon_svg_stream: IRandomAccessStream = unsafe {
-            CreateRandomAccessStreamOnFile(
-                PCWSTR::from_raw(icon_path_w.as_ptr()),
-                FileAccessMode::Read.0 as _,
-            )
+        self.root
+            .Parent()
             .unwrap()
-        };
-        let icon_svg_stream: IStream =
-            unsafe { CreateStreamOverRandomAccessStream(&icon_svg_stream).unwrap() };
-        let icon_surface = init
-            .subsystem
-            .new_2d_drawing_surface(size_sq(init.dip_to_pixels(Self::ICON_SIZE)))
+            .Children()
+            .unwrap()
+            .Remove(&self.root)
             .unwrap();
-        draw_2d(&icon_surface, |dc, offset| {
-            let dc5: ID2D1DeviceContext5 = dc.cast()?;
-
-            unsafe {
-                dc.SetDpi(init.dpi, init.dpi);
-                dc.SetTransform(&Matrix3x2::translation(
-                    init.signed_pixels_to_dip(offset.x),
-                    init.signed_pixels_to_dip(offset.y),
-                ));
-            }
-
-            let svg = unsafe {
-                dc5.CreateSvgDocument(
-                    &icon_svg_stream,
-                    D2D_SIZE_F {
-                        width: pixels_to_dip(20, init.dpi),
-                        height: pixels_to_dip(20, init.dpi),
-                    },
-                )?
-            };
-
-            unsafe {
-                dc.Clear(None);
-                dc5.DrawSvgDocument(&svg);
-            }
+        ht.remove_child(self.ht_root);
+    }
 
-            Ok::<_, windows_core::Error>(())
-        })
-        .unwrap();
+    pub fn shutdown(&self, ht: &mut AppHitTestTreeManager) {
+        ht.free_rec(self.ht_root).unwrap();
+    }
 
-        let label_layout = init
-            .subsystem
-            .new_text_layout_unrestricted(label, &init.subsystem.default_ui_format)
+    pub fn set_top(&self, ht: &mut AppHitTestTreeManager, top: f32) {
+        self.composition_properties
+            .InsertScalar(h!("TopOffset"), top)
             .unwrap();
-        let mut label_metrics = MaybeUninit::uninit();
-        let label_metrics = unsafe {
-            label_layout.GetMetrics(label_metrics.as_mut_ptr()).unwrap();
-            label_metrics.assume_init()
-        };
-        let label_surface = init
-            .subsystem
-            .new_2d_drawing_surface(Size {
-                Width: init.dip_to_pixels(label_metrics.width),
-                Height: init.dip_to_pixels(label_metrics.height),
+        self.root
+            .SetSize(Vector2 {
+                X: dip_to_pixels(self.width.get(), self.dpi),
+                Y: dip_to_pixels(-top - Self::SPACING, self.dpi),
             })
             .unwrap();
-        draw_2d(&label_surface, |dc, offset| {
-            unsafe {
-                dc.SetDpi(init.dpi, init.dpi);
-
-                dc.Clear(None);
-                dc.DrawTextLayout(
-                    D2D_POINT_2F {
-                        x: init.signed_pixels_to_dip(offset.x),
-                        y: init.signed_pixels_to_dip(offset.y),
-                    },
-                    &label_layout,
-                    &dc.CreateSolidColorBrush(&D2D1_COLOR_F_WHITE, None)?,
-                    D2D1_DRAW_TEXT_OPTIONS_NONE,
-                );
-            }
-
-            Ok::<_, windows_core::Error>(())
-        })
-        .unwrap();
-
-        let root = ContainerVisualParams::new()
-            .expand_width()
-            .height(init.dip_to_pixels(Self::HEIGHT))
-            .instantiate(&init.subsystem.compositor)
-            .unwrap();
-        let bg = SpriteVisualParams::new(
-            &init
-                .subsystem
-                .compositor
-                .CreateColorBrushWithColor(ui_color_from_websafe_hex_rgb(0xfff))
-                .unwrap(),
-        )
-        .expand()
-        .opacity(0.0)
-        .instantiate(&init.subsystem.compositor)
-        .unwrap();
-        let icon = SpriteVisualParams::new(
-            &CompositionSurfaceBrushParams::new(&icon_surface)
-                .instantiate(&init.subsystem.compositor)
-                .unwrap(),
-        )
-        .size_sq(init.dip_to_pixels(Self::ICON_SIZE))
-        .anchor_point(Vector2 { X: 0.0, Y: 0.5 })
-        .left(init.dip_to_pixels(Self::ICON_LEFT_OFFSET))
-        .relative_vertical_offset_adjustment(0.5)
-        .instantiate(&init.subsystem.compositor)
-        .unwrap();
-        let label = SpriteVisualParams::new(
-            &CompositionSurfaceBrushParams::new(&label_surface)
-                .instantiate(&init.subsystem.compositor)
-                .unwrap(),
-        )
-        .size(Vector2 {
-            X: init.dip_to_pixels(label_metrics.width),
-            Y: init.dip_to_pixels(label_metrics.height),
-        })
-        .anchor_point(Vector2 { X: 0.0, Y: 0.5 })
-        .relative_vertical_offset_adjustment(0.5)
-        .left(init.dip_to_pixels(Self::ICON_LEFT_OFFSET + Self::ICON_SIZE + Self::ICON_LABEL_GAP))
-        .instantiate(&init.subsystem.compositor)
-        .unwrap();
+        ht.get_mut(self.ht_root).top = top;
+        ht.get_mut(self.ht_root).height = -top - Self::SPACING;
 
-        let c = root.Children().unwrap();
-        c.InsertAtTop(&bg).unwrap();
-        c.InsertAtTop(&icon).unwrap();
-        c.InsertAtTop(&label).unwrap();
+        self.top.set(top);
+    }
 
-        let bg_imp_animations = init
-            .subsystem
-            .compositor
-            .CreateImplicitAnimationCollection()
-            .unwrap();
-        bg_imp_animations
-            .Insert(
-                h!("Opacity"),
-                &SimpleImplicitAnimationParams::new(
-                    &init
-                        .subsystem
-                        .compositor
-                        .CreateLinearEasingFunction()
-                        .unwrap(),
-                    h!("Opacity"),
-                    timespan_ms(100),
-                )
-                .instantiate_scalar(&init.subsystem.compositor)
-                .unwrap(),
-            )
+    pub fn set_width(&self, ht: &mut AppHitTestTreeManager, width: f32) {
+        self.root
+            .SetSize(Vector2 {
+                X: dip_to_pixels(width, self.dpi),
+                Y: dip_to_pixels(-self.top.get() - Self::SPACING, self.dpi),
+            })
             .unwrap();
-        bg.SetImplicitAnimations(&bg_imp_animations).unwrap();
-
-        let ht_root = init.ht.borrow_mut().alloc(HitTestTreeData {
-            left: 0.0,
-            top: 0.0,
-            left_adjustment_factor: 0.0,
-            top_adjustment_factor: 0.0,
-            width: 0.0,
-            height: Self::HEIGHT,
-            width_adjustment_factor: 1.0,
-            height_adjustment_factor: 0.0,
-            parent: None,
-            children: Vec::new(),
-            action_handler: None,
-        });
+        ht.get_mut(self.ht_root).width = width;
 
-        (
-            Self {
-                root,
-                bg,
-                ht_root,
-                dpi: Cell::new(init.dpi),
-                hovering: Cell::new(false),
-                active: Cell::new(false),
-            },
-            Self::ICON_LEFT_OFFSET
-                + Self::ICON_SIZE
-                + Self::ICON_LABEL_GAP
-                + label_metrics.width
-                + Self::ICON_LEFT_OFFSET,
-        )
+        self.width.set(width);
     }
 
-    pub fn mount(
-        &self,
-        children: &VisualCollection,
-        ht: &mut AppHitTestTreeManager,
-        ht_parent: HitTestTreeRef,
-    ) {
-        children.InsertAtTop(&self.root).unwrap();
-        ht.add_child(ht_parent, self.ht_root);
+    /// ウィンドウのクライアント領域の高さから、セルの表示領域の高さを求める
+    pub fn cell_area_height(&self, client_height: f32) -> f32 {
+        (client_height
+            - self.top.get()
+            - Self::SPACING
+            - Self::CELL_AREA_PADDINGS.top
+            - Self::CELL_AREA_PADDINGS.bottom)
+            .max(0.0)
     }
 
-    pub fn locate(&self, top: f32, ht: &mut AppHitTestTreeManager) {
-        let dpi = self.dpi.get();
+    pub fn scrollbar_thumb_height(content_height: f32, viewport_height: f32) -> f32 {
+        (viewport_height * viewport_height / content_height)
+            .max(Self::SCROLLBAR_MIN_THUMB_HEIGHT)
+            .min(viewport_height)
+    }
 
-        self.root
+    pub fn set_scroll(&self, scroll_top: f32, content_height: f32, viewport_height: f32) {
+        self.cell_scroll_root
             .SetOffset(Vector3 {
                 X: 0.0,
-                Y: dip_to_pixels(top, dpi),
+                Y: dip_to_pixels(-scroll_top, self.dpi),
                 Z: 0.0,
             })
             .unwrap();
-        ht.get_mut(self.ht_root).top = top;
-    }
 
-    fn update_bg_opacity(&self) {
-        match (self.hovering.get(), self.active.get()) {
-            (false, _) => self.bg.SetOpacity(0.0).unwrap(),
-            (true, false) => self.bg.SetOpacity(0.125).unwrap(),
-            (true, true) => self.bg.SetOpacity(0.25).unwrap(),
+        if content_height <= viewport_height {
+            // 全部見えているのでスクロールバーは出さない
+            self.scrollbar_thumb.SetOpacity(0.0).unwrap();
+            return;
         }
-    }
 
-    pub fn on_hover(&self) {
-        self.hovering.set(true);
-        self.update_bg_opacity();
+        let thumb_height = Self::scrollbar_thumb_height(content_height, viewport_height);
+        let thumb_top =
+            scroll_top / (content_height - viewport_height) * (viewport_height - thumb_height);
+        self.scrollbar_thumb
+            .SetSize(Vector2 {
+                X: dip_to_pixels(Self::SCROLLBAR_WIDTH, self.dpi),
+                Y: dip_to_pixels(thumb_height, self.dpi),
+            })
+            .unwrap();
+        self.scrollbar_thumb
+            .SetOffset(Vector3 {
+                X: dip_to_pixels(-Self::SCROLLBAR_WIDTH, self.dpi),
+                Y: dip_to_pixels(thumb_top, self.dpi),
+                Z: 0.0,
+            })
+            .unwrap();
+        self.scrollbar_thumb.SetOpacity(1.0).unwrap();
     }
 
-    pub fn on_hover_leave(&self) {
-        self.hovering.set(false);
-        // 離れた際はactiveも一緒に落とす
-        self.active.set(false);
-        self.update_bg_opacity();
+    pub fn transit_hidden(&self, ht: &mut AppHitTestTreeManager) {
+        self.composition_properties
+            .StartAnimation(h!("ShownRate"), &self.hide_animation)
+            .unwrap();
+        ht.get_mut(self.ht_root).left = -self.width.get();
     }
 
-    pub fn on_press(&self) {
-        self.active.set(true);
-        self.update_bg_opacity();
-    }
-
-    pub fn on_release(&self) {
-        self.active.set(false);
-        self.update_bg_opacity();
+    pub fn transit_shown(&self, ht: &mut AppHitTestTreeManager) {
+        self.composition_properties
+            .StartAnimation(h!("ShownRate"), &self.show_animation)
+            .unwrap();
+        ht.get_mut(self.ht_root).left = Self::SPACING;
     }
 }
 
-struct AppMenuBaseView {
-    root: ContainerVisual,
-    window_root: ContainerVisual,
-    composition_params: CompositionPropertySet,
-    show_animation: ScalarKeyFrameAnimation,
-    hide_animation: ScalarKeyFrameAnimation,
-    ht_root: HitTestTreeRef,
-    ht_window_root: HitTestTreeRef,
-    window_size: Cell<Size>,
-    top_offset: Cell<f32>,
-    dpi: Cell<f32>,
+/// スプライト一覧の行（グループ見出し/スプライト）とセルの対応
+///
+/// セルは見えている範囲の行（と前後少し）の分だけ作って、スクロールに合わせて使い回す
+pub struct SpriteListPaneContents {
+    subsystem: std::rc::Weak<Subsystem>,
+    ht: std::rc::Weak<RefCell<AppHitTestTreeManager>>,
+    background_worker_enqueue_access: BackgroundWorkerEnqueueWeakAccess,
+    background_worker_view_update_callback:
+        std::rc::Weak<RefCell<Vec<Box<dyn FnMut(&BackgroundWorkerStatus)>>>>,
+    view: std::rc::Weak<SpriteListPaneView>,
+    entries: RefCell<Vec<SpriteListEntry>>,
+    // Note: 折りたたみはModelに影響しないのでView側で持つ
+    collapsed_groups: RefCell<BTreeSet<String>>,
+    filter: RefCell<Option<SpriteFilter>>,
+    rows: RefCell<Vec<SpriteListRow>>,
+    /// 初めて行が割り当てられたときに作る（空文字列ではラベルのサーフェイスが作れないため）
+    cells: RefCell<Vec<Option<SpriteListCellView>>>,
+    /// 各セルがいま表示している行（行番号 % セル数 のセルを使う）
+    cell_rows: RefCell<Vec<Option<usize>>>,
+    scroll_top: Cell<f32>,
+    viewport_height: Cell<f32>,
 }
-impl AppMenuBaseView {
-    const CORNER_RADIUS: f32 = 8.0;
-    const BEAK_SIZE: f32 = 8.0;
-    const BEAK_LEFT_OFFSET: f32 = 8.0;
-    const FRAME_BASE_COLOR: windows::UI::Color =
-        ui_color_from_websafe_hex_rgb_with_alpha(0xfff, 64);
-    const MASK_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb_with_alpha(0x000, 128);
+impl SpriteListPaneContents {
+    /// 見えている範囲の前後に余分に用意しておく行数
+    const BUFFER_ROWS: usize = 4;
 
-    pub fn new(init: &mut ViewInitContext, top_offset: f32) -> Self {
-        let beak_path = unsafe { init.subsystem.d2d1_factory.CreatePathGeometry().unwrap() };
-        let beak_path_sink = unsafe { beak_path.Open().unwrap() };
-        unsafe {
-            beak_path_sink.BeginFigure(
-                D2D_POINT_2F {
-                    x: 0.0,
-                    y: Self::BEAK_SIZE,
-                },
-                D2D1_FIGURE_BEGIN_FILLED,
-            );
-            beak_path_sink.AddLines(&[
-                D2D_POINT_2F {
-                    x: Self::BEAK_SIZE,
-                    y: 0.0,
-                },
-                D2D_POINT_2F {
-                    x: Self::BEAK_SIZE * 2.0,
-                    y: Self::BEAK_SIZE,
-                },
-            ]);
-            beak_path_sink.EndFigure(D2D1_FIGURE_END_CLOSED);
-            beak_path_sink.Close().unwrap();
-            drop(beak_path_sink);
+    pub fn set_entries(&self, entries: Vec<SpriteListEntry>) {
+        *self.entries.borrow_mut() = entries;
+        self.update();
+    }
+
+    pub fn set_filter(&self, filter: Option<SpriteFilter>) {
+        *self.filter.borrow_mut() = filter;
+        self.update();
+    }
+
+    pub fn toggle_group_collapsed(&self, path: &str) {
+        let mut collapsed_groups = self.collapsed_groups.borrow_mut();
+        if !collapsed_groups.remove(path) {
+            collapsed_groups.insert(path.into());
         }
+        drop(collapsed_groups);
 
-        let beak_mask_surface = init
-            .subsystem
-            .new_2d_mask_surface(Size {
-                Width: init.dip_to_pixels(Self::BEAK_SIZE * 2.0),
-                Height: init.dip_to_pixels(Self::BEAK_SIZE),
-            })
-            .unwrap();
-        draw_2d(&beak_mask_surface, |dc, offset| {
-            unsafe {
-                dc.SetDpi(init.dpi, init.dpi);
-                dc.SetTransform(&Matrix3x2::translation(
-                    init.signed_pixels_to_dip(offset.x),
-                    init.signed_pixels_to_dip(offset.y),
-                ));
+        self.update();
+    }
+
+    pub fn set_viewport_height(&self, height: f32) {
+        self.viewport_height.set(height);
+        self.set_scroll_top(self.scroll_top.get());
+    }
+
+    pub fn scroll_top(&self) -> f32 {
+        self.scroll_top.get()
+    }
+
+    pub fn set_scroll_top(&self, scroll_top: f32) {
+        self.scroll_top
+            .set(scroll_top.clamp(0.0, self.max_scroll_top()));
+        self.realize();
+    }
+
+    fn content_height(&self) -> f32 {
+        self.row_count() as f32 * SpriteListCellView::CELL_HEIGHT
+    }
+
+    fn max_scroll_top(&self) -> f32 {
+        (self.content_height() - self.viewport_height.get()).max(0.0)
+    }
+
+    /// スクロールバーのつまみを1dip動かしたときのスクロール量
+    pub fn scroll_per_thumb_move(&self) -> f32 {
+        let content_height = self.content_height();
+        let viewport_height = self.viewport_height.get();
+        if content_height <= viewport_height {
+            return 0.0;
+        }
+
+        let thumb_height =
+            SpriteListPaneView::scrollbar_thumb_height(content_height, viewport_height);
+        (content_height - viewport_height) / (viewport_height - thumb_height).max(1.0)
+    }
+
+    /// 行が表示領域に収まるようにスクロールする
+    pub fn scroll_row_into_view(&self, row: usize) {
+        let top = row as f32 * SpriteListCellView::CELL_HEIGHT;
+        let bottom = top + SpriteListCellView::CELL_HEIGHT;
+        let scroll_top = self.scroll_top.get();
+        if top < scroll_top {
+            self.set_scroll_top(top);
+        } else if bottom > scroll_top + self.viewport_height.get() {
+            self.set_scroll_top(bottom - self.viewport_height.get());
+        }
+    }
+
+    /// セルの表示領域内の位置から行番号を求める
+    pub fn row_at(&self, local_y: f32) -> Option<usize> {
+        if local_y < 0.0 {
+            return None;
+        }
+
+        let index = ((local_y + self.scroll_top.get()) / SpriteListCellView::CELL_HEIGHT) as usize;
+        (index < self.row_count()).then_some(index)
+    }
+
+    fn cell_for_row(&self, row: usize) -> Option<usize> {
+        let cell_rows = self.cell_rows.borrow();
+        if cell_rows.is_empty() {
+            return None;
+        }
+
+        let n = row % cell_rows.len();
+        (cell_rows[n] == Some(row)).then_some(n)
+    }
+
+    pub fn hover_row(&self, row: usize) {
+        if let Some(n) = self.cell_for_row(row) {
+            if let Some(c) = &self.cells.borrow()[n] {
+                c.on_hover();
             }
+        }
+    }
 
-            unsafe {
-                dc.Clear(None);
-                dc.FillGeometry(
-                    &beak_path,
-                    &dc.CreateSolidColorBrush(&D2D1_COLOR_F_WHITE, None)?,
-                    None,
-                );
+    pub fn leave_row(&self, row: usize) {
+        if let Some(n) = self.cell_for_row(row) {
+            if let Some(c) = &self.cells.borrow()[n] {
+                c.on_leave();
             }
+        }
+    }
 
-            Ok::<_, windows_core::Error>(())
-        })
-        .unwrap();
+    fn update(&self) {
+        *self.rows.borrow_mut() = sprite_group::build_rows(
+            &self.entries.borrow(),
+            &self.collapsed_groups.borrow(),
+            self.filter.borrow().as_ref(),
+        );
+        // 行が減っていたらスクロール位置を詰める
+        self.scroll_top
+            .set(self.scroll_top.get().clamp(0.0, self.max_scroll_top()));
 
-        let window_base_brush = create_instant_effect_brush(
-            &init.subsystem,
-            &CompositeEffectParams::new(&[
-                GaussianBlurEffectParams::new(
-                    &CompositionEffectSourceParameter::Create(h!("source")).unwrap(),
-                )
-                .blur_amount_px(9.0)
-                .instantiate()
-                .unwrap()
-                .cast()
-                .unwrap(),
-                ColorSourceEffectParams {
-                    color: Some(Self::FRAME_BASE_COLOR),
-                }
-                .instantiate()
-                .unwrap()
-                .cast()
-                .unwrap(),
-            ])
-            .instantiate()
-            .unwrap(),
-            &[(
-                h!("source"),
-                init.subsystem
-                    .compositor
-                    .CreateBackdropBrush()
-                    .unwrap()
-                    .cast()
-                    .unwrap(),
-            )],
-        )
-        .unwrap();
+        // Note: 行の中身が変わっているので割り当てを全部やり直す
+        for r in self.cell_rows.borrow_mut().iter_mut() {
+            *r = None;
+        }
+        self.realize();
+    }
 
-        let root = ContainerVisualParams::new()
-            .expand()
-            .top(init.dip_to_pixels(top_offset))
-            .height(init.dip_to_pixels(-top_offset))
-            .instantiate(&init.subsystem.compositor)
-            .unwrap();
-        let mask = SpriteVisualParams::new(
-            &init
-                .subsystem
-                .compositor
-                .CreateColorBrushWithColor(Self::MASK_COLOR)
-                .unwrap(),
-        )
-        .expand()
-        .instantiate(&init.subsystem.compositor)
-        .unwrap();
-        let window_root = ContainerVisualParams::new()
-            .size_sq(init.dip_to_pixels(128.0))
-            .instantiate(&init.subsystem.compositor)
-            .unwrap();
-        let beak = SpriteVisualParams::new(
-            &CompositionMaskBrushParams {
-                source: &window_base_brush,
-                mask: &CompositionSurfaceBrushParams::new(&beak_mask_surface)
-                    .instantiate(&init.subsystem.compositor)
-                    .unwrap(),
+    /// 見えている範囲の行にセルを割り当てる
+    fn realize(&self) {
+        let Some(subsystem) = self.subsystem.upgrade() else {
+            // app teardown-ed
+            return;
+        };
+        let Some(background_worker_enqueue_access) =
+            self.background_worker_enqueue_access.upgrade()
+        else {
+            // app teardown-ed
+            return;
+        };
+        let Some(background_worker_view_update_callback) =
+            self.background_worker_view_update_callback.upgrade()
+        else {
+            // app teardown-ed
+            return;
+        };
+        let Some(ht) = self.ht.upgrade() else {
+            // parent teardown-ed
+            return;
+        };
+        let Some(view) = self.view.upgrade() else {
+            // parent teardown-ed
+            return;
+        };
+
+        let entries = self.entries.borrow();
+        let rows = self.rows.borrow();
+        let scroll_top = self.scroll_top.get();
+        let viewport_height = self.viewport_height.get();
+        view.set_scroll(scroll_top, self.content_height(), viewport_height);
+
+        let mut cells = self.cells.borrow_mut();
+        let mut cell_rows = self.cell_rows.borrow_mut();
+        // Note: 範囲の端が行の途中にかかっても足りるように+1しておく
+        let pool_size = (viewport_height / SpriteListCellView::CELL_HEIGHT).ceil() as usize
+            + 1
+            + Self::BUFFER_ROWS * 2;
+        if cells.len() != pool_size {
+            // 表示領域の高さが変わったので作り直す（割り当ても全部やり直し）
+            for c in cells.drain(..).flatten() {
+                c.unmount();
             }
-            .instantiate(&init.subsystem.compositor)
-            .unwrap(),
-        )
-        .size(Vector2 {
-            X: init.dip_to_pixels(Self::BEAK_SIZE * 2.0),
-            Y: init.dip_to_pixels(Self::BEAK_SIZE),
-        })
-        .offset_xy(Vector2 {
-            X: init.dip_to_pixels(Self::BEAK_LEFT_OFFSET),
-            Y: init.dip_to_pixels(-Self::BEAK_SIZE),
-        })
-        .instantiate(&init.subsystem.compositor)
-        .unwrap();
-        let window_frame = SpriteVisualParams::new(
-            &CompositionMaskBrushParams {
-                source: &window_base_brush,
-                mask: &CompositionNineGridBrushParams::new(
-                    &CompositionSurfaceBrushParams::new(
-                        &init
-                            .subsystem
-                            .rounded_rect_mask_surface(init.dpi, Self::CORNER_RADIUS)
-                            .unwrap(),
-                    )
-                    .stretch(CompositionStretch::Fill)
-                    .instantiate(&init.subsystem.compositor)
-                    .unwrap(),
-                )
-                .insets(init.dip_to_pixels(Self::CORNER_RADIUS))
-                .instantiate(&init.subsystem.compositor)
-                .unwrap(),
+            cells.resize_with(pool_size, || None);
+            cell_rows.clear();
+            cell_rows.resize(pool_size, None);
+        }
+
+        let first = ((scroll_top / SpriteListCellView::CELL_HEIGHT).floor() as usize)
+            .saturating_sub(Self::BUFFER_ROWS);
+        let last = (((scroll_top + viewport_height) / SpriteListCellView::CELL_HEIGHT).ceil()
+            as usize
+            + Self::BUFFER_ROWS)
+            .min(rows.len());
+
+        for (n, (cell, assigned)) in cells.iter_mut().zip(cell_rows.iter_mut()).enumerate() {
+            // このセルが担当する範囲内の行（範囲の長さはセル数以下なので高々1つ）
+            let row = first + (n + pool_size - first % pool_size) % pool_size;
+            if row >= last {
+                *assigned = None;
+                if let Some(c) = cell {
+                    c.on_leave();
+                    c.set_visible(false);
+                }
+                continue;
             }
-            .instantiate(&init.subsystem.compositor)
-            .unwrap(),
-        )
-        .expand()
-        .instantiate(&init.subsystem.compositor)
-        .unwrap();
+            if *assigned == Some(row) {
+                // 割り当て済み
+                continue;
+            }
+            *assigned = Some(row);
 
-        window_root.Children().unwrap().InsertAtTop(&beak).unwrap();
-        window_root
-            .Children()
-            .unwrap()
-            .InsertAtTop(&window_frame)
-            .unwrap();
+            let (label, sel) = match &rows[row] {
+                SpriteListRow::Group {
+                    name,
+                    collapsed,
+                    selected,
+                    ..
+                } => (
+                    format!("{} {name}", if *collapsed { "▸" } else { "▾" }),
+                    *selected,
+                ),
+                &SpriteListRow::Sprite { index, .. } => {
+                    (entries[index].target.name.clone(), entries[index].selected)
+                }
+            };
 
-        root.Children().unwrap().InsertAtTop(&mask).unwrap();
-        root.Children().unwrap().InsertAtTop(&window_root).unwrap();
+            let top = row as f32 * SpriteListCellView::CELL_HEIGHT;
+            let cell = match cell {
+                Some(c) => {
+                    // 別の行で使っていたかもしれないのでホバー状態を消しておく
+                    c.on_leave();
+                    c.set_name(&label, &subsystem);
+                    c.set_top(top);
+                    c.set_visible(true);
 
-        let composition_params = init.subsystem.compositor.CreatePropertySet().unwrap();
-        composition_params
-            .InsertScalar(h!("VisibleRate"), 0.0)
-            .unwrap();
-        composition_params
-            .InsertVector2(h!("WindowOffset"), Vector2 { X: 0.0, Y: 0.0 })
-            .unwrap();
-        composition_params
-            .InsertScalar(h!("DPI"), init.dpi)
-            .unwrap();
+                    c
+                }
+                None => {
+                    let new_cell = SpriteListCellView::new(
+                        &mut ViewInitContext {
+                            subsystem: &subsystem,
+                            ht: &ht,
+                            dpi: view.dpi,
+                            background_worker_enqueue_access: &background_worker_enqueue_access,
+                            background_worker_view_update_callback:
+                                &background_worker_view_update_callback,
+                        },
+                        &label,
+                        top,
+                    );
+                    new_cell.mount(&view.cell_scroll_root.Children().unwrap());
 
-        let opacity_expression = init
-            .subsystem
-            .compositor
-            .CreateExpressionAnimationWithExpression(h!("cp.VisibleRate"))
-            .unwrap();
-        opacity_expression
-            .SetExpressionReferenceParameter(h!("cp"), &composition_params)
-            .unwrap();
-        opacity_expression.SetTarget(h!("Opacity")).unwrap();
-        mask.StartAnimation(h!("Opacity"), &opacity_expression)
-            .unwrap();
-        window_root
-            .StartAnimation(h!("Opacity"), &opacity_expression)
-            .unwrap();
+                    cell.insert(new_cell)
+                }
+            };
+            cell.set_indent(rows[row].depth());
+            if sel {
+                cell.on_select();
+            } else {
+                cell.on_deselect();
+            }
+        }
+    }
 
-        let window_offset_expression = init
-            .subsystem
-            .compositor
-            .CreateExpressionAnimationWithExpression(h!(
-                "Vector3(cp.WindowOffset.X, cp.WindowOffset.Y - (16.0 * (1.0 - cp.VisibleRate) * cp.DPI / 96.0), 0.0)"
-            ))
-            .unwrap();
-        window_offset_expression
-            .SetExpressionReferenceParameter(h!("cp"), &composition_params)
-            .unwrap();
-        window_offset_expression.SetTarget(h!("Offset")).unwrap();
-        window_root
-            .StartAnimation(h!("Offset"), &window_offset_expression)
-            .unwrap();
+    fn row_count(&self) -> usize {
+        self.rows.borrow().len()
+    }
+}
 
-        let easing = init
-            .subsystem
-            .compositor
-            .CreateCubicBezierEasingFunction(
-                Vector2 { X: 0.0, Y: 0.0 },
-                Vector2 { X: 0.25, Y: 1.0 },
-            )
-            .unwrap();
-        let show_animation = SimpleScalarAnimationParams::new(0.0, 1.0, &easing)
-            .duration(timespan_ms(200))
-            .target(h!("VisibleRate"))
-            .instantiate(&init.subsystem.compositor)
-            .unwrap();
-        let hide_animation = SimpleScalarAnimationParams::new(1.0, 0.0, &easing)
-            .duration(timespan_ms(200))
-            .target(h!("VisibleRate"))
-            .instantiate(&init.subsystem.compositor)
-            .unwrap();
+pub struct SpriteListPaneHitActionHandler {
+    pub view: Rc<SpriteListPaneView>,
+    pub toggle_button_view: Rc<SpriteListToggleButtonView>,
+    pub contents: Rc<SpriteListPaneContents>,
+    pub search_box_view: Rc<SpriteListSearchBoxView>,
+    pub active_cell_index: Cell<Option<usize>>,
+    pub hidden: Cell<bool>,
+    adjust_drag_state: Cell<Option<(f32, f32)>>,
+    /// (ドラッグ開始時のclient_y, そのときのスクロール位置)
+    scroll_drag_state: Cell<Option<(f32, f32)>>,
+}
+impl SpriteListPaneHitActionHandler {
+    /// ホイール1ノッチでスクロールする行数
+    const WHEEL_SCROLL_ROWS: f32 = 3.0;
 
-        let ht_root = init.ht.borrow_mut().alloc(HitTestTreeData {
-            left: 0.0,
-            top: top_offset,
-            left_adjustment_factor: 0.0,
-            top_adjustment_factor: 0.0,
-            width: 0.0,
-            height: -top_offset,
-            width_adjustment_factor: 1.0,
-            height_adjustment_factor: 1.0,
-            parent: None,
-            children: Vec::new(),
-            action_handler: None,
-        });
-        let ht_window_root = init.ht.borrow_mut().alloc(HitTestTreeData {
-            left: 0.0,
-            top: 0.0,
-            left_adjustment_factor: 0.0,
-            top_adjustment_factor: 0.0,
-            width: 128.0,
-            height: 128.0,
-            width_adjustment_factor: 0.0,
-            height_adjustment_factor: 0.0,
-            parent: None,
-            children: Vec::new(),
-            action_handler: None,
-        });
-        init.ht.borrow_mut().add_child(ht_root, ht_window_root);
+    fn scroll_by_thumb_drag(&self, base_y: f32, base_scroll_top: f32, client_y: f32) {
+        // ホバー中だった行はスクロールで別の位置に行くので外しておく
+        if let Some(n) = self.active_cell_index.replace(None) {
+            self.contents.leave_row(n);
+        }
 
-        Self {
-            root,
-            window_root,
-            composition_params,
-            show_animation,
-            hide_animation,
-            ht_root,
-            ht_window_root,
-            window_size: Cell::new(Size {
-                Width: 128.0,
-                Height: 128.0,
-            }),
-            top_offset: Cell::new(top_offset),
-            dpi: Cell::new(init.dpi),
+        self.contents.set_scroll_top(
+            base_scroll_top + (client_y - base_y) * self.contents.scroll_per_thumb_move(),
+        );
+    }
+
+    /// キーボードでの行の選択を動かす（ホバーと同じ表示にする）
+    fn move_keyboard_row(&self, context: &mut AppState, delta: isize) {
+        let row_count = self.contents.row_count();
+        if row_count == 0 {
+            return;
+        }
+
+        let new_row = match self.active_cell_index.get() {
+            Some(n) => n.saturating_add_signed(delta).min(row_count - 1),
+            None if delta < 0 => row_count - 1,
+            None => 0,
+        };
+        if let Some(n) = self.active_cell_index.replace(Some(new_row)) {
+            self.contents.leave_row(n);
+        }
+
+        let row = self.contents.rows.borrow()[new_row].clone();
+        if let SpriteListRow::Sprite { index, .. } = row {
+            context.select_sprite(index);
         }
+
+        // Note: 選択を変えるとセルの割り当てがやり直されるので、表示はそのあとで更新する
+        self.contents.scroll_row_into_view(new_row);
+        self.contents.hover_row(new_row);
     }
+}
+impl HitTestTreeActionHandler for SpriteListPaneHitActionHandler {
+    type Context = AppState;
 
-    pub fn mount(
+    fn hit_active(&self, sender: HitTestTreeRef, _context: &Self::Context) -> bool {
+        if (sender == self.search_box_view.ht_root || sender == self.view.ht_cell_area)
+            && self.hidden.get()
+        {
+            // 隠れているときはフォーカスが当たらないようにする
+            return false;
+        }
+
+        true
+    }
+
+    fn cursor(&self, sender: HitTestTreeRef, _context: &mut AppState) -> Option<CursorHandle> {
+        if sender == self.view.ht_adjust_area && !self.hidden.get() {
+            // TODO: 必要そうならキャッシュする
+            return Some(unsafe { LoadCursorW(None, IDC_SIZEWE).unwrap() }.into());
+        }
+
+        None
+    }
+
+    fn on_pointer_enter(
         &self,
-        children: &VisualCollection,
+        sender: HitTestTreeRef,
+        _context: &mut AppState,
         ht: &mut AppHitTestTreeManager,
-        ht_parent: HitTestTreeRef,
-    ) {
-        children.InsertAtTop(&self.root).unwrap();
-        ht.add_child(ht_parent, self.ht_root);
+        args: PointerActionArgs,
+    ) -> EventContinueControl {
+        if sender == self.view.ht_root {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.toggle_button_view.ht_root {
+            self.toggle_button_view.on_hover();
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_scrollbar {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_cell_area {
+            let (_, local_y, _, _) = ht.translate_client_to_tree_local(
+                sender,
+                args.client_x,
+                args.client_y,
+                args.client_width,
+                args.client_height,
+            );
+
+            let index = self.contents.row_at(local_y);
+            if let Some(n) = index {
+                self.contents.hover_row(n);
+            }
+            self.active_cell_index.set(index);
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
     }
 
-    pub fn set_window_offset_by_beak_peak(
+    fn on_pointer_leave(
         &self,
-        peak_x: f32,
-        peak_y: f32,
-        ht: &mut AppHitTestTreeManager,
-    ) {
-        let dpi = self.dpi.get();
-        let top_offset = self.top_offset.get();
+        sender: HitTestTreeRef,
+        _context: &mut AppState,
+        _ht: &mut AppHitTestTreeManager,
+        _args: PointerActionArgs,
+    ) -> EventContinueControl {
+        if sender == self.view.ht_root {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
 
-        self.composition_params
-            .InsertVector2(
-                h!("WindowOffset"),
-                Vector2 {
-                    X: dip_to_pixels(peak_x - Self::BEAK_LEFT_OFFSET - Self::BEAK_SIZE, dpi),
-                    Y: dip_to_pixels(peak_y + Self::BEAK_SIZE - top_offset, dpi),
-                },
-            )
-            .unwrap();
-        ht.get_mut(self.ht_window_root).left = peak_x - Self::BEAK_LEFT_OFFSET - Self::BEAK_SIZE;
-        ht.get_mut(self.ht_window_root).top = peak_y + Self::BEAK_SIZE - top_offset;
+        if sender == self.toggle_button_view.ht_root {
+            self.toggle_button_view.on_hover_leave();
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_scrollbar {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_cell_area {
+            if let Some(x) = self.active_cell_index.replace(None) {
+                self.contents.leave_row(x);
+            }
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
     }
 
-    pub fn resize_window(&self, w: f32, h: f32, ht: &mut AppHitTestTreeManager) {
-        let dpi = self.dpi.get();
+    fn on_pointer_down(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppState,
+        _ht: &mut AppHitTestTreeManager,
+        args: PointerActionArgs,
+    ) -> EventContinueControl {
+        if sender == self.view.ht_root {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_adjust_area && !self.hidden.get() {
+            self.adjust_drag_state
+                .set(Some((args.client_x, self.view.width.get())));
+
+            return EventContinueControl::CAPTURE_ELEMENT | EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.toggle_button_view.ht_root {
+            self.toggle_button_view.on_press();
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_scrollbar && !self.hidden.get() {
+            self.scroll_drag_state
+                .set(Some((args.client_y, self.contents.scroll_top())));
+
+            return EventContinueControl::CAPTURE_ELEMENT | EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_pointer_move(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppState,
+        ht: &mut AppHitTestTreeManager,
+        args: PointerActionArgs,
+    ) -> EventContinueControl {
+        if sender == self.view.ht_root {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_adjust_area && !self.hidden.get() {
+            if let Some((base_x, base_width)) = self.adjust_drag_state.get() {
+                let new_width = (base_width + (args.client_x - base_x)).max(10.0);
+                self.view.set_width(ht, new_width);
+            }
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_scrollbar {
+            if let Some((base_y, base_scroll_top)) = self.scroll_drag_state.get() {
+                self.scroll_by_thumb_drag(base_y, base_scroll_top, args.client_y);
+            }
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_cell_area {
+            let (_, local_y, _, _) = ht.translate_client_to_tree_local(
+                sender,
+                args.client_x,
+                args.client_y,
+                args.client_width,
+                args.client_height,
+            );
+
+            let new_index = self.contents.row_at(local_y);
+            if self.active_cell_index.get() != new_index {
+                // active changed
+                // Note: 行を担当するセルがもう無いこともある（leave_row側で無視される）
+                if let Some(n) = self.active_cell_index.replace(new_index) {
+                    self.contents.leave_row(n);
+                }
+
+                if let Some(n) = new_index {
+                    self.contents.hover_row(n);
+                }
+            }
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_pointer_up(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppState,
+        ht: &mut AppHitTestTreeManager,
+        args: PointerActionArgs,
+    ) -> EventContinueControl {
+        if sender == self.view.ht_root {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_adjust_area && !self.hidden.get() {
+            if let Some((base_x, base_width)) = self.adjust_drag_state.replace(None) {
+                let new_width = (base_width + (args.client_x - base_x)).max(10.0);
+                self.view.set_width(ht, new_width);
+            }
+
+            return EventContinueControl::RELEASE_CAPTURE_ELEMENT
+                | EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.toggle_button_view.ht_root {
+            self.toggle_button_view.on_release();
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_scrollbar {
+            if let Some((base_y, base_scroll_top)) = self.scroll_drag_state.replace(None) {
+                self.scroll_by_thumb_drag(base_y, base_scroll_top, args.client_y);
+            }
+
+            return EventContinueControl::RELEASE_CAPTURE_ELEMENT
+                | EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_click(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppState,
+        ht: &mut AppHitTestTreeManager,
+        args: PointerActionArgs,
+    ) -> EventContinueControl {
+        if sender == self.view.ht_root {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.toggle_button_view.ht_root {
+            let hidden = !self.hidden.get();
+            self.hidden.set(hidden);
+
+            if hidden {
+                self.toggle_button_view.transit_hidden(ht);
+                self.view.transit_hidden(ht);
+            } else {
+                self.toggle_button_view.transit_shown(ht);
+                self.view.transit_shown(ht);
+            }
+
+            return EventContinueControl::STOP_PROPAGATION
+                | EventContinueControl::RECOMPUTE_POINTER_ENTER;
+        }
+
+        if sender == self.search_box_view.ht_select_all {
+            context.select_sprite_filter_matches();
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_scrollbar {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.search_box_view.ht_root {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if sender == self.view.ht_cell_area {
+            let (local_x, local_y, _, _) = ht.translate_client_to_tree_local(
+                sender,
+                args.client_x,
+                args.client_y,
+                args.client_width,
+                args.client_height,
+            );
+
+            let click_row = self
+                .contents
+                .row_at(local_y)
+                .map(|n| self.contents.rows.borrow()[n].clone());
+
+            match click_row {
+                None => {}
+                Some(SpriteListRow::Sprite { index, .. }) => {
+                    if is_control_key_pressed() {
+                        context.toggle_sprite_selection(index);
+                    } else {
+                        context.select_sprite(index);
+                    }
+                }
+                Some(SpriteListRow::Group { path, depth, .. }) => {
+                    let toggle_right = SpriteListCellView::LABEL_LEFT
+                        + depth as f32 * SpriteListCellView::INDENT
+                        + SpriteListCellView::GROUP_TOGGLE_WIDTH;
+                    if local_x < toggle_right {
+                        self.contents.toggle_group_collapsed(&path);
+
+                        return EventContinueControl::STOP_PROPAGATION
+                            | EventContinueControl::RECOMPUTE_POINTER_ENTER;
+                    }
+
+                    context.select_sprite_group(&path, is_control_key_pressed());
+                }
+            }
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_wheel(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppState,
+        _ht: &mut AppHitTestTreeManager,
+        _args: PointerActionArgs,
+        delta: WheelDelta,
+    ) -> EventContinueControl {
+        if sender == self.view.ht_cell_area && !self.hidden.get() {
+            // ホバー中だった行はスクロールで別の位置に行くので外しておく（入り直しで付け直される）
+            if let Some(n) = self.active_cell_index.replace(None) {
+                self.contents.leave_row(n);
+            }
+
+            self.contents.set_scroll_top(
+                self.contents.scroll_top()
+                    - delta.y * Self::WHEEL_SCROLL_ROWS * SpriteListCellView::CELL_HEIGHT,
+            );
+
+            return EventContinueControl::STOP_PROPAGATION
+                | EventContinueControl::RECOMPUTE_POINTER_ENTER;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_focus(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppState,
+        _ht: &mut AppHitTestTreeManager,
+        _focus_visible: bool,
+    ) {
+        if sender == self.search_box_view.ht_root {
+            self.search_box_view.set_active(true);
+        }
+    }
+
+    fn on_blur(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppState,
+        _ht: &mut AppHitTestTreeManager,
+    ) {
+        if sender == self.search_box_view.ht_root {
+            self.search_box_view.set_active(false);
+        }
+    }
+
+    fn on_key_down(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppState,
+        _ht: &mut AppHitTestTreeManager,
+        args: KeyActionArgs,
+    ) -> EventContinueControl {
+        if self.hidden.get() {
+            return EventContinueControl::empty();
+        }
+
+        if sender == self.view.ht_cell_area {
+            match args.key {
+                KeyCode::UP => self.move_keyboard_row(context, -1),
+                KeyCode::DOWN => self.move_keyboard_row(context, 1),
+                KeyCode::RETURN | KeyCode::SPACE => {
+                    let row = self
+                        .active_cell_index
+                        .get()
+                        .and_then(|n| self.contents.rows.borrow().get(n).cloned());
+                    match row {
+                        None => {}
+                        Some(SpriteListRow::Sprite { index, .. }) => {
+                            context.toggle_sprite_selection(index);
+                        }
+                        Some(SpriteListRow::Group { path, .. }) => {
+                            self.contents.toggle_group_collapsed(&path);
+                        }
+                    }
+                    if let Some(n) = self.active_cell_index.get() {
+                        self.contents.hover_row(n);
+                    }
+                }
+                _ => return EventContinueControl::empty(),
+            }
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_char(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppState,
+        _ht: &mut AppHitTestTreeManager,
+        ch: char,
+    ) -> EventContinueControl {
+        if sender != self.search_box_view.ht_root || self.hidden.get() {
+            return EventContinueControl::empty();
+        }
+
+        match ch {
+            // backspace
+            '\u{08}' => {
+                let mut query = context.sprite_filter_query().to_owned();
+                query.pop();
+                context.set_sprite_filter_query(query);
+            }
+            '\r' => {
+                context.select_sprite_filter_matches();
+
+                return EventContinueControl::STOP_PROPAGATION
+                    | EventContinueControl::RELEASE_FOCUS;
+            }
+            // escape
+            '\u{1b}' => {
+                context.set_sprite_filter_query(String::new());
+
+                return EventContinueControl::STOP_PROPAGATION
+                    | EventContinueControl::RELEASE_FOCUS;
+            }
+            c if c.is_control() => {}
+            c => {
+                let mut query = context.sprite_filter_query().to_owned();
+                query.push(c);
+                context.set_sprite_filter_query(query);
+            }
+        }
+
+        EventContinueControl::STOP_PROPAGATION
+    }
+}
+
+pub struct SpriteListPanePresenter {
+    view: Rc<SpriteListPaneView>,
+    ht_action_handler: Rc<SpriteListPaneHitActionHandler>,
+}
+impl SpriteListPanePresenter {
+    pub fn new(init: &mut PresenterInitContext) -> Self {
+        let view = Rc::new(SpriteListPaneView::new(&mut init.for_view));
+        let toggle_button_view = Rc::new(SpriteListToggleButtonView::new(&mut init.for_view));
+        let contents = Rc::new(SpriteListPaneContents {
+            subsystem: Rc::downgrade(init.for_view.subsystem),
+            ht: Rc::downgrade(init.for_view.ht),
+            background_worker_enqueue_access: init
+                .for_view
+                .background_worker_enqueue_access
+                .downgrade(),
+            background_worker_view_update_callback: Rc::downgrade(
+                init.for_view.background_worker_view_update_callback,
+            ),
+            view: Rc::downgrade(&view),
+            entries: RefCell::new(Vec::new()),
+            collapsed_groups: RefCell::new(BTreeSet::new()),
+            filter: RefCell::new(None),
+            rows: RefCell::new(Vec::new()),
+            cells: RefCell::new(Vec::new()),
+            cell_rows: RefCell::new(Vec::new()),
+            scroll_top: Cell::new(0.0),
+            viewport_height: Cell::new(0.0),
+        });
+
+        let search_box_view = Rc::new(SpriteListSearchBoxView::new(&mut init.for_view));
+
+        toggle_button_view.mount(
+            &view.root.Children().unwrap(),
+            &mut init.for_view.ht.borrow_mut(),
+            view.ht_root,
+        );
+        search_box_view.mount(
+            &view.root.Children().unwrap(),
+            &mut init.for_view.ht.borrow_mut(),
+            view.ht_root,
+        );
+
+        init.app_state
+            .borrow_mut()
+            .register_sprite_filter_view_feedback({
+                let subsystem = Rc::downgrade(init.for_view.subsystem);
+                let contents = Rc::downgrade(&contents);
+                let search_box_view = Rc::downgrade(&search_box_view);
+
+                move |query, filter| {
+                    let Some(subsystem) = subsystem.upgrade() else {
+                        // app teardown-ed
+                        return;
+                    };
+                    let Some(contents) = contents.upgrade() else {
+                        // parent teardown-ed
+                        return;
+                    };
+                    let Some(search_box_view) = search_box_view.upgrade() else {
+                        // parent teardown-ed
+                        return;
+                    };
+
+                    search_box_view.set_query(query, &subsystem);
+                    contents.set_filter(filter.cloned());
+                }
+            });
+
+        init.app_state.borrow_mut().register_sprites_view_feedback({
+            let contents = Rc::downgrade(&contents);
+
+            move |sprites| {
+                let Some(contents) = contents.upgrade() else {
+                    // parent teardown-ed
+                    return;
+                };
+
+                contents.set_entries(sprites.iter().map(SpriteListEntry::from_sprite).collect());
+            }
+        });
+
+        let ht_action_handler = Rc::new(SpriteListPaneHitActionHandler {
+            view: view.clone(),
+            toggle_button_view: toggle_button_view.clone(),
+            contents,
+            search_box_view: search_box_view.clone(),
+            active_cell_index: Cell::new(None),
+            hidden: Cell::new(false),
+            adjust_drag_state: Cell::new(None),
+            scroll_drag_state: Cell::new(None),
+        });
+        init.for_view
+            .ht
+            .borrow_mut()
+            .get_mut(view.ht_root)
+            .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
+        init.for_view
+            .ht
+            .borrow_mut()
+            .get_mut(view.ht_adjust_area)
+            .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
+        init.for_view
+            .ht
+            .borrow_mut()
+            .get_mut(view.ht_cell_area)
+            .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
+        init.for_view
+            .ht
+            .borrow_mut()
+            .get_mut(view.ht_scrollbar)
+            .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
+        init.for_view
+            .ht
+            .borrow_mut()
+            .get_mut(toggle_button_view.ht_root)
+            .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
+        init.for_view
+            .ht
+            .borrow_mut()
+            .get_mut(search_box_view.ht_root)
+            .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
+        init.for_view
+            .ht
+            .borrow_mut()
+            .get_mut(search_box_view.ht_select_all)
+            .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
+
+        Self {
+            view,
+            ht_action_handler,
+        }
+    }
+
+    pub fn mount(
+        &self,
+        children: &VisualCollection,
+        ht: &mut AppHitTestTreeManager,
+        ht_parent: HitTestTreeRef,
+    ) {
+        self.view.mount(children, ht, ht_parent);
+    }
+
+    pub fn unmount(&self, ht: &mut AppHitTestTreeManager) {
+        self.view.unmount(ht);
+    }
+
+    pub fn shutdown(&self, ht: &mut AppHitTestTreeManager) {
+        self.view.shutdown(ht);
+    }
+
+    pub fn set_top(&self, ht: &mut AppHitTestTreeManager, top: f32) {
+        self.view.set_top(ht, top);
+    }
+
+    /// ウィンドウのクライアント領域の高さ（dip）に合わせて表示するセルの範囲を変える
+    pub fn resize(&self, client_height: f32) {
+        self.ht_action_handler
+            .contents
+            .set_viewport_height(self.view.cell_area_height(client_height));
+    }
+}
+
+struct AppMenuEntryView {
+    root: ContainerVisual,
+    bg: SpriteVisual,
+    label: SpriteVisual,
+    ht_root: HitTestTreeRef,
+    dpi: Cell<f32>,
+    hovering: Cell<bool>,
+    active: Cell<bool>,
+    focused: Cell<bool>,
+}
+impl AppMenuEntryView {
+    const HEIGHT: f32 = 24.0;
+    const ICON_SIZE: f32 = 20.0;
+    const ICON_LEFT_OFFSET: f32 = 8.0;
+    const ICON_LABEL_GAP: f32 = 4.0;
+
+    /// ラベルを描いたサーフェスとその大きさ（dip）
+    fn new_label_surface(
+        subsystem: &Subsystem,
+        label: &str,
+        dpi: f32,
+    ) -> (CompositionDrawingSurface, f32, f32) {
+        let label_layout = subsystem
+            .new_text_layout_unrestricted(label, &subsystem.default_ui_format)
+            .unwrap();
+        let mut label_metrics = MaybeUninit::uninit();
+        let label_metrics = unsafe {
+            label_layout.GetMetrics(label_metrics.as_mut_ptr()).unwrap();
+            label_metrics.assume_init()
+        };
+        let label_surface = subsystem
+            .new_2d_drawing_surface(Size {
+                Width: dip_to_pixels(label_metrics.width, dpi),
+                Height: dip_to_pixels(label_metrics.height, dpi),
+            })
+            .unwrap();
+        draw_2d(&label_surface, |dc, offset| {
+            unsafe {
+                dc.SetDpi(dpi, dpi);
+
+                dc.Clear(None);
+                dc.DrawTextLayout(
+                    D2D_POINT_2F {
+                        x: signed_pixels_to_dip(offset.x, dpi),
+                        y: signed_pixels_to_dip(offset.y, dpi),
+                    },
+                    &label_layout,
+                    &dc.CreateSolidColorBrush(&D2D1_COLOR_F_WHITE, None)?,
+                    D2D1_DRAW_TEXT_OPTIONS_NONE,
+                );
+            }
+
+            Ok::<_, windows_core::Error>(())
+        })
+        .unwrap();
+
+        (label_surface, label_metrics.width, label_metrics.height)
+    }
+
+    pub fn new(init: &mut ViewInitContext, icon_path: &str, label: &str) -> (Self, f32) {
+        let icon_path_w = icon_path
+            .encode_utf16()
+            .chain(core::iter::once(0))
+            .collect::<Vec<_>>();
+        let icon_svg_stream: IRandomAccessStream = unsafe {
+            CreateRandomAccessStreamOnFile(
+                PCWSTR::from_raw(icon_path_w.as_ptr()),
+                FileAccessMode::Read.0 as _,
+            )
+            .unwrap()
+        };
+        let icon_svg_stream: IStream =
+            unsafe { CreateStreamOverRandomAccessStream(&icon_svg_stream).unwrap() };
+        let icon_surface = init
+            .subsystem
+            .new_2d_drawing_surface(size_sq(init.dip_to_pixels(Self::ICON_SIZE)))
+            .unwrap();
+        draw_2d(&icon_surface, |dc, offset| {
+            let dc5: ID2D1DeviceContext5 = dc.cast()?;
+
+            unsafe {
+                dc.SetDpi(init.dpi, init.dpi);
+                dc.SetTransform(&Matrix3x2::translation(
+                    init.signed_pixels_to_dip(offset.x),
+                    init.signed_pixels_to_dip(offset.y),
+                ));
+            }
+
+            let svg = unsafe {
+                dc5.CreateSvgDocument(
+                    &icon_svg_stream,
+                    D2D_SIZE_F {
+                        width: pixels_to_dip(20, init.dpi),
+                        height: pixels_to_dip(20, init.dpi),
+                    },
+                )?
+            };
+
+            unsafe {
+                dc.Clear(None);
+                dc5.DrawSvgDocument(&svg);
+            }
+
+            Ok::<_, windows_core::Error>(())
+        })
+        .unwrap();
+
+        let (label_surface, label_width, label_height) =
+            Self::new_label_surface(init.subsystem, label, init.dpi);
+
+        let root = ContainerVisualParams::new()
+            .expand_width()
+            .height(init.dip_to_pixels(Self::HEIGHT))
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+        let bg = SpriteVisualParams::new(
+            &init
+                .subsystem
+                .compositor
+                .CreateColorBrushWithColor(ui_color_from_websafe_hex_rgb(0xfff))
+                .unwrap(),
+        )
+        .expand()
+        .opacity(0.0)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+        let icon = SpriteVisualParams::new(
+            &CompositionSurfaceBrushParams::new(&icon_surface)
+                .instantiate(&init.subsystem.compositor)
+                .unwrap(),
+        )
+        .size_sq(init.dip_to_pixels(Self::ICON_SIZE))
+        .anchor_point(Vector2 { X: 0.0, Y: 0.5 })
+        .left(init.dip_to_pixels(Self::ICON_LEFT_OFFSET))
+        .relative_vertical_offset_adjustment(0.5)
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+        let label = SpriteVisualParams::new(
+            &CompositionSurfaceBrushParams::new(&label_surface)
+                .instantiate(&init.subsystem.compositor)
+                .unwrap(),
+        )
+        .size(Vector2 {
+            X: init.dip_to_pixels(label_width),
+            Y: init.dip_to_pixels(label_height),
+        })
+        .anchor_point(Vector2 { X: 0.0, Y: 0.5 })
+        .relative_vertical_offset_adjustment(0.5)
+        .left(init.dip_to_pixels(Self::ICON_LEFT_OFFSET + Self::ICON_SIZE + Self::ICON_LABEL_GAP))
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+
+        let c = root.Children().unwrap();
+        c.InsertAtTop(&bg).unwrap();
+        c.InsertAtTop(&icon).unwrap();
+        c.InsertAtTop(&label).unwrap();
+
+        let bg_imp_animations = init
+            .subsystem
+            .compositor
+            .CreateImplicitAnimationCollection()
+            .unwrap();
+        bg_imp_animations
+            .Insert(
+                h!("Opacity"),
+                &SimpleImplicitAnimationParams::new(
+                    &init
+                        .subsystem
+                        .compositor
+                        .CreateLinearEasingFunction()
+                        .unwrap(),
+                    h!("Opacity"),
+                    timespan_ms(100),
+                )
+                .instantiate_scalar(&init.subsystem.compositor)
+                .unwrap(),
+            )
+            .unwrap();
+        bg.SetImplicitAnimations(&bg_imp_animations).unwrap();
+
+        let ht_root = init.ht.borrow_mut().alloc(HitTestTreeData {
+            left: 0.0,
+            top: 0.0,
+            left_adjustment_factor: 0.0,
+            top_adjustment_factor: 0.0,
+            width: 0.0,
+            height: Self::HEIGHT,
+            width_adjustment_factor: 1.0,
+            height_adjustment_factor: 0.0,
+            parent: None,
+            children: Vec::new(),
+            focusable: true,
+            action_handler: None,
+        });
+
+        (
+            Self {
+                root,
+                bg,
+                label,
+                ht_root,
+                dpi: Cell::new(init.dpi),
+                hovering: Cell::new(false),
+                active: Cell::new(false),
+                focused: Cell::new(false),
+            },
+            Self::ICON_LEFT_OFFSET
+                + Self::ICON_SIZE
+                + Self::ICON_LABEL_GAP
+                + label_width
+                + Self::ICON_LEFT_OFFSET,
+        )
+    }
+
+    /// 設定値を表示しているラベルを描き直す（メニューの幅は変えないので、作るときに一番長いものを渡しておくこと）
+    pub fn set_label(&self, label: &str, subsystem: &Subsystem) {
+        let dpi = self.dpi.get();
+        let (label_surface, label_width, label_height) =
+            Self::new_label_surface(subsystem, label, dpi);
+
+        self.label
+            .SetBrush(
+                &CompositionSurfaceBrushParams::new(&label_surface)
+                    .instantiate(&subsystem.compositor)
+                    .unwrap(),
+            )
+            .unwrap();
+        self.label
+            .SetSize(Vector2 {
+                X: dip_to_pixels(label_width, dpi),
+                Y: dip_to_pixels(label_height, dpi),
+            })
+            .unwrap();
+    }
+
+    pub fn mount(
+        &self,
+        children: &VisualCollection,
+        ht: &mut AppHitTestTreeManager,
+        ht_parent: HitTestTreeRef,
+    ) {
+        children.InsertAtTop(&self.root).unwrap();
+        ht.add_child(ht_parent, self.ht_root);
+    }
+
+    pub fn locate(&self, top: f32, ht: &mut AppHitTestTreeManager) {
+        let dpi = self.dpi.get();
+
+        self.root
+            .SetOffset(Vector3 {
+                X: 0.0,
+                Y: dip_to_pixels(top, dpi),
+                Z: 0.0,
+            })
+            .unwrap();
+        ht.get_mut(self.ht_root).top = top;
+    }
+
+    fn update_bg_opacity(&self) {
+        match (self.hovering.get() || self.focused.get(), self.active.get()) {
+            (false, _) => self.bg.SetOpacity(0.0).unwrap(),
+            (true, false) => self.bg.SetOpacity(0.125).unwrap(),
+            (true, true) => self.bg.SetOpacity(0.25).unwrap(),
+        }
+    }
+
+    pub fn on_hover(&self) {
+        self.hovering.set(true);
+        self.update_bg_opacity();
+    }
+
+    pub fn on_hover_leave(&self) {
+        self.hovering.set(false);
+        // 離れた際はactiveも一緒に落とす
+        self.active.set(false);
+        self.update_bg_opacity();
+    }
+
+    pub fn on_press(&self) {
+        self.active.set(true);
+        self.update_bg_opacity();
+    }
+
+    pub fn on_release(&self) {
+        self.active.set(false);
+        self.update_bg_opacity();
+    }
+
+    pub fn on_focus_visible(&self) {
+        self.focused.set(true);
+        self.update_bg_opacity();
+    }
+
+    pub fn on_blur(&self) {
+        self.focused.set(false);
+        self.update_bg_opacity();
+    }
+}
+
+struct AppMenuBaseView {
+    root: ContainerVisual,
+    window_root: ContainerVisual,
+    composition_params: CompositionPropertySet,
+    show_animation: ScalarKeyFrameAnimation,
+    hide_animation: ScalarKeyFrameAnimation,
+    ht_root: HitTestTreeRef,
+    ht_window_root: HitTestTreeRef,
+    window_size: Cell<Size>,
+    top_offset: Cell<f32>,
+    dpi: Cell<f32>,
+}
+impl AppMenuBaseView {
+    const CORNER_RADIUS: f32 = 8.0;
+    const BEAK_SIZE: f32 = 8.0;
+    const BEAK_LEFT_OFFSET: f32 = 8.0;
+    const FRAME_BASE_COLOR: windows::UI::Color =
+        ui_color_from_websafe_hex_rgb_with_alpha(0xfff, 64);
+    const MASK_COLOR: windows::UI::Color = ui_color_from_websafe_hex_rgb_with_alpha(0x000, 128);
+
+    pub fn new(init: &mut ViewInitContext, top_offset: f32) -> Self {
+        let beak_path = unsafe { init.subsystem.d2d1_factory.CreatePathGeometry().unwrap() };
+        let beak_path_sink = unsafe { beak_path.Open().unwrap() };
+        unsafe {
+            beak_path_sink.BeginFigure(
+                D2D_POINT_2F {
+                    x: 0.0,
+                    y: Self::BEAK_SIZE,
+                },
+                D2D1_FIGURE_BEGIN_FILLED,
+            );
+            beak_path_sink.AddLines(&[
+                D2D_POINT_2F {
+                    x: Self::BEAK_SIZE,
+                    y: 0.0,
+                },
+                D2D_POINT_2F {
+                    x: Self::BEAK_SIZE * 2.0,
+                    y: Self::BEAK_SIZE,
+                },
+            ]);
+            beak_path_sink.EndFigure(D2D1_FIGURE_END_CLOSED);
+            beak_path_sink.Close().unwrap();
+            drop(beak_path_sink);
+        }
+
+        let beak_mask_surface = init
+            .subsystem
+            .new_2d_mask_surface(Size {
+                Width: init.dip_to_pixels(Self::BEAK_SIZE * 2.0),
+                Height: init.dip_to_pixels(Self::BEAK_SIZE),
+            })
+            .unwrap();
+        draw_2d(&beak_mask_surface, |dc, offset| {
+            unsafe {
+                dc.SetDpi(init.dpi, init.dpi);
+                dc.SetTransform(&Matrix3x2::translation(
+                    init.signed_pixels_to_dip(offset.x),
+                    init.signed_pixels_to_dip(offset.y),
+                ));
+            }
+
+            unsafe {
+                dc.Clear(None);
+                dc.FillGeometry(
+                    &beak_path,
+                    &dc.CreateSolidColorBrush(&D2D1_COLOR_F_WHITE, None)?,
+                    None,
+                );
+            }
+
+            Ok::<_, windows_core::Error>(())
+        })
+        .unwrap();
+
+        let window_base_brush = create_instant_effect_brush(
+            &init.subsystem,
+            &CompositeEffectParams::new(&[
+                GaussianBlurEffectParams::new(
+                    &CompositionEffectSourceParameter::Create(h!("source")).unwrap(),
+                )
+                .blur_amount_px(9.0)
+                .instantiate()
+                .unwrap()
+                .cast()
+                .unwrap(),
+                ColorSourceEffectParams {
+                    color: Some(Self::FRAME_BASE_COLOR),
+                }
+                .instantiate()
+                .unwrap()
+                .cast()
+                .unwrap(),
+            ])
+            .instantiate()
+            .unwrap(),
+            &[(
+                h!("source"),
+                init.subsystem
+                    .compositor
+                    .CreateBackdropBrush()
+                    .unwrap()
+                    .cast()
+                    .unwrap(),
+            )],
+        )
+        .unwrap();
+
+        let root = ContainerVisualParams::new()
+            .expand()
+            .top(init.dip_to_pixels(top_offset))
+            .height(init.dip_to_pixels(-top_offset))
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+        let mask = SpriteVisualParams::new(
+            &init
+                .subsystem
+                .compositor
+                .CreateColorBrushWithColor(Self::MASK_COLOR)
+                .unwrap(),
+        )
+        .expand()
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+        let window_root = ContainerVisualParams::new()
+            .size_sq(init.dip_to_pixels(128.0))
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+        let beak = SpriteVisualParams::new(
+            &CompositionMaskBrushParams {
+                source: &window_base_brush,
+                mask: &CompositionSurfaceBrushParams::new(&beak_mask_surface)
+                    .instantiate(&init.subsystem.compositor)
+                    .unwrap(),
+            }
+            .instantiate(&init.subsystem.compositor)
+            .unwrap(),
+        )
+        .size(Vector2 {
+            X: init.dip_to_pixels(Self::BEAK_SIZE * 2.0),
+            Y: init.dip_to_pixels(Self::BEAK_SIZE),
+        })
+        .offset_xy(Vector2 {
+            X: init.dip_to_pixels(Self::BEAK_LEFT_OFFSET),
+            Y: init.dip_to_pixels(-Self::BEAK_SIZE),
+        })
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+        let window_frame = SpriteVisualParams::new(
+            &CompositionMaskBrushParams {
+                source: &window_base_brush,
+                mask: &CompositionNineGridBrushParams::new(
+                    &CompositionSurfaceBrushParams::new(
+                        &init
+                            .subsystem
+                            .rounded_rect_mask_surface(init.dpi, Self::CORNER_RADIUS)
+                            .unwrap(),
+                    )
+                    .stretch(CompositionStretch::Fill)
+                    .instantiate(&init.subsystem.compositor)
+                    .unwrap(),
+                )
+                .insets(init.dip_to_pixels(Self::CORNER_RADIUS))
+                .instantiate(&init.subsystem.compositor)
+                .unwrap(),
+            }
+            .instantiate(&init.subsystem.compositor)
+            .unwrap(),
+        )
+        .expand()
+        .instantiate(&init.subsystem.compositor)
+        .unwrap();
+
+        window_root.Children().unwrap().InsertAtTop(&beak).unwrap();
+        window_root
+            .Children()
+            .unwrap()
+            .InsertAtTop(&window_frame)
+            .unwrap();
+
+        root.Children().unwrap().InsertAtTop(&mask).unwrap();
+        root.Children().unwrap().InsertAtTop(&window_root).unwrap();
+
+        let composition_params = init.subsystem.compositor.CreatePropertySet().unwrap();
+        composition_params
+            .InsertScalar(h!("VisibleRate"), 0.0)
+            .unwrap();
+        composition_params
+            .InsertVector2(h!("WindowOffset"), Vector2 { X: 0.0, Y: 0.0 })
+            .unwrap();
+        composition_params
+            .InsertScalar(h!("DPI"), init.dpi)
+            .unwrap();
+
+        let opacity_expression = init
+            .subsystem
+            .compositor
+            .CreateExpressionAnimationWithExpression(h!("cp.VisibleRate"))
+            .unwrap();
+        opacity_expression
+            .SetExpressionReferenceParameter(h!("cp"), &composition_params)
+            .unwrap();
+        opacity_expression.SetTarget(h!("Opacity")).unwrap();
+        mask.StartAnimation(h!("Opacity"), &opacity_expression)
+            .unwrap();
+        window_root
+            .StartAnimation(h!("Opacity"), &opacity_expression)
+            .unwrap();
+
+        let window_offset_expression = init
+            .subsystem
+            .compositor
+            .CreateExpressionAnimationWithExpression(h!(
+                "Vector3(cp.WindowOffset.X, cp.WindowOffset.Y - (16.0 * (1.0 - cp.VisibleRate) * cp.DPI / 96.0), 0.0)"
+            ))
+            .unwrap();
+        window_offset_expression
+            .SetExpressionReferenceParameter(h!("cp"), &composition_params)
+            .unwrap();
+        window_offset_expression.SetTarget(h!("Offset")).unwrap();
+        window_root
+            .StartAnimation(h!("Offset"), &window_offset_expression)
+            .unwrap();
+
+        let easing = init
+            .subsystem
+            .compositor
+            .CreateCubicBezierEasingFunction(
+                Vector2 { X: 0.0, Y: 0.0 },
+                Vector2 { X: 0.25, Y: 1.0 },
+            )
+            .unwrap();
+        let show_animation = SimpleScalarAnimationParams::new(0.0, 1.0, &easing)
+            .duration(timespan_ms(200))
+            .target(h!("VisibleRate"))
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+        let hide_animation = SimpleScalarAnimationParams::new(1.0, 0.0, &easing)
+            .duration(timespan_ms(200))
+            .target(h!("VisibleRate"))
+            .instantiate(&init.subsystem.compositor)
+            .unwrap();
+
+        let ht_root = init.ht.borrow_mut().alloc(HitTestTreeData {
+            left: 0.0,
+            top: top_offset,
+            left_adjustment_factor: 0.0,
+            top_adjustment_factor: 0.0,
+            width: 0.0,
+            height: -top_offset,
+            width_adjustment_factor: 1.0,
+            height_adjustment_factor: 1.0,
+            parent: None,
+            children: Vec::new(),
+            focusable: false,
+            action_handler: None,
+        });
+        let ht_window_root = init.ht.borrow_mut().alloc(HitTestTreeData {
+            left: 0.0,
+            top: 0.0,
+            left_adjustment_factor: 0.0,
+            top_adjustment_factor: 0.0,
+            width: 128.0,
+            height: 128.0,
+            width_adjustment_factor: 0.0,
+            height_adjustment_factor: 0.0,
+            parent: None,
+            children: Vec::new(),
+            focusable: false,
+            action_handler: None,
+        });
+        init.ht.borrow_mut().add_child(ht_root, ht_window_root);
+
+        Self {
+            root,
+            window_root,
+            composition_params,
+            show_animation,
+            hide_animation,
+            ht_root,
+            ht_window_root,
+            window_size: Cell::new(Size {
+                Width: 128.0,
+                Height: 128.0,
+            }),
+            top_offset: Cell::new(top_offset),
+            dpi: Cell::new(init.dpi),
+        }
+    }
+
+    pub fn mount(
+        &self,
+        children: &VisualCollection,
+        ht: &mut AppHitTestTreeManager,
+        ht_parent: HitTestTreeRef,
+    ) {
+        children.InsertAtTop(&self.root).unwrap();
+        ht.add_child(ht_parent, self.ht_root);
+    }
+
+    pub fn set_window_offset_by_beak_peak(
+        &self,
+        peak_x: f32,
+        peak_y: f32,
+        ht: &mut AppHitTestTreeManager,
+    ) {
+        let dpi = self.dpi.get();
+        let top_offset = self.top_offset.get();
+
+        self.composition_params
+            .InsertVector2(
+                h!("WindowOffset"),
+                Vector2 {
+                    X: dip_to_pixels(peak_x - Self::BEAK_LEFT_OFFSET - Self::BEAK_SIZE, dpi),
+                    Y: dip_to_pixels(peak_y + Self::BEAK_SIZE - top_offset, dpi),
+                },
+            )
+            .unwrap();
+        ht.get_mut(self.ht_window_root).left = peak_x - Self::BEAK_LEFT_OFFSET - Self::BEAK_SIZE;
+        ht.get_mut(self.ht_window_root).top = peak_y + Self::BEAK_SIZE - top_offset;
+    }
+
+    pub fn resize_window(&self, w: f32, h: f32, ht: &mut AppHitTestTreeManager) {
+        let dpi = self.dpi.get();
+
+        let mut window_size = self.window_size.get();
+        window_size.Width = w;
+        window_size.Height = h;
+
+        self.window_root
+            .SetSize(Vector2 {
+                X: dip_to_pixels(window_size.Width, dpi),
+                Y: dip_to_pixels(window_size.Height, dpi),
+            })
+            .unwrap();
+        ht.get_mut(self.ht_window_root).width = w;
+        ht.get_mut(self.ht_window_root).height = h;
+
+        self.window_size.set(window_size);
+    }
+
+    pub fn show(&self, immediate: bool) {
+        if immediate {
+            self.composition_params
+                .InsertScalar(h!("VisibleRate"), 1.0)
+                .unwrap();
+        } else {
+            self.composition_params
+                .StartAnimation(h!("VisibleRate"), &self.show_animation)
+                .unwrap();
+        }
+    }
+
+    pub fn hide(&self, immediate: bool) {
+        if immediate {
+            self.composition_params
+                .InsertScalar(h!("VisibleRate"), 0.0)
+                .unwrap();
+        } else {
+            self.composition_params
+                .StartAnimation(h!("VisibleRate"), &self.hide_animation)
+                .unwrap();
+        }
+    }
+}
+
+#[implement(IVector<T>)]
+pub struct VectorWrapper<T>(Vec<T>)
+where
+    T: windows_core::RuntimeType + 'static;
+impl<T> IIterable_Impl<T> for VectorWrapper_Impl<T>
+where
+    T: windows_core::RuntimeType + 'static,
+{
+    fn First(&self) -> windows_core::Result<windows_collections::IIterator<T>> {
+        unimplemented!("IIterable::First")
+    }
+}
+impl<T> IVector_Impl<T> for VectorWrapper_Impl<T>
+where
+    T: windows_core::RuntimeType + 'static,
+{
+    fn Append(&self, value: windows_core::Ref<'_, T>) -> windows_core::Result<()> {
+        unimplemented!("IVector::Append");
+    }
+
+    fn Clear(&self) -> windows_core::Result<()> {
+        unimplemented!("IVector::Clear");
+    }
+
+    fn GetAt(&self, index: u32) -> windows_core::Result<T> {
+        Ok(self.0[index as usize].clone())
+    }
+
+    fn GetMany(
+        &self,
+        startIndex: u32,
+        items: &mut [<T as windows_core::Type<T>>::Default],
+    ) -> windows_core::Result<u32> {
+        unimplemented!("IVector::GetMany");
+    }
+
+    fn GetView(&self) -> windows_core::Result<windows_collections::IVectorView<T>> {
+        unimplemented!("IVector::GetView")
+    }
+
+    fn IndexOf(
+        &self,
+        value: windows_core::Ref<'_, T>,
+        index: &mut u32,
+    ) -> windows_core::Result<bool> {
+        unimplemented!("IVector::IndexOf")
+    }
+
+    fn InsertAt(&self, index: u32, value: windows_core::Ref<'_, T>) -> windows_core::Result<()> {
+        unimplemented!("IVector::InsertAt")
+    }
+
+    fn RemoveAt(&self, index: u32) -> windows_core::Result<()> {
+        unimplemented!("RemoveAt");
+    }
+
+    fn RemoveAtEnd(&self) -> windows_core::Result<()> {
+        unimplemented!("IVector::RemoveAtEnd");
+    }
+
+    fn ReplaceAll(
+        &self,
+        items: &[<T as windows_core::Type<T>>::Default],
+    ) -> windows_core::Result<()> {
+        unimplemented!("IVector::ReplaceAll")
+    }
+
+    fn SetAt(&self, index: u32, value: windows_core::Ref<'_, T>) -> windows_core::Result<()> {
+        unimplemented!("IVector::SetAt")
+    }
+
+    fn Size(&self) -> windows_core::Result<u32> {
+        Ok(self.0.len() as _)
+    }
+}
+
+struct AppMenuHitTestActionHandler {
+    base: Rc<AppMenuBaseView>,
+    entries: Rc<Vec<AppMenuEntryView>>,
+    view_worker_enqueue_access: ViewWorkerEnqueueWeakAccess,
+    background_worker_enqueue_access: BackgroundWorkerEnqueueWeakAccess,
+}
+impl AppMenuHitTestActionHandler {
+    /// メニュー項目の操作を実行する（項目以外ならfalse）
+    fn activate_entry(&self, sender: HitTestTreeRef, context: &mut AppState, hwnd: HWND) -> bool {
+        if sender == self.entries[0].ht_root {
+            let picker = FileOpenPicker::new().unwrap();
+            unsafe {
+                picker
+                    .cast::<IInitializeWithWindow>()
+                    .unwrap()
+                    .Initialize(hwnd)
+                    .unwrap();
+            }
+            picker.FileTypeFilter().unwrap().Append(h!(".psa")).unwrap();
+            picker
+                .PickSingleFileAsync()
+                .unwrap()
+                .SetCompleted(&AsyncOperationCompletedHandler::new({
+                    let view_worker_enqueue_access = self.view_worker_enqueue_access.clone();
+                    let background_worker_enqueue_access =
+                        self.background_worker_enqueue_access.clone();
+
+                    move |op, status| match status {
+                        AsyncStatus::Started => unreachable!(),
+                        AsyncStatus::Error => {
+                            panic!("async op error: {}", op.unwrap().ErrorCode().unwrap());
+                        }
+                        AsyncStatus::Canceled => {
+                            tracing::warn!("operation was cancelled");
+                            Ok(())
+                        }
+                        AsyncStatus::Completed => {
+                            let res: StorageFile = match op.unwrap().get() {
+                                Ok(x) => x,
+                                Err(e) if e.code() == windows::Win32::Foundation::S_OK => {
+                                    // successfully cancelled
+                                    return Ok(());
+                                }
+                                Err(e) => panic!("async op error: {e:?}"),
+                            };
+
+                            tracing::info!("File Save: {}", res.Path().unwrap());
+
+                            let Some(vwq) = view_worker_enqueue_access.upgrade() else {
+                                // app teardown-ed
+                                return Ok(());
+                            };
+
+                            vwq.enqueue({
+                                let path = res.Path().unwrap();
+
+                                let view_worker_enqueue_access = view_worker_enqueue_access.clone();
+                                let background_worker_enqueue_access =
+                                    background_worker_enqueue_access.clone();
+
+                                move |app_state| {
+                                    let request = app_state.load(&path.to_os_string()).unwrap();
+                                    app_state.toggle_menu();
+
+                                    let Some(background_worker_enqueue_access) =
+                                        background_worker_enqueue_access.upgrade()
+                                    else {
+                                        // app teardown-ed
+                                        return;
+                                    };
+                                    // Note: ソース画像のヘッダを読むのでUIスレッドでは行わない
+                                    background_worker_enqueue_access.enqueue(
+                                        BackgroundWork::job("Checking source sizes", move |ctx| {
+                                            let mismatches = request.check();
+                                            if ctx.is_cancelled() {
+                                                // 別のファイルを開いたので捨てる
+                                                return Ok(());
+                                            }
+
+                                            let Some(vwq) = view_worker_enqueue_access.upgrade()
+                                            else {
+                                                // app teardown-ed
+                                                return Ok(());
+                                            };
+                                            vwq.enqueue(move |app_state| {
+                                                app_state.apply_source_size_check(
+                                                    request.generation(),
+                                                    mismatches,
+                                                );
+                                            });
+
+                                            Ok(())
+                                        })
+                                        .with_cancellation_token(
+                                            app_state.document_cancellation_token().clone(),
+                                        ),
+                                    );
+                                }
+                            });
+
+                            Ok(())
+                        }
+                        _ => unreachable!(),
+                    }
+                }))
+                .unwrap();
+
+            return true;
+        }
+
+        if sender == self.entries[1].ht_root {
+            let picker = FileSavePicker::new().unwrap();
+            unsafe {
+                picker
+                    .cast::<IInitializeWithWindow>()
+                    .unwrap()
+                    .Initialize(hwnd)
+                    .unwrap();
+            }
+            picker
+                .FileTypeChoices()
+                .unwrap()
+                .Insert(
+                    h!("Peridot Sprite Atlas asset"),
+                    &IVector::from(VectorWrapper(vec![HSTRING::from(".psa")])),
+                )
+                .unwrap();
+            let complete_handler = AsyncOperationCompletedHandler::new({
+                let view_worker_enqueue_access = self.view_worker_enqueue_access.clone();
+
+                move |op, status| match status {
+                    AsyncStatus::Started => unreachable!(),
+                    AsyncStatus::Error => {
+                        panic!("async op error: {}", op.unwrap().ErrorCode().unwrap());
+                    }
+                    AsyncStatus::Canceled => {
+                        tracing::warn!("operation was cancelled");
+                        Ok(())
+                    }
+                    AsyncStatus::Completed => {
+                        let res: StorageFile = match op.unwrap().get() {
+                            Ok(x) => x,
+                            Err(e) if e.code() == windows::Win32::Foundation::S_OK => {
+                                // successfully cancelled
+                                return Ok(());
+                            }
+                            Err(e) => panic!("async op error: {e:?}"),
+                        };
+
+                        tracing::info!("File Save: {}", res.Path().unwrap());
+
+                        let Some(vwq) = view_worker_enqueue_access.upgrade() else {
+                            // app teardown-ed
+                            return Ok(());
+                        };
+
+                        vwq.enqueue({
+                            let path = res.Path().unwrap();
+
+                            move |app_state| {
+                                tracing::info!({ %path }, "File Save(ViewThread)");
+
+                                app_state.save(&path.to_os_string()).unwrap();
+                                app_state.toggle_menu();
+                            }
+                        });
+
+                        Ok(())
+                    }
+                    _ => unreachable!(),
+                }
+            });
+            let op = picker.PickSaveFileAsync().unwrap();
+            op.SetCompleted(&complete_handler).unwrap();
+
+            return true;
+        }
+
+        if sender == self.entries[3].ht_root {
+            let Some(background_worker_enqueue_access) =
+                self.background_worker_enqueue_access.upgrade()
+            else {
+                // app teardown-ed
+                return true;
+            };
+
+            let request = context.auto_pack_request();
+            let view_worker_enqueue_access = self.view_worker_enqueue_access.clone();
+            background_worker_enqueue_access.enqueue(
+                BackgroundWork::job("Auto packing sprites", move |ctx| {
+                    let layout = request.pack(|p| ctx.report_progress(p))?;
+                    if ctx.is_cancelled() {
+                        // 別のファイルを開いたので捨てる
+                        return Ok(());
+                    }
+
+                    let Some(vwq) = view_worker_enqueue_access.upgrade() else {
+                        // app teardown-ed
+                        return Ok(());
+                    };
+                    vwq.enqueue(move |app_state| {
+                        if !app_state.apply_auto_pack(&request, layout) {
+                            tracing::warn!("sprites changed while packing, auto packing discarded");
+                        }
+                    });
+
+                    Ok(())
+                })
+                .with_cancellation_token(context.document_cancellation_token().clone()),
+            );
+            context.toggle_menu();
+
+            return true;
+        }
+
+        if sender == self.entries[5].ht_root {
+            if let Err(e) = context.commit_sprite_candidates() {
+                tracing::warn!(reason = ?e, "sprite candidates do not fit in the atlas");
+            }
+            context.toggle_menu();
+
+            return true;
+        }
 
-        let mut window_size = self.window_size.get();
-        window_size.Width = w;
-        window_size.Height = h;
+        if sender == self.entries[6].ht_root {
+            context.discard_sprite_candidates();
+            context.toggle_menu();
 
-        self.window_root
-            .SetSize(Vector2 {
-                X: dip_to_pixels(window_size.Width, dpi),
-                Y: dip_to_pixels(window_size.Height, dpi),
-            })
-            .unwrap();
-        ht.get_mut(self.ht_window_root).width = w;
-        ht.get_mut(self.ht_window_root).height = h;
+            return true;
+        }
 
-        self.window_size.set(window_size);
-    }
+        if sender == self.entries[7].ht_root {
+            // Note: 続けて切り替えられるようにメニューは閉じない
+            context.cycle_selected_sprites_pivot_preset();
 
-    pub fn show(&self, immediate: bool) {
-        if immediate {
-            self.composition_params
-                .InsertScalar(h!("VisibleRate"), 1.0)
-                .unwrap();
-        } else {
-            self.composition_params
-                .StartAnimation(h!("VisibleRate"), &self.show_animation)
-                .unwrap();
+            return true;
         }
-    }
 
-    pub fn hide(&self, immediate: bool) {
-        if immediate {
-            self.composition_params
-                .InsertScalar(h!("VisibleRate"), 0.0)
-                .unwrap();
-        } else {
-            self.composition_params
-                .StartAnimation(h!("VisibleRate"), &self.hide_animation)
-                .unwrap();
+        if sender == self.entries[8].ht_root {
+            context.toggle_alpha_mode();
+            tracing::info!({ alpha_mode = ?context.alpha_mode() }, "alpha mode changed");
+            context.toggle_menu();
+
+            return true;
         }
-    }
-}
 
-#[implement(IVector<T>)]
-pub struct VectorWrapper<T>(Vec<T>)
-where
-    T: windows_core::RuntimeType + 'static;
-impl<T> IIterable_Impl<T> for VectorWrapper_Impl<T>
-where
-    T: windows_core::RuntimeType + 'static,
-{
-    fn First(&self) -> windows_core::Result<windows_collections::IIterator<T>> {
-        unimplemented!("IIterable::First")
-    }
-}
-impl<T> IVector_Impl<T> for VectorWrapper_Impl<T>
-where
-    T: windows_core::RuntimeType + 'static,
-{
-    fn Append(&self, value: windows_core::Ref<'_, T>) -> windows_core::Result<()> {
-        unimplemented!("IVector::Append");
-    }
+        if sender == self.entries[9].ht_root {
+            // Note: 続けて切り替えられるようにメニューは閉じない
+            context.cycle_mip_levels();
+            tracing::info!({ mip_levels = context.mip_levels() }, "mip levels changed");
 
-    fn Clear(&self) -> windows_core::Result<()> {
-        unimplemented!("IVector::Clear");
-    }
+            return true;
+        }
 
-    fn GetAt(&self, index: u32) -> windows_core::Result<T> {
-        Ok(self.0[index as usize].clone())
-    }
+        if sender == self.entries[10].ht_root {
+            // Note: 続けて切り替えられるようにメニューは閉じない
+            context.cycle_region_detect_alpha_threshold();
 
-    fn GetMany(
-        &self,
-        startIndex: u32,
-        items: &mut [<T as windows_core::Type<T>>::Default],
-    ) -> windows_core::Result<u32> {
-        unimplemented!("IVector::GetMany");
-    }
+            return true;
+        }
 
-    fn GetView(&self) -> windows_core::Result<windows_collections::IVectorView<T>> {
-        unimplemented!("IVector::GetView")
-    }
+        if sender == self.entries[11].ht_root {
+            // Note: 続けて切り替えられるようにメニューは閉じない
+            context.cycle_region_detect_merge_distance();
 
-    fn IndexOf(
-        &self,
-        value: windows_core::Ref<'_, T>,
-        index: &mut u32,
-    ) -> windows_core::Result<bool> {
-        unimplemented!("IVector::IndexOf")
-    }
+            return true;
+        }
 
-    fn InsertAt(&self, index: u32, value: windows_core::Ref<'_, T>) -> windows_core::Result<()> {
-        unimplemented!("IVector::InsertAt")
-    }
+        if sender == self.entries[12].ht_root {
+            // Note: 続けて切り替えられるようにメニューは閉じない
+            context.cycle_grid_slice_cell_size();
 
-    fn RemoveAt(&self, index: u32) -> windows_core::Result<()> {
-        unimplemented!("RemoveAt");
-    }
+            return true;
+        }
 
-    fn RemoveAtEnd(&self) -> windows_core::Result<()> {
-        unimplemented!("IVector::RemoveAtEnd");
-    }
+        if sender == self.entries[13].ht_root {
+            // Note: 続けて切り替えられるようにメニューは閉じない
+            context.cycle_grid_slice_margin();
 
-    fn ReplaceAll(
-        &self,
-        items: &[<T as windows_core::Type<T>>::Default],
-    ) -> windows_core::Result<()> {
-        unimplemented!("IVector::ReplaceAll")
-    }
+            return true;
+        }
 
-    fn SetAt(&self, index: u32, value: windows_core::Ref<'_, T>) -> windows_core::Result<()> {
-        unimplemented!("IVector::SetAt")
-    }
+        if sender == self.entries[14].ht_root {
+            // Note: 続けて切り替えられるようにメニューは閉じない
+            context.cycle_grid_slice_spacing();
 
-    fn Size(&self) -> windows_core::Result<u32> {
-        Ok(self.0.len() as _)
-    }
-}
+            return true;
+        }
 
-struct AppMenuHitTestActionHandler {
-    base: Rc<AppMenuBaseView>,
-    entries: Rc<Vec<AppMenuEntryView>>,
-    view_worker_enqueue_access: ViewWorkerEnqueueWeakAccess,
+        false
+    }
 }
 impl HitTestTreeActionHandler for AppMenuHitTestActionHandler {
     type Context = AppState;
@@ -3932,142 +6029,100 @@ impl HitTestTreeActionHandler for AppMenuHitTestActionHandler {
             return EventContinueControl::STOP_PROPAGATION;
         }
 
-        if sender == self.entries[0].ht_root {
-            let picker = FileOpenPicker::new().unwrap();
-            unsafe {
-                picker
-                    .cast::<IInitializeWithWindow>()
-                    .unwrap()
-                    .Initialize(args.hwnd)
-                    .unwrap();
-            }
-            picker.FileTypeFilter().unwrap().Append(h!(".psa")).unwrap();
-            picker
-                .PickSingleFileAsync()
-                .unwrap()
-                .SetCompleted(&AsyncOperationCompletedHandler::new({
-                    let view_worker_enqueue_access = self.view_worker_enqueue_access.clone();
-
-                    move |op, status| match status {
-                        AsyncStatus::Started => unreachable!(),
-                        AsyncStatus::Error => {
-                            panic!("async op error: {}", op.unwrap().ErrorCode().unwrap());
-                        }
-                        AsyncStatus::Canceled => {
-                            tracing::warn!("operation was cancelled");
-                            Ok(())
-                        }
-                        AsyncStatus::Completed => {
-                            let res: StorageFile = match op.unwrap().get() {
-                                Ok(x) => x,
-                                Err(e) if e.code() == windows::Win32::Foundation::S_OK => {
-                                    // successfully cancelled
-                                    return Ok(());
-                                }
-                                Err(e) => panic!("async op error: {e:?}"),
-                            };
-
-                            tracing::info!("File Save: {}", res.Path().unwrap());
-
-                            let Some(vwq) = view_worker_enqueue_access.upgrade() else {
-                                // app teardown-ed
-                                return Ok(());
-                            };
-
-                            vwq.enqueue({
-                                let path = res.Path().unwrap();
+        if self.activate_entry(sender, context, args.window.into()) {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
 
-                                move |app_state| {
-                                    app_state.load(&path.to_os_string()).unwrap();
-                                    app_state.toggle_menu();
-                                }
-                            });
+        if sender == self.base.ht_root {
+            context.toggle_menu();
+            return EventContinueControl::STOP_PROPAGATION;
+        }
 
-                            Ok(())
-                        }
-                        _ => unreachable!(),
-                    }
-                }))
-                .unwrap();
+        EventContinueControl::empty()
+    }
 
-            return EventContinueControl::STOP_PROPAGATION;
+    fn on_focus(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppState,
+        _ht: &mut AppHitTestTreeManager,
+        focus_visible: bool,
+    ) {
+        for x in self.entries.iter() {
+            if sender == x.ht_root {
+                // Note: クリックでのフォーカスはホバー表示で十分なので出さない
+                if focus_visible {
+                    x.on_focus_visible();
+                }
+                return;
+            }
         }
+    }
 
-        if sender == self.entries[1].ht_root {
-            let picker = FileSavePicker::new().unwrap();
-            unsafe {
-                picker
-                    .cast::<IInitializeWithWindow>()
-                    .unwrap()
-                    .Initialize(args.hwnd)
-                    .unwrap();
+    fn on_blur(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppState,
+        _ht: &mut AppHitTestTreeManager,
+    ) {
+        for x in self.entries.iter() {
+            if sender == x.ht_root {
+                x.on_blur();
+                return;
             }
-            picker
-                .FileTypeChoices()
-                .unwrap()
-                .Insert(
-                    h!("Peridot Sprite Atlas asset"),
-                    &IVector::from(VectorWrapper(vec![HSTRING::from(".psa")])),
-                )
-                .unwrap();
-            let complete_handler = AsyncOperationCompletedHandler::new({
-                let view_worker_enqueue_access = self.view_worker_enqueue_access.clone();
+        }
+    }
 
-                move |op, status| match status {
-                    AsyncStatus::Started => unreachable!(),
-                    AsyncStatus::Error => {
-                        panic!("async op error: {}", op.unwrap().ErrorCode().unwrap());
-                    }
-                    AsyncStatus::Canceled => {
-                        tracing::warn!("operation was cancelled");
-                        Ok(())
-                    }
-                    AsyncStatus::Completed => {
-                        let res: StorageFile = match op.unwrap().get() {
-                            Ok(x) => x,
-                            Err(e) if e.code() == windows::Win32::Foundation::S_OK => {
-                                // successfully cancelled
-                                return Ok(());
-                            }
-                            Err(e) => panic!("async op error: {e:?}"),
-                        };
+    fn on_key_down(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppState,
+        _ht: &mut AppHitTestTreeManager,
+        args: KeyActionArgs,
+    ) -> EventContinueControl {
+        if !context.is_visible_menu() || !self.entries.iter().any(|x| sender == x.ht_root) {
+            // 閉じたあとにフォーカスが残っていても反応しないようにする
+            return EventContinueControl::empty();
+        }
 
-                        tracing::info!("File Save: {}", res.Path().unwrap());
+        match args.key {
+            KeyCode::RETURN | KeyCode::SPACE => {
+                self.activate_entry(sender, context, args.window.into());
 
-                        let Some(vwq) = view_worker_enqueue_access.upgrade() else {
-                            // app teardown-ed
-                            return Ok(());
-                        };
+                EventContinueControl::STOP_PROPAGATION
+            }
+            KeyCode::ESCAPE => {
+                context.toggle_menu();
 
-                        vwq.enqueue({
-                            let path = res.Path().unwrap();
+                EventContinueControl::STOP_PROPAGATION | EventContinueControl::RELEASE_FOCUS
+            }
+            _ => EventContinueControl::empty(),
+        }
+    }
+}
 
-                            move |app_state| {
-                                tracing::info!({ %path }, "File Save(ViewThread)");
+fn mip_levels_label(mip_levels: u32) -> String {
+    format!("ミップマップのレベル数: {mip_levels}")
+}
 
-                                app_state.save(&path.to_os_string()).unwrap();
-                                app_state.toggle_menu();
-                            }
-                        });
+fn region_detect_alpha_threshold_label(alpha_threshold: u8) -> String {
+    format!("自動検出のアルファのしきい値: {alpha_threshold}")
+}
 
-                        Ok(())
-                    }
-                    _ => unreachable!(),
-                }
-            });
-            let op = picker.PickSaveFileAsync().unwrap();
-            op.SetCompleted(&complete_handler).unwrap();
+fn region_detect_merge_distance_label(merge_distance: u32) -> String {
+    format!("自動検出で領域をまとめる距離: {merge_distance}px")
+}
 
-            return EventContinueControl::STOP_PROPAGATION;
-        }
+fn grid_slice_cell_size_label(cell_size: u32) -> String {
+    format!("グリッドのセルの大きさ: {cell_size}px")
+}
 
-        if sender == self.base.ht_root {
-            context.toggle_menu();
-            return EventContinueControl::STOP_PROPAGATION;
-        }
+fn grid_slice_margin_label(margin: u32) -> String {
+    format!("グリッドの外周の余白: {margin}px")
+}
 
-        EventContinueControl::empty()
-    }
+fn grid_slice_spacing_label(spacing: u32) -> String {
+    format!("グリッドのセルの間隔: {spacing}px")
 }
 
 pub struct AppMenuPresenter {
@@ -4108,6 +6163,82 @@ impl AppMenuPresenter {
         );
         entries.push(e);
         max_width = max_width.max(w);
+        // TODO: 専用のアイコンを用意する
+        let (e, w) = AppMenuEntryView::new(
+            &mut init.for_view,
+            "./resources/category.svg",
+            "検出したスプライト候補を追加",
+        );
+        entries.push(e);
+        max_width = max_width.max(w);
+        let (e, w) = AppMenuEntryView::new(
+            &mut init.for_view,
+            "./resources/category.svg",
+            "検出したスプライト候補を破棄",
+        );
+        entries.push(e);
+        max_width = max_width.max(w);
+        let (e, w) = AppMenuEntryView::new(
+            &mut init.for_view,
+            "./resources/category.svg",
+            "ピボットのプリセットを切り替え",
+        );
+        entries.push(e);
+        max_width = max_width.max(w);
+        let (e, w) = AppMenuEntryView::new(
+            &mut init.for_view,
+            "./resources/category.svg",
+            "アルファの持ち方を切り替え（straight/premultiplied）",
+        );
+        entries.push(e);
+        max_width = max_width.max(w);
+        // Note: 幅は一番長くなる値で決めておき、実際の値はフィードバックで描き直す
+        let (e, w) = AppMenuEntryView::new(
+            &mut init.for_view,
+            "./resources/category.svg",
+            &mip_levels_label(peridot::MAX_MIP_LEVELS),
+        );
+        entries.push(e);
+        max_width = max_width.max(w);
+        let (e, w) = AppMenuEntryView::new(
+            &mut init.for_view,
+            "./resources/category.svg",
+            &region_detect_alpha_threshold_label(
+                *AppState::REGION_DETECT_ALPHA_THRESHOLDS.last().unwrap(),
+            ),
+        );
+        entries.push(e);
+        max_width = max_width.max(w);
+        let (e, w) = AppMenuEntryView::new(
+            &mut init.for_view,
+            "./resources/category.svg",
+            &region_detect_merge_distance_label(
+                *AppState::REGION_DETECT_MERGE_DISTANCES.last().unwrap(),
+            ),
+        );
+        entries.push(e);
+        max_width = max_width.max(w);
+        let (e, w) = AppMenuEntryView::new(
+            &mut init.for_view,
+            "./resources/category.svg",
+            &grid_slice_cell_size_label(*AppState::GRID_SLICE_CELL_SIZES.last().unwrap()),
+        );
+        entries.push(e);
+        max_width = max_width.max(w);
+        let (e, w) = AppMenuEntryView::new(
+            &mut init.for_view,
+            "./resources/category.svg",
+            &grid_slice_margin_label(*AppState::GRID_SLICE_GAPS.last().unwrap()),
+        );
+        entries.push(e);
+        max_width = max_width.max(w);
+        let (e, w) = AppMenuEntryView::new(
+            &mut init.for_view,
+            "./resources/category.svg",
+            &grid_slice_spacing_label(*AppState::GRID_SLICE_GAPS.last().unwrap()),
+        );
+        entries.push(e);
+        max_width = max_width.max(w);
 
         for (n, x) in entries.iter().enumerate() {
             x.mount(
@@ -4132,6 +6263,10 @@ impl AppMenuPresenter {
             base: base.clone(),
             entries: entries.clone(),
             view_worker_enqueue_access: init.view_worker_enqueue_access.clone(),
+            background_worker_enqueue_access: init
+                .for_view
+                .background_worker_enqueue_access
+                .downgrade(),
         });
         init.for_view
             .ht
@@ -4146,6 +6281,61 @@ impl AppMenuPresenter {
                 .action_handler = Some(Rc::downgrade(&ht_action_handler) as _);
         }
 
+        init.app_state
+            .borrow_mut()
+            .register_mip_levels_view_feedback({
+                let entries = Rc::downgrade(&entries);
+                let subsystem = init.for_view.subsystem.clone();
+
+                move |mip_levels| {
+                    let Some(entries) = entries.upgrade() else {
+                        // parent teardown-ed
+                        return;
+                    };
+
+                    entries[9].set_label(&mip_levels_label(mip_levels), &subsystem);
+                }
+            });
+        init.app_state
+            .borrow_mut()
+            .register_region_detect_params_view_feedback({
+                let entries = Rc::downgrade(&entries);
+                let subsystem = init.for_view.subsystem.clone();
+
+                move |params| {
+                    let Some(entries) = entries.upgrade() else {
+                        // parent teardown-ed
+                        return;
+                    };
+
+                    entries[10].set_label(
+                        &region_detect_alpha_threshold_label(params.alpha_threshold),
+                        &subsystem,
+                    );
+                    entries[11].set_label(
+                        &region_detect_merge_distance_label(params.merge_distance),
+                        &subsystem,
+                    );
+                }
+            });
+        init.app_state
+            .borrow_mut()
+            .register_grid_slice_params_view_feedback({
+                let entries = Rc::downgrade(&entries);
+                let subsystem = init.for_view.subsystem.clone();
+
+                move |params| {
+                    let Some(entries) = entries.upgrade() else {
+                        // parent teardown-ed
+                        return;
+                    };
+
+                    entries[12]
+                        .set_label(&grid_slice_cell_size_label(params.cell_width), &subsystem);
+                    entries[13].set_label(&grid_slice_margin_label(params.margin), &subsystem);
+                    entries[14].set_label(&grid_slice_spacing_label(params.spacing), &subsystem);
+                }
+            });
         init.app_state
             .borrow_mut()
             .register_visible_menu_view_feedback({
@@ -4188,139 +6378,6 @@ impl AppMenuPresenter {
     }
 }
 
-pub struct QuadTreeElementIndexIter<'a> {
-    qt: &'a QuadTree,
-    index: u64,
-    current_level: usize,
-    current_internal_iter: Option<std::collections::hash_set::Iter<'a, usize>>,
-}
-impl Iterator for QuadTreeElementIndexIter<'_> {
-    type Item = usize;
-
-    fn next(&mut self) -> Option<usize> {
-        if let Some(a) = self.current_internal_iter.as_mut() {
-            if let Some(&x) = a.next() {
-                return Some(x);
-            }
-
-            // 全部回りきった
-            self.current_internal_iter = None;
-            self.current_level += 1;
-        }
-
-        // 下層の要素を探す
-        while self.current_level < 32 {
-            let index = if self.current_level == 0 {
-                0
-            } else {
-                self.index >> (64 - self.current_level * 2)
-            };
-            let elements = &self
-                .qt
-                .element_index_for_region
-                .get(self.current_level)
-                .and_then(|xs| xs.get(index as usize));
-            if let Some(elements) = elements.filter(|x| !x.is_empty()) {
-                // この層には要素がある
-                self.current_internal_iter = Some(elements.iter());
-                return self.next();
-            }
-
-            self.current_level += 1;
-        }
-
-        // もうない
-        None
-    }
-}
-
-/// ビットを一つおきに分散させる
-/// 例: 0b11000110 => 0b01_01_00_00_00_01_01_00
-const fn interleave(bits: u64) -> u64 {
-    let bits = (bits | (bits << 32)) & 0xffff_ffff_ffff_ffff;
-    let bits = (bits | (bits << 16)) & 0x0000_ffff_0000_ffff;
-    let bits = (bits | (bits << 8)) & 0x00ff_00ff_00ff_00ff;
-    let bits = (bits | (bits << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
-    let bits = (bits | (bits << 2)) & 0x3333_3333_3333_3333;
-    let bits = (bits | (bits << 1)) & 0x5555_5555_5555_5555;
-
-    bits
-}
-
-// http://marupeke296.com/COL_2D_No8_QuadTree.html だいたいこれの実装
-pub struct QuadTree {
-    pub element_index_for_region: Vec<Vec<HashSet<usize>>>,
-}
-impl QuadTree {
-    pub fn new() -> Self {
-        Self {
-            element_index_for_region: Vec::new(),
-        }
-    }
-
-    pub fn bind(&mut self, level: usize, index: u64, n: usize) {
-        while self.element_index_for_region.len() <= level {
-            self.element_index_for_region.push(Vec::new());
-        }
-
-        while self.element_index_for_region[level].len() <= index as _ {
-            self.element_index_for_region[level].push(HashSet::new());
-        }
-
-        self.element_index_for_region[level][index as usize].insert(n);
-    }
-
-    pub const fn iter_possible_element_indices(
-        &self,
-        x_pixels: u32,
-        y_pixels: u32,
-    ) -> impl Iterator<Item = usize> {
-        QuadTreeElementIndexIter {
-            qt: self,
-            index: Self::compute_location_index(x_pixels, y_pixels),
-            current_level: 0,
-            current_internal_iter: None,
-        }
-    }
-
-    pub const fn compute_location_index(location_x_pixels: u32, location_y_pixels: u32) -> u64 {
-        // 一旦一律16(2^4)px角まで分割する
-        let (xv, yv) = (
-            (location_x_pixels >> 4) as u64,
-            (location_y_pixels >> 4) as u64,
-        );
-
-        // のちのシフト操作で情報が欠けないように検査いれる
-        assert!(xv.leading_zeros() >= 32, "too many divisions!");
-        assert!(yv.leading_zeros() >= 32, "too many divisions!");
-
-        interleave(xv) | (interleave(yv) << 1)
-    }
-
-    pub const fn rect_index_and_level(
-        left: u32,
-        top: u32,
-        right: u32,
-        bottom: u32,
-    ) -> (u64, usize) {
-        let lt_location = Self::compute_location_index(left, top);
-        let rb_location = Self::compute_location_index(right, bottom);
-        // xorをとるとズレているレベルの2bitが00にならないので、それでどの分割レベルで跨いでいないか（どのレベルの所属インデックスまでが一致しているか）を判定できるっぽい
-        let xor = lt_location ^ rb_location;
-
-        // 先頭の0のビット数を数えて、
-        // 0, 1...Lv0(root, 分割無し)
-        // 2, 3...Lv1(全体を4分割したうちのどこか)
-        // 4, 5...Lv2(全体を16分割したうちのどこか)
-        // ...となるように計算する
-        let level = (xor.leading_zeros() / 2) as usize;
-        // 符号なし整数なので右シフト後は上が0で埋まるはず
-        let index = lt_location >> (64 - level * 2);
-
-        (index, level)
-    }
-}
-
 enum DragState {
     None,
     Grid {
@@ -4338,14 +6395,43 @@ enum DragState {
         drag_start_client_x_pixels: f32,
         drag_start_client_y_pixels: f32,
     },
+    Pivot {
+        index: usize,
+    },
+}
+
+/// Ctrlを押しながらのクリックは選択の追加/解除にする
+#[inline]
+fn is_control_key_pressed() -> bool {
+    // Note: 最上位ビットが立っていれば押されている
+    unsafe { GetKeyState(VK_CONTROL.0 as _) < 0 }
+}
+
+/// Shiftを押しながらのピボット操作はピクセル中心に吸着させる
+#[inline]
+fn is_shift_key_pressed() -> bool {
+    unsafe { GetKeyState(VK_SHIFT.0 as _) < 0 }
+}
+
+fn current_modifier_keys() -> ModifierKeys {
+    let mut m = ModifierKeys::empty();
+    m.set(ModifierKeys::SHIFT, is_shift_key_pressed());
+    m.set(ModifierKeys::CONTROL, is_control_key_pressed());
+    m.set(ModifierKeys::ALT, unsafe {
+        GetKeyState(VK_MENU.0 as _) < 0
+    });
+
+    m
 }
 
 struct AppWindowHitTestTreeActionHandler {
     grid_view: Arc<AtlasBaseGridView>,
     sprite_atlas_border_view: Rc<SpriteAtlasBorderView>,
+    sprite_candidates_view: Rc<SpriteCandidatesView>,
+    sprite_filter_matches_view: Rc<SpriteFilterMatchesView>,
     selected_sprite_marker_view: Rc<CurrentSelectedSpriteMarkerView>,
+    pivot_marker_view: Rc<SpritePivotMarkerView>,
     qt: RefCell<QuadTree>,
-    sprite_rect_cached: RefCell<Vec<(u32, u32, u32, u32)>>,
     drag_data: RefCell<DragState>,
     dpi: Cell<f32>,
     ht_root: HitTestTreeRef,
@@ -4361,6 +6447,11 @@ impl HitTestTreeActionHandler for AppWindowHitTestTreeActionHandler {
         args: PointerActionArgs,
     ) -> EventContinueControl {
         if sender == self.ht_root {
+            if !matches!(*self.drag_data.borrow(), DragState::None) {
+                // 中ボタンでのドラッグ中
+                return EventContinueControl::STOP_PROPAGATION;
+            }
+
             let dpi = self.dpi.get();
             let (current_offset_x, current_offset_y) = *self.grid_view.offset_pixels.read();
 
@@ -4368,6 +6459,22 @@ impl HitTestTreeActionHandler for AppWindowHitTestTreeActionHandler {
                 dip_to_pixels(args.client_x, dpi) + current_offset_x,
                 dip_to_pixels(args.client_y, dpi) + current_offset_y,
             );
+            // ピボットマーカーは先頭の選択スプライトにだけ出ている
+            if let Some((index, x)) = context.selected_sprites_with_index().next() {
+                let (pivot_x, pivot_y) = (
+                    x.left as f32 + x.pivot_x * x.width as f32,
+                    x.top as f32 + x.pivot_y * x.height as f32,
+                );
+                let grab_radius = dip_to_pixels(SpritePivotMarkerView::GRAB_RADIUS, dpi);
+                if (pointing_x - pivot_x).powi(2) + (pointing_y - pivot_y).powi(2)
+                    <= grab_radius.powi(2)
+                {
+                    *self.drag_data.borrow_mut() = DragState::Pivot { index };
+
+                    return EventContinueControl::STOP_PROPAGATION
+                        | EventContinueControl::CAPTURE_ELEMENT;
+                }
+            }
             let sprite_drag_target_index =
                 context.selected_sprites_with_index().rev().find(|(_, x)| {
                     x.left as f32 <= pointing_x
@@ -4378,6 +6485,7 @@ impl HitTestTreeActionHandler for AppWindowHitTestTreeActionHandler {
             if let Some((sprite_drag_target_index, target_sprite_ref)) = sprite_drag_target_index {
                 // 選択中のスプライトの上で操作が開始された
                 self.selected_sprite_marker_view.hide();
+                self.pivot_marker_view.hide();
                 *self.drag_data.borrow_mut() = DragState::Sprite {
                     index: sprite_drag_target_index,
                     base_x_pixels: target_sprite_ref.left as f32,
@@ -4388,12 +6496,7 @@ impl HitTestTreeActionHandler for AppWindowHitTestTreeActionHandler {
                     drag_start_client_y_pixels: dip_to_pixels(args.client_y, dpi),
                 };
             } else {
-                *self.drag_data.borrow_mut() = DragState::Grid {
-                    base_x_pixels: current_offset_x,
-                    base_y_pixels: current_offset_y,
-                    drag_start_client_x_pixels: dip_to_pixels(args.client_x, dpi),
-                    drag_start_client_y_pixels: dip_to_pixels(args.client_y, dpi),
-                };
+                self.begin_grid_drag(&args);
             }
 
             return EventContinueControl::STOP_PROPAGATION | EventContinueControl::CAPTURE_ELEMENT;
@@ -4405,7 +6508,7 @@ impl HitTestTreeActionHandler for AppWindowHitTestTreeActionHandler {
     fn on_pointer_move(
         &self,
         sender: HitTestTreeRef,
-        _context: &mut AppState,
+        context: &mut AppState,
         _ht: &mut AppHitTestTreeManager,
         args: PointerActionArgs,
     ) -> EventContinueControl {
@@ -4427,8 +6530,14 @@ impl HitTestTreeActionHandler for AppWindowHitTestTreeActionHandler {
                         .set_offset(base_x_pixels + dx, base_y_pixels + dy);
                     self.sprite_atlas_border_view
                         .set_view_offset(base_x_pixels + dx, base_y_pixels + dy);
+                    self.sprite_candidates_view
+                        .set_view_offset(base_x_pixels + dx, base_y_pixels + dy);
+                    self.sprite_filter_matches_view
+                        .set_view_offset(base_x_pixels + dx, base_y_pixels + dy);
                     self.selected_sprite_marker_view
                         .set_view_offset(base_x_pixels + dx, base_y_pixels + dy);
+                    self.pivot_marker_view
+                        .set_view_offset(base_x_pixels + dx, base_y_pixels + dy);
 
                     return EventContinueControl::STOP_PROPAGATION;
                 }
@@ -4451,6 +6560,17 @@ impl HitTestTreeActionHandler for AppWindowHitTestTreeActionHandler {
                     );
                     self.grid_view.update_sprite_offset(index, sx as _, sy as _);
 
+                    return EventContinueControl::STOP_PROPAGATION;
+                }
+                &DragState::Pivot { index } => {
+                    // 確定するまではマーカーだけ動かす
+                    let (pivot_x, pivot_y) = self.pivot_from_client(context, index, &args);
+                    let s = &context.sprites()[index];
+                    self.pivot_marker_view.show_at(
+                        s.left as f32 + pivot_x * s.width as f32,
+                        s.top as f32 + pivot_y * s.height as f32,
+                    );
+
                     return EventContinueControl::STOP_PROPAGATION;
                 }
             }
@@ -4484,8 +6604,14 @@ impl HitTestTreeActionHandler for AppWindowHitTestTreeActionHandler {
                         .set_offset(base_x_pixels + dx, base_y_pixels + dy);
                     self.sprite_atlas_border_view
                         .set_view_offset(base_x_pixels + dx, base_y_pixels + dy);
+                    self.sprite_candidates_view
+                        .set_view_offset(base_x_pixels + dx, base_y_pixels + dy);
+                    self.sprite_filter_matches_view
+                        .set_view_offset(base_x_pixels + dx, base_y_pixels + dy);
                     self.selected_sprite_marker_view
                         .set_view_offset(base_x_pixels + dx, base_y_pixels + dy);
+                    self.pivot_marker_view
+                        .set_view_offset(base_x_pixels + dx, base_y_pixels + dy);
                 }
                 DragState::Sprite {
                     index,
@@ -4515,6 +6641,10 @@ impl HitTestTreeActionHandler for AppWindowHitTestTreeActionHandler {
                         base_height_pixels,
                     );
                 }
+                DragState::Pivot { index } => {
+                    let (pivot_x, pivot_y) = self.pivot_from_client(context, index, &args);
+                    context.set_sprite_pivot(index, pivot_x, pivot_y);
+                }
             }
 
             return EventContinueControl::STOP_PROPAGATION
@@ -4536,22 +6666,40 @@ impl HitTestTreeActionHandler for AppWindowHitTestTreeActionHandler {
             let x = dip_to_pixels(args.client_x, dpi) + self.grid_view.offset_pixels.read().0;
             let y = dip_to_pixels(args.client_y, dpi) + self.grid_view.offset_pixels.read().1;
 
-            let mut max_index = None;
-            for n in self
-                .qt
-                .borrow()
-                .iter_possible_element_indices(x as _, y as _)
-            {
-                let (left, top, right, bottom) = self.sprite_rect_cached.borrow()[n];
-                if left as f32 <= x && x <= right as f32 && top as f32 <= y && y <= bottom as f32 {
-                    // 大きいインデックスのものが最前面にいるのでmaxをとる
-                    max_index = Some(max_index.map_or(n, |x: usize| x.max(n)));
-                }
+            if let Some(n) = context.sprite_candidates().iter().rposition(|c| {
+                c.left as f32 <= x
+                    && x <= c.right() as f32
+                    && c.top as f32 <= y
+                    && y <= c.bottom() as f32
+            }) {
+                // 候補の確認中は候補の採用/不採用の切り替えを優先する
+                context.toggle_sprite_candidate(n);
+
+                return EventContinueControl::STOP_PROPAGATION;
             }
 
-            if let Some(mx) = max_index {
-                context.select_sprite(mx);
+            let qt = self.qt.borrow();
+            // Note: 負の座標にはスプライトはない（u32にすると0に丸められてしまうので弾いておく）
+            let max_index = if x < 0.0 || y < 0.0 {
+                None
             } else {
+                qt.iter_at_point(x as _, y as _)
+                    .filter(|&n| {
+                        let r = qt.rect(n).unwrap();
+                        x <= r.right as f32 && y <= r.bottom as f32
+                    })
+                    // 大きいインデックスのものが最前面にいるのでmaxをとる
+                    .max()
+            };
+            drop(qt);
+
+            if let Some(mx) = max_index {
+                if is_control_key_pressed() {
+                    context.toggle_sprite_selection(mx);
+                } else {
+                    context.select_sprite(mx);
+                }
+            } else if !is_control_key_pressed() {
                 context.deselect_sprite();
             }
 
@@ -4560,6 +6708,77 @@ impl HitTestTreeActionHandler for AppWindowHitTestTreeActionHandler {
 
         EventContinueControl::empty()
     }
+
+    fn on_aux_pointer_down(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppState,
+        _ht: &mut AppHitTestTreeManager,
+        args: PointerActionArgs,
+        button: PointerButton,
+    ) -> EventContinueControl {
+        if sender == self.ht_root
+            && button == PointerButton::Middle
+            && matches!(*self.drag_data.borrow(), DragState::None)
+        {
+            // 中ボタンでのドラッグはどこを掴んでもスクロールにする
+            self.begin_grid_drag(&args);
+
+            return EventContinueControl::STOP_PROPAGATION | EventContinueControl::CAPTURE_ELEMENT;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_aux_pointer_up(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppState,
+        ht: &mut AppHitTestTreeManager,
+        args: PointerActionArgs,
+        button: PointerButton,
+    ) -> EventContinueControl {
+        if sender == self.ht_root
+            && button == PointerButton::Middle
+            && matches!(*self.drag_data.borrow(), DragState::Grid { .. })
+        {
+            // 確定処理は左ボタンでのドラッグと同じ
+            return self.on_pointer_up(sender, context, ht, args);
+        }
+
+        EventContinueControl::empty()
+    }
+}
+
+impl AppWindowHitTestTreeActionHandler {
+    fn begin_grid_drag(&self, args: &PointerActionArgs) {
+        let dpi = self.dpi.get();
+        let (current_offset_x, current_offset_y) = *self.grid_view.offset_pixels.read();
+
+        *self.drag_data.borrow_mut() = DragState::Grid {
+            base_x_pixels: current_offset_x,
+            base_y_pixels: current_offset_y,
+            drag_start_client_x_pixels: dip_to_pixels(args.client_x, dpi),
+            drag_start_client_y_pixels: dip_to_pixels(args.client_y, dpi),
+        };
+    }
+
+    fn pivot_from_client(
+        &self,
+        context: &AppState,
+        index: usize,
+        args: &PointerActionArgs,
+    ) -> (f32, f32) {
+        let dpi = self.dpi.get();
+        let (current_offset_x, current_offset_y) = *self.grid_view.offset_pixels.read();
+        let s = &context.sprites()[index];
+
+        s.pivot_from_local_pixels(
+            dip_to_pixels(args.client_x, dpi) + current_offset_x - s.left as f32,
+            dip_to_pixels(args.client_y, dpi) + current_offset_y - s.top as f32,
+            is_shift_key_pressed(),
+        )
+    }
 }
 
 pub struct AppWindowDpiHandler {
@@ -4576,8 +6795,12 @@ struct AppWindowPresenter {
     ht_root: HitTestTreeRef,
     grid_view: Arc<AtlasBaseGridView>,
     _sprite_atlas_border_view: Rc<SpriteAtlasBorderView>,
+    _sprite_candidates_view: Rc<SpriteCandidatesView>,
+    _sprite_filter_matches_view: Rc<SpriteFilterMatchesView>,
     _selected_sprite_marker_view: Rc<CurrentSelectedSpriteMarkerView>,
+    _pivot_marker_view: Rc<SpritePivotMarkerView>,
     sprite_list_pane: SpriteListPanePresenter,
+    _animation_pane: AnimationPanePresenter,
     header: AppHeaderPresenter,
     _menu: AppMenuPresenter,
     file_dnd_overlay: Rc<FileDragAndDropOverlayView>,
@@ -4614,6 +6837,7 @@ impl AppWindowPresenter {
             height_adjustment_factor: 1.0,
             parent: None,
             children: vec![],
+            focusable: false,
             action_handler: None,
         });
 
@@ -4625,11 +6849,19 @@ impl AppWindowPresenter {
 
         let sprite_atlas_border_view = Rc::new(SpriteAtlasBorderView::new(&mut init.for_view));
 
+        let sprite_candidates_view = Rc::new(SpriteCandidatesView::new(&mut init.for_view));
+
+        let sprite_filter_matches_view = Rc::new(SpriteFilterMatchesView::new(&mut init.for_view));
+
         let selected_sprite_marker_view =
             Rc::new(CurrentSelectedSpriteMarkerView::new(&mut init.for_view));
 
+        let pivot_marker_view = Rc::new(SpritePivotMarkerView::new(&mut init.for_view));
+
         let sprite_list_pane = SpriteListPanePresenter::new(init);
 
+        let animation_pane = AnimationPanePresenter::new(init);
+
         let header = AppHeaderPresenter::new(init);
 
         let menu = AppMenuPresenter::new(init, header.height());
@@ -4642,17 +6874,31 @@ impl AppWindowPresenter {
         let file_dnd_overlay = Rc::new(FileDragAndDropOverlayView::new(&mut init.for_view));
 
         sprite_list_pane.set_top(&mut init.for_view.ht.borrow_mut(), header.height());
+        sprite_list_pane.resize(init_client_size_pixels.to_dip(init.for_view.dpi).Height);
+        animation_pane.set_top(&mut init.for_view.ht.borrow_mut(), header.height());
         grid_view.set_offset(0.0, -init.for_view.dip_to_pixels(header.height()));
+        sprite_candidates_view.set_view_offset(0.0, -init.for_view.dip_to_pixels(header.height()));
+        sprite_filter_matches_view
+            .set_view_offset(0.0, -init.for_view.dip_to_pixels(header.height()));
+        pivot_marker_view.set_view_offset(0.0, -init.for_view.dip_to_pixels(header.height()));
 
         root.Children().unwrap().InsertAtBottom(&bg).unwrap();
         grid_view.mount(&root.Children().unwrap());
         sprite_atlas_border_view.mount(&root.Children().unwrap());
+        sprite_candidates_view.mount(&root.Children().unwrap());
+        sprite_filter_matches_view.mount(&root.Children().unwrap());
         selected_sprite_marker_view.mount(&root.Children().unwrap());
+        pivot_marker_view.mount(&root.Children().unwrap());
         sprite_list_pane.mount(
             &root.Children().unwrap(),
             &mut init.for_view.ht.borrow_mut(),
             ht_root,
         );
+        animation_pane.mount(
+            &root.Children().unwrap(),
+            &mut init.for_view.ht.borrow_mut(),
+            ht_root,
+        );
         header.mount(
             &root.Children().unwrap(),
             &mut init.for_view.ht.borrow_mut(),
@@ -4668,9 +6914,11 @@ impl AppWindowPresenter {
         let ht_action_handler = Rc::new(AppWindowHitTestTreeActionHandler {
             grid_view: grid_view.clone(),
             sprite_atlas_border_view: sprite_atlas_border_view.clone(),
+            sprite_candidates_view: sprite_candidates_view.clone(),
+            sprite_filter_matches_view: sprite_filter_matches_view.clone(),
             selected_sprite_marker_view: selected_sprite_marker_view.clone(),
+            pivot_marker_view: pivot_marker_view.clone(),
             qt: RefCell::new(QuadTree::new()),
-            sprite_rect_cached: RefCell::new(Vec::new()),
             drag_data: RefCell::new(DragState::None),
             dpi: Cell::new(init.for_view.dpi),
             ht_root,
@@ -4686,9 +6934,24 @@ impl AppWindowPresenter {
         });
         init.dpi_handlers.push(Rc::downgrade(&dpi_handler) as _);
 
+        init.app_state
+            .borrow_mut()
+            .register_document_cancellation_token_view_feedback({
+                let grid_view = Arc::downgrade(&grid_view);
+
+                move |token| {
+                    let Some(grid_view) = grid_view.upgrade() else {
+                        // parent teardown-ed
+                        return;
+                    };
+
+                    grid_view.set_document_cancellation_token(token.clone());
+                }
+            });
         init.app_state.borrow_mut().register_sprites_view_feedback({
             let grid_view = Arc::downgrade(&grid_view);
             let selected_sprite_marker_view = Rc::downgrade(&selected_sprite_marker_view);
+            let pivot_marker_view = Rc::downgrade(&pivot_marker_view);
             let mut last_selected_index = None;
             let ht_action_handler = Rc::downgrade(&ht_action_handler);
 
@@ -4702,82 +6965,44 @@ impl AppWindowPresenter {
                     // parent teardown-ed
                     return;
                 };
+                let Some(pivot_marker_view) = pivot_marker_view.upgrade() else {
+                    // parent teardown-ed
+                    return;
+                };
                 let Some(ht_action_handler) = ht_action_handler.upgrade() else {
                     // parent teardown-ed
                     return;
                 };
 
-                while ht_action_handler.sprite_rect_cached.borrow().len() > sprites.len() {
+                let mut qt = ht_action_handler.qt.borrow_mut();
+                let removed = qt
+                    .indices()
+                    .filter(|&n| n >= sprites.len())
+                    .collect::<Vec<_>>();
+                for n in removed {
                     // 削除分
-                    let n = ht_action_handler.sprite_rect_cached.borrow().len() - 1;
-                    let old = ht_action_handler
-                        .sprite_rect_cached
-                        .borrow_mut()
-                        .pop()
-                        .unwrap();
-                    let (index, level) = QuadTree::rect_index_and_level(old.0, old.1, old.2, old.3);
-
-                    ht_action_handler.qt.borrow_mut().element_index_for_region[level]
-                        [index as usize]
-                        .remove(&n);
+                    qt.remove(n);
                 }
-                for (n, (old, new)) in ht_action_handler
-                    .sprite_rect_cached
-                    .borrow_mut()
-                    .iter_mut()
-                    .zip(sprites.iter())
-                    .enumerate()
-                {
-                    // 移動分
-                    if old.0 == new.left
-                        && old.1 == new.top
-                        && old.2 == new.right()
-                        && old.3 == new.bottom()
-                    {
+                for (n, s) in sprites.iter().enumerate() {
+                    let rect = QuadTreeRect {
+                        left: s.left,
+                        top: s.top,
+                        right: s.right(),
+                        bottom: s.bottom(),
+                    };
+                    if qt.rect(n) == Some(rect) {
                         // 座標変化なし
                         continue;
                     }
 
-                    let (old_index, old_level) =
-                        QuadTree::rect_index_and_level(old.0, old.1, old.2, old.3);
-                    let (new_index, new_level) = QuadTree::rect_index_and_level(
-                        new.left,
-                        new.top,
-                        new.right(),
-                        new.bottom(),
-                    );
-                    *old = (new.left, new.top, new.right(), new.bottom());
-
-                    if old_level == new_level && old_index == new_index {
-                        // 所属ブロックに変化なし
-                        continue;
+                    // 追加分/移動分
+                    if let Err(e) = qt.insert(n, rect) {
+                        tracing::warn!(reason = ?e, n, "sprite cannot be hit-tested");
+                        // Note: 範囲外に出たものは古い位置で当たらないように外しておく
+                        qt.remove(n);
                     }
-
-                    ht_action_handler.qt.borrow_mut().element_index_for_region[old_level]
-                        [old_index as usize]
-                        .remove(&n);
-                    ht_action_handler
-                        .qt
-                        .borrow_mut()
-                        .bind(new_level, new_index, n);
-                }
-                let new_base = ht_action_handler.sprite_rect_cached.borrow().len();
-                for (n, new) in sprites.iter().enumerate().skip(new_base) {
-                    // 追加分
-                    let (index, level) = QuadTree::rect_index_and_level(
-                        new.left,
-                        new.top,
-                        new.right(),
-                        new.bottom(),
-                    );
-                    ht_action_handler.qt.borrow_mut().bind(level, index, n);
-                    ht_action_handler.sprite_rect_cached.borrow_mut().push((
-                        new.left,
-                        new.top,
-                        new.right(),
-                        new.bottom(),
-                    ));
                 }
+                drop(qt);
 
                 grid_view.update_sprites(sprites);
 
@@ -4796,6 +7021,17 @@ impl AppWindowPresenter {
                         selected_sprite_marker_view.hide();
                     }
                 }
+
+                // ピボットは選択が変わらなくても変化しうるので毎回更新する
+                if let Some(x) = selected_index {
+                    let s = &sprites[x];
+                    pivot_marker_view.show_at(
+                        s.left as f32 + s.pivot_x * s.width as f32,
+                        s.top as f32 + s.pivot_y * s.height as f32,
+                    );
+                } else {
+                    pivot_marker_view.hide();
+                }
             }
         });
         init.app_state
@@ -4818,14 +7054,87 @@ impl AppWindowPresenter {
                     grid_view.set_atlas_size(size.width, size.height);
                 }
             });
+        init.app_state
+            .borrow_mut()
+            .register_alpha_mode_view_feedback({
+                let grid_view = Arc::downgrade(&grid_view);
+
+                move |alpha_mode| {
+                    let Some(grid_view) = grid_view.upgrade() else {
+                        // parent teardown-ed
+                        return;
+                    };
+
+                    grid_view.set_alpha_mode(alpha_mode);
+                }
+            });
+        init.app_state.borrow_mut().register_sprites_view_feedback({
+            let sprite_filter_matches_view = Rc::downgrade(&sprite_filter_matches_view);
+
+            move |sprites| {
+                let Some(sprite_filter_matches_view) = sprite_filter_matches_view.upgrade() else {
+                    // parent teardown-ed
+                    return;
+                };
+
+                sprite_filter_matches_view.set_sprites(sprites);
+            }
+        });
+        init.app_state
+            .borrow_mut()
+            .register_sprite_filter_view_feedback({
+                let sprite_filter_matches_view = Rc::downgrade(&sprite_filter_matches_view);
+
+                move |_, filter| {
+                    let Some(sprite_filter_matches_view) = sprite_filter_matches_view.upgrade()
+                    else {
+                        // parent teardown-ed
+                        return;
+                    };
+
+                    sprite_filter_matches_view.set_filter(filter.cloned());
+                }
+            });
+        init.app_state
+            .borrow_mut()
+            .register_sprite_candidates_view_feedback({
+                let sprite_candidates_view = Rc::downgrade(&sprite_candidates_view);
+
+                move |candidates| {
+                    let Some(sprite_candidates_view) = sprite_candidates_view.upgrade() else {
+                        // parent teardown-ed
+                        return;
+                    };
+
+                    sprite_candidates_view.set_candidates(candidates);
+                }
+            });
+        init.app_state
+            .borrow_mut()
+            .register_sprite_candidate_sheet_view_feedback({
+                let sprite_candidates_view = Rc::downgrade(&sprite_candidates_view);
+
+                move |sheet| {
+                    let Some(sprite_candidates_view) = sprite_candidates_view.upgrade() else {
+                        // parent teardown-ed
+                        return;
+                    };
+
+                    sprite_candidates_view.set_sheet(sheet);
+                }
+            });
 
         Self {
             root,
             ht_root,
             grid_view,
             _sprite_atlas_border_view: sprite_atlas_border_view,
+            _sprite_candidates_view: sprite_candidates_view,
+            _sprite_filter_matches_view: sprite_filter_matches_view,
             _selected_sprite_marker_view: selected_sprite_marker_view,
+            _pivot_marker_view: pivot_marker_view,
             sprite_list_pane,
+            _animation_pane: animation_pane,
             header,
             _menu: menu,
             file_dnd_overlay,
@@ -4840,7 +7149,8 @@ struct AppWindowStateModel {
     client_size_pixels: SizePixels,
     dpi: f32,
     dpi_handlers: Vec<std::rc::Weak<dyn DpiHandler>>,
-    pointer_input_manager: PointerInputManager,
+    pointer_input_manager: PointerInputManager<Win32InputPlatform>,
+    keyboard_focus_manager: KeyboardFocusManager,
     _composition_target: DesktopWindowTarget,
     root_presenter: AppWindowPresenter,
     app_state: Rc<RefCell<AppState>>,
@@ -4852,7 +7162,7 @@ impl AppWindowStateModel {
         app_state: &Rc<RefCell<AppState>>,
         background_worker: &BackgroundWorker,
         background_worker_view_update_callback: &Rc<
-            RefCell<Vec<Box<dyn FnMut(&[Option<String>])>>>,
+            RefCell<Vec<Box<dyn FnMut(&BackgroundWorkerStatus)>>>,
         >,
         view_worker_enqueue_access: &ViewWorkerEnqueueWeakAccess,
     ) -> Self {
@@ -4873,6 +7183,7 @@ impl AppWindowStateModel {
         let dpi = unsafe { GetDpiForWindow(bound_hwnd) as f32 };
         let mut dpi_handlers = Vec::new();
         let pointer_input_manager = PointerInputManager::new();
+        let keyboard_focus_manager = KeyboardFocusManager::new();
 
         let composition_target = unsafe {
             subsystem
@@ -4897,6 +7208,62 @@ impl AppWindowStateModel {
             &client_size_pixels,
         );
         composition_target.SetRoot(&root_presenter.root).unwrap();
+        app_state
+            .borrow_mut()
+            .register_source_size_mismatches_view_feedback({
+                // Note: HWNDはSendではないので値で持ち回る
+                let owner = bound_hwnd.0 as usize;
+                let view_worker_enqueue_access = view_worker_enqueue_access.clone();
+
+                move |mismatches, generation| {
+                    if mismatches.is_empty() {
+                        return;
+                    }
+
+                    // Note: ダイアログはモーダルでメッセージループを回すので、AppStateを借用しているこのスレッドでは出さない
+                    let mismatches = mismatches.to_vec();
+                    let view_worker_enqueue_access = view_worker_enqueue_access.clone();
+                    std::thread::Builder::new()
+                        .name("SourceReconcileDialog".into())
+                        .spawn(move || {
+                            let choice =
+                                source_reconcile_dialog::ask(HWND(owner as _), &mismatches);
+                            let Some(vwq) = view_worker_enqueue_access.upgrade() else {
+                                // app teardown-ed
+                                return;
+                            };
+
+                            vwq.enqueue(move |app_state| {
+                                // Note: 聞いている間に別のファイルを開いていたら、その答えは使わない
+                                let Some(choice) = choice else {
+                                    app_state.dismiss_source_size_mismatches(generation);
+                                    return;
+                                };
+
+                                let overlaps = app_state.reconcile_source_sizes(
+                                    generation,
+                                    choice.anchor,
+                                    choice.clamp_slices,
+                                );
+                                if overlaps.is_empty() {
+                                    return;
+                                }
+
+                                tracing::warn!({ ?overlaps }, "reconciled sprites overlap");
+                                std::thread::Builder::new()
+                                    .name("SourceReconcileDialog".into())
+                                    .spawn(move || {
+                                        source_reconcile_dialog::report_overlaps(
+                                            HWND(owner as _),
+                                            &overlaps,
+                                        );
+                                    })
+                                    .unwrap();
+                            });
+                        })
+                        .unwrap();
+                }
+            });
 
         ht.borrow().dump(root_presenter.ht_root);
 
@@ -4908,6 +7275,7 @@ impl AppWindowStateModel {
             dpi,
             dpi_handlers,
             pointer_input_manager,
+            keyboard_focus_manager,
             _composition_target: composition_target,
             root_presenter,
             app_state: app_state.clone(),
@@ -4981,17 +7349,40 @@ impl AppWindowStateModel {
             self.client_size_pixels.width,
             self.client_size_pixels.height,
         );
+        self.root_presenter
+            .sprite_list_pane
+            .resize(self.client_size_pixels.to_dip(self.dpi).Height);
+    }
+
+    pub fn on_key_down(&mut self, hwnd: HWND, key: VIRTUAL_KEY) -> bool {
+        self.keyboard_focus_manager.on_key_down(
+            hwnd.into(),
+            &mut self.ht.borrow_mut(),
+            &mut self.app_state.borrow_mut(),
+            self.root_presenter.ht_root,
+            key.into(),
+            is_shift_key_pressed(),
+        )
+    }
+
+    pub fn on_char(&mut self, ch: char) -> bool {
+        self.keyboard_focus_manager.on_char(
+            &mut self.ht.borrow_mut(),
+            &mut self.app_state.borrow_mut(),
+            ch,
+        )
     }
 
     pub fn on_mouse_move(&mut self, hwnd: HWND, x_pixels: i16, y_pixels: i16) {
         self.pointer_input_manager.on_mouse_move(
-            hwnd,
+            hwnd.into(),
             &mut self.ht.borrow_mut(),
             &mut self.app_state.borrow_mut(),
             self.root_presenter.ht_root,
-            self.client_size_pixels.to_dip(self.dpi),
+            self.client_size_pixels.to_dip(self.dpi).into(),
             signed_pixels_to_dip(x_pixels as _, self.dpi),
             signed_pixels_to_dip(y_pixels as _, self.dpi),
+            current_modifier_keys(),
         );
 
         // WM_SETCURSORが飛ばないことがあるのでここで設定する
@@ -5000,32 +7391,106 @@ impl AppWindowStateModel {
             .cursor(&self.ht.borrow(), &mut self.app_state.borrow_mut())
         {
             unsafe {
-                SetCursor(Some(c));
+                SetCursor(Some(c.into()));
             }
         }
     }
 
     pub fn on_mouse_left_down(&mut self, hwnd: HWND, x_pixels: i16, y_pixels: i16) {
+        self.keyboard_focus_manager.on_mouse_left_down(
+            &mut self.ht.borrow_mut(),
+            &mut self.app_state.borrow_mut(),
+            self.root_presenter.ht_root,
+            self.client_size_pixels.to_dip(self.dpi).into(),
+            signed_pixels_to_dip(x_pixels as _, self.dpi),
+            signed_pixels_to_dip(y_pixels as _, self.dpi),
+        );
         self.pointer_input_manager.on_mouse_left_down(
-            hwnd,
+            hwnd.into(),
             &mut self.ht.borrow_mut(),
             &mut self.app_state.borrow_mut(),
             self.root_presenter.ht_root,
-            self.client_size_pixels.to_dip(self.dpi),
+            self.client_size_pixels.to_dip(self.dpi).into(),
             signed_pixels_to_dip(x_pixels as _, self.dpi),
             signed_pixels_to_dip(y_pixels as _, self.dpi),
+            current_modifier_keys(),
         );
     }
 
     pub fn on_mouse_left_up(&mut self, hwnd: HWND, x_pixels: i16, y_pixels: i16) {
         self.pointer_input_manager.on_mouse_left_up(
-            hwnd,
+            hwnd.into(),
+            &mut self.ht.borrow_mut(),
+            &mut self.app_state.borrow_mut(),
+            self.root_presenter.ht_root,
+            self.client_size_pixels.to_dip(self.dpi).into(),
+            signed_pixels_to_dip(x_pixels as _, self.dpi),
+            signed_pixels_to_dip(y_pixels as _, self.dpi),
+            current_modifier_keys(),
+        );
+    }
+
+    pub fn on_mouse_aux_down(
+        &mut self,
+        hwnd: HWND,
+        button: PointerButton,
+        x_pixels: i16,
+        y_pixels: i16,
+    ) {
+        self.pointer_input_manager.on_mouse_aux_down(
+            hwnd.into(),
+            &mut self.ht.borrow_mut(),
+            &mut self.app_state.borrow_mut(),
+            self.root_presenter.ht_root,
+            self.client_size_pixels.to_dip(self.dpi).into(),
+            button,
+            signed_pixels_to_dip(x_pixels as _, self.dpi),
+            signed_pixels_to_dip(y_pixels as _, self.dpi),
+            current_modifier_keys(),
+        );
+    }
+
+    pub fn on_mouse_aux_up(
+        &mut self,
+        hwnd: HWND,
+        button: PointerButton,
+        x_pixels: i16,
+        y_pixels: i16,
+    ) {
+        self.pointer_input_manager.on_mouse_aux_up(
+            hwnd.into(),
             &mut self.ht.borrow_mut(),
             &mut self.app_state.borrow_mut(),
             self.root_presenter.ht_root,
-            self.client_size_pixels.to_dip(self.dpi),
+            self.client_size_pixels.to_dip(self.dpi).into(),
+            button,
             signed_pixels_to_dip(x_pixels as _, self.dpi),
             signed_pixels_to_dip(y_pixels as _, self.dpi),
+            current_modifier_keys(),
+        );
+    }
+
+    /// `screen_x`/`screen_y`はスクリーン座標（ホイールのメッセージはスクリーン座標で来る）
+    pub fn on_mouse_wheel(&mut self, hwnd: HWND, screen_x: i16, screen_y: i16, delta: WheelDelta) {
+        let mut p = [POINT {
+            x: screen_x as _,
+            y: screen_y as _,
+        }];
+        unsafe {
+            MapWindowPoints(None, Some(hwnd), &mut p);
+        }
+        let [POINT { x, y }] = p;
+
+        self.pointer_input_manager.on_mouse_wheel(
+            hwnd.into(),
+            &mut self.ht.borrow_mut(),
+            &mut self.app_state.borrow_mut(),
+            self.root_presenter.ht_root,
+            self.client_size_pixels.to_dip(self.dpi).into(),
+            signed_pixels_to_dip(x, self.dpi),
+            signed_pixels_to_dip(y, self.dpi),
+            current_modifier_keys(),
+            delta,
         );
     }
 
@@ -5035,7 +7500,7 @@ impl AppWindowStateModel {
             .cursor(&self.ht.borrow(), &mut self.app_state.borrow_mut())
         {
             unsafe {
-                SetCursor(Some(c));
+                SetCursor(Some(c.into()));
             }
 
             return true;
@@ -5051,6 +7516,8 @@ pub struct DropTargetHandler {
     pub overlay_view: Rc<FileDragAndDropOverlayView>,
     pub dd_helper: IDropTargetHelper,
     pub app_state: std::rc::Weak<RefCell<AppState>>,
+    pub background_worker_enqueue_access: BackgroundWorkerEnqueueAccess,
+    pub view_worker_enqueue_access: ViewWorkerEnqueueWeakAccess,
 }
 impl IDropTarget_Impl for DropTargetHandler_Impl {
     fn DragEnter(
@@ -5102,7 +7569,7 @@ impl IDropTarget_Impl for DropTargetHandler_Impl {
     fn Drop(
         &self,
         pdataobj: windows_core::Ref<'_, windows::Win32::System::Com::IDataObject>,
-        _grfkeystate: windows::Win32::System::SystemServices::MODIFIERKEYS_FLAGS,
+        grfkeystate: windows::Win32::System::SystemServices::MODIFIERKEYS_FLAGS,
         pt: &windows::Win32::Foundation::POINTL,
         pdweffect: *mut windows::Win32::System::Ole::DROPEFFECT,
     ) -> windows_core::Result<()> {
@@ -5122,6 +7589,8 @@ impl IDropTarget_Impl for DropTargetHandler_Impl {
         let hdrop: HDROP = unsafe { core::mem::transmute(glock.ptr) };
         let file_count = unsafe { DragQueryFileW(hdrop, 0xffff_ffff, None) };
         let mut sprites = Vec::with_capacity(file_count as _);
+        let mut detect_target = None;
+        let mut grid_slice_target = None;
         for n in 0..file_count {
             let len = unsafe { DragQueryFileW(hdrop, n, None) };
             let mut path = Vec::with_capacity((len + 1) as _);
@@ -5136,6 +7605,7 @@ impl IDropTarget_Impl for DropTargetHandler_Impl {
             let path = PathBuf::from(OsString::from_wide(&path[..path.len() - 1]));
             if path.is_dir() {
                 // process all files in directory(rec)
+                let root = path.clone();
                 for entry in walkdir::WalkDir::new(&path)
                     .into_iter()
                     .filter_map(|e| e.ok())
@@ -5146,28 +7616,39 @@ impl IDropTarget_Impl for DropTargetHandler_Impl {
                         continue;
                     }
 
-                    let mut fs = std::fs::File::open(&path).unwrap();
-                    let Some(png_meta) = source_reader::png::Metadata::try_read(&mut fs) else {
-                        // PNGじゃないのは一旦見逃す
+                    let Some((width, height)) = source_reader::read_dimensions(path) else {
+                        // 対応していない形式のものは一旦見逃す
                         continue;
                     };
 
-                    sprites.push(SpriteInfo::new(
+                    let mut sprite = SpriteInfo::new(
                         path.file_stem().unwrap().to_str().unwrap().into(),
                         path.to_path_buf(),
-                        png_meta.width,
-                        png_meta.height,
-                    ));
+                        width,
+                        height,
+                    );
+                    sprite.group = sprite_group::derive_from_source_path(&root, path);
+                    sprites.push(sprite);
                 }
+            } else if file_count == 1
+                && grfkeystate.contains(windows::Win32::System::SystemServices::MK_CONTROL)
+            {
+                // Ctrlを押しながら1枚だけドロップされたときは、メニューで選んだ大きさのグリッドで切り出す
+                grid_slice_target = Some(path);
+            } else if file_count == 1
+                && grfkeystate.contains(windows::Win32::System::SystemServices::MK_SHIFT)
+            {
+                // Shiftを押しながら1枚だけドロップされたときはスプライトシートとみなして中身を自動検出する
+                detect_target = Some(path);
             } else {
-                let mut fs = std::fs::File::open(&path).unwrap();
-                let png_meta = source_reader::png::Metadata::try_read(&mut fs).expect("not a png?");
+                let (width, height) =
+                    source_reader::read_dimensions(&path).expect("not a supported image?");
 
                 sprites.push(SpriteInfo::new(
                     path.file_stem().unwrap().to_str().unwrap().into(),
                     path.to_path_buf(),
-                    png_meta.width,
-                    png_meta.height,
+                    width,
+                    height,
                 ));
             }
         }
@@ -5186,6 +7667,91 @@ impl IDropTarget_Impl for DropTargetHandler_Impl {
             core::ptr::write(pdweffect, DROPEFFECT_LINK);
         }
 
+        if let Some(path) = detect_target
+            && let Some(app_state) = self.app_state.upgrade()
+        {
+            let view_worker_enqueue_access = self.view_worker_enqueue_access.clone();
+            let cancellation_token = app_state.borrow().document_cancellation_token().clone();
+            let region_detect_params = *app_state.borrow().region_detect_params();
+
+            self.background_worker_enqueue_access.enqueue(
+                BackgroundWork::new(BackgroundWorkKind::DetectSpriteRegions(
+                    path,
+                    region_detect_params,
+                    Box::new(move |path, regions, image| {
+                        let Some(view_worker_enqueue_access) = view_worker_enqueue_access.upgrade()
+                        else {
+                            // app teardown-ed
+                            return;
+                        };
+
+                        let candidates = regions
+                            .into_iter()
+                            .map(|r| SpriteCandidate {
+                                source_path: path.clone(),
+                                left: r.left,
+                                top: r.top,
+                                width: r.width,
+                                height: r.height,
+                                accepted: true,
+                            })
+                            .collect();
+                        let sheet = image.into_rgba8();
+                        view_worker_enqueue_access.enqueue(move |app_state| {
+                            app_state.set_sprite_candidates(candidates, sheet);
+                        });
+                    }),
+                ))
+                .with_priority(BackgroundWorkPriority::Low)
+                .with_cancellation_token(cancellation_token),
+            );
+        }
+
+        if let Some(path) = grid_slice_target
+            && let Some(app_state) = self.app_state.upgrade()
+        {
+            let view_worker_enqueue_access = self.view_worker_enqueue_access.clone();
+            let cancellation_token = app_state.borrow().document_cancellation_token().clone();
+            let params = *app_state.borrow().grid_slice_params();
+
+            self.background_worker_enqueue_access.enqueue(
+                BackgroundWork::job("Slicing sprite sheet", move |ctx| {
+                    let image = image::open(&path)?;
+                    let cells = grid_slice::non_empty_cells(&image, &params);
+                    if ctx.is_cancelled() {
+                        return Ok(());
+                    }
+
+                    let Some(view_worker_enqueue_access) = view_worker_enqueue_access.upgrade()
+                    else {
+                        // app teardown-ed
+                        return Ok(());
+                    };
+
+                    // Note: 自動検出と同じく候補として出して、採用するものを選んでから追加する
+                    let candidates = cells
+                        .into_iter()
+                        .map(|c| SpriteCandidate {
+                            source_path: path.clone(),
+                            left: c.left,
+                            top: c.top,
+                            width: params.cell_width,
+                            height: params.cell_height,
+                            accepted: true,
+                        })
+                        .collect();
+                    let sheet = image.into_rgba8();
+                    view_worker_enqueue_access.enqueue(move |app_state| {
+                        app_state.set_sprite_candidates(candidates, sheet);
+                    });
+
+                    Ok(())
+                })
+                .with_priority(BackgroundWorkPriority::Low)
+                .with_cancellation_token(cancellation_token),
+            );
+        }
+
         if let Some(m) = self.app_state.upgrade() {
             m.borrow_mut().add_sprites(sprites);
         }
@@ -5303,6 +7869,10 @@ impl ViewWorkerQueue {
 
 fn main() {
     tracing_subscriber::fmt().pretty().init();
+    if let Some(code) = cli::try_run(std::env::args_os()) {
+        std::process::exit(code);
+    }
+
     unsafe {
         OleInitialize(None).unwrap();
     }
@@ -5375,9 +7945,10 @@ fn main() {
     }
 
     let view_worker_queue = ViewWorkerQueue::new();
-    let mut bg_worker_vf = vec![None; background_worker.worker_count()];
-    let bg_worker_vf_update_callback =
-        Rc::new(RefCell::new(Vec::<Box<dyn FnMut(&[Option<String>])>>::new()));
+    let mut bg_worker_status = BackgroundWorkerStatus::new(background_worker.worker_count());
+    let bg_worker_vf_update_callback = Rc::new(RefCell::new(Vec::<
+        Box<dyn FnMut(&BackgroundWorkerStatus)>,
+    >::new()));
     let app_state = Rc::new(RefCell::new(AppState::new()));
 
     let mut app_window_state_model = AppWindowStateModel::new(
@@ -5400,6 +7971,8 @@ fn main() {
                 dd_helper: CoCreateInstance(&CLSID_DragDropHelper, None, CLSCTX_INPROC_SERVER)
                     .unwrap(),
                 app_state: Rc::downgrade(&app_state),
+                background_worker_enqueue_access: background_worker.enqueue_access(),
+                view_worker_enqueue_access: view_worker_queue.enqueue_weak_access(),
             }),
         )
         .unwrap();
@@ -5469,22 +8042,29 @@ fn main() {
 
         if r == WAIT_OBJECT_0 {
             // notify to ui thread
+            let mut bg_worker_status_changed = false;
             while let Some(vf) = background_worker.try_pop_view_feedback() {
                 match vf {
-                    BackgroundWorkerViewFeedback::BeginWork(n, msg) => {
+                    BackgroundWorkerViewFeedback::BeginWork(n, ref msg, _) => {
                         tracing::info!("Thread #{n} has started a work: {msg}");
-                        bg_worker_vf[n] = Some(msg);
-                        for x in bg_worker_vf_update_callback.borrow_mut().iter_mut() {
-                            x(&bg_worker_vf);
-                        }
                     }
                     BackgroundWorkerViewFeedback::EndWork(n) => {
                         tracing::info!("Thread #{n} has finished a work");
-                        bg_worker_vf[n] = None;
-                        for x in bg_worker_vf_update_callback.borrow_mut().iter_mut() {
-                            x(&bg_worker_vf);
-                        }
                     }
+                    BackgroundWorkerViewFeedback::Failed(n, ref e) => {
+                        tracing::error!("Thread #{n} has failed a work: {e}");
+                    }
+                    BackgroundWorkerViewFeedback::Progress(..)
+                    | BackgroundWorkerViewFeedback::GroupProgress(..) => (),
+                }
+
+                bg_worker_status.apply(&vf);
+                bg_worker_status_changed = true;
+            }
+            // Note: 進捗はまとめて届くことが多いので、最後の状態だけ通知する
+            if bg_worker_status_changed {
+                for x in bg_worker_vf_update_callback.borrow_mut().iter_mut() {
+                    x(&bg_worker_status);
                 }
             }
 
@@ -5676,6 +8256,119 @@ extern "system" fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM)
         return LRESULT(0);
     }
 
+    if msg == WM_RBUTTONDOWN {
+        let Some(state) = (unsafe {
+            (GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut AppWindowStateModel).as_mut()
+        }) else {
+            return unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) };
+        };
+
+        state.on_mouse_aux_down(
+            hwnd,
+            PointerButton::Right,
+            (lparam.0 & 0xffff) as i16,
+            ((lparam.0 >> 16) & 0xffff) as i16,
+        );
+        return LRESULT(0);
+    }
+
+    if msg == WM_RBUTTONUP {
+        let Some(state) = (unsafe {
+            (GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut AppWindowStateModel).as_mut()
+        }) else {
+            return unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) };
+        };
+
+        state.on_mouse_aux_up(
+            hwnd,
+            PointerButton::Right,
+            (lparam.0 & 0xffff) as i16,
+            ((lparam.0 >> 16) & 0xffff) as i16,
+        );
+        return LRESULT(0);
+    }
+
+    if msg == WM_MBUTTONDOWN {
+        let Some(state) = (unsafe {
+            (GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut AppWindowStateModel).as_mut()
+        }) else {
+            return unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) };
+        };
+
+        state.on_mouse_aux_down(
+            hwnd,
+            PointerButton::Middle,
+            (lparam.0 & 0xffff) as i16,
+            ((lparam.0 >> 16) & 0xffff) as i16,
+        );
+        return LRESULT(0);
+    }
+
+    if msg == WM_MBUTTONUP {
+        let Some(state) = (unsafe {
+            (GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut AppWindowStateModel).as_mut()
+        }) else {
+            return unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) };
+        };
+
+        state.on_mouse_aux_up(
+            hwnd,
+            PointerButton::Middle,
+            (lparam.0 & 0xffff) as i16,
+            ((lparam.0 >> 16) & 0xffff) as i16,
+        );
+        return LRESULT(0);
+    }
+
+    if msg == WM_MOUSEWHEEL || msg == WM_MOUSEHWHEEL {
+        let Some(state) = (unsafe {
+            (GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut AppWindowStateModel).as_mut()
+        }) else {
+            return unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) };
+        };
+
+        // Note: 回転量はwparamの上位ワード（WHEEL_DELTAで1ノッチ）
+        let notches = ((wparam.0 >> 16) & 0xffff) as i16 as f32 / WHEEL_DELTA as f32;
+        state.on_mouse_wheel(
+            hwnd,
+            (lparam.0 & 0xffff) as i16,
+            ((lparam.0 >> 16) & 0xffff) as i16,
+            if msg == WM_MOUSEHWHEEL {
+                WheelDelta { x: notches, y: 0.0 }
+            } else {
+                WheelDelta { x: 0.0, y: notches }
+            },
+        );
+        return LRESULT(0);
+    }
+
+    if msg == WM_KEYDOWN {
+        let Some(state) = (unsafe {
+            (GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut AppWindowStateModel).as_mut()
+        }) else {
+            return unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) };
+        };
+
+        if state.on_key_down(hwnd, VIRTUAL_KEY(wparam.0 as _)) {
+            return LRESULT(0);
+        }
+    }
+
+    if msg == WM_CHAR {
+        let Some(state) = (unsafe {
+            (GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut AppWindowStateModel).as_mut()
+        }) else {
+            return unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) };
+        };
+
+        // Note: サロゲートペアは単体ではcharにできないので一旦見逃す
+        if let Some(ch) = char::from_u32(wparam.0 as _) {
+            if state.on_char(ch) {
+                return LRESULT(0);
+            }
+        }
+    }
+
     if msg == WM_SETCURSOR {
         let Some(state) = (unsafe {
             (GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut AppWindowStateModel).as_mut()
@@ -7,10 +7,14 @@ use std::{
 
 use uuid::Uuid;
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Sprite {
     pub id: Uuid,
     pub name: String,
     pub source_path: PathBuf,
+    /// ソース画像内の切り出し位置（ファイル全体を使う場合は0）
+    pub source_left: u32,
+    pub source_top: u32,
     pub width: u32,
     pub height: u32,
     pub left: u32,
@@ -19,22 +23,213 @@ pub struct Sprite {
     pub border_top: u32,
     pub border_right: u32,
     pub border_bottom: u32,
+    /// スプライト内の基準点（左上が(0, 0)、右下が(1, 1)）
+    pub pivot_x: f32,
+    pub pivot_y: f32,
+    /// `/`区切りのグループパス（空文字列はグループなし）
+    pub group: String,
+}
+impl Sprite {
+    pub const DEFAULT_PIVOT: (f32, f32) = (0.5, 0.5);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationLoopMode {
+    /// 最後のフレームで止まる
+    Once,
+    Loop,
+    /// 最後まで行ったら逆順に戻る
+    PingPong,
+}
+impl AnimationLoopMode {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Once => "once",
+            Self::Loop => "loop",
+            Self::PingPong => "pingpong",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "once" => Some(Self::Once),
+            "loop" => Some(Self::Loop),
+            "pingpong" => Some(Self::PingPong),
+            _ => None,
+        }
+    }
+}
+
+/// アトラス画像のアルファの持ち方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// 色はアルファを掛けずに持つ
+    Straight,
+    /// 色にアルファを掛けて持つ
+    Premultiplied,
+}
+impl AlphaMode {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Straight => "straight",
+            Self::Premultiplied => "premultiplied",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "straight" => Some(Self::Straight),
+            "premultiplied" => Some(Self::Premultiplied),
+            _ => None,
+        }
+    }
+}
+
+/// 書き出すときのブロック圧縮の形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureCompression {
+    /// 圧縮しない
+    None,
+    /// RGB + 1bitアルファ（8バイト/ブロック）
+    Bc1,
+    /// RGB + 補間アルファ（16バイト/ブロック）
+    Bc3,
+    /// RGBA（16バイト/ブロック）
+    Bc7,
+    /// ETC2 RGBA8（EACアルファ付き、16バイト/ブロック）
+    Etc2,
+}
+impl TextureCompression {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Bc1 => "bc1",
+            Self::Bc3 => "bc3",
+            Self::Bc7 => "bc7",
+            Self::Etc2 => "etc2",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "none" => Some(Self::None),
+            "bc1" => Some(Self::Bc1),
+            "bc3" => Some(Self::Bc3),
+            "bc7" => Some(Self::Bc7),
+            "etc2" => Some(Self::Etc2),
+            _ => None,
+        }
+    }
+
+    /// 圧縮の単位になるブロックの幅（高さも同じ）
+    pub const fn block_size(&self) -> u32 {
+        match self {
+            Self::None => 1,
+            _ => 4,
+        }
+    }
+}
+
+/// ブロック圧縮の品質と速度のどちらを優先するか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionQuality {
+    Fast,
+    High,
+}
+impl CompressionQuality {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Fast => "fast",
+            Self::High => "high",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "fast" => Some(Self::Fast),
+            "high" => Some(Self::High),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationFrame {
+    pub sprite_id: Uuid,
+    pub duration_ms: u32,
+}
+
+/// ミップマップのレベル数の上限（16384x16384から1x1まで）
+pub const MAX_MIP_LEVELS: u32 = 15;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub name: String,
+    pub loop_mode: AnimationLoopMode,
+    pub frames: Vec<AnimationFrame>,
 }
 
 pub struct SpriteAtlasAsset {
     /// needs sorted by id
     pub sprites: Vec<Sprite>,
+    /// needs sorted by name
+    pub animations: Vec<Animation>,
     pub width: u32,
     pub height: u32,
+    pub alpha_mode: AlphaMode,
+    /// 書き出すときのミップマップのレベル数（1ならミップマップなし）
+    pub mip_levels: u32,
+    pub compression: TextureCompression,
+    pub compression_quality: CompressionQuality,
 }
 impl SpriteAtlasAsset {
+    /// 1: 初版
+    /// 2: source_left, source_topを追加
+    /// 3: アニメーション（anim/frame行）を追加
+    /// 4: pivot_x, pivot_yを追加
+    /// 5: groupを追加
+    /// 6: cfg行にalpha_modeを追加
+    /// 7: cfg行にmip_levelsを追加
+    /// 8: cfg行にcompression, compression_qualityを追加
+    pub const FORMAT_VERSION: u32 = 8;
+
     pub fn write(&self, sink: &mut (impl Write + ?Sized)) -> std::io::Result<()> {
-        writeln!(sink, "cfg={},{}", self.width, self.height)?;
+        // Note: グループはカンマ区切りの途中にあるので、カンマを含むと読めなくなる（取り込むときに置き換えている）
+        // 途中まで書いてしまわないように先に調べる
+        if !(1..=MAX_MIP_LEVELS).contains(&self.mip_levels) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "mip_levels must be between 1 and {MAX_MIP_LEVELS}: {}",
+                    self.mip_levels
+                ),
+            ));
+        }
+        if let Some(s) = self.sprites.iter().find(|s| s.group.contains(',')) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("group of sprite {} contains a comma: {:?}", s.id, s.group),
+            ));
+        }
+
+        writeln!(sink, "ver={}", Self::FORMAT_VERSION)?;
+        writeln!(
+            sink,
+            "cfg={},{},{},{},{},{}",
+            self.width,
+            self.height,
+            self.alpha_mode.as_str(),
+            self.mip_levels,
+            self.compression.as_str(),
+            self.compression_quality.as_str()
+        )?;
 
         for &Sprite {
             ref id,
             ref name,
             ref source_path,
+            source_left,
+            source_top,
             width,
             height,
             left,
@@ -43,24 +238,43 @@ impl SpriteAtlasAsset {
             border_top,
             border_right,
             border_bottom,
+            pivot_x,
+            pivot_y,
+            ref group,
         } in self.sprites.iter()
         {
             // Note: 比較的変わりにくいもの -> 変わりやすいもの の順でならべている（行ごとの差分を見やすくするため）
             writeln!(
                 sink,
-                "{id}={width},{height},{border_left},{border_top},{border_right},{border_bottom},{left},{top},{source_path},{name}",
+                "{id}={width},{height},{border_left},{border_top},{border_right},{border_bottom},{source_left},{source_top},{pivot_x},{pivot_y},{left},{top},{group},{source_path},{name}",
                 id = id.as_simple(),
                 source_path = source_path.display()
             )?;
         }
 
+        // アニメーションはスプライトのあとにまとめて書く（frame行は直前のanim行に属する）
+        for a in self.animations.iter() {
+            writeln!(sink, "anim={},{}", a.loop_mode.as_str(), a.name)?;
+            for f in a.frames.iter() {
+                writeln!(sink, "frame={},{}", f.sprite_id.as_simple(), f.duration_ms)?;
+            }
+        }
+
         Ok(())
     }
 
     pub fn read(src: &mut (impl BufRead + ?Sized)) -> Result<Self, SpriteAtlasAssetReadError> {
         let mut sprites = Vec::new();
+        let mut animations = Vec::<Animation>::new();
         let mut width = 32;
         let mut height = 32;
+        // Note: 6より前はプレビューが常にstraightとして扱っていたのでそれに合わせる
+        let mut alpha_mode = AlphaMode::Straight;
+        let mut mip_levels = 1;
+        let mut compression = TextureCompression::None;
+        let mut compression_quality = CompressionQuality::Fast;
+        // verがないものは初版
+        let mut version = 1;
 
         for l in src.lines() {
             let l = l?;
@@ -71,6 +285,19 @@ impl SpriteAtlasAsset {
                 .ok_or(SpriteAtlasAssetReadError::MissingSpriteParams)?;
             let mut params = params.split(',');
 
+            if id == "ver" {
+                version = params
+                    .next()
+                    .ok_or(SpriteAtlasAssetReadError::MissingParam("ver"))?
+                    .parse()
+                    .map_err(|e| SpriteAtlasAssetReadError::InvalidParamFormat("ver", e))?;
+                if version > Self::FORMAT_VERSION {
+                    return Err(SpriteAtlasAssetReadError::UnsupportedVersion(version));
+                }
+
+                continue;
+            }
+
             if id == "cfg" {
                 width = params
                     .next()
@@ -82,6 +309,79 @@ impl SpriteAtlasAsset {
                     .ok_or(SpriteAtlasAssetReadError::MissingParam("height"))?
                     .parse()
                     .map_err(|e| SpriteAtlasAssetReadError::InvalidParamFormat("height", e))?;
+                if version >= 6 {
+                    let s = params
+                        .next()
+                        .ok_or(SpriteAtlasAssetReadError::MissingParam("alpha_mode"))?;
+                    alpha_mode = AlphaMode::parse(s)
+                        .ok_or_else(|| SpriteAtlasAssetReadError::InvalidAlphaMode(s.into()))?;
+                }
+                if version >= 7 {
+                    mip_levels = params
+                        .next()
+                        .ok_or(SpriteAtlasAssetReadError::MissingParam("mip_levels"))?
+                        .parse()
+                        .map_err(|e| {
+                            SpriteAtlasAssetReadError::InvalidParamFormat("mip_levels", e)
+                        })?;
+                    if !(1..=MAX_MIP_LEVELS).contains(&mip_levels) {
+                        return Err(SpriteAtlasAssetReadError::InvalidMipLevels);
+                    }
+                }
+                if version >= 8 {
+                    let s = params
+                        .next()
+                        .ok_or(SpriteAtlasAssetReadError::MissingParam("compression"))?;
+                    compression = TextureCompression::parse(s)
+                        .ok_or_else(|| SpriteAtlasAssetReadError::InvalidCompression(s.into()))?;
+                    let s = params
+                        .next()
+                        .ok_or(SpriteAtlasAssetReadError::MissingParam(
+                            "compression_quality",
+                        ))?;
+                    compression_quality = CompressionQuality::parse(s).ok_or_else(|| {
+                        SpriteAtlasAssetReadError::InvalidCompressionQuality(s.into())
+                    })?;
+                }
+
+                continue;
+            }
+
+            if id == "anim" {
+                let loop_mode = params
+                    .next()
+                    .ok_or(SpriteAtlasAssetReadError::MissingParam("loop_mode"))?;
+                animations.push(Animation {
+                    loop_mode: AnimationLoopMode::parse(loop_mode).ok_or_else(|| {
+                        SpriteAtlasAssetReadError::InvalidLoopMode(loop_mode.into())
+                    })?,
+                    // Note: 名前にはカンマが含まれうるので残りを全部使う
+                    name: params.collect::<Vec<_>>().join(","),
+                    frames: Vec::new(),
+                });
+
+                continue;
+            }
+
+            if id == "frame" {
+                let Some(a) = animations.last_mut() else {
+                    return Err(SpriteAtlasAssetReadError::FrameOutsideAnimation);
+                };
+                a.frames.push(AnimationFrame {
+                    sprite_id: params
+                        .next()
+                        .ok_or(SpriteAtlasAssetReadError::MissingParam("sprite_id"))?
+                        .parse::<uuid::fmt::Simple>()
+                        .map_err(SpriteAtlasAssetReadError::InvalidID)?
+                        .into(),
+                    duration_ms: params
+                        .next()
+                        .ok_or(SpriteAtlasAssetReadError::MissingParam("duration_ms"))?
+                        .parse()
+                        .map_err(|e| {
+                            SpriteAtlasAssetReadError::InvalidParamFormat("duration_ms", e)
+                        })?,
+                });
 
                 continue;
             }
@@ -125,6 +425,50 @@ impl SpriteAtlasAsset {
                     .map_err(|e| {
                         SpriteAtlasAssetReadError::InvalidParamFormat("border_bottom", e)
                     })?,
+                source_left: if version >= 2 {
+                    params
+                        .next()
+                        .ok_or(SpriteAtlasAssetReadError::MissingParam("source_left"))?
+                        .parse()
+                        .map_err(|e| {
+                            SpriteAtlasAssetReadError::InvalidParamFormat("source_left", e)
+                        })?
+                } else {
+                    0
+                },
+                source_top: if version >= 2 {
+                    params
+                        .next()
+                        .ok_or(SpriteAtlasAssetReadError::MissingParam("source_top"))?
+                        .parse()
+                        .map_err(|e| {
+                            SpriteAtlasAssetReadError::InvalidParamFormat("source_top", e)
+                        })?
+                } else {
+                    0
+                },
+                pivot_x: if version >= 4 {
+                    params
+                        .next()
+                        .ok_or(SpriteAtlasAssetReadError::MissingParam("pivot_x"))?
+                        .parse()
+                        .map_err(|e| {
+                            SpriteAtlasAssetReadError::InvalidFloatParamFormat("pivot_x", e)
+                        })?
+                } else {
+                    Sprite::DEFAULT_PIVOT.0
+                },
+                pivot_y: if version >= 4 {
+                    params
+                        .next()
+                        .ok_or(SpriteAtlasAssetReadError::MissingParam("pivot_y"))?
+                        .parse()
+                        .map_err(|e| {
+                            SpriteAtlasAssetReadError::InvalidFloatParamFormat("pivot_y", e)
+                        })?
+                } else {
+                    Sprite::DEFAULT_PIVOT.1
+                },
                 left: params
                     .next()
                     .ok_or(SpriteAtlasAssetReadError::MissingParam("left"))?
@@ -135,6 +479,14 @@ impl SpriteAtlasAsset {
                     .ok_or(SpriteAtlasAssetReadError::MissingParam("top"))?
                     .parse()
                     .map_err(|e| SpriteAtlasAssetReadError::InvalidParamFormat("top", e))?,
+                group: if version >= 5 {
+                    params
+                        .next()
+                        .ok_or(SpriteAtlasAssetReadError::MissingParam("group"))?
+                        .into()
+                } else {
+                    String::new()
+                },
                 source_path: params
                     .next()
                     .ok_or(SpriteAtlasAssetReadError::MissingParam("source_path"))?
@@ -148,8 +500,13 @@ impl SpriteAtlasAsset {
 
         Ok(Self {
             sprites,
+            animations,
             width,
             height,
+            alpha_mode,
+            mip_levels,
+            compression,
+            compression_quality,
         })
     }
 }
@@ -158,6 +515,8 @@ impl SpriteAtlasAsset {
 pub enum SpriteAtlasAssetReadError {
     #[error(transparent)]
     IO(#[from] std::io::Error),
+    #[error("unsupported format version: {0}")]
+    UnsupportedVersion(u32),
     #[error("invalid id: {0}")]
     InvalidID(uuid::Error),
     #[error("missing sprite params")]
@@ -166,4 +525,99 @@ pub enum SpriteAtlasAssetReadError {
     MissingParam(&'static str),
     #[error("invalid param format({0}): {1}")]
     InvalidParamFormat(&'static str, std::num::ParseIntError),
+    #[error("invalid param format({0}): {1}")]
+    InvalidFloatParamFormat(&'static str, std::num::ParseFloatError),
+    #[error("invalid loop mode: {0}")]
+    InvalidLoopMode(String),
+    #[error("invalid alpha mode: {0}")]
+    InvalidAlphaMode(String),
+    #[error("mip_levels must be between 1 and {MAX_MIP_LEVELS}")]
+    InvalidMipLevels,
+    #[error("invalid compression: {0}")]
+    InvalidCompression(String),
+    #[error("invalid compression quality: {0}")]
+    InvalidCompressionQuality(String),
+    #[error("frame line appeared before any anim line")]
+    FrameOutsideAnimation,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset_with_group(group: &str) -> SpriteAtlasAsset {
+        SpriteAtlasAsset {
+            sprites: vec![Sprite {
+                id: Uuid::from_u128(1),
+                name: "hero_idle".into(),
+                source_path: PathBuf::from("chars/hero_idle.png"),
+                source_left: 0,
+                source_top: 0,
+                width: 16,
+                height: 24,
+                left: 32,
+                top: 8,
+                border_left: 0,
+                border_top: 0,
+                border_right: 0,
+                border_bottom: 0,
+                pivot_x: 0.5,
+                pivot_y: 1.0,
+                group: group.into(),
+            }],
+            animations: Vec::new(),
+            width: 64,
+            height: 64,
+            alpha_mode: AlphaMode::Straight,
+            mip_levels: 1,
+            compression: TextureCompression::None,
+            compression_quality: CompressionQuality::Fast,
+        }
+    }
+
+    #[test]
+    fn group_round_trip() {
+        let asset = asset_with_group("chars/hero");
+        let mut buf = Vec::new();
+        asset.write(&mut buf).unwrap();
+
+        let read = SpriteAtlasAsset::read(&mut &buf[..]).unwrap();
+        assert_eq!(read.sprites, asset.sprites);
+    }
+
+    #[test]
+    fn group_with_comma_is_rejected() {
+        let mut buf = Vec::new();
+        let e = asset_with_group("chars/hero,old")
+            .write(&mut buf)
+            .unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn too_many_mip_levels_are_rejected() {
+        let mut asset = asset_with_group("");
+        for mip_levels in [0, MAX_MIP_LEVELS + 1] {
+            asset.mip_levels = mip_levels;
+            let mut buf = Vec::new();
+            let e = asset.write(&mut buf).unwrap_err();
+            assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+            assert!(buf.is_empty());
+        }
+
+        // 手で書き換えられたファイルは読むときに弾く
+        asset.mip_levels = MAX_MIP_LEVELS;
+        let mut buf = Vec::new();
+        asset.write(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap().replacen(
+            &format!(",{MAX_MIP_LEVELS},"),
+            &format!(",{},", MAX_MIP_LEVELS + 1),
+            1,
+        );
+        assert!(matches!(
+            SpriteAtlasAsset::read(&mut text.as_bytes()),
+            Err(SpriteAtlasAssetReadError::InvalidMipLevels)
+        ));
+    }
 }
@@ -0,0 +1,476 @@
+//! Dynamic QuadTree (spatial index of rects)
+//!
+//! http://marupeke296.com/COL_2D_No8_QuadTree.html の線形四分木をベースに、
+//! 要素の追加/削除/移動と範囲検索、最近傍検索をできるようにしたもの
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+/// 矩形（right, bottomも含む）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuadTreeRect {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+impl QuadTreeRect {
+    pub const fn contains(&self, x: u32, y: u32) -> bool {
+        self.left <= x && x <= self.right && self.top <= y && y <= self.bottom
+    }
+
+    pub const fn intersects(&self, other: &Self) -> bool {
+        self.left <= other.right
+            && other.left <= self.right
+            && self.top <= other.bottom
+            && other.top <= self.bottom
+    }
+
+    /// 点からの距離の2乗（中にあれば0）
+    pub const fn distance_squared(&self, x: u32, y: u32) -> u64 {
+        // Note: 片側は必ず0になる
+        let dx = (self.left.saturating_sub(x) + x.saturating_sub(self.right)) as u64;
+        let dy = (self.top.saturating_sub(y) + y.saturating_sub(self.bottom)) as u64;
+
+        dx * dx + dy * dy
+    }
+}
+
+/// ビットを一つおきに分散させる（下位32bitのみ）
+/// 例: 0b11000110 => 0b01_01_00_00_00_01_01_00
+const fn interleave(bits: u64) -> u64 {
+    let bits = bits & 0x0000_0000_ffff_ffff;
+    let bits = (bits | (bits << 16)) & 0x0000_ffff_0000_ffff;
+    let bits = (bits | (bits << 8)) & 0x00ff_00ff_00ff_00ff;
+    let bits = (bits | (bits << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+    let bits = (bits | (bits << 2)) & 0x3333_3333_3333_3333;
+    (bits | (bits << 1)) & 0x5555_5555_5555_5555
+}
+
+/// interleaveの逆（偶数ビットだけを詰める）
+const fn deinterleave(bits: u64) -> u64 {
+    let bits = bits & 0x5555_5555_5555_5555;
+    let bits = (bits | (bits >> 1)) & 0x3333_3333_3333_3333;
+    let bits = (bits | (bits >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+    let bits = (bits | (bits >> 4)) & 0x00ff_00ff_00ff_00ff;
+    let bits = (bits | (bits >> 8)) & 0x0000_ffff_0000_ffff;
+    (bits | (bits >> 16)) & 0x0000_0000_ffff_ffff
+}
+
+#[derive(Default)]
+struct Node {
+    /// Note: 検索時に要素表を引かなくて済むように矩形も一緒に持っておく
+    elements: Vec<(usize, QuadTreeRect)>,
+    /// このノード以下（自身を含む）にある要素の数（空の枝を辿らないようにするため）
+    subtree_count: usize,
+}
+
+struct Element {
+    rect: QuadTreeRect,
+    node_key: u64,
+}
+
+/// ノードは「レベルの番兵ビット + 所属空間の番号」のキーで管理する
+///
+/// ルートが1で、子は`親 << 2 | 0..4`になる（親は`子 >> 2`で求まる）
+pub struct QuadTree {
+    nodes: HashMap<u64, Node>,
+    elements: HashMap<usize, Element>,
+}
+impl QuadTree {
+    /// 最小の分割単位（2^4 = 16px角）
+    const CELL_SIZE_BITS: u32 = 4;
+    /// 分割の最大深さ
+    pub const MAX_LEVEL: u32 = 16;
+    /// 扱える座標の上限（これ以上は`QuadTreeError::OutOfRange`）
+    pub const EXTENT: u32 = 1 << (Self::MAX_LEVEL + Self::CELL_SIZE_BITS);
+    const ROOT_KEY: u64 = 1;
+
+    pub fn new() -> Self {
+        Self {
+            nodes: HashMap::new(),
+            elements: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn rect(&self, n: usize) -> Option<QuadTreeRect> {
+        self.elements.get(&n).map(|e| e.rect)
+    }
+
+    /// 登録されている要素（順不同）
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.elements.keys().copied()
+    }
+
+    fn compute_location_index(x: u32, y: u32) -> Result<u64, QuadTreeError> {
+        if x >= Self::EXTENT || y >= Self::EXTENT {
+            return Err(QuadTreeError::OutOfRange { x, y });
+        }
+
+        let (xv, yv) = (
+            (x >> Self::CELL_SIZE_BITS) as u64,
+            (y >> Self::CELL_SIZE_BITS) as u64,
+        );
+
+        Ok(interleave(xv) | (interleave(yv) << 1))
+    }
+
+    const fn level_of(key: u64) -> u32 {
+        (63 - key.leading_zeros()) / 2
+    }
+
+    /// 矩形を完全に含む最小のノードのキー
+    fn node_key_for_rect(rect: &QuadTreeRect) -> Result<u64, QuadTreeError> {
+        if rect.left > rect.right || rect.top > rect.bottom {
+            return Err(QuadTreeError::InvalidRect(*rect));
+        }
+
+        let lt_location = Self::compute_location_index(rect.left, rect.top)?;
+        let rb_location = Self::compute_location_index(rect.right, rect.bottom)?;
+        // xorをとるとズレているレベルの2bitが00にならないので、それでどの分割レベルで跨いでいないかを判定できる
+        let xor = lt_location ^ rb_location;
+        let differing_levels = (64 - xor.leading_zeros()).div_ceil(2);
+        let level = Self::MAX_LEVEL - differing_levels;
+        let index = lt_location >> (differing_levels * 2);
+
+        Ok((1 << (level * 2)) | index)
+    }
+
+    /// ノードが覆う範囲
+    fn node_rect(key: u64) -> QuadTreeRect {
+        let level = Self::level_of(key);
+        let index = key & !(1 << (level * 2));
+        let size_bits = Self::MAX_LEVEL - level + Self::CELL_SIZE_BITS;
+        let (left, top) = (
+            (deinterleave(index) as u32) << size_bits,
+            (deinterleave(index >> 1) as u32) << size_bits,
+        );
+
+        QuadTreeRect {
+            left,
+            top,
+            right: left + ((1 << size_bits) - 1),
+            bottom: top + ((1 << size_bits) - 1),
+        }
+    }
+
+    fn bind(&mut self, key: u64, n: usize, rect: QuadTreeRect) {
+        self.nodes.entry(key).or_default().elements.push((n, rect));
+
+        let mut k = key;
+        loop {
+            self.nodes.entry(k).or_default().subtree_count += 1;
+            if k == Self::ROOT_KEY {
+                break;
+            }
+            k >>= 2;
+        }
+    }
+
+    fn unbind(&mut self, key: u64, n: usize) {
+        if let Some(x) = self.nodes.get_mut(&key)
+            && let Some(p) = x.elements.iter().position(|&(e, _)| e == n)
+        {
+            x.elements.swap_remove(p);
+        }
+
+        let mut k = key;
+        loop {
+            let x = self
+                .nodes
+                .get_mut(&k)
+                .expect("ancestor nodes must exist while bound");
+            x.subtree_count -= 1;
+            if x.subtree_count == 0 {
+                // 空になった枝は消しておく
+                self.nodes.remove(&k);
+            }
+            if k == Self::ROOT_KEY {
+                break;
+            }
+            k >>= 2;
+        }
+    }
+
+    /// 要素を追加する（すでにあれば置き換えて、前の矩形を返す）
+    pub fn insert(
+        &mut self,
+        n: usize,
+        rect: QuadTreeRect,
+    ) -> Result<Option<QuadTreeRect>, QuadTreeError> {
+        let node_key = Self::node_key_for_rect(&rect)?;
+
+        let old = self.elements.insert(n, Element { rect, node_key });
+        if let Some(ref o) = old {
+            if o.node_key == node_key {
+                // 所属ノードに変化なし（矩形だけ差し替える）
+                let x = self.nodes.get_mut(&node_key).unwrap();
+                if let Some(e) = x.elements.iter_mut().find(|(e, _)| *e == n) {
+                    e.1 = rect;
+                }
+
+                return Ok(Some(o.rect));
+            }
+
+            self.unbind(o.node_key, n);
+        }
+        self.bind(node_key, n, rect);
+
+        Ok(old.map(|x| x.rect))
+    }
+
+    /// 要素を移動する
+    ///
+    /// 範囲外への移動はエラーになり、そのときは元の位置のまま残る
+    pub fn move_to(&mut self, n: usize, rect: QuadTreeRect) -> Result<(), QuadTreeError> {
+        if !self.elements.contains_key(&n) {
+            return Err(QuadTreeError::NotFound(n));
+        }
+
+        self.insert(n, rect).map(drop)
+    }
+
+    pub fn remove(&mut self, n: usize) -> Option<QuadTreeRect> {
+        let e = self.elements.remove(&n)?;
+        self.unbind(e.node_key, n);
+
+        Some(e.rect)
+    }
+
+    /// 点を含む要素を列挙する（順不同）
+    pub fn iter_at_point(&self, x: u32, y: u32) -> impl Iterator<Item = usize> + '_ {
+        // 範囲外の点を含む要素はない
+        let location = Self::compute_location_index(x, y).ok();
+
+        (0..=Self::MAX_LEVEL)
+            .filter_map(move |level| {
+                let location = location?;
+                let key = (1 << (level * 2)) | (location >> ((Self::MAX_LEVEL - level) * 2));
+
+                self.nodes.get(&key)
+            })
+            .flat_map(|node| node.elements.iter())
+            .filter_map(move |&(n, r)| r.contains(x, y).then_some(n))
+    }
+
+    /// 矩形と重なる要素を列挙する（順不同）
+    pub fn query_rect(&self, rect: &QuadTreeRect) -> Vec<usize> {
+        let mut result = Vec::new();
+        if !self.nodes.contains_key(&Self::ROOT_KEY) {
+            return result;
+        }
+
+        let mut stack = vec![Self::ROOT_KEY];
+        while let Some(key) = stack.pop() {
+            let node = &self.nodes[&key];
+            result.extend(
+                node.elements
+                    .iter()
+                    .filter_map(|&(n, r)| r.intersects(rect).then_some(n)),
+            );
+
+            if Self::level_of(key) == Self::MAX_LEVEL {
+                continue;
+            }
+            for c in 0..4 {
+                let child_key = (key << 2) | c;
+                if self.nodes.contains_key(&child_key)
+                    && Self::node_rect(child_key).intersects(rect)
+                {
+                    stack.push(child_key);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// 点に最も近い要素（同じ距離なら大きいインデックスのもの）
+    pub fn nearest(&self, x: u32, y: u32) -> Option<usize> {
+        if !self.nodes.contains_key(&Self::ROOT_KEY) {
+            return None;
+        }
+
+        let mut best = None::<(u64, usize)>;
+        // Note: ノードまでの距離が近い順に見ていき、いまの最良より遠いノードが出てきたら打ち切る
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse((
+            Self::node_rect(Self::ROOT_KEY).distance_squared(x, y),
+            Self::ROOT_KEY,
+        )));
+        while let Some(Reverse((node_distance, key))) = queue.pop() {
+            if best.is_some_and(|(d, _)| node_distance > d) {
+                break;
+            }
+
+            for &(n, r) in self.nodes[&key].elements.iter() {
+                let d = r.distance_squared(x, y);
+                if best.is_none_or(|(bd, bn)| d < bd || (d == bd && n > bn)) {
+                    best = Some((d, n));
+                }
+            }
+
+            if Self::level_of(key) == Self::MAX_LEVEL {
+                continue;
+            }
+            for c in 0..4 {
+                let child_key = (key << 2) | c;
+                if self.nodes.contains_key(&child_key) {
+                    queue.push(Reverse((
+                        Self::node_rect(child_key).distance_squared(x, y),
+                        child_key,
+                    )));
+                }
+            }
+        }
+
+        best.map(|(_, n)| n)
+    }
+}
+
+impl Default for QuadTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum QuadTreeError {
+    #[error("location ({x}, {y}) is out of the supported range")]
+    OutOfRange { x: u32, y: u32 },
+    #[error("invalid rect: {0:?}")]
+    InvalidRect(QuadTreeRect),
+    #[error("element #{0} not found")]
+    NotFound(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テスト用の決まった並びの乱数（xorshift）
+    struct XorShift(u32);
+    impl XorShift {
+        fn next(&mut self, bound: u32) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+
+            self.0 % bound
+        }
+
+        fn rect(&mut self) -> QuadTreeRect {
+            let (left, top) = (self.next(4096), self.next(4096));
+
+            QuadTreeRect {
+                left,
+                top,
+                right: left + self.next(256),
+                bottom: top + self.next(256),
+            }
+        }
+    }
+
+    const fn rect(left: u32, top: u32, right: u32, bottom: u32) -> QuadTreeRect {
+        QuadTreeRect {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    #[test]
+    fn insert_move_remove() {
+        let mut tree = QuadTree::default();
+        assert_eq!(tree.insert(0, rect(0, 0, 8, 8)).unwrap(), None);
+        assert_eq!(tree.insert(1, rect(100, 100, 200, 120)).unwrap(), None);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.iter_at_point(4, 4).collect::<Vec<_>>(), [0]);
+
+        tree.move_to(0, rect(1000, 1000, 1010, 1010)).unwrap();
+        assert_eq!(tree.iter_at_point(4, 4).count(), 0);
+        assert_eq!(tree.iter_at_point(1005, 1010).collect::<Vec<_>>(), [0]);
+        assert!(matches!(
+            tree.move_to(5, rect(0, 0, 1, 1)),
+            Err(QuadTreeError::NotFound(5))
+        ));
+
+        assert_eq!(tree.remove(1), Some(rect(100, 100, 200, 120)));
+        assert_eq!(tree.remove(1), None);
+        assert_eq!(tree.remove(0), Some(rect(1000, 1000, 1010, 1010)));
+        assert!(tree.is_empty());
+        // 空になった枝は残らない
+        assert!(tree.nodes.is_empty());
+    }
+
+    #[test]
+    fn invalid_inputs_are_rejected() {
+        let mut tree = QuadTree::new();
+        tree.insert(0, rect(0, 0, 1, 1)).unwrap();
+
+        assert!(matches!(
+            tree.insert(1, rect(0, 0, QuadTree::EXTENT, 1)),
+            Err(QuadTreeError::OutOfRange { x, .. }) if x == QuadTree::EXTENT
+        ));
+        assert!(matches!(
+            tree.insert(1, rect(5, 0, 4, 1)),
+            Err(QuadTreeError::InvalidRect(_))
+        ));
+        // 失敗した移動では元の位置のまま
+        assert!(tree.move_to(0, rect(0, 0, 1, QuadTree::EXTENT)).is_err());
+        assert_eq!(tree.rect(0), Some(rect(0, 0, 1, 1)));
+        assert_eq!(tree.iter_at_point(QuadTree::EXTENT, 0).count(), 0);
+    }
+
+    #[test]
+    fn queries_match_brute_force() {
+        let mut random = XorShift(0x1234_5678);
+        let mut tree = QuadTree::new();
+        let mut rects = HashMap::new();
+        for n in 0..300 {
+            let r = random.rect();
+            tree.insert(n, r).unwrap();
+            rects.insert(n, r);
+        }
+        for n in (0..300).step_by(3) {
+            let r = random.rect();
+            tree.move_to(n, r).unwrap();
+            rects.insert(n, r);
+        }
+        for n in (1..300).step_by(7) {
+            tree.remove(n);
+            rects.remove(&n);
+        }
+
+        for _ in 0..100 {
+            let query = random.rect();
+            let mut expected = rects
+                .iter()
+                .filter_map(|(&n, r)| r.intersects(&query).then_some(n))
+                .collect::<Vec<_>>();
+            let mut found = tree.query_rect(&query);
+            expected.sort();
+            found.sort();
+            assert_eq!(found, expected);
+
+            let (x, y) = (random.next(5000), random.next(5000));
+            let expected = rects
+                .iter()
+                .map(|(&n, r)| (r.distance_squared(x, y), Reverse(n)))
+                .min()
+                .map(|(_, Reverse(n))| n);
+            assert_eq!(tree.nearest(x, y), expected);
+        }
+    }
+}
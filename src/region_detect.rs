@@ -0,0 +1,196 @@
+//! Automatic sprite detection by connected opaque regions
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RegionDetectParams {
+    /// これより大きいアルファ値のピクセルを不透明とみなす
+    pub alpha_threshold: u8,
+    /// 矩形同士の隙間がこのピクセル数以下なら一つの領域にまとめる
+    pub merge_distance: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetectedRegion {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+impl DetectedRegion {
+    pub const fn right(&self) -> u32 {
+        self.left + self.width
+    }
+
+    pub const fn bottom(&self) -> u32 {
+        self.top + self.height
+    }
+
+    /// 横方向・縦方向それぞれの隙間（重なっている場合は0）
+    fn gap(&self, other: &Self) -> (u32, u32) {
+        (
+            self.left
+                .max(other.left)
+                .saturating_sub(self.right().min(other.right())),
+            self.top
+                .max(other.top)
+                .saturating_sub(self.bottom().min(other.bottom())),
+        )
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        let (left, top) = (self.left.min(other.left), self.top.min(other.top));
+        let (right, bottom) = (
+            self.right().max(other.right()),
+            self.bottom().max(other.bottom()),
+        );
+
+        Self {
+            left,
+            top,
+            width: right - left,
+            height: bottom - top,
+        }
+    }
+}
+
+/// 8近傍で連結した不透明ピクセルの塊を検出して、その外接矩形を上から順に返す
+pub fn detect(image: &image::DynamicImage, params: &RegionDetectParams) -> Vec<DetectedRegion> {
+    let image = image.to_rgba8();
+    let (width, height) = image.dimensions();
+    let opaque = image
+        .pixels()
+        .map(|p| p.0[3] > params.alpha_threshold)
+        .collect::<Vec<_>>();
+
+    let mut visited = vec![false; opaque.len()];
+    let mut regions = Vec::new();
+    let mut stack = Vec::new();
+    for start in 0..opaque.len() {
+        if !opaque[start] || visited[start] {
+            continue;
+        }
+
+        visited[start] = true;
+        stack.push(start);
+        let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+        while let Some(n) = stack.pop() {
+            let (x, y) = ((n % width as usize) as u32, (n / width as usize) as u32);
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x + 1);
+            bottom = bottom.max(y + 1);
+
+            for (dx, dy) in [
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ] {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+
+                let nn = ny as usize * width as usize + nx as usize;
+                if opaque[nn] && !visited[nn] {
+                    visited[nn] = true;
+                    stack.push(nn);
+                }
+            }
+        }
+
+        regions.push(DetectedRegion {
+            left,
+            top,
+            width: right - left,
+            height: bottom - top,
+        });
+    }
+
+    // 近いもの同士をまとめる（まとめた結果さらに近くなるものがあるので変化がなくなるまで繰り返す）
+    let mut merged = true;
+    while merged {
+        merged = false;
+        let mut n = 0;
+        while n < regions.len() {
+            let mut m = n + 1;
+            while m < regions.len() {
+                let (gx, gy) = regions[n].gap(&regions[m]);
+                if gx <= params.merge_distance && gy <= params.merge_distance {
+                    regions[n] = regions[n].union(&regions[m]);
+                    regions.swap_remove(m);
+                    merged = true;
+                    continue;
+                }
+
+                m += 1;
+            }
+
+            n += 1;
+        }
+    }
+
+    regions.sort_by_key(|r| (r.top, r.left));
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_with(width: u32, height: u32, pixels: &[(u32, u32, u8)]) -> image::DynamicImage {
+        let mut image = image::RgbaImage::new(width, height);
+        for &(x, y, a) in pixels {
+            image.put_pixel(x, y, image::Rgba([255, 255, 255, a]));
+        }
+
+        image::DynamicImage::ImageRgba8(image)
+    }
+
+    const fn region(left: u32, top: u32, width: u32, height: u32) -> DetectedRegion {
+        DetectedRegion {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn diagonal_pixels_are_connected() {
+        let image = image_with(8, 8, &[(1, 1, 255), (2, 2, 255), (3, 1, 255), (6, 6, 255)]);
+        assert_eq!(
+            detect(&image, &RegionDetectParams::default()),
+            [region(1, 1, 3, 2), region(6, 6, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn pixels_under_threshold_are_transparent() {
+        let image = image_with(4, 4, &[(0, 0, 10), (2, 2, 11)]);
+        let params = RegionDetectParams {
+            alpha_threshold: 10,
+            ..Default::default()
+        };
+        assert_eq!(detect(&image, &params), [region(2, 2, 1, 1)]);
+    }
+
+    #[test]
+    fn near_regions_are_merged() {
+        // 隙間は左の2つが2ピクセル、右が3ピクセル
+        let image = image_with(16, 4, &[(0, 0, 255), (3, 0, 255), (7, 1, 255)]);
+        let params = |merge_distance| RegionDetectParams {
+            alpha_threshold: 0,
+            merge_distance,
+        };
+        assert_eq!(detect(&image, &params(1)).len(), 3);
+        assert_eq!(
+            detect(&image, &params(2)),
+            [region(0, 0, 4, 1), region(7, 1, 1, 1)]
+        );
+        assert_eq!(detect(&image, &params(3)), [region(0, 0, 8, 2)]);
+    }
+}
@@ -0,0 +1,253 @@
+//! Rust source code generation of sprite constants for the Peridot runtime
+
+use std::{collections::BTreeMap, fmt::Write as _, io::Write};
+
+use crate::peridot::{Sprite, SpriteAtlasAsset};
+
+pub fn write(
+    asset: &SpriteAtlasAsset,
+    source_name: &str,
+    sink: &mut (impl Write + ?Sized),
+) -> Result<(), GenerateError> {
+    let mut sprites_by_ident = BTreeMap::<_, Vec<&Sprite>>::new();
+    for s in asset.sprites.iter() {
+        sprites_by_ident
+            .entry(identifier_for(s))
+            .or_default()
+            .push(s);
+    }
+
+    let collisions = sprites_by_ident
+        .iter()
+        .filter(|(_, xs)| xs.len() > 1)
+        .map(|(ident, xs)| IdentifierCollision {
+            ident: ident.clone(),
+            names: xs.iter().map(|x| x.name.clone()).collect(),
+        })
+        .collect::<Vec<_>>();
+    if !collisions.is_empty() {
+        return Err(GenerateError::IdentifierCollisions(collisions));
+    }
+
+    writeln!(
+        sink,
+        "// Generated from {source_name} by peridot-sprite-atlas-visualizer. DO NOT EDIT."
+    )?;
+    writeln!(sink)?;
+    writeln!(sink, "#[derive(Debug, Clone, Copy, PartialEq)]")?;
+    writeln!(sink, "pub struct SpriteDef {{")?;
+    writeln!(sink, "    pub id: u128,")?;
+    writeln!(sink, "    /// left, top, width, height (pixels)")?;
+    writeln!(sink, "    pub rect: [u32; 4],")?;
+    writeln!(sink, "    /// uv = base * uv_st.xy + uv_st.zw")?;
+    writeln!(sink, "    pub uv_st: [f32; 4],")?;
+    writeln!(sink, "    /// left, top, right, bottom (pixels)")?;
+    writeln!(sink, "    pub insets: [u32; 4],")?;
+    writeln!(sink, "    /// x, y (normalized, top-left origin)")?;
+    writeln!(sink, "    pub pivot: [f32; 2],")?;
+    writeln!(sink, "}}")?;
+
+    for (ident, xs) in sprites_by_ident.iter() {
+        let s = xs[0];
+        // AtlasBaseGridView::update_spritesと同じ計算（アトラス全体を1とした拡縮+平行移動）
+        let uv_st = [
+            s.width as f32 / asset.width as f32,
+            s.height as f32 / asset.height as f32,
+            s.left as f32 / asset.width as f32,
+            s.top as f32 / asset.height as f32,
+        ];
+
+        writeln!(sink)?;
+        writeln!(sink, "/// {}", s.name)?;
+        writeln!(sink, "pub const {ident}: SpriteDef = SpriteDef {{")?;
+        writeln!(sink, "    id: 0x{},", s.id.as_simple())?;
+        writeln!(
+            sink,
+            "    rect: [{}, {}, {}, {}],",
+            s.left, s.top, s.width, s.height
+        )?;
+        writeln!(
+            sink,
+            "    uv_st: [{:?}, {:?}, {:?}, {:?}],",
+            uv_st[0], uv_st[1], uv_st[2], uv_st[3]
+        )?;
+        writeln!(
+            sink,
+            "    insets: [{}, {}, {}, {}],",
+            s.border_left, s.border_top, s.border_right, s.border_bottom
+        )?;
+        writeln!(sink, "    pivot: [{:?}, {:?}],", s.pivot_x, s.pivot_y)?;
+        writeln!(sink, "}};")?;
+    }
+
+    Ok(())
+}
+
+/// スプライトの定数名
+///
+/// Note: 日本語などASCII以外を含む名前はそのままでは識別子にできず、残った部分だけだと空になったりほかとぶつかったりするので、
+/// IDから作った名前にする（名前を変えても変わらない）
+fn identifier_for(sprite: &Sprite) -> String {
+    sprite
+        .name
+        .is_ascii()
+        .then(|| sanitize_identifier(&sprite.name))
+        .flatten()
+        .unwrap_or_else(|| {
+            format!(
+                "SPRITE_{}",
+                sprite.id.as_simple().to_string().to_ascii_uppercase()
+            )
+        })
+}
+
+/// スプライト名をSCREAMING_SNAKE_CASEの識別子にする（使えない文字は`_`に置き換える）
+///
+/// 例: `walkLeft-0` => `WALK_LEFT_0`
+pub fn sanitize_identifier(name: &str) -> Option<String> {
+    let mut ident = String::with_capacity(name.len());
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            if c.is_ascii_uppercase() && prev_lower {
+                // camelCaseの区切り
+                ident.push('_');
+            }
+            ident.push(c.to_ascii_uppercase());
+            prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        } else {
+            if !ident.ends_with('_') {
+                ident.push('_');
+            }
+            prev_lower = false;
+        }
+    }
+    let ident = ident.trim_matches('_');
+    if ident.is_empty() {
+        return None;
+    }
+
+    if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        // 数字始まりは識別子にできない
+        return Some(format!("_{ident}"));
+    }
+
+    Some(ident.into())
+}
+
+#[derive(Debug)]
+pub struct IdentifierCollision {
+    pub ident: String,
+    pub names: Vec<String>,
+}
+
+fn format_collisions(collisions: &[IdentifierCollision]) -> String {
+    let mut s = String::new();
+    for c in collisions {
+        let _ = write!(s, "\n  {}: {}", c.ident, c.names.join(", "));
+    }
+
+    s
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GenerateError {
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+    #[error("identifier collisions:{}", format_collisions(.0))]
+    IdentifierCollisions(Vec<IdentifierCollision>),
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::peridot::{AlphaMode, CompressionQuality, TextureCompression};
+
+    fn asset(names: &[&str]) -> SpriteAtlasAsset {
+        SpriteAtlasAsset {
+            sprites: names
+                .iter()
+                .enumerate()
+                .map(|(n, name)| Sprite {
+                    id: Uuid::from_u128(n as u128 + 1),
+                    name: (*name).into(),
+                    source_path: "sprites.png".into(),
+                    source_left: 0,
+                    source_top: 0,
+                    width: 32,
+                    height: 16,
+                    left: 16,
+                    top: 8,
+                    border_left: 0,
+                    border_top: 0,
+                    border_right: 0,
+                    border_bottom: 0,
+                    pivot_x: 0.5,
+                    pivot_y: 1.0,
+                    group: String::new(),
+                })
+                .collect(),
+            animations: Vec::new(),
+            width: 64,
+            height: 32,
+            alpha_mode: AlphaMode::Straight,
+            mip_levels: 1,
+            compression: TextureCompression::None,
+            compression_quality: CompressionQuality::Fast,
+        }
+    }
+
+    fn generate(names: &[&str]) -> Result<String, GenerateError> {
+        let mut buf = Vec::new();
+        write(&asset(names), "sprites.psa", &mut buf)?;
+
+        Ok(String::from_utf8(buf).unwrap())
+    }
+
+    #[test]
+    fn names_become_screaming_snake_case() {
+        assert_eq!(
+            sanitize_identifier("walkLeft-0").as_deref(),
+            Some("WALK_LEFT_0")
+        );
+        assert_eq!(
+            sanitize_identifier("hero idle").as_deref(),
+            Some("HERO_IDLE")
+        );
+        assert_eq!(sanitize_identifier("__a--b__").as_deref(), Some("A_B"));
+        assert_eq!(sanitize_identifier("9slice").as_deref(), Some("_9SLICE"));
+        assert_eq!(sanitize_identifier("---"), None);
+    }
+
+    #[test]
+    fn non_ascii_names_fall_back_to_id() {
+        let code = generate(&["剣", "盾_01", "---"]).unwrap();
+        assert!(code.contains("pub const SPRITE_00000000000000000000000000000001: SpriteDef"));
+        assert!(code.contains("pub const SPRITE_00000000000000000000000000000002: SpriteDef"));
+        assert!(code.contains("pub const SPRITE_00000000000000000000000000000003: SpriteDef"));
+        assert!(code.contains("/// 剣\n"));
+    }
+
+    #[test]
+    fn collisions_are_reported() {
+        let Err(GenerateError::IdentifierCollisions(collisions)) =
+            generate(&["walk-left", "walk_left", "idle"])
+        else {
+            panic!("collision must be reported");
+        };
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].ident, "WALK_LEFT");
+        assert_eq!(collisions[0].names, ["walk-left", "walk_left"]);
+    }
+
+    #[test]
+    fn uv_is_relative_to_atlas() {
+        let code = generate(&["hero"]).unwrap();
+        assert!(code.contains("pub const HERO: SpriteDef = SpriteDef {\n"));
+        assert!(code.contains("    rect: [16, 8, 32, 16],\n"));
+        assert!(code.contains("    uv_st: [0.5, 0.5, 0.25, 0.25],\n"));
+        assert!(code.contains("    pivot: [0.5, 1.0],\n"));
+    }
+}
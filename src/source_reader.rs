@@ -1 +1,26 @@
+use std::path::Path;
+
 pub mod png;
+
+/// スプライトのソースとして使える画像なら、その大きさを返す
+///
+/// PNGのほかはHDRのもの（OpenEXR/Radiance HDR）だけを受け付ける
+pub fn read_dimensions(path: &Path) -> Option<(u32, u32)> {
+    let mut fs = std::fs::File::open(path).ok()?;
+    if let Some(png_meta) = png::Metadata::try_read(&mut fs) {
+        return Some((png_meta.width, png_meta.height));
+    }
+
+    let reader = image::ImageReader::open(path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?;
+    if !matches!(
+        reader.format(),
+        Some(image::ImageFormat::OpenExr | image::ImageFormat::Hdr)
+    ) {
+        return None;
+    }
+
+    reader.into_dimensions().ok()
+}
@@ -0,0 +1,167 @@
+//! ソース画像の大きさが変わっていたときの確認ダイアログ（TaskDialog）
+
+use windows::{
+    Win32::{
+        Foundation::HWND,
+        UI::Controls::{
+            TASKDIALOG_BUTTON, TASKDIALOGCONFIG, TASKDIALOGCONFIG_0, TD_INFORMATION_ICON,
+            TD_WARNING_ICON, TDCBF_CLOSE_BUTTON, TDF_ALLOW_DIALOG_CANCELLATION,
+            TDF_POSITION_RELATIVE_TO_WINDOW, TDF_USE_COMMAND_LINKS, TDF_VERIFICATION_FLAG_CHECKED,
+            TaskDialogIndirect,
+        },
+    },
+    core::PCWSTR,
+};
+use windows_core::{BOOL, HSTRING};
+
+use crate::app_state::{AnchorCorner, SourceSizeMismatch};
+
+/// 本文に並べる最大の件数（残りは件数だけ出す）
+const MAX_LISTED_ITEMS: usize = 10;
+
+const UPDATE_BUTTON_ID: i32 = 100;
+const KEEP_BUTTON_ID: i32 = 101;
+
+const ANCHORS: [(AnchorCorner, &str); 4] = [
+    (AnchorCorner::TopLeft, "左上の角を動かさない"),
+    (AnchorCorner::TopRight, "右上の角を動かさない"),
+    (AnchorCorner::BottomLeft, "左下の角を動かさない"),
+    (AnchorCorner::BottomRight, "右下の角を動かさない"),
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReconcileChoice {
+    pub anchor: AnchorCorner,
+    pub clamp_slices: bool,
+}
+
+fn list_items<T>(items: &[T], mut line: impl FnMut(&T) -> String) -> String {
+    let mut s = items
+        .iter()
+        .take(MAX_LISTED_ITEMS)
+        .map(&mut line)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if items.len() > MAX_LISTED_ITEMS {
+        s.push_str(&format!("\n...ほか{}件", items.len() - MAX_LISTED_ITEMS));
+    }
+
+    s
+}
+
+/// 大きさを合わせるかどうかを聞く（合わせないならNone）
+///
+/// Note: ダイアログを閉じるまで戻ってこないので、AppStateを借用しているビュースレッドからは呼ばないこと
+pub fn ask(owner: HWND, mismatches: &[SourceSizeMismatch]) -> Option<ReconcileChoice> {
+    let instruction = HSTRING::from(format!(
+        "{}個のスプライトがソース画像の大きさと合わなくなっています",
+        mismatches.len()
+    ));
+    let content = HSTRING::from(list_items(mismatches, |m| {
+        let (w, h) = m.stored_size;
+        match m.fitted_size {
+            (0, _) | (_, 0) => format!(
+                "{}: {w}x{h}は{}の外にあります（そのままにします）",
+                m.name,
+                m.source_path.display()
+            ),
+            (fw, fh) => format!("{}: {w}x{h} -> {fw}x{fh}", m.name),
+        }
+    }));
+    let title = HSTRING::from("ソース画像が変更されています");
+    let update_label =
+        HSTRING::from("大きさを合わせる\nスプライトの大きさをソース画像に合わせます");
+    let keep_label = HSTRING::from(
+        "記録している大きさのままにする\nソース画像を直すまで、合わないスプライトは表示されません",
+    );
+    let verification = HSTRING::from("9スライスの幅を新しい大きさに収める");
+    let buttons = [
+        TASKDIALOG_BUTTON {
+            nButtonID: UPDATE_BUTTON_ID,
+            pszButtonText: PCWSTR(update_label.as_ptr()),
+        },
+        TASKDIALOG_BUTTON {
+            nButtonID: KEEP_BUTTON_ID,
+            pszButtonText: PCWSTR(keep_label.as_ptr()),
+        },
+    ];
+    let anchor_labels = ANCHORS.map(|(_, l)| HSTRING::from(l));
+    let radio_buttons = core::array::from_fn::<_, 4, _>(|n| TASKDIALOG_BUTTON {
+        nButtonID: n as _,
+        pszButtonText: PCWSTR(anchor_labels[n].as_ptr()),
+    });
+
+    let config = TASKDIALOGCONFIG {
+        cbSize: core::mem::size_of::<TASKDIALOGCONFIG>() as _,
+        hwndParent: owner,
+        dwFlags: TDF_ALLOW_DIALOG_CANCELLATION
+            | TDF_POSITION_RELATIVE_TO_WINDOW
+            | TDF_USE_COMMAND_LINKS
+            | TDF_VERIFICATION_FLAG_CHECKED,
+        pszWindowTitle: PCWSTR(title.as_ptr()),
+        Anonymous1: TASKDIALOGCONFIG_0 {
+            pszMainIcon: TD_WARNING_ICON,
+        },
+        pszMainInstruction: PCWSTR(instruction.as_ptr()),
+        pszContent: PCWSTR(content.as_ptr()),
+        cButtons: buttons.len() as _,
+        pButtons: buttons.as_ptr(),
+        nDefaultButton: UPDATE_BUTTON_ID,
+        cRadioButtons: radio_buttons.len() as _,
+        pRadioButtons: radio_buttons.as_ptr(),
+        nDefaultRadioButton: 0,
+        pszVerificationText: PCWSTR(verification.as_ptr()),
+        ..Default::default()
+    };
+    let (mut button, mut radio_button, mut clamp_slices) = (0, 0, BOOL(0));
+    if let Err(e) = unsafe {
+        TaskDialogIndirect(
+            &config,
+            Some(&mut button),
+            Some(&mut radio_button),
+            Some(&mut clamp_slices),
+        )
+    } {
+        tracing::warn!({ %e }, "TaskDialogIndirect failed");
+        return None;
+    }
+
+    if button != UPDATE_BUTTON_ID {
+        // 閉じられたときも記録している大きさのままにする
+        return None;
+    }
+
+    Some(ReconcileChoice {
+        anchor: ANCHORS
+            .get(radio_button as usize)
+            .map_or(AnchorCorner::TopLeft, |&(a, _)| a),
+        clamp_slices: clamp_slices.as_bool(),
+    })
+}
+
+/// 大きさを合わせたことで重なるようになったスプライトを知らせる
+pub fn report_overlaps(owner: HWND, overlaps: &[(String, String)]) {
+    let title = HSTRING::from("ソース画像が変更されています");
+    let instruction = HSTRING::from(format!(
+        "大きさを合わせたことで{}組のスプライトが重なりました",
+        overlaps.len()
+    ));
+    let content = HSTRING::from(list_items(overlaps, |(a, b)| format!("{a} と {b}")));
+
+    let config = TASKDIALOGCONFIG {
+        cbSize: core::mem::size_of::<TASKDIALOGCONFIG>() as _,
+        hwndParent: owner,
+        dwFlags: TDF_ALLOW_DIALOG_CANCELLATION | TDF_POSITION_RELATIVE_TO_WINDOW,
+        dwCommonButtons: TDCBF_CLOSE_BUTTON,
+        pszWindowTitle: PCWSTR(title.as_ptr()),
+        Anonymous1: TASKDIALOGCONFIG_0 {
+            pszMainIcon: TD_INFORMATION_ICON,
+        },
+        pszMainInstruction: PCWSTR(instruction.as_ptr()),
+        pszContent: PCWSTR(content.as_ptr()),
+        ..Default::default()
+    };
+    if let Err(e) = unsafe { TaskDialogIndirect(&config, None, None, None) } {
+        tracing::warn!({ %e }, "TaskDialogIndirect failed");
+    }
+}
@@ -0,0 +1,180 @@
+//! Sprite Search Query
+//!
+//! 空白区切りの条件をすべて満たすスプライトにマッチする
+//!
+//! - `walk` : 名前に含まれる（大文字小文字は区別しない）
+//! - `walk_*` : 名前がglobにマッチする（`*`と`?`が使える）
+//! - `path:chars/hero` : ソース画像のパスに含まれる
+//! - `w:16..64`, `h:..32`, `w:24` : 幅/高さの範囲（両端を含む、片側は省略可）
+//! - `has:slice` : 9-sliceの設定がある
+
+use std::path::PathBuf;
+
+use crate::app_state::SpriteInfo;
+
+/// フィルタの判定に必要なスプライトの情報（Viewで保持しておくためのもの）
+#[derive(Debug, Clone)]
+pub struct SpriteFilterTarget {
+    pub name: String,
+    pub source_path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub has_nine_slice: bool,
+}
+impl SpriteFilterTarget {
+    pub fn from_sprite(sprite: &SpriteInfo) -> Self {
+        Self {
+            name: sprite.name.clone(),
+            source_path: sprite.source_path.clone(),
+            width: sprite.width,
+            height: sprite.height,
+            has_nine_slice: sprite.left_slice != 0
+                || sprite.right_slice != 0
+                || sprite.top_slice != 0
+                || sprite.bottom_slice != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SizeRange {
+    min: Option<u32>,
+    max: Option<u32>,
+}
+impl SizeRange {
+    fn parse(s: &str) -> Option<Self> {
+        let Some((min, max)) = s.split_once("..") else {
+            let v = s.parse().ok()?;
+            return Some(Self {
+                min: Some(v),
+                max: Some(v),
+            });
+        };
+
+        let parse_bound = |s: &str| {
+            if s.is_empty() {
+                Some(None)
+            } else {
+                s.parse().ok().map(Some)
+            }
+        };
+
+        Some(Self {
+            min: parse_bound(min)?,
+            max: parse_bound(max)?,
+        })
+    }
+
+    fn contains(&self, v: u32) -> bool {
+        self.min.is_none_or(|x| x <= v) && self.max.is_none_or(|x| v <= x)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    NameContains(String),
+    NameGlob(Vec<char>),
+    PathContains(String),
+    Width(SizeRange),
+    Height(SizeRange),
+    HasNineSlice,
+}
+impl Term {
+    fn parse(token: &str) -> Result<Self, SpriteFilterParseError> {
+        let Some((key, value)) = token.split_once(':') else {
+            let pattern = token.to_lowercase();
+            if pattern.contains(['*', '?']) {
+                return Ok(Self::NameGlob(pattern.chars().collect()));
+            }
+
+            return Ok(Self::NameContains(pattern));
+        };
+
+        match key {
+            "path" => Ok(Self::PathContains(value.to_lowercase().replace('\\', "/"))),
+            "w" => SizeRange::parse(value)
+                .map(Self::Width)
+                .ok_or_else(|| SpriteFilterParseError::InvalidRange(value.into())),
+            "h" => SizeRange::parse(value)
+                .map(Self::Height)
+                .ok_or_else(|| SpriteFilterParseError::InvalidRange(value.into())),
+            "has" if value == "slice" => Ok(Self::HasNineSlice),
+            _ => Err(SpriteFilterParseError::UnknownCondition(token.into())),
+        }
+    }
+
+    fn matches(&self, target: &SpriteFilterTarget) -> bool {
+        match self {
+            Self::NameContains(x) => target.name.to_lowercase().contains(x.as_str()),
+            Self::NameGlob(x) => {
+                glob_match(x, &target.name.to_lowercase().chars().collect::<Vec<_>>())
+            }
+            Self::PathContains(x) => target
+                .source_path
+                .to_string_lossy()
+                .to_lowercase()
+                .replace('\\', "/")
+                .contains(x.as_str()),
+            Self::Width(r) => r.contains(target.width),
+            Self::Height(r) => r.contains(target.height),
+            Self::HasNineSlice => target.has_nine_slice,
+        }
+    }
+}
+
+/// `*`（0文字以上）と`?`（1文字）だけのglob
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // 最後に見た`*`の位置と、そこからマッチを始めたテキスト位置
+    let mut backtrack = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((bp, bt)) = backtrack {
+            // `*`に1文字多く食わせてやり直す
+            backtrack = Some((bp, bt + 1));
+            p = bp + 1;
+            t = bt + 1;
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[derive(Debug, Clone)]
+pub struct SpriteFilter {
+    terms: Vec<Term>,
+}
+impl SpriteFilter {
+    /// 空のクエリ（条件なし）はNone
+    pub fn parse(query: &str) -> Result<Option<Self>, SpriteFilterParseError> {
+        let terms = query
+            .split_whitespace()
+            .map(Term::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if terms.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self { terms }))
+    }
+
+    pub fn matches(&self, target: &SpriteFilterTarget) -> bool {
+        self.terms.iter().all(|t| t.matches(target))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SpriteFilterParseError {
+    #[error("invalid size range: {0}")]
+    InvalidRange(String),
+    #[error("unknown condition: {0}")]
+    UnknownCondition(String),
+}
@@ -0,0 +1,162 @@
+//! Sprite Groups (folder-like hierarchy of sprites)
+
+use std::{
+    collections::BTreeSet,
+    path::{Component, Path},
+};
+
+use crate::{
+    app_state::SpriteInfo,
+    sprite_filter::{SpriteFilter, SpriteFilterTarget},
+};
+
+/// グループパスの区切り文字（空文字列はグループなし）
+pub const SEPARATOR: char = '/';
+
+fn components(group: &str) -> impl Iterator<Item = &str> {
+    group.split(SEPARATOR).filter(|x| !x.is_empty())
+}
+
+/// ドロップされたディレクトリ（`root`）から見たソース画像の置き場所をグループパスにする
+///
+/// root自体の名前も最上位のグループとして含める（root直下のファイルもrootのグループに入る）
+pub fn derive_from_source_path(root: &Path, source_path: &Path) -> String {
+    let base = root.parent().unwrap_or(root);
+    let Some(dir) = source_path.parent() else {
+        return String::new();
+    };
+    let Ok(rel) = dir.strip_prefix(base) else {
+        return String::new();
+    };
+
+    rel.components()
+        .filter_map(|c| match c {
+            // Note: .psaはカンマ区切りなのでカンマは置き換えておく
+            Component::Normal(x) => x.to_str().map(|x| x.replace(',', "_")),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(&SEPARATOR.to_string())
+}
+
+/// `sprite_group`が`group`自身かそのサブグループであるか
+pub fn contains(group: &str, sprite_group: &str) -> bool {
+    let mut s = components(sprite_group);
+
+    components(group).all(|g| s.next() == Some(g))
+}
+
+/// スプライト一覧の表示に必要な情報
+#[derive(Debug, Clone)]
+pub struct SpriteListEntry {
+    pub target: SpriteFilterTarget,
+    pub group: String,
+    pub selected: bool,
+}
+impl SpriteListEntry {
+    pub fn from_sprite(sprite: &SpriteInfo) -> Self {
+        Self {
+            target: SpriteFilterTarget::from_sprite(sprite),
+            group: sprite.group.clone(),
+            selected: sprite.selected,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpriteListRow {
+    Group {
+        path: String,
+        name: String,
+        depth: usize,
+        collapsed: bool,
+        /// グループ内のスプライトがすべて選択されているか
+        selected: bool,
+    },
+    Sprite {
+        index: usize,
+        depth: usize,
+    },
+}
+impl SpriteListRow {
+    pub const fn depth(&self) -> usize {
+        match self {
+            &Self::Group { depth, .. } | &Self::Sprite { depth, .. } => depth,
+        }
+    }
+}
+
+/// 折りたたまれたグループの中身を除いて、グループ見出しとスプライトの行を並べる
+///
+/// グループなしのスプライトが先頭に来て、同じグループ内のスプライトは元の順番のまま並ぶ
+/// `filter`がある場合はマッチしたスプライトと、それを含むグループだけを並べる
+pub fn build_rows(
+    entries: &[SpriteListEntry],
+    collapsed: &BTreeSet<String>,
+    filter: Option<&SpriteFilter>,
+) -> Vec<SpriteListRow> {
+    fn is_hidden(components: &[&str], collapsed: &BTreeSet<String>) -> bool {
+        (1..=components.len())
+            .any(|l| collapsed.contains(&components[..l].join(&SEPARATOR.to_string())))
+    }
+
+    let mut order = (0..entries.len())
+        .filter(|&n| filter.is_none_or(|f| f.matches(&entries[n].target)))
+        .collect::<Vec<_>>();
+    // Note: 文字列のままだと"a-b" < "a/c"のように親子関係が崩れるので要素ごとに比較する
+    order.sort_by(|&a, &b| components(&entries[a].group).cmp(components(&entries[b].group)));
+
+    let mut rows = Vec::new();
+    let mut opened = Vec::<&str>::new();
+    for n in order {
+        let cs = components(&entries[n].group).collect::<Vec<_>>();
+        let common = opened
+            .iter()
+            .zip(cs.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        opened.truncate(common);
+
+        for &c in &cs[common..] {
+            opened.push(c);
+            if is_hidden(&opened[..opened.len() - 1], collapsed) {
+                continue;
+            }
+
+            let path = opened.join(&SEPARATOR.to_string());
+            rows.push(SpriteListRow::Group {
+                collapsed: collapsed.contains(&path),
+                selected: entries
+                    .iter()
+                    .filter(|x| contains(&path, &x.group))
+                    .all(|x| x.selected),
+                name: c.into(),
+                depth: opened.len() - 1,
+                path,
+            });
+        }
+
+        if !is_hidden(&cs, collapsed) {
+            rows.push(SpriteListRow::Sprite {
+                index: n,
+                depth: cs.len(),
+            });
+        }
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_group_has_no_comma() {
+        let group = derive_from_source_path(
+            Path::new("assets/chars"),
+            Path::new("assets/chars/hero,old/idle.png"),
+        );
+        assert_eq!(group, "chars/hero_old");
+    }
+}
@@ -0,0 +1,240 @@
+//! スプライトの自動配置
+//!
+//! ミップマップを作るときに隣のスプライトがにじまないように、
+//! 位置と大きさを2^(レベル数-1)の倍数にそろえ、いちばん小さいレベルで縮小フィルタの届く分だけ隙間をあける
+//! ブロック圧縮するときは、ブロックを共有しないようにブロックの大きさの倍数にもそろえる
+
+use crate::{
+    atlas_image::MipFilter,
+    peridot,
+    texture_allocator::{AtlasRegion, TextureAtlasAllocator},
+};
+
+/// 配置結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedLayout {
+    /// アトラスの大きさ（2のべき乗の正方形）
+    pub size: u32,
+    /// 入力と同じ順番の左上位置
+    pub positions: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PackError {
+    #[error("mip_levels must be between 1 and {}", peridot::MAX_MIP_LEVELS)]
+    InvalidMipLevels,
+    #[error("sprites do not fit in {0}x{0}")]
+    TooLarge(u32),
+}
+
+/// 位置と大きさをそろえる単位（いちばん小さいレベルの1テクセルがベースレベルの何テクセルか）
+///
+/// `mip_levels`は1から`peridot::MAX_MIP_LEVELS`まで
+pub const fn mip_alignment(mip_levels: u32) -> u32 {
+    1 << (mip_levels - 1)
+}
+
+/// `sizes`のスプライトを`max_size`以下のなるべく小さい正方形に詰める
+///
+/// `block_size`は圧縮のブロックの幅（圧縮しないなら1、2のべき乗であること）
+pub fn pack(
+    sizes: &[(u32, u32)],
+    mip_levels: u32,
+    block_size: u32,
+    max_size: u32,
+) -> Result<PackedLayout, PackError> {
+    pack_with_progress(sizes, mip_levels, block_size, max_size, |_| ())
+}
+
+/// `pack`と同じ（`on_progress`に進捗を0.0〜1.0で渡す）
+///
+/// Note: 何回大きさを広げるかは詰めてみるまでわからないので、`max_size`まで広げる前提の割合にしている
+pub fn pack_with_progress(
+    sizes: &[(u32, u32)],
+    mip_levels: u32,
+    block_size: u32,
+    max_size: u32,
+    mut on_progress: impl FnMut(f32),
+) -> Result<PackedLayout, PackError> {
+    if !(1..=peridot::MAX_MIP_LEVELS).contains(&mip_levels) {
+        return Err(PackError::InvalidMipLevels);
+    }
+    // Note: どちらも2のべき乗なので大きいほうにそろえれば両方の倍数になる
+    let alignment = mip_alignment(mip_levels).max(block_size);
+    // ミップマップなしなら隙間はいらない
+    // Note: 書き出すときのフィルタは詰める時点ではわからないので、いちばん広く読むKaiserに合わせる
+    let gutter = if mip_levels > 1 {
+        MipFilter::Kaiser.gutter()
+    } else {
+        0
+    };
+
+    // Note: そろえる単位を1として詰めると、割り当て結果が自然に単位の倍数になる
+    let cells = sizes
+        .iter()
+        .map(|&(w, h)| {
+            (
+                w.div_ceil(alignment) + gutter,
+                h.div_ceil(alignment) + gutter,
+            )
+        })
+        .collect::<Vec<_>>();
+    let order = largest_first(&cells);
+
+    let mut size = alignment.next_power_of_two();
+    let attempts = max_size.max(size).ilog2() - size.ilog2() + 1;
+    for attempt in 0.. {
+        if size > max_size {
+            return Err(PackError::TooLarge(max_size));
+        }
+
+        let mut allocator = TextureAtlasAllocator::new(size / alignment, 1);
+        let mut positions = vec![(0, 0); cells.len()];
+        let fit = order.iter().enumerate().all(|(placed, &n)| {
+            on_progress((attempt as f32 + placed as f32 / order.len() as f32) / attempts as f32);
+
+            if cells[n].0 == 0 || cells[n].1 == 0 {
+                // 大きさのないものはどこに置いてもいい
+                return true;
+            }
+
+            match allocator.alloc(cells[n].0, cells[n].1) {
+                Ok(r) => {
+                    positions[n] = (r.left * alignment, r.top * alignment);
+                    true
+                }
+                Err(_) => false,
+            }
+        });
+        if fit {
+            on_progress(1.0);
+            return Ok(PackedLayout { size, positions });
+        }
+
+        size *= 2;
+    }
+
+    unreachable!("size exceeds max_size before attempts run out")
+}
+
+/// 置いてあるスプライト（`occupied`、left, top, width, height）を動かさずに、空いているところへ`sizes`を置く
+///
+/// `min_size`と置いてあるものが入る大きさから始めて、入るまで2倍ずつ広げる（ミップマップや圧縮のそろえはしない）
+pub fn place_around(
+    occupied: &[[u32; 4]],
+    sizes: &[(u32, u32)],
+    min_size: u32,
+    max_size: u32,
+) -> Result<PackedLayout, PackError> {
+    let extent = occupied
+        .iter()
+        .map(|&[left, top, width, height]| (left + width).max(top + height))
+        .max()
+        .unwrap_or(0);
+    let order = largest_first(sizes);
+
+    let mut size = min_size.max(extent).max(1).next_power_of_two();
+    while size <= max_size {
+        let mut allocator = TextureAtlasAllocator::new(size, 1);
+        for &[left, top, width, height] in occupied {
+            if width == 0 || height == 0 {
+                continue;
+            }
+
+            // Note: 全部入る大きさから始めているので失敗しない
+            allocator
+                .occupy(&AtlasRegion {
+                    page: 0,
+                    left,
+                    top,
+                    width,
+                    height,
+                })
+                .expect("occupied region must fit in the atlas");
+        }
+
+        let mut positions = vec![(0, 0); sizes.len()];
+        let fit = order.iter().all(|&n| {
+            if sizes[n].0 == 0 || sizes[n].1 == 0 {
+                return true;
+            }
+
+            match allocator.alloc(sizes[n].0, sizes[n].1) {
+                Ok(r) => {
+                    positions[n] = (r.left, r.top);
+                    true
+                }
+                Err(_) => false,
+            }
+        });
+        if fit {
+            return Ok(PackedLayout { size, positions });
+        }
+
+        size *= 2;
+    }
+
+    Err(PackError::TooLarge(max_size))
+}
+
+/// 大きいものから入れたほうが詰まりやすい
+fn largest_first(sizes: &[(u32, u32)]) -> Vec<usize> {
+    let mut order = (0..sizes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&n| {
+        core::cmp::Reverse((sizes[n].0.max(sizes[n].1), sizes[n].0 * sizes[n].1))
+    });
+
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intersects(a: [u32; 4], b: [u32; 4]) -> bool {
+        a[0] < b[0] + b[2] && b[0] < a[0] + a[2] && a[1] < b[1] + b[3] && b[1] < a[1] + a[3]
+    }
+
+    #[test]
+    fn placed_around_existing_sprites() {
+        let occupied = [[0, 0, 32, 32], [40, 8, 16, 16]];
+        let sizes = [(16, 16), (32, 8), (8, 8), (24, 24)];
+        let layout = place_around(&occupied, &sizes, 64, 16384).unwrap();
+
+        let mut rects = occupied.to_vec();
+        for (&(w, h), &(left, top)) in sizes.iter().zip(&layout.positions) {
+            let r = [left, top, w, h];
+            assert!(left + w <= layout.size && top + h <= layout.size);
+            assert!(rects.iter().all(|&x| !intersects(x, r)), "{r:?} overlaps");
+            rects.push(r);
+        }
+        assert_eq!(layout.size, 64);
+    }
+
+    #[test]
+    fn atlas_grows_when_full() {
+        let layout = place_around(&[[0, 0, 32, 32]], &[(32, 32)], 32, 16384).unwrap();
+        assert_eq!(layout.size, 64);
+        assert!(!intersects(
+            [0, 0, 32, 32],
+            [layout.positions[0].0, layout.positions[0].1, 32, 32]
+        ));
+
+        assert!(matches!(
+            place_around(&[[0, 0, 32, 32]], &[(32, 32)], 32, 32),
+            Err(PackError::TooLarge(32))
+        ));
+    }
+
+    #[test]
+    fn too_many_mip_levels_are_rejected() {
+        assert!(matches!(
+            pack(&[(8, 8)], 0, 1, 16384),
+            Err(PackError::InvalidMipLevels)
+        ));
+        assert!(matches!(
+            pack(&[(8, 8)], peridot::MAX_MIP_LEVELS + 1, 1, 16384),
+            Err(PackError::InvalidMipLevels)
+        ));
+    }
+}
@@ -2,7 +2,15 @@ use core::mem::MaybeUninit;
 use windows::{
     UI::Composition::CompositionDrawingSurface,
     Win32::{
-        Foundation::POINT, Graphics::Direct2D::ID2D1DeviceContext,
+        Foundation::POINT,
+        Graphics::{
+            Direct2D::{
+                Common::{D2D_SIZE_U, D2D1_ALPHA_MODE_PREMULTIPLIED, D2D1_PIXEL_FORMAT},
+                D2D1_BITMAP_OPTIONS_NONE, D2D1_BITMAP_PROPERTIES1, ID2D1Bitmap1,
+                ID2D1DeviceContext,
+            },
+            Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM,
+        },
         System::WinRT::Composition::ICompositionDrawingSurfaceInterop,
     },
 };
@@ -30,3 +38,43 @@ where
 
     r
 }
+
+/// RGBAの画像をD2Dに渡せる形（BGRA, premultiplied alpha）に変換する
+fn to_premultiplied_bgra(image: &image::RgbaImage) -> Vec<u8> {
+    image
+        .pixels()
+        .flat_map(|p| {
+            let [r, g, b, a] = p.0;
+            let pm = |c: u8| ((c as u16 * a as u16 + 127) / 255) as u8;
+
+            [pm(b), pm(g), pm(r), a]
+        })
+        .collect()
+}
+
+/// RGBAの画像から描画に使えるビットマップを作る
+pub fn create_bitmap_from_rgba(
+    dc: &ID2D1DeviceContext,
+    image: &image::RgbaImage,
+) -> windows_core::Result<ID2D1Bitmap1> {
+    unsafe {
+        dc.CreateBitmap(
+            D2D_SIZE_U {
+                width: image.width(),
+                height: image.height(),
+            },
+            Some(to_premultiplied_bgra(image).as_ptr() as _),
+            image.width() * 4,
+            &D2D1_BITMAP_PROPERTIES1 {
+                pixelFormat: D2D1_PIXEL_FORMAT {
+                    format: DXGI_FORMAT_B8G8R8A8_UNORM,
+                    alphaMode: D2D1_ALPHA_MODE_PREMULTIPLIED,
+                },
+                dpiX: 96.0,
+                dpiY: 96.0,
+                bitmapOptions: D2D1_BITMAP_OPTIONS_NONE,
+                colorContext: core::mem::ManuallyDrop::new(None),
+            },
+        )
+    }
+}
@@ -0,0 +1,453 @@
+//! テクスチャアトラスの領域割り当て（MaxRects）
+//!
+//! GPUリソースとは切り離してあるので、実際のテクスチャへの転送は呼び出し側で行う
+
+/// 割り当てた領域（ページはテクスチャ配列のスライス番号）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AtlasRegion {
+    pub page: u32,
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    left: u32,
+    top: u32,
+    width: u32,
+    height: u32,
+}
+impl Rect {
+    const fn right(&self) -> u32 {
+        self.left + self.width
+    }
+
+    const fn bottom(&self) -> u32 {
+        self.top + self.height
+    }
+
+    const fn intersects(&self, other: &Self) -> bool {
+        self.left < other.right()
+            && other.left < self.right()
+            && self.top < other.bottom()
+            && other.top < self.bottom()
+    }
+
+    const fn contains(&self, other: &Self) -> bool {
+        self.left <= other.left
+            && self.top <= other.top
+            && other.right() <= self.right()
+            && other.bottom() <= self.bottom()
+    }
+}
+
+struct Page {
+    /// 空き領域（重なりあり、互いに包含されないもの）
+    free_rects: Vec<Rect>,
+    allocated_count: usize,
+}
+impl Page {
+    fn new(size: u32) -> Self {
+        Self {
+            free_rects: vec![Rect {
+                left: 0,
+                top: 0,
+                width: size,
+                height: size,
+            }],
+            allocated_count: 0,
+        }
+    }
+
+    /// 入る空き領域のうち、短辺の余りが最小のもの（Best Short Side Fit）
+    fn find_position(&self, width: u32, height: u32) -> Option<Rect> {
+        self.free_rects
+            .iter()
+            .filter(|r| r.width >= width && r.height >= height)
+            .min_by_key(|r| {
+                let (lw, lh) = (r.width - width, r.height - height);
+
+                (lw.min(lh), lw.max(lh))
+            })
+            .map(|r| Rect {
+                left: r.left,
+                top: r.top,
+                width,
+                height,
+            })
+    }
+
+    fn place(&mut self, placed: Rect) {
+        let mut split = Vec::new();
+        let mut n = 0;
+        while n < self.free_rects.len() {
+            let f = self.free_rects[n];
+            if !f.intersects(&placed) {
+                n += 1;
+                continue;
+            }
+
+            // 重なった空き領域は、置いた矩形の外側の部分（最大4つ）に分割する
+            self.free_rects.swap_remove(n);
+            if placed.left > f.left {
+                split.push(Rect {
+                    width: placed.left - f.left,
+                    ..f
+                });
+            }
+            if placed.right() < f.right() {
+                split.push(Rect {
+                    left: placed.right(),
+                    width: f.right() - placed.right(),
+                    ..f
+                });
+            }
+            if placed.top > f.top {
+                split.push(Rect {
+                    height: placed.top - f.top,
+                    ..f
+                });
+            }
+            if placed.bottom() < f.bottom() {
+                split.push(Rect {
+                    top: placed.bottom(),
+                    height: f.bottom() - placed.bottom(),
+                    ..f
+                });
+            }
+        }
+
+        for r in split {
+            self.insert_free_rect(r);
+        }
+        self.allocated_count += 1;
+    }
+
+    fn release(&mut self, size: u32, r: Rect) {
+        self.allocated_count -= 1;
+        if self.allocated_count == 0 {
+            // 全部空いたので最初の状態に戻す
+            *self = Self::new(size);
+            return;
+        }
+
+        // 辺をまるごと共有する空き領域があればつなげて大きくする
+        // Note: 完全ではないので、細切れになった分は`defragment`で詰め直す
+        let mut r = r;
+        while let Some(n) = self.free_rects.iter().position(|f| {
+            (f.top == r.top && f.height == r.height && (f.right() == r.left || r.right() == f.left))
+                || (f.left == r.left
+                    && f.width == r.width
+                    && (f.bottom() == r.top || r.bottom() == f.top))
+        }) {
+            let f = self.free_rects.swap_remove(n);
+            let (left, top) = (f.left.min(r.left), f.top.min(r.top));
+            r = Rect {
+                left,
+                top,
+                width: f.right().max(r.right()) - left,
+                height: f.bottom().max(r.bottom()) - top,
+            };
+        }
+
+        self.insert_free_rect(r);
+    }
+
+    /// 互いに包含されない状態を保ったまま空き領域を追加する
+    fn insert_free_rect(&mut self, r: Rect) {
+        if self.free_rects.iter().any(|f| f.contains(&r)) {
+            return;
+        }
+
+        self.free_rects.retain(|f| !r.contains(f));
+        self.free_rects.push(r);
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AtlasAllocError {
+    #[error("region {0}x{1} is larger than a page")]
+    TooLarge(u32, u32),
+    #[error("region must not be empty")]
+    Empty,
+    #[error("no space left in any page")]
+    Full,
+}
+
+/// 正方形のページを必要に応じて`max_pages`まで増やしながら割り当てる
+pub struct TextureAtlasAllocator {
+    page_size: u32,
+    max_pages: u32,
+    pages: Vec<Page>,
+}
+impl TextureAtlasAllocator {
+    pub fn new(page_size: u32, max_pages: u32) -> Self {
+        Self {
+            page_size,
+            max_pages,
+            pages: vec![Page::new(page_size)],
+        }
+    }
+
+    /// いま使っているページ数（テクスチャ配列に必要なスライス数）
+    pub fn page_count(&self) -> u32 {
+        self.pages.len() as _
+    }
+
+    pub fn alloc(&mut self, width: u32, height: u32) -> Result<AtlasRegion, AtlasAllocError> {
+        if width == 0 || height == 0 {
+            return Err(AtlasAllocError::Empty);
+        }
+        if width > self.page_size || height > self.page_size {
+            return Err(AtlasAllocError::TooLarge(width, height));
+        }
+
+        let found = self
+            .pages
+            .iter()
+            .enumerate()
+            .find_map(|(n, p)| p.find_position(width, height).map(|r| (n, r)));
+        let (page, r) = match found {
+            Some(x) => x,
+            None => {
+                if self.pages.len() as u32 >= self.max_pages {
+                    return Err(AtlasAllocError::Full);
+                }
+
+                self.pages.push(Page::new(self.page_size));
+                let r = self
+                    .pages
+                    .last()
+                    .and_then(|p| p.find_position(width, height))
+                    .expect("new page must have enough space");
+                (self.pages.len() - 1, r)
+            }
+        };
+
+        self.pages[page].place(r);
+        Ok(AtlasRegion {
+            page: page as _,
+            left: r.left,
+            top: r.top,
+            width: r.width,
+            height: r.height,
+        })
+    }
+
+    /// `region`を使用中にする（すでに置いてあるものを避けて割り当てるためのもの）
+    ///
+    /// Note: ほかの使用中の領域と重なっていてもよいが、その場合は`free`で戻さないこと
+    pub fn occupy(&mut self, region: &AtlasRegion) -> Result<(), AtlasAllocError> {
+        if region.width == 0 || region.height == 0 {
+            return Err(AtlasAllocError::Empty);
+        }
+        if region.left + region.width > self.page_size
+            || region.top + region.height > self.page_size
+        {
+            return Err(AtlasAllocError::TooLarge(
+                region.left + region.width,
+                region.top + region.height,
+            ));
+        }
+        if region.page >= self.max_pages {
+            return Err(AtlasAllocError::Full);
+        }
+
+        while self.pages.len() <= region.page as usize {
+            self.pages.push(Page::new(self.page_size));
+        }
+        self.pages[region.page as usize].place(Rect {
+            left: region.left,
+            top: region.top,
+            width: region.width,
+            height: region.height,
+        });
+
+        Ok(())
+    }
+
+    pub fn free(&mut self, region: &AtlasRegion) {
+        let Some(p) = self.pages.get_mut(region.page as usize) else {
+            tracing::warn!({ ?region }, "freeing region in unknown page");
+            return;
+        };
+
+        p.release(
+            self.page_size,
+            Rect {
+                left: region.left,
+                top: region.top,
+                width: region.width,
+                height: region.height,
+            },
+        );
+        // Note: ページ番号がずれないように末尾の空きページだけ捨てる
+        while self.pages.len() > 1 && self.pages.last().is_some_and(|p| p.allocated_count == 0) {
+            self.pages.pop();
+        }
+    }
+
+    /// 使用中の領域をすべて詰め直す
+    ///
+    /// `regions`には割り当て中の領域を全部渡すこと。成功したら同じ順番で新しい位置を返す
+    /// （テクスチャの中身は呼び出し側で移動する）。詰め直しても入らなかった場合は何も変えずにNoneを返す
+    pub fn defragment(&mut self, regions: &[AtlasRegion]) -> Option<Vec<AtlasRegion>> {
+        let mut order = (0..regions.len()).collect::<Vec<_>>();
+        // 大きいものから入れたほうが詰まりやすい
+        order.sort_by_key(|&n| {
+            core::cmp::Reverse((
+                regions[n].height.max(regions[n].width),
+                regions[n].width * regions[n].height,
+            ))
+        });
+
+        let mut repacked = Self::new(self.page_size, self.max_pages);
+        let mut relocated = regions.to_vec();
+        for n in order {
+            relocated[n] = repacked.alloc(regions[n].width, regions[n].height).ok()?;
+        }
+
+        *self = repacked;
+        Some(relocated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: &AtlasRegion, b: &AtlasRegion) -> bool {
+        a.page == b.page
+            && a.left < b.left + b.width
+            && b.left < a.left + a.width
+            && a.top < b.top + b.height
+            && b.top < a.top + a.height
+    }
+
+    fn assert_disjoint(regions: &[AtlasRegion], page_size: u32) {
+        for (n, a) in regions.iter().enumerate() {
+            assert!(a.left + a.width <= page_size && a.top + a.height <= page_size);
+            for b in &regions[n + 1..] {
+                assert!(!overlaps(a, b), "{a:?} overlaps {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn random_alloc_and_free_never_overlap() {
+        // 再現できるように固定の種のxorshiftを使う
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move |n: u32| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as u32
+        };
+
+        let mut allocator = TextureAtlasAllocator::new(256, 4);
+        let mut regions = Vec::new();
+        for _ in 0..2000 {
+            if !regions.is_empty() && next(3) == 0 {
+                let r = regions.swap_remove(next(regions.len() as u32) as usize);
+                allocator.free(&r);
+            } else {
+                match allocator.alloc(next(64) + 1, next(64) + 1) {
+                    Ok(r) => regions.push(r),
+                    Err(AtlasAllocError::Full) => (),
+                    Err(e) => panic!("unexpected error: {e}"),
+                }
+            }
+
+            assert_disjoint(&regions, 256);
+            assert!(regions.iter().all(|r| r.page < allocator.page_count()));
+        }
+    }
+
+    #[test]
+    fn freed_region_is_reused() {
+        let mut allocator = TextureAtlasAllocator::new(64, 1);
+        let a = allocator.alloc(32, 32).unwrap();
+        let b = allocator.alloc(32, 32).unwrap();
+        allocator.free(&a);
+
+        let c = allocator.alloc(32, 32).unwrap();
+        assert_eq!(c, a);
+        assert!(!overlaps(&b, &c));
+    }
+
+    #[test]
+    fn pages_grow_up_to_max_pages() {
+        let mut allocator = TextureAtlasAllocator::new(64, 2);
+        assert_eq!(allocator.alloc(64, 64).unwrap().page, 0);
+        assert_eq!(allocator.alloc(40, 40).unwrap().page, 1);
+        assert_eq!(allocator.page_count(), 2);
+
+        assert!(matches!(
+            allocator.alloc(40, 40),
+            Err(AtlasAllocError::Full)
+        ));
+        assert!(matches!(
+            allocator.alloc(65, 1),
+            Err(AtlasAllocError::TooLarge(65, 1))
+        ));
+        assert!(matches!(allocator.alloc(0, 1), Err(AtlasAllocError::Empty)));
+        assert_eq!(allocator.page_count(), 2);
+    }
+
+    #[test]
+    fn only_trailing_empty_pages_are_dropped() {
+        let mut allocator = TextureAtlasAllocator::new(64, 3);
+        let pages = [(); 3].map(|_| allocator.alloc(64, 64).unwrap());
+        assert_eq!(pages.map(|r| r.page), [0, 1, 2]);
+
+        allocator.free(&pages[2]);
+        assert_eq!(allocator.page_count(), 2);
+
+        // 途中のページが空いても番号がずれないように残す
+        allocator.free(&pages[0]);
+        assert_eq!(allocator.page_count(), 2);
+
+        allocator.free(&pages[1]);
+        assert_eq!(allocator.page_count(), 1);
+        assert_eq!(allocator.alloc(64, 64).unwrap().page, 0);
+    }
+
+    #[test]
+    fn defragment_keeps_order_without_overlap() {
+        let mut allocator = TextureAtlasAllocator::new(64, 1);
+        let all = (0..16)
+            .map(|_| allocator.alloc(16, 16).unwrap())
+            .collect::<Vec<_>>();
+        // 市松模様に空けて細切れにする
+        let mut kept = Vec::new();
+        for (n, r) in all.iter().enumerate() {
+            if (n + n / 4) % 2 == 0 {
+                allocator.free(r);
+            } else {
+                kept.push(*r);
+            }
+        }
+        assert!(matches!(
+            allocator.alloc(32, 32),
+            Err(AtlasAllocError::Full)
+        ));
+        let big = kept.len();
+        kept.push(allocator.alloc(16, 8).unwrap());
+
+        let relocated = allocator.defragment(&kept).unwrap();
+        assert_eq!(relocated.len(), kept.len());
+        for (a, b) in kept.iter().zip(&relocated) {
+            assert_eq!((a.width, a.height), (b.width, b.height));
+        }
+        assert_eq!((relocated[big].width, relocated[big].height), (16, 8));
+        assert_disjoint(&relocated, 64);
+
+        // 詰め直したあとの空きに割り当てたものも重ならない
+        let r = allocator.alloc(32, 32).unwrap();
+        let mut all = relocated;
+        all.push(r);
+        assert_disjoint(&all, 64);
+    }
+}
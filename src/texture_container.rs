@@ -0,0 +1,583 @@
+//! ミップマップ付きテクスチャのコンテナ（DDS/KTX2）書き出し
+//!
+//! 各レベルはRGBA8（sRGB）/RGBA16/RGBA32F（リニア）のどれかで、すべて同じ形式であること
+//! ブロック圧縮するときは8bitのsRGBにしてから圧縮する
+
+use std::io::Write;
+
+use image::DynamicImage;
+
+use crate::{
+    atlas_image::{SampleFormat, linearize_unorm16, to_srgb_rgba8},
+    block_compression,
+    peridot::{AlphaMode, CompressionQuality, TextureCompression},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerFormat {
+    Dds,
+    Ktx2,
+}
+impl ContainerFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "dds" => Some(Self::Dds),
+            "ktx2" => Some(Self::Ktx2),
+            _ => None,
+        }
+    }
+
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Dds => "dds",
+            Self::Ktx2 => "ktx2",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WriteError {
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+    #[error("no levels")]
+    NoLevels,
+    #[error("all levels must have the same sample format")]
+    MixedFormat,
+    #[error("{} cannot be stored in {}", .0.as_str(), .1.extension())]
+    UnsupportedCompression(TextureCompression, ContainerFormat),
+}
+
+/// テクセルの格納形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TexelFormat {
+    Uncompressed(SampleFormat),
+    Compressed(TextureCompression),
+}
+impl TexelFormat {
+    /// 1ブロック（圧縮しないなら1テクセル）のバイト数
+    const fn block_bytes(self) -> u32 {
+        match self {
+            Self::Uncompressed(SampleFormat::Unorm8) => 4,
+            Self::Uncompressed(SampleFormat::Unorm16) => 8,
+            Self::Uncompressed(SampleFormat::Float32) => 16,
+            Self::Compressed(c) => block_compression::block_bytes(c),
+        }
+    }
+
+    const fn block_size(self) -> u32 {
+        match self {
+            Self::Uncompressed(_) => 1,
+            Self::Compressed(c) => c.block_size(),
+        }
+    }
+}
+
+pub fn write(
+    format: ContainerFormat,
+    levels: &[DynamicImage],
+    alpha_mode: AlphaMode,
+    compression: TextureCompression,
+    quality: CompressionQuality,
+    sink: &mut (impl Write + ?Sized),
+) -> Result<(), WriteError> {
+    let Some(base) = levels.first() else {
+        return Err(WriteError::NoLevels);
+    };
+    let sample_format = SampleFormat::of(base.color());
+    if levels
+        .iter()
+        .any(|x| SampleFormat::of(x.color()) != sample_format)
+    {
+        return Err(WriteError::MixedFormat);
+    }
+
+    // Note: DDSにはETC2の形式がない
+    if format == ContainerFormat::Dds && compression == TextureCompression::Etc2 {
+        return Err(WriteError::UnsupportedCompression(compression, format));
+    }
+
+    let texel_format = if compression == TextureCompression::None {
+        TexelFormat::Uncompressed(sample_format)
+    } else {
+        TexelFormat::Compressed(compression)
+    };
+    let data = levels
+        .iter()
+        .map(|l| match texel_format {
+            TexelFormat::Compressed(c) => {
+                block_compression::compress(&to_srgb_rgba8(l, alpha_mode), c, quality)
+            }
+            // Note: 16bitにはsRGBの形式がないのでリニアにして入れる
+            TexelFormat::Uncompressed(SampleFormat::Unorm16) => {
+                level_bytes(&linearize_unorm16(l, alpha_mode), SampleFormat::Unorm16)
+            }
+            TexelFormat::Uncompressed(f) => level_bytes(l, f),
+        })
+        .collect::<Vec<_>>();
+    let size = (base.width(), base.height());
+
+    match format {
+        ContainerFormat::Dds => write_dds(size, &data, texel_format, alpha_mode, sink)?,
+        ContainerFormat::Ktx2 => write_ktx2(size, &data, texel_format, alpha_mode, sink)?,
+    }
+
+    Ok(())
+}
+
+/// レベルの中身（リトルエンディアン）
+fn level_bytes(level: &DynamicImage, format: SampleFormat) -> Vec<u8> {
+    match format {
+        SampleFormat::Unorm8 => level.to_rgba8().into_raw(),
+        SampleFormat::Unorm16 => level
+            .to_rgba16()
+            .into_raw()
+            .into_iter()
+            .flat_map(u16::to_le_bytes)
+            .collect(),
+        SampleFormat::Float32 => level
+            .to_rgba32f()
+            .into_raw()
+            .into_iter()
+            .flat_map(f32::to_le_bytes)
+            .collect(),
+    }
+}
+
+fn write_u32s(sink: &mut (impl Write + ?Sized), values: &[u32]) -> std::io::Result<()> {
+    for v in values {
+        sink.write_all(&v.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn write_dds(
+    (width, height): (u32, u32),
+    levels: &[Vec<u8>],
+    format: TexelFormat,
+    alpha_mode: AlphaMode,
+    sink: &mut (impl Write + ?Sized),
+) -> std::io::Result<()> {
+    const DDSD_CAPS: u32 = 0x1;
+    const DDSD_HEIGHT: u32 = 0x2;
+    const DDSD_WIDTH: u32 = 0x4;
+    const DDSD_PITCH: u32 = 0x8;
+    const DDSD_PIXELFORMAT: u32 = 0x1000;
+    const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+    const DDSD_LINEARSIZE: u32 = 0x80000;
+    const DDPF_FOURCC: u32 = 0x4;
+    const DDSCAPS_COMPLEX: u32 = 0x8;
+    const DDSCAPS_TEXTURE: u32 = 0x1000;
+    const DDSCAPS_MIPMAP: u32 = 0x400000;
+    const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+
+    let dxgi_format = match format {
+        TexelFormat::Uncompressed(SampleFormat::Unorm8) => 29, // DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+        TexelFormat::Uncompressed(SampleFormat::Unorm16) => 11, // DXGI_FORMAT_R16G16B16A16_UNORM
+        TexelFormat::Uncompressed(SampleFormat::Float32) => 2, // DXGI_FORMAT_R32G32B32A32_FLOAT
+        TexelFormat::Compressed(TextureCompression::Bc1) => 72, // DXGI_FORMAT_BC1_UNORM_SRGB
+        TexelFormat::Compressed(TextureCompression::Bc3) => 78, // DXGI_FORMAT_BC3_UNORM_SRGB
+        TexelFormat::Compressed(TextureCompression::Bc7) => 99, // DXGI_FORMAT_BC7_UNORM_SRGB
+        // writeで弾いている
+        TexelFormat::Compressed(TextureCompression::None | TextureCompression::Etc2) => {
+            unreachable!("no dxgi format")
+        }
+    };
+    let dds_alpha_mode = match alpha_mode {
+        AlphaMode::Straight => 1,      // DDS_ALPHA_MODE_STRAIGHT
+        AlphaMode::Premultiplied => 2, // DDS_ALPHA_MODE_PREMULTIPLIED
+    };
+    let caps = if levels.len() > 1 {
+        DDSCAPS_TEXTURE | DDSCAPS_COMPLEX | DDSCAPS_MIPMAP
+    } else {
+        DDSCAPS_TEXTURE
+    };
+    // 圧縮しているときはベースレベル全体のバイト数、していないときは1行のバイト数を入れる
+    let (pitch_flag, pitch_or_linear_size) = match format {
+        TexelFormat::Uncompressed(_) => (DDSD_PITCH, width * format.block_bytes()),
+        TexelFormat::Compressed(_) => (DDSD_LINEARSIZE, levels[0].len() as u32),
+    };
+
+    sink.write_all(b"DDS ")?;
+    write_u32s(
+        sink,
+        &[
+            124,
+            DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | pitch_flag | DDSD_PIXELFORMAT | DDSD_MIPMAPCOUNT,
+            height,
+            width,
+            pitch_or_linear_size,
+            0,
+            levels.len() as _,
+        ],
+    )?;
+    write_u32s(sink, &[0; 11])?;
+    // ピクセルフォーマットはDX10拡張ヘッダで指定する
+    write_u32s(
+        sink,
+        &[32, DDPF_FOURCC, u32::from_le_bytes(*b"DX10"), 0, 0, 0, 0, 0],
+    )?;
+    write_u32s(sink, &[caps, 0, 0, 0, 0])?;
+    write_u32s(
+        sink,
+        &[
+            dxgi_format,
+            D3D10_RESOURCE_DIMENSION_TEXTURE2D,
+            0,
+            1,
+            dds_alpha_mode,
+        ],
+    )?;
+
+    for l in levels {
+        sink.write_all(l)?;
+    }
+
+    Ok(())
+}
+
+fn write_ktx2(
+    (width, height): (u32, u32),
+    levels: &[Vec<u8>],
+    format: TexelFormat,
+    alpha_mode: AlphaMode,
+    sink: &mut (impl Write + ?Sized),
+) -> std::io::Result<()> {
+    const IDENTIFIER: [u8; 12] = [
+        0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+    ];
+    const HEADER_BYTES: u32 = 12 + 9 * 4 + 4 * 4 + 2 * 8;
+    const LEVEL_INDEX_ENTRY_BYTES: u32 = 3 * 8;
+
+    // Note: 圧縮形式のtypeSizeは1
+    let (vk_format, type_size) = match format {
+        TexelFormat::Uncompressed(SampleFormat::Unorm8) => (43, 1), // VK_FORMAT_R8G8B8A8_SRGB
+        TexelFormat::Uncompressed(SampleFormat::Unorm16) => (91, 2), // VK_FORMAT_R16G16B16A16_UNORM
+        TexelFormat::Uncompressed(SampleFormat::Float32) => (109, 4), // VK_FORMAT_R32G32B32A32_SFLOAT
+        TexelFormat::Compressed(TextureCompression::Bc1) => (134, 1), // VK_FORMAT_BC1_RGBA_SRGB_BLOCK
+        TexelFormat::Compressed(TextureCompression::Bc3) => (138, 1), // VK_FORMAT_BC3_SRGB_BLOCK
+        TexelFormat::Compressed(TextureCompression::Bc7) => (146, 1), // VK_FORMAT_BC7_SRGB_BLOCK
+        TexelFormat::Compressed(TextureCompression::Etc2) => (152, 1), // VK_FORMAT_ETC2_R8G8B8A8_SRGB_BLOCK
+        TexelFormat::Compressed(TextureCompression::None) => unreachable!("not compressed"),
+    };
+    let dfd = ktx2_data_format_descriptor(format, alpha_mode);
+    let dfd_offset = HEADER_BYTES + LEVEL_INDEX_ENTRY_BYTES * levels.len() as u32;
+
+    // レベルは小さいものから順に、ブロックの大きさの倍数の位置に置く（どの形式でも4の倍数になる）
+    let alignment = format.block_bytes() as u64;
+    let mut offsets = vec![0u64; levels.len()];
+    let mut cursor = (dfd_offset + dfd.len() as u32) as u64;
+    for (n, d) in levels.iter().enumerate().rev() {
+        cursor = cursor.next_multiple_of(alignment);
+        offsets[n] = cursor;
+        cursor += d.len() as u64;
+    }
+
+    sink.write_all(&IDENTIFIER)?;
+    write_u32s(
+        sink,
+        &[
+            vk_format,
+            type_size,
+            width,
+            height,
+            0,
+            0,
+            1,
+            levels.len() as _,
+            // supercompressionなし
+            0,
+            dfd_offset,
+            dfd.len() as _,
+            // key/valueデータなし
+            0,
+            0,
+        ],
+    )?;
+    // supercompressionのグローバルデータなし
+    sink.write_all(&[0; 16])?;
+    for (o, d) in offsets.iter().zip(levels.iter()) {
+        sink.write_all(&o.to_le_bytes())?;
+        sink.write_all(&(d.len() as u64).to_le_bytes())?;
+        sink.write_all(&(d.len() as u64).to_le_bytes())?;
+    }
+    sink.write_all(&dfd)?;
+
+    let mut written = (dfd_offset + dfd.len() as u32) as u64;
+    for (n, d) in levels.iter().enumerate().rev() {
+        sink.write_all(&vec![0; (offsets[n] - written) as usize])?;
+        sink.write_all(d)?;
+        written = offsets[n] + d.len() as u64;
+    }
+
+    Ok(())
+}
+
+/// Data Format Descriptorのサンプル（チャンネルごとのビットの位置と値の範囲）
+struct DfdSample {
+    channel: u32,
+    bit_offset: u32,
+    bit_length: u32,
+    qualifiers: u32,
+    lower: u32,
+    upper: u32,
+}
+
+/// KTX2のData Format Descriptor（Basic Descriptor Block 1つ）
+fn ktx2_data_format_descriptor(format: TexelFormat, alpha_mode: AlphaMode) -> Vec<u8> {
+    const KHR_DF_MODEL_RGBSDA: u32 = 1;
+    const KHR_DF_MODEL_BC1A: u32 = 128;
+    const KHR_DF_MODEL_BC3: u32 = 130;
+    const KHR_DF_MODEL_BC7: u32 = 134;
+    const KHR_DF_MODEL_ETC2: u32 = 161;
+    const KHR_DF_PRIMARIES_BT709: u32 = 1;
+    const KHR_DF_TRANSFER_LINEAR: u32 = 1;
+    const KHR_DF_TRANSFER_SRGB: u32 = 2;
+    const KHR_DF_FLAG_ALPHA_PREMULTIPLIED: u32 = 1;
+    const KHR_DF_SAMPLE_DATATYPE_LINEAR: u32 = 0x10;
+    const KHR_DF_SAMPLE_DATATYPE_SIGNED: u32 = 0x40;
+    const KHR_DF_SAMPLE_DATATYPE_FLOAT: u32 = 0x80;
+    const KHR_DF_CHANNEL_COLOR: u32 = 0;
+    const KHR_DF_CHANNEL_BC1A_ALPHAPRESENT: u32 = 1;
+    const KHR_DF_CHANNEL_ETC2_COLOR: u32 = 2;
+    const KHR_DF_CHANNEL_ALPHA: u32 = 15;
+
+    let transfer = match format {
+        TexelFormat::Uncompressed(SampleFormat::Unorm16 | SampleFormat::Float32) => {
+            KHR_DF_TRANSFER_LINEAR
+        }
+        _ => KHR_DF_TRANSFER_SRGB,
+    };
+    let flags = match alpha_mode {
+        AlphaMode::Straight => 0,
+        AlphaMode::Premultiplied => KHR_DF_FLAG_ALPHA_PREMULTIPLIED,
+    };
+
+    let (model, samples): (u32, Vec<DfdSample>) = match format {
+        TexelFormat::Uncompressed(f) => {
+            let bits = format.block_bytes() * 2;
+            let (qualifiers, lower, upper) = match f {
+                SampleFormat::Unorm8 => (0, 0, u8::MAX as u32),
+                SampleFormat::Unorm16 => (0, 0, u16::MAX as u32),
+                SampleFormat::Float32 => (
+                    KHR_DF_SAMPLE_DATATYPE_FLOAT | KHR_DF_SAMPLE_DATATYPE_SIGNED,
+                    (-1.0f32).to_bits(),
+                    1.0f32.to_bits(),
+                ),
+            };
+
+            (
+                KHR_DF_MODEL_RGBSDA,
+                [0, 1, 2, KHR_DF_CHANNEL_ALPHA]
+                    .into_iter()
+                    .enumerate()
+                    .map(|(n, channel)| DfdSample {
+                        channel,
+                        bit_offset: n as u32 * bits,
+                        bit_length: bits,
+                        qualifiers,
+                        lower,
+                        upper,
+                    })
+                    .collect(),
+            )
+        }
+        TexelFormat::Compressed(c) => {
+            let model = match c {
+                TextureCompression::Bc1 => KHR_DF_MODEL_BC1A,
+                TextureCompression::Bc3 => KHR_DF_MODEL_BC3,
+                TextureCompression::Bc7 => KHR_DF_MODEL_BC7,
+                TextureCompression::Etc2 => KHR_DF_MODEL_ETC2,
+                TextureCompression::None => unreachable!("not compressed"),
+            };
+            let channels: &[u32] = match c {
+                TextureCompression::Bc1 => &[KHR_DF_CHANNEL_BC1A_ALPHAPRESENT],
+                TextureCompression::Bc3 => &[KHR_DF_CHANNEL_ALPHA, KHR_DF_CHANNEL_COLOR],
+                TextureCompression::Bc7 => &[KHR_DF_CHANNEL_COLOR],
+                TextureCompression::Etc2 => &[KHR_DF_CHANNEL_ALPHA, KHR_DF_CHANNEL_ETC2_COLOR],
+                TextureCompression::None => unreachable!("not compressed"),
+            };
+            // アルファと色が別のブロックになっているものはそれぞれが1サンプル
+            let bits = format.block_bytes() * 8 / channels.len() as u32;
+
+            (
+                model,
+                channels
+                    .iter()
+                    .enumerate()
+                    .map(|(n, &channel)| DfdSample {
+                        channel,
+                        bit_offset: n as u32 * bits,
+                        bit_length: bits,
+                        qualifiers: 0,
+                        lower: 0,
+                        upper: u32::MAX,
+                    })
+                    .collect(),
+            )
+        }
+    };
+    let block_bytes = 24 + 16 * samples.len() as u32;
+    // 圧縮していないときは1x1x1x1（ブロックの大きさ-1で表す）
+    let block_dimension = format.block_size() - 1;
+
+    let mut words = vec![
+        4 + block_bytes,
+        // vendorId = 0(Khronos), descriptorType = 0(basic)
+        0,
+        2 | (block_bytes << 16),
+        model | (KHR_DF_PRIMARIES_BT709 << 8) | (transfer << 16) | (flags << 24),
+        block_dimension | (block_dimension << 8),
+        format.block_bytes(),
+        0,
+    ];
+    for s in samples {
+        // Note: sRGBのときもアルファはリニア
+        let qualifiers = if s.channel == KHR_DF_CHANNEL_ALPHA && transfer == KHR_DF_TRANSFER_SRGB {
+            s.qualifiers | KHR_DF_SAMPLE_DATATYPE_LINEAR
+        } else {
+            s.qualifiers
+        };
+
+        words.extend([
+            s.bit_offset | ((s.bit_length - 1) << 16) | ((s.channel | qualifiers) << 24),
+            0,
+            s.lower,
+            s.upper,
+        ]);
+    }
+
+    words.into_iter().flat_map(u32::to_le_bytes).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(data: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+    }
+
+    /// 8x8と4x4の2レベル
+    fn levels() -> Vec<DynamicImage> {
+        [8, 4]
+            .map(|size| {
+                DynamicImage::ImageRgba8(image::RgbaImage::from_fn(size, size, |x, y| {
+                    image::Rgba([x as u8 * 16, y as u8 * 16, size as u8, 255])
+                }))
+            })
+            .into()
+    }
+
+    fn write_to_vec(
+        format: ContainerFormat,
+        levels: &[DynamicImage],
+        compression: TextureCompression,
+    ) -> Result<Vec<u8>, WriteError> {
+        let mut buf = Vec::new();
+        write(
+            format,
+            levels,
+            AlphaMode::Straight,
+            compression,
+            CompressionQuality::Fast,
+            &mut buf,
+        )?;
+
+        Ok(buf)
+    }
+
+    /// DDSのヘッダ（マジックとDX10拡張ヘッダを含む）のバイト数
+    const DDS_HEADER_BYTES: usize = 4 + 124 + 20;
+
+    #[test]
+    fn dds_header() {
+        let levels = levels();
+        let data = write_to_vec(ContainerFormat::Dds, &levels, TextureCompression::None).unwrap();
+
+        assert_eq!(&data[..4], b"DDS ");
+        assert_eq!(u32_at(&data, 4), 124);
+        assert_eq!((u32_at(&data, 12), u32_at(&data, 16)), (8, 8));
+        // 非圧縮なので1行のバイト数
+        assert_eq!(u32_at(&data, 20), 8 * 4);
+        assert_eq!(u32_at(&data, 28), 2);
+        assert_eq!(&data[84..88], b"DX10");
+        // DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+        assert_eq!(u32_at(&data, 128), 29);
+        // レベルは大きいものから順に並ぶ
+        assert_eq!(
+            &data[DDS_HEADER_BYTES..DDS_HEADER_BYTES + 8 * 8 * 4],
+            levels[0].as_bytes()
+        );
+        assert_eq!(&data[DDS_HEADER_BYTES + 8 * 8 * 4..], levels[1].as_bytes());
+
+        let data = write_to_vec(ContainerFormat::Dds, &levels, TextureCompression::Bc1).unwrap();
+        // DXGI_FORMAT_BC1_UNORM_SRGB、ベースレベルのバイト数
+        assert_eq!(u32_at(&data, 128), 72);
+        assert_eq!(u32_at(&data, 20), 4 * 8);
+        assert_eq!(data.len(), DDS_HEADER_BYTES + 4 * 8 + 8);
+    }
+
+    #[test]
+    fn ktx2_header() {
+        let levels = levels();
+        for (compression, vk_format, block_bytes) in [
+            (TextureCompression::None, 43, 4),
+            (TextureCompression::Bc7, 146, 16),
+            (TextureCompression::Etc2, 152, 16),
+        ] {
+            let data = write_to_vec(ContainerFormat::Ktx2, &levels, compression).unwrap();
+
+            assert_eq!(&data[..12], b"\xabKTX 20\xbb\r\n\x1a\n");
+            assert_eq!(u32_at(&data, 12), vk_format);
+            assert_eq!((u32_at(&data, 20), u32_at(&data, 24)), (8, 8));
+            assert_eq!(u32_at(&data, 40), 2);
+
+            // DFDはレベルの索引のすぐ後ろで、先頭に自身のバイト数が入っている
+            let (dfd_offset, dfd_length) = (u32_at(&data, 48) as usize, u32_at(&data, 52) as usize);
+            assert_eq!(dfd_offset, 80 + 2 * 24);
+            assert_eq!(u32_at(&data, dfd_offset) as usize, dfd_length);
+
+            let index = |n: usize| (u64_at(&data, 80 + n * 24), u64_at(&data, 80 + n * 24 + 8));
+            let ((base_offset, base_length), (small_offset, small_length)) = (index(0), index(1));
+            if compression == TextureCompression::None {
+                assert_eq!((base_length, small_length), (8 * 8 * 4, 4 * 4 * 4));
+                assert_eq!(
+                    &data[base_offset as usize..][..base_length as usize],
+                    levels[0].as_bytes()
+                );
+            } else {
+                assert_eq!((base_length, small_length), (4 * 16, 16));
+            }
+            // 小さいレベルから順に、ブロックのバイト数の倍数の位置に置かれる
+            assert!(small_offset >= (dfd_offset + dfd_length) as u64);
+            assert!(base_offset >= small_offset + small_length);
+            assert_eq!(small_offset % block_bytes, 0);
+            assert_eq!(base_offset % block_bytes, 0);
+            assert_eq!(data.len() as u64, base_offset + base_length);
+        }
+    }
+
+    #[test]
+    fn invalid_inputs_are_rejected() {
+        assert!(matches!(
+            write_to_vec(ContainerFormat::Dds, &[], TextureCompression::None),
+            Err(WriteError::NoLevels)
+        ));
+        assert!(matches!(
+            write_to_vec(ContainerFormat::Dds, &levels(), TextureCompression::Etc2),
+            Err(WriteError::UnsupportedCompression(..))
+        ));
+
+        let mut levels = levels();
+        levels[1] = DynamicImage::ImageRgba16(levels[1].to_rgba16());
+        assert!(matches!(
+            write_to_vec(ContainerFormat::Ktx2, &levels, TextureCompression::None),
+            Err(WriteError::MixedFormat)
+        ));
+    }
+}
@@ -0,0 +1,592 @@
+//! Headless Input Replay
+//!
+//! ウィンドウなしで記録したポインタ入力をHitTestTreeManagerに流して、
+//! どの要素にどのイベントがどの順で届いたか（とキャプチャの出入り）を記録する
+//!
+//! `cargo test --test input_replay`（ウィンドウを使わないのでWindows以外でも動く）
+
+use std::{
+    cell::{Cell, RefCell},
+    marker::PhantomData,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+// Note: バイナリクレートなのでモジュールを直接取り込む
+#[path = "../src/hittest.rs"]
+#[allow(dead_code)]
+mod hittest;
+#[path = "../src/input.rs"]
+#[allow(dead_code)]
+mod input;
+
+use hittest::{
+    HitTestTreeActionHandler, HitTestTreeManager, HitTestTreeRef, PointerActionArgs, WheelDelta,
+};
+use input::{
+    ClientSize, EventContinueControl, InputPlatform, ModifierKeys, PointerButton,
+    PointerInputManager, WindowHandle,
+};
+
+/// キャプチャの出入り
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureLogEntry {
+    Set,
+    Release,
+}
+
+/// 時刻を再生側で進める`InputPlatform`
+pub struct ReplayInputPlatform {
+    base: Instant,
+    elapsed: Cell<Duration>,
+    double_click_interval: Duration,
+    capture_log: RefCell<Vec<CaptureLogEntry>>,
+}
+impl ReplayInputPlatform {
+    pub fn new(double_click_interval: Duration) -> Self {
+        Self {
+            base: Instant::now(),
+            elapsed: Cell::new(Duration::ZERO),
+            double_click_interval,
+            capture_log: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn set_elapsed(&self, elapsed: Duration) {
+        self.elapsed.set(elapsed);
+    }
+
+    pub fn capture_log(&self) -> Vec<CaptureLogEntry> {
+        self.capture_log.borrow().clone()
+    }
+}
+impl InputPlatform for ReplayInputPlatform {
+    fn set_capture(&self, _window: WindowHandle) {
+        self.capture_log.borrow_mut().push(CaptureLogEntry::Set);
+    }
+
+    fn release_capture(&self) {
+        self.capture_log.borrow_mut().push(CaptureLogEntry::Release);
+    }
+
+    fn now(&self) -> Instant {
+        self.base + self.elapsed.get()
+    }
+
+    fn double_click_interval(&self) -> Duration {
+        self.double_click_interval
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RecordedPointerEventKind {
+    Move,
+    LeftDown,
+    LeftUp,
+    AuxDown(PointerButton),
+    AuxUp(PointerButton),
+    Wheel(WheelDelta),
+}
+
+/// 記録されたポインタ入力1つ分
+#[derive(Debug, Clone, Copy)]
+pub struct RecordedPointerEvent {
+    /// 再生開始からの経過時間
+    pub time: Duration,
+    pub kind: RecordedPointerEventKind,
+    pub client_x: f32,
+    pub client_y: f32,
+    pub modifiers: ModifierKeys,
+}
+
+/// 記録されたポインタ入力を順に流す
+pub fn replay<ActionContext>(
+    manager: &mut PointerInputManager<ReplayInputPlatform>,
+    ht: &mut HitTestTreeManager<ActionContext>,
+    action_context: &mut ActionContext,
+    ht_root: HitTestTreeRef,
+    client_size: ClientSize,
+    events: &[RecordedPointerEvent],
+) {
+    // Note: ウィンドウはないのでnullのまま渡す（キャプチャはReplayInputPlatformが記録するだけ）
+    let window = WindowHandle(core::ptr::null_mut());
+
+    for e in events {
+        manager.platform().set_elapsed(e.time);
+
+        match e.kind {
+            RecordedPointerEventKind::Move => manager.on_mouse_move(
+                window,
+                ht,
+                action_context,
+                ht_root,
+                client_size,
+                e.client_x,
+                e.client_y,
+                e.modifiers,
+            ),
+            RecordedPointerEventKind::LeftDown => manager.on_mouse_left_down(
+                window,
+                ht,
+                action_context,
+                ht_root,
+                client_size,
+                e.client_x,
+                e.client_y,
+                e.modifiers,
+            ),
+            RecordedPointerEventKind::LeftUp => manager.on_mouse_left_up(
+                window,
+                ht,
+                action_context,
+                ht_root,
+                client_size,
+                e.client_x,
+                e.client_y,
+                e.modifiers,
+            ),
+            RecordedPointerEventKind::AuxDown(button) => manager.on_mouse_aux_down(
+                window,
+                ht,
+                action_context,
+                ht_root,
+                client_size,
+                button,
+                e.client_x,
+                e.client_y,
+                e.modifiers,
+            ),
+            RecordedPointerEventKind::AuxUp(button) => manager.on_mouse_aux_up(
+                window,
+                ht,
+                action_context,
+                ht_root,
+                client_size,
+                button,
+                e.client_x,
+                e.client_y,
+                e.modifiers,
+            ),
+            RecordedPointerEventKind::Wheel(delta) => manager.on_mouse_wheel(
+                window,
+                ht,
+                action_context,
+                ht_root,
+                client_size,
+                e.client_x,
+                e.client_y,
+                e.modifiers,
+                delta,
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionKind {
+    PointerEnter,
+    PointerLeave,
+    PointerMove,
+    PointerDown,
+    PointerUp,
+    Click,
+    DoubleClick,
+    AuxPointerDown(PointerButton),
+    AuxPointerUp(PointerButton),
+    AuxClick(PointerButton),
+    Wheel,
+}
+
+/// 届いたイベントを記録するだけのハンドラ
+///
+/// `respond`で要素とイベントの種類ごとに返すフラグを指定できる（指定がなければ何もせず親に伝える）
+pub struct RecordingActionHandler<ActionContext> {
+    log: RefCell<Vec<(HitTestTreeRef, ActionKind)>>,
+    responses: RefCell<Vec<(HitTestTreeRef, ActionKind, EventContinueControl)>>,
+    _context: PhantomData<fn(&mut ActionContext)>,
+}
+impl<ActionContext> RecordingActionHandler<ActionContext> {
+    pub fn new() -> Rc<Self> {
+        Rc::new(Self {
+            log: RefCell::new(Vec::new()),
+            responses: RefCell::new(Vec::new()),
+            _context: PhantomData,
+        })
+    }
+
+    pub fn respond(&self, sender: HitTestTreeRef, kind: ActionKind, flags: EventContinueControl) {
+        self.responses.borrow_mut().push((sender, kind, flags));
+    }
+
+    /// 記録されたイベントを取り出す（記録は空になる）
+    pub fn take_log(&self) -> Vec<(HitTestTreeRef, ActionKind)> {
+        core::mem::take(&mut *self.log.borrow_mut())
+    }
+
+    fn record(&self, sender: HitTestTreeRef, kind: ActionKind) -> EventContinueControl {
+        self.log.borrow_mut().push((sender, kind));
+
+        self.responses
+            .borrow()
+            .iter()
+            .find(|&&(s, k, _)| s == sender && k == kind)
+            .map_or(EventContinueControl::empty(), |&(_, _, f)| f)
+    }
+}
+impl<ActionContext> HitTestTreeActionHandler for RecordingActionHandler<ActionContext> {
+    type Context = ActionContext;
+
+    fn on_pointer_enter(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut ActionContext,
+        _ht: &mut HitTestTreeManager<ActionContext>,
+        _args: PointerActionArgs,
+    ) -> EventContinueControl {
+        self.record(sender, ActionKind::PointerEnter)
+    }
+
+    fn on_pointer_leave(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut ActionContext,
+        _ht: &mut HitTestTreeManager<ActionContext>,
+        _args: PointerActionArgs,
+    ) -> EventContinueControl {
+        self.record(sender, ActionKind::PointerLeave)
+    }
+
+    fn on_pointer_move(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut ActionContext,
+        _ht: &mut HitTestTreeManager<ActionContext>,
+        _args: PointerActionArgs,
+    ) -> EventContinueControl {
+        self.record(sender, ActionKind::PointerMove)
+    }
+
+    fn on_pointer_down(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut ActionContext,
+        _ht: &mut HitTestTreeManager<ActionContext>,
+        _args: PointerActionArgs,
+    ) -> EventContinueControl {
+        self.record(sender, ActionKind::PointerDown)
+    }
+
+    fn on_pointer_up(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut ActionContext,
+        _ht: &mut HitTestTreeManager<ActionContext>,
+        _args: PointerActionArgs,
+    ) -> EventContinueControl {
+        self.record(sender, ActionKind::PointerUp)
+    }
+
+    fn on_click(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut ActionContext,
+        _ht: &mut HitTestTreeManager<ActionContext>,
+        _args: PointerActionArgs,
+    ) -> EventContinueControl {
+        self.record(sender, ActionKind::Click)
+    }
+
+    fn on_double_click(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut ActionContext,
+        _ht: &mut HitTestTreeManager<ActionContext>,
+        _args: PointerActionArgs,
+    ) -> EventContinueControl {
+        self.record(sender, ActionKind::DoubleClick)
+    }
+
+    fn on_aux_pointer_down(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut ActionContext,
+        _ht: &mut HitTestTreeManager<ActionContext>,
+        _args: PointerActionArgs,
+        button: PointerButton,
+    ) -> EventContinueControl {
+        self.record(sender, ActionKind::AuxPointerDown(button))
+    }
+
+    fn on_aux_pointer_up(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut ActionContext,
+        _ht: &mut HitTestTreeManager<ActionContext>,
+        _args: PointerActionArgs,
+        button: PointerButton,
+    ) -> EventContinueControl {
+        self.record(sender, ActionKind::AuxPointerUp(button))
+    }
+
+    fn on_aux_click(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut ActionContext,
+        _ht: &mut HitTestTreeManager<ActionContext>,
+        _args: PointerActionArgs,
+        button: PointerButton,
+    ) -> EventContinueControl {
+        self.record(sender, ActionKind::AuxClick(button))
+    }
+
+    fn on_wheel(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut ActionContext,
+        _ht: &mut HitTestTreeManager<ActionContext>,
+        _args: PointerActionArgs,
+        _delta: WheelDelta,
+    ) -> EventContinueControl {
+        self.record(sender, ActionKind::Wheel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittest::HitTestTreeData;
+
+    const CLIENT_SIZE: ClientSize = ClientSize {
+        width: 100.0,
+        height: 100.0,
+    };
+
+    /// 左右に2つの要素を並べた木
+    struct Fixture {
+        ht: HitTestTreeManager<()>,
+        handler: Rc<RecordingActionHandler<()>>,
+        root: HitTestTreeRef,
+        left: HitTestTreeRef,
+        right: HitTestTreeRef,
+        manager: PointerInputManager<ReplayInputPlatform>,
+    }
+    impl Fixture {
+        fn new() -> Self {
+            let mut ht = HitTestTreeManager::new();
+            let handler = RecordingActionHandler::new();
+            let mut node = |left: f32, width: f32, width_adjustment_factor: f32| {
+                ht.alloc(HitTestTreeData {
+                    left,
+                    top: 0.0,
+                    left_adjustment_factor: 0.0,
+                    top_adjustment_factor: 0.0,
+                    width,
+                    height: 0.0,
+                    width_adjustment_factor,
+                    height_adjustment_factor: 1.0,
+                    parent: None,
+                    children: Vec::new(),
+                    focusable: false,
+                    action_handler: Some(Rc::downgrade(&handler) as _),
+                })
+            };
+            let root = node(0.0, 0.0, 1.0);
+            let left = node(0.0, 40.0, 0.0);
+            let right = node(60.0, 40.0, 0.0);
+            ht.add_child(root, left);
+            ht.add_child(root, right);
+
+            Self {
+                ht,
+                handler,
+                root,
+                left,
+                right,
+                manager: PointerInputManager::with_platform(ReplayInputPlatform::new(
+                    Duration::from_millis(500),
+                )),
+            }
+        }
+
+        fn replay(&mut self, events: &[RecordedPointerEvent]) -> Vec<(HitTestTreeRef, ActionKind)> {
+            replay(
+                &mut self.manager,
+                &mut self.ht,
+                &mut (),
+                self.root,
+                CLIENT_SIZE,
+                events,
+            );
+
+            self.handler.take_log()
+        }
+    }
+
+    fn event(time_ms: u64, kind: RecordedPointerEventKind, x: f32) -> RecordedPointerEvent {
+        RecordedPointerEvent {
+            time: Duration::from_millis(time_ms),
+            kind,
+            client_x: x,
+            client_y: 50.0,
+            modifiers: ModifierKeys::empty(),
+        }
+    }
+
+    #[test]
+    fn enter_and_leave_bubble_to_ancestors() {
+        let mut f = Fixture::new();
+        let (root, left, right) = (f.root, f.left, f.right);
+
+        // 何もホバーしていない状態からでもenterが届く
+        assert_eq!(
+            f.replay(&[event(0, RecordedPointerEventKind::Move, 20.0)]),
+            [
+                (left, ActionKind::PointerEnter),
+                (root, ActionKind::PointerEnter),
+                (left, ActionKind::PointerMove),
+                (root, ActionKind::PointerMove),
+            ]
+        );
+        assert_eq!(
+            f.replay(&[event(10, RecordedPointerEventKind::Move, 80.0)]),
+            [
+                (left, ActionKind::PointerLeave),
+                (root, ActionKind::PointerLeave),
+                (right, ActionKind::PointerEnter),
+                (root, ActionKind::PointerEnter),
+                (right, ActionKind::PointerMove),
+                (root, ActionKind::PointerMove),
+            ]
+        );
+        // 同じ要素の中で動いてもenter/leaveは届かない
+        assert_eq!(
+            f.replay(&[event(20, RecordedPointerEventKind::Move, 90.0)]),
+            [
+                (right, ActionKind::PointerMove),
+                (root, ActionKind::PointerMove),
+            ]
+        );
+    }
+
+    #[test]
+    fn click_and_double_click_bubble() {
+        let mut f = Fixture::new();
+        let (root, left) = (f.root, f.left);
+        f.handler.respond(
+            left,
+            ActionKind::Click,
+            EventContinueControl::STOP_PROPAGATION,
+        );
+        f.replay(&[event(0, RecordedPointerEventKind::Move, 20.0)]);
+
+        assert_eq!(
+            f.replay(&[
+                event(100, RecordedPointerEventKind::LeftDown, 20.0),
+                event(150, RecordedPointerEventKind::LeftUp, 20.0),
+            ]),
+            [
+                (left, ActionKind::PointerDown),
+                (root, ActionKind::PointerDown),
+                (left, ActionKind::PointerMove),
+                (root, ActionKind::PointerMove),
+                (left, ActionKind::PointerUp),
+                (root, ActionKind::PointerUp),
+                (left, ActionKind::Click),
+            ]
+        );
+        let log = f.replay(&[
+            event(300, RecordedPointerEventKind::LeftDown, 20.0),
+            event(350, RecordedPointerEventKind::LeftUp, 20.0),
+        ]);
+        assert_eq!(
+            log.iter()
+                .filter(|(_, k)| matches!(k, ActionKind::Click | ActionKind::DoubleClick))
+                .copied()
+                .collect::<Vec<_>>(),
+            [
+                (left, ActionKind::Click),
+                (left, ActionKind::DoubleClick),
+                (root, ActionKind::DoubleClick),
+            ]
+        );
+        // ダブルクリックの間隔を過ぎたら1回目のクリックとして扱う
+        let log = f.replay(&[
+            event(2000, RecordedPointerEventKind::LeftDown, 20.0),
+            event(2050, RecordedPointerEventKind::LeftUp, 20.0),
+        ]);
+        assert!(!log.iter().any(|(_, k)| *k == ActionKind::DoubleClick));
+    }
+
+    #[test]
+    fn captured_element_receives_events_until_released() {
+        let mut f = Fixture::new();
+        let (root, left, right) = (f.root, f.left, f.right);
+        f.handler.respond(
+            left,
+            ActionKind::PointerDown,
+            EventContinueControl::CAPTURE_ELEMENT | EventContinueControl::STOP_PROPAGATION,
+        );
+        f.handler.respond(
+            left,
+            ActionKind::PointerUp,
+            EventContinueControl::RELEASE_CAPTURE_ELEMENT,
+        );
+        f.replay(&[event(0, RecordedPointerEventKind::Move, 20.0)]);
+
+        assert_eq!(
+            f.replay(&[
+                event(10, RecordedPointerEventKind::LeftDown, 20.0),
+                event(20, RecordedPointerEventKind::Move, 80.0),
+            ]),
+            [
+                (left, ActionKind::PointerDown),
+                (left, ActionKind::PointerMove),
+            ]
+        );
+        assert_eq!(f.manager.capturing(), Some(left));
+        assert_eq!(f.manager.platform().capture_log(), [CaptureLogEntry::Set]);
+
+        // 離したところでキャプチャが外れ、ポインタの下の要素に入り直す（動きすぎたのでクリックにはならない）
+        assert_eq!(
+            f.replay(&[event(30, RecordedPointerEventKind::LeftUp, 80.0)]),
+            [
+                (left, ActionKind::PointerMove),
+                (left, ActionKind::PointerUp),
+                (left, ActionKind::PointerLeave),
+                (root, ActionKind::PointerLeave),
+                (right, ActionKind::PointerEnter),
+                (root, ActionKind::PointerEnter),
+                (right, ActionKind::PointerMove),
+                (root, ActionKind::PointerMove),
+            ]
+        );
+        assert_eq!(f.manager.capturing(), None);
+        assert_eq!(
+            f.manager.platform().capture_log(),
+            [CaptureLogEntry::Set, CaptureLogEntry::Release]
+        );
+    }
+
+    #[test]
+    fn freed_capturing_element_releases_capture() {
+        let mut f = Fixture::new();
+        let left = f.left;
+        f.handler.respond(
+            left,
+            ActionKind::PointerDown,
+            EventContinueControl::CAPTURE_ELEMENT | EventContinueControl::STOP_PROPAGATION,
+        );
+        f.replay(&[
+            event(0, RecordedPointerEventKind::Move, 20.0),
+            event(10, RecordedPointerEventKind::LeftDown, 20.0),
+        ]);
+        f.ht.free(left).unwrap();
+
+        f.replay(&[event(20, RecordedPointerEventKind::Move, 30.0)]);
+        assert_eq!(f.manager.capturing(), None);
+        assert_eq!(
+            f.manager.platform().capture_log(),
+            [CaptureLogEntry::Set, CaptureLogEntry::Release]
+        );
+    }
+}